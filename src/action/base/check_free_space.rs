@@ -0,0 +1,183 @@
+use std::path::{Path, PathBuf};
+
+use tracing::{span, Span};
+
+use crate::action::{
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+};
+
+/// A conservative estimate of the space an install needs, including unpacking the Nix closure
+pub const DEFAULT_REQUIRED_FREE_SPACE: u64 = 2 * 1024 * 1024 * 1024;
+
+/** Ensure the filesystem holding `path` has at least `required` bytes free
+
+As `path` may not exist yet, the nearest existing ancestor is checked instead.
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+#[serde(tag = "action_name", rename = "check_free_space")]
+pub struct CheckFreeSpace {
+    path: PathBuf,
+    required: u64,
+}
+
+impl CheckFreeSpace {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(
+        path: impl AsRef<Path>,
+        required: u64,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        let this = Self {
+            path: path.as_ref().to_path_buf(),
+            required,
+        };
+
+        // Fail before anything is changed, the check is repeated during execution
+        this.check().map_err(Self::error)?;
+
+        Ok(StatefulAction::uncompleted(this))
+    }
+
+    fn check(&self) -> Result<(), CheckFreeSpaceError> {
+        let checked = self
+            .path
+            .ancestors()
+            .find(|ancestor| ancestor.exists())
+            .unwrap_or(Path::new("/"));
+        let stat = nix::sys::statvfs::statvfs(checked)
+            .map_err(|e| CheckFreeSpaceError::Statvfs(checked.to_path_buf(), e))?;
+
+        #[allow(clippy::unnecessary_cast)] // The field types differ between platforms
+        let available = stat.blocks_available() as u64 * stat.fragment_size() as u64;
+        tracing::debug!(
+            path = %checked.display(),
+            available,
+            required = self.required,
+            "Checked free space"
+        );
+
+        if available < self.required {
+            return Err(CheckFreeSpaceError::NotEnoughSpace {
+                path: checked.to_path_buf(),
+                required: self.required,
+                available,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "check_free_space")]
+impl Action for CheckFreeSpace {
+    fn action_tag() -> ActionTag {
+        ActionTag("check_free_space")
+    }
+    fn tracing_synopsis(&self) -> String {
+        format!(
+            "Check for {} of free space for `{}`",
+            format_bytes(self.required),
+            self.path.display()
+        )
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "check_free_space",
+            path = tracing::field::display(self.path.display()),
+            required = self.required,
+        )
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            self.tracing_synopsis(),
+            vec![
+                "Nix is unpacked and copied into the store, so make sure it will fit first"
+                    .to_string(),
+            ],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        self.check().map_err(Self::error)?;
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        vec![]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        Ok(())
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next in &UNITS[1..] {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+    format!("{value:.1} {unit}")
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum CheckFreeSpaceError {
+    #[error("Checking free space of `{0}`")]
+    Statvfs(PathBuf, #[source] nix::errno::Errno),
+    #[error(
+        "Not enough free space on `{path}`, need {required}, have {available}. Free up some space, or if you are sure it will fit, pass a smaller `--required-free-space`",
+        path = .path.display(),
+        required = format_bytes(*.required),
+        available = format_bytes(*.available),
+    )]
+    NotEnoughSpace {
+        path: PathBuf,
+        required: u64,
+        available: u64,
+    },
+}
+
+impl From<CheckFreeSpaceError> for ActionErrorKind {
+    fn from(val: CheckFreeSpaceError) -> Self {
+        ActionErrorKind::Custom(Box::new(val))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_bytes, CheckFreeSpace};
+
+    #[test]
+    fn formats_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(2 * 1024 * 1024 * 1024), "2.0 GiB");
+        assert_eq!(format_bytes(1536 * 1024), "1.5 MiB");
+    }
+
+    #[tokio::test]
+    async fn checks_nearest_existing_ancestor() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let missing = temp_dir.path().join("not").join("yet");
+
+        CheckFreeSpace::plan(&missing, 1).await.unwrap();
+        let err = CheckFreeSpace::plan(&missing, u64::MAX).await.unwrap_err();
+        assert!(
+            err.kind().to_string().contains("Not enough free space"),
+            "{err:?}"
+        );
+    }
+}
@@ -16,6 +16,13 @@ use crate::util::OnMissing;
 
 If `force_prune_on_revert` is set, the folder will always be deleted on
 [`revert`](CreateDirectory::revert).
+
+Missing parents are created too, and are removed again on revert if they are empty. Parents which
+already existed are left alone.
+
+An existing empty directory (like a pre-created mountpoint) with the wrong owner or mode is adopted, and
+given the owner and mode it would have been created with. A populated one with the wrong owner is an error.
+An adopted directory is kept on revert, and given back its prior owner and mode.
 */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 #[serde(tag = "action_name", rename = "create_directory")]
@@ -26,6 +33,20 @@ pub struct CreateDirectory {
     pub(crate) mode: Option<u32>,
     pub(crate) is_mountpoint: bool,
     pub(crate) force_prune_on_revert: bool,
+    /// The directories [`execute`](CreateDirectory::execute) created, outermost first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) created: Vec<PathBuf>,
+    /// The owner and mode of the existing directory [`execute`](CreateDirectory::execute) adopted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) adopted: Option<AdoptedDirectory>,
+}
+
+/// The owner and mode an existing directory had before it was adopted
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AdoptedDirectory {
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    pub(crate) mode: u32,
 }
 
 impl CreateDirectory {
@@ -53,6 +74,13 @@ impl CreateDirectory {
                     path.to_owned(),
                 )));
             }
+            let is_empty = path
+                .read_dir()
+                .map_err(|e| ActionErrorKind::Read(path.clone(), e))
+                .map_err(Self::error)?
+                .next()
+                .is_none();
+            let mut needs_adopting = false;
 
             // Does it have the right user/group?
             if let Some(user) = &user {
@@ -64,7 +92,9 @@ impl CreateDirectory {
                     .map_err(Self::error)?
                     .uid;
                 let found_uid = metadata.uid();
-                if found_uid != expected_uid.as_raw() {
+                if found_uid != expected_uid.as_raw() && is_empty {
+                    needs_adopting = true;
+                } else if found_uid != expected_uid.as_raw() {
                     return Err(Self::error(ActionErrorKind::PathUserMismatch(
                         path.clone(),
                         found_uid,
@@ -81,7 +111,9 @@ impl CreateDirectory {
                     .map_err(Self::error)?
                     .gid;
                 let found_gid = metadata.gid();
-                if found_gid != expected_gid.as_raw() {
+                if found_gid != expected_gid.as_raw() && is_empty {
+                    needs_adopting = true;
+                } else if found_gid != expected_gid.as_raw() {
                     return Err(Self::error(ActionErrorKind::PathGroupMismatch(
                         path.clone(),
                         found_gid,
@@ -90,14 +122,27 @@ impl CreateDirectory {
                 }
             }
 
+            if let Some(mode) = mode {
+                needs_adopting |= is_empty && metadata.permissions().mode() & 0o7777 != mode;
+            }
+
             // Is it a mountpoint?
             is_mountpoint = path_is_mountpoint(&path).await.map_err(Self::error)?;
-            tracing::debug!(
-                is_mountpoint,
-                "Creating directory `{}` already complete",
-                path.display(),
-            );
-            ActionState::Completed
+            if needs_adopting {
+                tracing::debug!(
+                    is_mountpoint,
+                    "Directory `{}` exists and is empty, setting its owner and mode",
+                    path.display(),
+                );
+                ActionState::Uncompleted
+            } else {
+                tracing::debug!(
+                    is_mountpoint,
+                    "Creating directory `{}` already complete",
+                    path.display(),
+                );
+                ActionState::Completed
+            }
         } else {
             ActionState::Uncompleted
         };
@@ -110,8 +155,11 @@ impl CreateDirectory {
                 mode,
                 is_mountpoint,
                 force_prune_on_revert,
+                created: vec![],
+                adopted: None,
             },
             state: action_state,
+            duration: None,
         })
     }
 }
@@ -150,15 +198,12 @@ impl Action for CreateDirectory {
             user,
             group,
             mode,
-            is_mountpoint, // If `is_mountpoint = true` the `ActionState` should be completed.
+            is_mountpoint, // If `is_mountpoint = true` only the owner and mode are set.
             force_prune_on_revert: _,
+            created,
+            adopted,
         } = self;
 
-        if *is_mountpoint {
-            // A `/nix` mount exists, we don't need to do anything.
-            return Ok(());
-        }
-
         let gid = if let Some(group) = group {
             Some(
                 Group::from_name(group.as_str())
@@ -184,10 +229,33 @@ impl Action for CreateDirectory {
             None
         };
 
-        tokio::fs::create_dir_all(&path)
-            .await
-            .map_err(|e| ActionErrorKind::CreateDirectory(path.clone(), e))
-            .map_err(Self::error)?;
+        if path.exists() {
+            let metadata = tokio::fs::metadata(&path)
+                .await
+                .map_err(|e| ActionErrorKind::GettingMetadata(path.clone(), e))
+                .map_err(Self::error)?;
+            *adopted = Some(AdoptedDirectory {
+                uid: metadata.uid(),
+                gid: metadata.gid(),
+                mode: metadata.permissions().mode() & 0o7777,
+            });
+        }
+
+        // A `/nix` mount exists, so there is nothing to create
+        if !*is_mountpoint {
+            let mut missing = path
+                .ancestors()
+                .take_while(|ancestor| !ancestor.exists())
+                .map(Path::to_path_buf)
+                .collect::<Vec<_>>();
+            missing.reverse();
+
+            tokio::fs::create_dir_all(&path)
+                .await
+                .map_err(|e| ActionErrorKind::CreateDirectory(path.clone(), e))
+                .map_err(Self::error)?;
+            *created = missing;
+        }
         chown(path, uid, gid)
             .map_err(|e| ActionErrorKind::Chown(path.clone(), e))
             .map_err(Self::error)?;
@@ -210,6 +278,8 @@ impl Action for CreateDirectory {
             mode: _,
             is_mountpoint,
             force_prune_on_revert,
+            created: _,
+            adopted,
         } = &self;
         match (is_mountpoint, force_prune_on_revert) {
             (true, true) => vec![ActionDescription::new(
@@ -217,6 +287,13 @@ impl Action for CreateDirectory {
                 vec![],
             )],
             (true, false) => vec![],
+            (false, _) if adopted.is_some() => vec![ActionDescription::new(
+                format!(
+                    "Restore the owner and mode of the directory `{}`",
+                    path.display()
+                ),
+                vec![],
+            )],
             (false, _) => vec![ActionDescription::new(
                 format!(
                     "Remove the directory `{}`{}",
@@ -241,7 +318,11 @@ impl Action for CreateDirectory {
             mode: _,
             is_mountpoint,
             force_prune_on_revert,
+            created,
+            adopted,
         } = self;
+        // The directory was there before, so it stays, like a mountpoint
+        let keep = *is_mountpoint || adopted.is_some();
 
         let contents = path
             .read_dir()
@@ -250,9 +331,9 @@ impl Action for CreateDirectory {
             .collect::<Vec<_>>();
         let is_empty = contents.is_empty();
 
-        match (is_mountpoint, is_empty, force_prune_on_revert) {
+        match (keep, is_empty, force_prune_on_revert) {
             (true, _, true) => {
-                tracing::debug!("Cleaning mountpoint `{}`", path.display());
+                tracing::debug!("Cleaning `{}`", path.display());
                 for child_path in contents {
                     let child_path = child_path
                         .map_err(|e| ActionErrorKind::ReadDir(path.clone(), e))
@@ -276,19 +357,51 @@ impl Action for CreateDirectory {
                 }
             },
             (true, _, false) => {
-                tracing::debug!("Not cleaning mountpoint `{}`", path.display());
-            },
-            (false, true, _) | (false, false, true) => {
-                crate::util::remove_dir_all(path, OnMissing::Error)
-                    .await
-                    .map_err(|e| ActionErrorKind::Remove(path.clone(), e))
-                    .map_err(Self::error)?
+                tracing::debug!("Not cleaning `{}`", path.display());
             },
+            (false, true, _) | (false, false, true) => super::remove_directory::remove_tree(path)
+                .await
+                .map_err(Self::error)?,
             (false, false, false) => {
                 tracing::debug!("Not removing `{}`, the folder is not empty", path.display());
+                return Ok(());
             },
         };
 
+        if let Some(AdoptedDirectory { uid, gid, mode }) = adopted {
+            chown(
+                path.as_path(),
+                Some(nix::unistd::Uid::from_raw(*uid)),
+                Some(nix::unistd::Gid::from_raw(*gid)),
+            )
+            .map_err(|e| ActionErrorKind::Chown(path.clone(), e))
+            .map_err(Self::error)?;
+            tokio::fs::set_permissions(&path, PermissionsExt::from_mode(*mode))
+                .await
+                .map_err(|e| ActionErrorKind::SetPermissions(*mode, path.to_owned(), e))
+                .map_err(Self::error)?;
+        }
+
+        // Remove the parents we created, innermost first, stopping at the first one with other contents
+        for parent in created.iter().rev().filter(|created| *created != path) {
+            let is_empty = match std::fs::read_dir(parent) {
+                Ok(mut entries) => entries.next().is_none(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(Self::error(ActionErrorKind::Read(parent.clone(), e))),
+            };
+            if !is_empty {
+                tracing::debug!(
+                    "Not removing `{}`, the folder is not empty",
+                    parent.display()
+                );
+                break;
+            }
+            tokio::fs::remove_dir(parent)
+                .await
+                .map_err(|e| ActionErrorKind::Remove(parent.clone(), e))
+                .map_err(Self::error)?;
+        }
+
         Ok(())
     }
 }
@@ -407,4 +520,148 @@ mod test {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn creates_and_deletes_only_missing_parents() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let existing = temp_dir.path().join("existing");
+        tokio::fs::create_dir(&existing).await?;
+        let test_dir = existing.join("var").join("nix").join("profiles");
+        let mut action = CreateDirectory::plan(test_dir.clone(), None, None, None, false).await?;
+
+        action.try_execute().await?;
+
+        assert!(test_dir.exists(), "Folder should have been created");
+        assert_eq!(
+            action.action.created,
+            vec![
+                existing.join("var"),
+                existing.join("var").join("nix"),
+                test_dir.clone(),
+            ]
+        );
+
+        action.try_revert().await?;
+
+        assert!(
+            !existing.join("var").exists(),
+            "Created parents should have been deleted"
+        );
+        assert!(
+            existing.exists(),
+            "Existing parent should not have been deleted"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn leaves_created_parents_with_other_contents() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let test_dir = temp_dir.path().join("var").join("nix").join("profiles");
+        let mut action = CreateDirectory::plan(test_dir.clone(), None, None, None, false).await?;
+
+        action.try_execute().await?;
+
+        let stub_file = temp_dir.path().join("var").join("stub");
+        tokio::fs::write(&stub_file, "More content").await?;
+
+        action.try_revert().await?;
+
+        assert!(!test_dir.exists(), "Folder should have been deleted");
+        assert!(
+            !temp_dir.path().join("var").join("nix").exists(),
+            "Empty created parent should have been deleted"
+        );
+        assert!(
+            stub_file.exists(),
+            "Parent with other contents should not have been deleted"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn adopts_existing_empty_directory() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let test_dir = temp_dir.path().join("nix");
+        tokio::fs::create_dir(&test_dir).await?;
+        tokio::fs::set_permissions(&test_dir, PermissionsExt::from_mode(0o700)).await?;
+
+        let mut action = CreateDirectory::plan(test_dir.clone(), None, None, 0o755, false).await?;
+        assert_eq!(action.state, ActionState::Uncompleted);
+        action.try_execute().await?;
+        assert_eq!(
+            tokio::fs::metadata(&test_dir).await?.permissions().mode() & 0o7777,
+            0o755
+        );
+
+        // Already as it would have been created
+        let action = CreateDirectory::plan(test_dir.clone(), None, None, 0o755, false).await?;
+        assert_eq!(action.state, ActionState::Completed);
+
+        // Owned by someone else, but empty, so it can be taken over
+        let action =
+            CreateDirectory::plan(test_dir.clone(), "nobody".to_string(), None, None, false)
+                .await?;
+        assert_eq!(action.state, ActionState::Uncompleted);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn restores_adopted_directory_on_revert() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let test_dir = temp_dir.path().join("nix");
+        tokio::fs::create_dir(&test_dir).await?;
+        tokio::fs::set_permissions(&test_dir, PermissionsExt::from_mode(0o700)).await?;
+        let metadata = tokio::fs::metadata(&test_dir).await?;
+
+        let mut action = CreateDirectory::plan(test_dir.clone(), None, None, 0o755, true).await?;
+        action.try_execute().await?;
+        assert_eq!(
+            action.inner().adopted,
+            Some(AdoptedDirectory {
+                uid: metadata.uid(),
+                gid: metadata.gid(),
+                mode: 0o700,
+            })
+        );
+        tokio::fs::write(test_dir.join("stub"), "").await?;
+
+        action.try_revert().await?;
+        // It was there before, so only the contents are pruned
+        assert!(test_dir.exists());
+        assert!(!test_dir.join("stub").exists());
+        let metadata = tokio::fs::metadata(&test_dir).await?;
+        assert_eq!(metadata.permissions().mode() & 0o7777, 0o700);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refuses_populated_directory_or_file() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let test_dir = temp_dir.path().join("nix");
+        tokio::fs::create_dir(&test_dir).await?;
+        tokio::fs::write(test_dir.join("stub"), "").await?;
+        tokio::fs::set_permissions(&test_dir, PermissionsExt::from_mode(0o700)).await?;
+
+        // The contents are not ours to take over
+        let action = CreateDirectory::plan(test_dir.clone(), None, None, 0o755, false).await?;
+        assert_eq!(action.state, ActionState::Completed);
+        assert!(
+            CreateDirectory::plan(test_dir.clone(), "nobody".to_string(), None, None, false)
+                .await
+                .is_err()
+        );
+
+        let test_file = temp_dir.path().join("file");
+        tokio::fs::write(&test_file, "").await?;
+        assert!(CreateDirectory::plan(test_file, None, None, None, false)
+            .await
+            .is_err());
+
+        Ok(())
+    }
 }
@@ -8,14 +8,19 @@ use crate::execute_command;
 
 use crate::action::{Action, ActionDescription, StatefulAction};
 
+/// `groupadd` exits with this when the group name is already in use, see `groupadd(8)`
+const GROUPADD_NAME_NOT_UNIQUE: i32 = 9;
+/// `groupdel` exits with this when the group does not exist, see `groupdel(8)`
+const GROUPDEL_GROUP_MISSING: i32 = 6;
+
 /**
 Create an operating system level user group
 */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 #[serde(tag = "action_name", rename = "create_group")]
 pub struct CreateGroup {
-    name: String,
-    gid: u32,
+    pub(crate) name: String,
+    pub(crate) gid: u32,
 }
 
 impl CreateGroup {
@@ -117,14 +122,26 @@ impl Action for CreateGroup {
             },
             _ => {
                 if which::which("groupadd").is_ok() {
-                    execute_command(
+                    match execute_command(
                         Command::new("groupadd")
                             .process_group(0)
                             .args(["-g", &gid.to_string(), "--system", name])
                             .stdin(std::process::Stdio::null()),
                     )
                     .await
-                    .map_err(Self::error)?;
+                    {
+                        Ok(_) => (),
+                        // Created since planning (like by a concurrent install), with the same GID
+                        Err(e)
+                            if e.exit_code() == Some(GROUPADD_NAME_NOT_UNIQUE)
+                                && Group::from_name(name).is_ok_and(|group| {
+                                    group.is_some_and(|g| g.gid.as_raw() == *gid)
+                                }) =>
+                        {
+                            tracing::debug!("Group `{name}` already exists with GID {gid}");
+                        },
+                        Err(e) => return Err(Self::error(e)),
+                    }
                 } else if which::which("addgroup").is_ok() {
                     execute_command(
                         Command::new("addgroup")
@@ -175,14 +192,20 @@ impl Action for CreateGroup {
             },
             _ => {
                 if which::which("groupdel").is_ok() {
-                    execute_command(
+                    match execute_command(
                         Command::new("groupdel")
                             .process_group(0)
-                            .arg(name)
+                            .arg(&name)
                             .stdin(std::process::Stdio::null()),
                     )
                     .await
-                    .map_err(Self::error)?;
+                    {
+                        Ok(_) => (),
+                        Err(e) if e.exit_code() == Some(GROUPDEL_GROUP_MISSING) => {
+                            tracing::debug!("Group `{name}` was already deleted");
+                        },
+                        Err(e) => return Err(Self::error(e)),
+                    }
                 } else if which::which("delgroup").is_ok() {
                     execute_command(
                         Command::new("delgroup")
@@ -201,3 +224,54 @@ impl Action for CreateGroup {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use nix::unistd::{Gid, Group};
+
+    use tokio::process::Command;
+
+    use super::CreateGroup;
+    use crate::action::{Action, ActionErrorKind, ActionState};
+    use crate::execute_command;
+
+    #[test]
+    fn existing_group_is_completed_or_mismatched() {
+        let root = Group::from_gid(Gid::from_raw(0))
+            .unwrap()
+            .expect("GID 0 should have a group");
+
+        let planned = CreateGroup::plan(root.name.clone(), 0).unwrap();
+        assert_eq!(planned.state, ActionState::Completed);
+
+        let err = CreateGroup::plan(root.name.clone(), 31999).unwrap_err();
+        assert!(
+            matches!(
+                err.kind(),
+                ActionErrorKind::GroupGidMismatch(name, 0, 31999) if *name == root.name
+            ),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_command_keeps_its_exit_code() {
+        let err = execute_command(Command::new("sh").args(["-c", "exit 9"]))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, ActionErrorKind::CommandOutput { .. }),
+            "{err:?}"
+        );
+        assert_eq!(err.exit_code(), Some(9));
+
+        // Wrapping the error in the action's doesn't lose it
+        let err = CreateGroup::error(err);
+        assert_eq!(err.kind().exit_code(), Some(9));
+
+        let err = execute_command(&mut Command::new("/nonexistent/nix-installer-test"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.exit_code(), None);
+    }
+}
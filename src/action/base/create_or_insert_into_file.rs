@@ -20,13 +20,22 @@ use tracing::{span, Span};
 pub enum Position {
     Beginning,
     End,
+    /// Just after the first line which is this one (ignoring surrounding whitespace), or at the
+    /// end if there is no such line
+    AfterLine(String),
 }
 
 /** Create a file at the given location with the provided `buf` as
 contents, optionally with an owning user, group, and mode.
 
 If the file exists, the provided `buf` will be inserted at its
-beginning or end, depending on the position field.
+beginning or end, or after a given line, depending on the position field.
+
+If `backup` is set, an existing file is first copied to `<path>.nix-installer.bak-<seconds since
+the epoch>`, and restored byte-for-byte on revert. An existing backup is never overwritten.
+
+On revert, if `buf` was edited in the file since, but its first and last lines are
+comments (like `# Nix` and `# End Nix`), the lines between those markers are removed instead.
  */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 #[serde(tag = "action_name", rename = "create_or_insert_into_file")]
@@ -37,6 +46,11 @@ pub struct CreateOrInsertIntoFile {
     mode: Option<u32>,
     buf: String,
     position: Position,
+    #[serde(default)]
+    backup: bool,
+    /// Where the original file was backed up to during execute, if it was
+    #[serde(default)]
+    backup_path: Option<PathBuf>,
 }
 
 impl CreateOrInsertIntoFile {
@@ -48,6 +62,7 @@ impl CreateOrInsertIntoFile {
         mode: impl Into<Option<u32>>,
         buf: String,
         position: Position,
+        backup: bool,
     ) -> Result<StatefulAction<Self>, ActionError> {
         let path = path.as_ref().to_path_buf();
         let mode = mode.into();
@@ -60,6 +75,8 @@ impl CreateOrInsertIntoFile {
             mode,
             buf,
             position,
+            backup,
+            backup_path: None,
         };
         if this.path.exists() {
             // If the path exists, perhaps we can just skip this
@@ -137,7 +154,7 @@ impl CreateOrInsertIntoFile {
                 .map_err(|e| ActionErrorKind::Read(this.path.clone(), e))
                 .map_err(Self::error)?;
 
-            if discovered_buf.contains(&this.buf) {
+            if find_fragment(&discovered_buf, &this.buf).is_some() {
                 tracing::debug!("Inserting into `{}` already complete", this.path.display(),);
                 return Ok(StatefulAction::completed(this));
             }
@@ -191,6 +208,8 @@ impl Action for CreateOrInsertIntoFile {
             mode,
             buf,
             position,
+            backup,
+            backup_path,
         } = self;
 
         let mut orig_file = match OpenOptions::new().read(true).open(&path).await {
@@ -199,6 +218,10 @@ impl Action for CreateOrInsertIntoFile {
             Err(e) => return Err(Self::error(ActionErrorKind::Open(path.to_owned(), e))),
         };
 
+        if *backup && orig_file.is_some() {
+            *backup_path = Some(crate::util::backup_file(path).await.map_err(Self::error)?);
+        }
+
         // Create a temporary file in the same directory as the one
         // that the final file goes in, so that we can rename it
         // atomically
@@ -231,31 +254,47 @@ impl Action for CreateOrInsertIntoFile {
                 ActionErrorKind::Open(temp_file_path.clone(), e)
             }).map_err(Self::error)?;
 
-        if *position == Position::End {
+        if let Position::AfterLine(line) = position {
+            let mut orig_contents = String::new();
             if let Some(ref mut orig_file) = orig_file {
-                tokio::io::copy(orig_file, &mut temp_file)
+                orig_file
+                    .read_to_string(&mut orig_contents)
                     .await
-                    .map_err(|e| {
-                        ActionErrorKind::Copy(path.to_owned(), temp_file_path.to_owned(), e)
-                    })
+                    .map_err(|e| ActionErrorKind::Read(path.to_owned(), e))
                     .map_err(Self::error)?;
             }
-        }
+            temp_file
+                .write_all(insert_after_line(&orig_contents, line, buf).as_bytes())
+                .await
+                .map_err(|e| ActionErrorKind::Write(temp_file_path.clone(), e))
+                .map_err(Self::error)?;
+        } else {
+            if *position == Position::End {
+                if let Some(ref mut orig_file) = orig_file {
+                    tokio::io::copy(orig_file, &mut temp_file)
+                        .await
+                        .map_err(|e| {
+                            ActionErrorKind::Copy(path.to_owned(), temp_file_path.to_owned(), e)
+                        })
+                        .map_err(Self::error)?;
+                }
+            }
 
-        temp_file
-            .write_all(buf.as_bytes())
-            .await
-            .map_err(|e| ActionErrorKind::Write(temp_file_path.clone(), e))
-            .map_err(Self::error)?;
+            temp_file
+                .write_all(buf.as_bytes())
+                .await
+                .map_err(|e| ActionErrorKind::Write(temp_file_path.clone(), e))
+                .map_err(Self::error)?;
 
-        if *position == Position::Beginning {
-            if let Some(ref mut orig_file) = orig_file {
-                tokio::io::copy(orig_file, &mut temp_file)
-                    .await
-                    .map_err(|e| {
-                        ActionErrorKind::Copy(path.to_owned(), temp_file_path.to_owned(), e)
-                    })
-                    .map_err(Self::error)?;
+            if *position == Position::Beginning {
+                if let Some(ref mut orig_file) = orig_file {
+                    tokio::io::copy(orig_file, &mut temp_file)
+                        .await
+                        .map_err(|e| {
+                            ActionErrorKind::Copy(path.to_owned(), temp_file_path.to_owned(), e)
+                        })
+                        .map_err(Self::error)?;
+                }
             }
         }
 
@@ -329,7 +368,19 @@ impl Action for CreateOrInsertIntoFile {
             mode: _,
             buf,
             position: _,
+            backup: _,
+            backup_path,
         } = &self;
+        if let Some(backup_path) = backup_path {
+            return vec![ActionDescription::new(
+                format!(
+                    "Restore file `{}` from `{}`",
+                    path.display(),
+                    backup_path.display()
+                ),
+                vec![],
+            )];
+        }
         vec![ActionDescription::new(
             format!("Delete Nix related fragment from file `{}`", path.display()),
             vec![format!(
@@ -348,7 +399,19 @@ impl Action for CreateOrInsertIntoFile {
             mode: _,
             buf,
             position: _,
+            backup: _,
+            backup_path,
         } = self;
+
+        if let Some(backup) = backup_path.as_ref().filter(|backup| backup.exists()) {
+            tokio::fs::rename(&backup, &path)
+                .await
+                .map_err(|e| ActionErrorKind::Rename(backup.to_owned(), path.to_owned(), e))
+                .map_err(Self::error)?;
+            *backup_path = None;
+            return Ok(());
+        }
+
         // The user already deleted it
         if !path.exists() {
             return Ok(());
@@ -369,9 +432,19 @@ impl Action for CreateOrInsertIntoFile {
             .map_err(|e| ActionErrorKind::Read(path.to_owned(), e))
             .map_err(Self::error)?;
 
-        if let Some(start) = file_contents.rfind(buf.as_str()) {
+        // Prefer removing a fragment on its own line, but the fragment may have been inserted
+        // after a line with no trailing newline
+        if let Some(start) =
+            find_fragment(&file_contents, buf).or_else(|| file_contents.rfind(buf.as_str()))
+        {
             let end = start + buf.len();
             file_contents.replace_range(start..end, "")
+        } else if let Some(block) = find_marked_block(&file_contents, buf) {
+            tracing::debug!(
+                path = %path.display(),
+                "The inserted fragment was edited, removing the lines between its markers"
+            );
+            file_contents.replace_range(block, "")
         }
 
         if file_contents.is_empty() {
@@ -401,6 +474,72 @@ impl Action for CreateOrInsertIntoFile {
     }
 }
 
+/// Insert `fragment` into `buf` just after the first line which is `line`, or at the end
+fn insert_after_line(buf: &str, line: &str, fragment: &str) -> String {
+    let insert_at = buf
+        .split_inclusive('\n')
+        .scan(0, |offset, existing| {
+            *offset += existing.len();
+            Some((*offset, existing))
+        })
+        .find(|(_, existing)| existing.trim() == line.trim())
+        .map_or(buf.len(), |(end, _)| end);
+    let (head, tail) = buf.split_at(insert_at);
+    let separator = if head.is_empty() || head.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    format!("{head}{separator}{fragment}{tail}")
+}
+
+/// Find the last occurrence of `fragment` in `buf` which begins at the start of a line
+///
+/// This prevents a fragment such as `nix\n` from matching the tail of an unrelated line like `unix\n`.
+fn find_fragment(buf: &str, fragment: &str) -> Option<usize> {
+    buf.rmatch_indices(fragment)
+        .map(|(idx, _)| idx)
+        .find(|&idx| idx == 0 || fragment.starts_with('\n') || buf[..idx].ends_with('\n'))
+}
+
+/// Find the last block in `buf` running from the first line of `fragment` to its last, when
+/// both are comments, so a fragment edited since it was inserted can still be removed
+///
+/// The block covers whole lines, including the end marker's newline.
+fn find_marked_block(buf: &str, fragment: &str) -> Option<std::ops::Range<usize>> {
+    let mut fragment_lines = fragment
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+    let start_marker = fragment_lines.next()?;
+    let end_marker = fragment_lines.next_back()?;
+    if !start_marker.starts_with('#') || !end_marker.starts_with('#') || start_marker == end_marker
+    {
+        return None;
+    }
+
+    let lines = buf
+        .split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line))
+        })
+        .collect::<Vec<_>>();
+    lines
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, (_, line))| line.trim() == start_marker)
+        .find_map(|(index, &(start, _))| {
+            lines[index + 1..]
+                .iter()
+                .take_while(|(_, line)| line.trim() != start_marker)
+                .find(|(_, line)| line.trim() == end_marker)
+                .map(|&(end, line)| start..end + line.len())
+        })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -418,6 +557,7 @@ mod test {
             None,
             "Test".into(),
             Position::Beginning,
+            false,
         )
         .await?;
 
@@ -447,6 +587,7 @@ mod test {
             None,
             "Test".into(),
             Position::Beginning,
+            false,
         )
         .await?;
 
@@ -488,6 +629,7 @@ mod test {
                 None,
                 expected_content.into(),
                 position,
+                false,
             )
             .await?;
 
@@ -503,6 +645,126 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn only_matches_and_reverts_whole_lines() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let test_file = temp_dir.path().join("only_matches_and_reverts_whole_lines");
+
+        write(test_file.as_path(), "unix\n").await?;
+
+        let mut action = CreateOrInsertIntoFile::plan(
+            test_file.clone(),
+            None,
+            None,
+            None,
+            "nix\n".into(),
+            Position::End,
+            false,
+        )
+        .await?;
+        assert_eq!(action.state, crate::action::ActionState::Uncompleted);
+
+        action.try_execute().await?;
+        assert_eq!(read_to_string(&test_file).await?, "unix\nnix\n");
+
+        write(test_file.as_path(), "nix\nunix\n").await?;
+        action.try_revert().await?;
+
+        assert_eq!(read_to_string(&test_file).await?, "unix\n");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn inserts_after_line_and_reverts() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let test_file = temp_dir.path().join("inserts_after_line_and_reverts");
+
+        let original = "[boot]\nsystemd=false\n\n[user]\ndefault=me\n";
+        write(test_file.as_path(), original).await?;
+
+        let mut action = CreateOrInsertIntoFile::plan(
+            test_file.clone(),
+            None,
+            None,
+            None,
+            "command = nix-daemon\n".into(),
+            Position::AfterLine("[boot]".into()),
+            false,
+        )
+        .await?;
+        action.try_execute().await?;
+        assert_eq!(
+            read_to_string(&test_file).await?,
+            "[boot]\ncommand = nix-daemon\nsystemd=false\n\n[user]\ndefault=me\n"
+        );
+
+        action.try_revert().await?;
+        assert_eq!(read_to_string(&test_file).await?, original);
+
+        Ok(())
+    }
+
+    #[test]
+    fn insert_after_missing_or_unterminated_line() {
+        assert_eq!(insert_after_line("[boot]", "[boot]", "a\n"), "[boot]\na\n");
+        assert_eq!(
+            insert_after_line("[user]\n", "[boot]", "[boot]\na\n"),
+            "[user]\n[boot]\na\n"
+        );
+        assert_eq!(insert_after_line("", "[boot]", "a\n"), "a\n");
+    }
+
+    #[tokio::test]
+    async fn removes_edited_block_between_markers() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let test_file = temp_dir.path().join("removes_edited_block_between_markers");
+
+        write(test_file.as_path(), "export A=1\n").await?;
+
+        let mut action = CreateOrInsertIntoFile::plan(
+            test_file.clone(),
+            None,
+            None,
+            None,
+            "# Nix\nif [ -e nix.sh ]; then\n    . nix.sh\nfi\n# End Nix\n".into(),
+            Position::Beginning,
+            false,
+        )
+        .await?;
+        action.try_execute().await?;
+
+        // Edited before, inside, and after the inserted block
+        write(
+            test_file.as_path(),
+            "# Set by me\n# Nix\nif [ -e nix.sh ]; then\n  . nix.sh # sourced\nfi\n# End Nix\nexport A=1\nexport B=2\n",
+        )
+        .await?;
+        action.try_revert().await?;
+
+        assert_eq!(
+            read_to_string(&test_file).await?,
+            "# Set by me\nexport A=1\nexport B=2\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn marked_block_needs_both_comment_markers() {
+        assert_eq!(
+            find_marked_block("a\n# Nix\nb\n# End Nix\nc\n", "\n# Nix\nx\n# End Nix\n\n"),
+            Some(2..20)
+        );
+        // An unterminated block is left alone
+        assert_eq!(
+            find_marked_block("# Nix\nb\n", "# Nix\nx\n# End Nix\n"),
+            None
+        );
+        // Without comment markers, there is nothing to bound the block
+        assert_eq!(find_marked_block("nix\nb\n", "nix\nx\n"), None);
+    }
+
     #[tokio::test]
     async fn recognizes_wrong_mode_and_does_not_error() -> eyre::Result<()> {
         let temp_dir = tempfile::tempdir()?;
@@ -523,6 +785,7 @@ mod test {
             Some(expected_mode),
             "Some different content".into(),
             Position::End,
+            false,
         )
         .await?;
 
@@ -552,6 +815,7 @@ mod test {
             Some(initial_mode),
             "Some content".into(),
             Position::End,
+            false,
         )
         .await?;
 
@@ -564,6 +828,94 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn restores_backup_on_revert() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let test_file = temp_dir.path().join("restores_backup_on_revert");
+        let original_content = "PS1='hand edited'\n";
+        write(&test_file, original_content).await?;
+
+        let mut action = CreateOrInsertIntoFile::plan(
+            test_file.clone(),
+            None,
+            None,
+            None,
+            "# Nix\n".into(),
+            Position::Beginning,
+            true,
+        )
+        .await?;
+        action.try_execute().await?;
+
+        let backup = action
+            .inner()
+            .backup_path
+            .clone()
+            .expect("the file was backed up");
+        assert_eq!(read_to_string(&backup).await?, original_content);
+
+        // Mangle the file, the backup should still be restored exactly
+        write(&test_file, "corrupted").await?;
+        action.try_revert().await?;
+
+        assert_eq!(read_to_string(&test_file).await?, original_content);
+        assert!(!backup.exists(), "Backup should have been moved back");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refuses_to_overwrite_backup() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let test_file = temp_dir.path().join("refuses_to_overwrite_backup");
+        write(&test_file, "PS1='hand edited'\n").await?;
+
+        // A backup left by an earlier install in the same second, and the seconds after it in
+        // case the clock ticks over before execute
+        let now = std::time::SystemTime::now();
+        let mut earlier_backups = vec![];
+        for secs in 0..5 {
+            let backup = crate::util::backup_path_for(
+                &test_file,
+                now + std::time::Duration::from_secs(secs),
+            );
+            write(&backup, "earlier").await?;
+            earlier_backups.push(backup);
+        }
+
+        let mut action = CreateOrInsertIntoFile::plan(
+            &test_file,
+            None,
+            None,
+            None,
+            "# Nix\n".into(),
+            Position::Beginning,
+            true,
+        )
+        .await?;
+        match action.try_execute().await {
+            Err(err) => match err.kind() {
+                ActionErrorKind::FileExists(path) => assert!(earlier_backups.contains(path)),
+                _ => {
+                    return Err(eyre!(
+                        "Should have returned an ActionErrorKind::FileExists error"
+                    ))
+                },
+            },
+            _ => {
+                return Err(eyre!(
+                    "Should have returned an ActionErrorKind::FileExists error"
+                ))
+            },
+        }
+        for backup in earlier_backups {
+            assert_eq!(read_to_string(&backup).await?, "earlier");
+        }
+        assert_eq!(read_to_string(&test_file).await?, "PS1='hand edited'\n");
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn errors_on_dir() -> eyre::Result<()> {
         let temp_dir = tempfile::tempdir()?;
@@ -575,6 +927,7 @@ mod test {
             None,
             "Some different content".into(),
             Position::End,
+            false,
         )
         .await
         {
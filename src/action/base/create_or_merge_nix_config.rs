@@ -16,10 +16,21 @@ use crate::{
 pub(crate) const TRUSTED_USERS_CONF_NAME: &str = "trusted-users";
 pub(crate) const EXPERIMENTAL_FEATURES_CONF_NAME: &str = "experimental-features";
 pub(crate) const EXTRA_EXPERIMENTAL_FEATURES_CONF_NAME: &str = "extra-experimental-features";
-/// The `nix.conf` configuration names that are safe to merge.
-// FIXME(@cole-h): make configurable by downstream users?
+pub(crate) const BUILDERS_USE_SUBSTITUTES_CONF_NAME: &str = "builders-use-substitutes";
+pub(crate) const MAX_JOBS_CONF_NAME: &str = "max-jobs";
+pub(crate) const CORES_CONF_NAME: &str = "cores";
+pub(crate) const FLAKE_REGISTRY_CONF_NAME: &str = "flake-registry";
+pub(crate) const STORE_CONF_NAME: &str = "store";
+pub(crate) const EXTRA_SUBSTITUTERS_CONF_NAME: &str = "extra-substituters";
+pub(crate) const EXTRA_TRUSTED_PUBLIC_KEYS_CONF_NAME: &str = "extra-trusted-public-keys";
+/// The `nix.conf` configuration names that are always safe to merge, callers of
+/// [`CreateOrMergeNixConfig::plan`] may add more.
 // NOTE(cole-h): evaluate if any additions here need to be handled in PlaceNixConfiguration::setup_extra_config
-const MERGEABLE_CONF_NAMES: &[&str] = &[EXPERIMENTAL_FEATURES_CONF_NAME];
+const MERGEABLE_CONF_NAMES: &[&str] = &[
+    EXPERIMENTAL_FEATURES_CONF_NAME,
+    EXTRA_SUBSTITUTERS_CONF_NAME,
+    EXTRA_TRUSTED_PUBLIC_KEYS_CONF_NAME,
+];
 const NIX_CONF_MODE: u32 = 0o644;
 const NIX_CONF_COMMENT_CHAR: char = '#';
 
@@ -44,6 +55,10 @@ impl From<CreateOrMergeNixConfigError> for ActionErrorKind {
 }
 
 /// Create or merge an existing `nix.conf` at the specified path.
+///
+/// If `backup` is set, an existing file is first copied to `<path>.nix-installer.bak-<timestamp>`
+/// (in seconds since the epoch), since merging rewrites it. Reverting then restores that backup
+/// instead of deleting the file.
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 #[serde(tag = "action_name", rename = "create_or_merge_nix_config")]
 pub struct CreateOrMergeNixConfig {
@@ -51,6 +66,14 @@ pub struct CreateOrMergeNixConfig {
     pending_nix_config: NixConfig,
     header: String,
     footer: Option<String>,
+    /// Names merged as space separated lists, in addition to [`MERGEABLE_CONF_NAMES`]
+    #[serde(default)]
+    mergeable_keys: Vec<String>,
+    #[serde(default)]
+    backup: bool,
+    /// Where the existing file was backed up to, once it has been
+    #[serde(default)]
+    backup_path: Option<PathBuf>,
 }
 
 impl CreateOrMergeNixConfig {
@@ -60,6 +83,8 @@ impl CreateOrMergeNixConfig {
         pending_nix_config: NixConfig,
         header: String,
         footer: Option<String>,
+        mergeable_keys: Vec<String>,
+        backup: bool,
     ) -> Result<StatefulAction<Self>, ActionError> {
         let path = path.as_ref().to_path_buf();
 
@@ -68,6 +93,9 @@ impl CreateOrMergeNixConfig {
             pending_nix_config,
             header,
             footer,
+            mergeable_keys,
+            backup,
+            backup_path: None,
         };
 
         if this.path.exists() {
@@ -78,6 +106,7 @@ impl CreateOrMergeNixConfig {
                 &this.pending_nix_config,
                 &this.path,
                 is_existing_custom_conf,
+                &this.mergeable_keys,
             )
             .await?;
 
@@ -99,6 +128,7 @@ impl CreateOrMergeNixConfig {
         pending_nix_config: &NixConfig,
         existing_nix_config: &NixConfig,
         path: &Path,
+        mergeable_keys: &[String],
     ) -> Result<(NixConfig, NixConfig), CreateOrMergeNixConfigError> {
         let mut merged_nix_config = NixConfig::new();
         let mut unmergeable_config_names = Vec::new();
@@ -117,12 +147,16 @@ impl CreateOrMergeNixConfig {
                     // merged_nix_config will be empty and this will be marked as completed. We
                     // don't return early here because there may be more config options to
                     // check.
-                } else if MERGEABLE_CONF_NAMES.contains(&pending_conf_name.as_str()) {
+                } else if MERGEABLE_CONF_NAMES.contains(&pending_conf_name.as_str())
+                    || mergeable_keys.contains(pending_conf_name)
+                {
                     let mut merged_conf_value =
                         Vec::with_capacity(pending_conf_value.len() + existing_conf_value.len());
-                    merged_conf_value.extend(pending_conf_value);
-                    merged_conf_value.extend(existing_conf_value);
-                    merged_conf_value.dedup();
+                    for value in pending_conf_value.into_iter().chain(existing_conf_value) {
+                        if !merged_conf_value.contains(&value) {
+                            merged_conf_value.push(value);
+                        }
+                    }
                     let merged_conf_value = merged_conf_value.join(" ");
                     let merged_conf_value = merged_conf_value.trim();
 
@@ -153,6 +187,7 @@ impl CreateOrMergeNixConfig {
         pending_nix_config: &NixConfig,
         existing_config_file: &Path,
         is_existing_custom_conf: bool,
+        mergeable_keys: &[String],
     ) -> Result<(NixConfig, NixConfig), ActionError> {
         let path = existing_config_file.to_path_buf();
         let metadata = path
@@ -177,6 +212,7 @@ impl CreateOrMergeNixConfig {
             pending_nix_config,
             &existing_nix_config,
             &path,
+            mergeable_keys,
         )
         .map_err(Self::error)?;
 
@@ -313,144 +349,38 @@ impl Action for CreateOrMergeNixConfig {
                 Self::error(ActionErrorKind::Open(temp_file_path.clone(), e))
             })?;
 
-        let (mut merged_nix_config, mut existing_nix_config) = if self.path.exists() {
-            let (merged_nix_config, existing_nix_config) =
-                Self::validate_nix_config_against_path(&self.pending_nix_config, &self.path, false)
-                    .await?;
+        let (mut merged_nix_config, existing_nix_config) = if self.path.exists() {
+            let (merged_nix_config, existing_nix_config) = Self::validate_nix_config_against_path(
+                &self.pending_nix_config,
+                &self.path,
+                false,
+                &self.mergeable_keys,
+            )
+            .await?;
             (merged_nix_config, Some(existing_nix_config))
         } else {
             (self.pending_nix_config.clone(), None)
         };
 
-        let mut new_config = String::new();
+        if self.backup && existing_nix_config.is_some() {
+            self.backup_path = Some(
+                crate::util::backup_file(&self.path)
+                    .await
+                    .map_err(Self::error)?,
+            );
+        }
 
-        if let Some(existing_nix_config) = existing_nix_config.as_mut() {
-            let mut discovered_buf = tokio::fs::read_to_string(&self.path)
+        // Only the settings being merged are rewritten, so comments in an existing file survive
+        let mut new_config = if existing_nix_config.is_some() {
+            let existing = tokio::fs::read_to_string(&self.path)
                 .await
                 .map_err(|e| Self::error(ActionErrorKind::Read(self.path.to_path_buf(), e)))?;
-
-            // We append a newline to ensure that, in the case there are comments at the end of the
-            // file and _NO_ trailing newline, we still preserve the entire block of comments.
-            discovered_buf.push('\n');
-
-            let (associated_lines, _, _) = discovered_buf.split('\n').fold(
-                (Vec::new(), Vec::new(), false),
-                |(mut all_assoc, mut current_assoc, mut associating): (
-                    Vec<Vec<String>>,
-                    Vec<String>,
-                    bool,
-                ),
-                 line| {
-                    let line = line.trim();
-
-                    // Don't associate our "Generated by" comment if it appears
-                    if line.starts_with("# Generated by") {
-                        return (all_assoc, current_assoc, associating);
-                    }
-
-                    if line.starts_with(NIX_CONF_COMMENT_CHAR) {
-                        associating = true;
-                    } else if line.is_empty() || !line.starts_with(NIX_CONF_COMMENT_CHAR) {
-                        associating = false;
-                    }
-
-                    current_assoc.push(line.to_string());
-
-                    if !associating {
-                        all_assoc.push(current_assoc);
-                        current_assoc = Vec::new();
-                    }
-
-                    (all_assoc, current_assoc, associating)
-                },
-            );
-
-            for line_group in associated_lines {
-                if line_group.is_empty() || line_group.iter().all(|line| line.is_empty()) {
-                    continue;
-                }
-
-                // This expect should never reasonably panic, because we would need a line group
-                // consisting solely of a comment and nothing else, but unconditionally appending a
-                // newline to the config string before grouping above prevents this from occurring.
-                let line_idx = line_group
-                    .iter()
-                    .position(|line| !line.starts_with(NIX_CONF_COMMENT_CHAR))
-                    .expect("There should always be one line without a comment character");
-
-                let setting_line = &line_group[line_idx];
-                let comments = line_group[..line_idx].join("\n");
-
-                // If we're here, but the line without a comment char is empty, we have
-                // standalone comments to preserve, but no settings with inline comments.
-                if setting_line.is_empty() {
-                    for line in &line_group {
-                        new_config.push_str(line);
-                        new_config.push('\n');
-                    }
-
-                    continue;
-                }
-
-                // Preserve inline comments for settings we've merged
-                let to_remove = if let Some((name, value)) = existing_nix_config
-                    .settings()
-                    .iter()
-                    .find(|(name, _value)| setting_line.starts_with(*name))
-                {
-                    let inline_comment_idx =
-                        if let Some(idx) = setting_line.find(NIX_CONF_COMMENT_CHAR) {
-                            idx
-                        } else {
-                            continue;
-                        };
-
-                    let inline_comment = &setting_line[inline_comment_idx..];
-
-                    new_config.push_str(&comments);
-                    new_config.push('\n');
-                    new_config.push_str(name);
-                    new_config.push_str(" = ");
-
-                    if let Some(merged_value) = merged_nix_config.settings_mut().swap_remove(name) {
-                        new_config.push_str(&merged_value);
-                        new_config.push(' ');
-                    } else {
-                        new_config.push_str(value);
-                    }
-
-                    new_config.push_str(inline_comment);
-                    new_config.push('\n');
-
-                    Some(name.clone())
-                } else {
-                    new_config.push_str(&comments);
-                    new_config.push('\n');
-                    new_config.push_str(setting_line);
-                    new_config.push('\n');
-
-                    None
-                };
-
-                if let Some(to_remove) = to_remove {
-                    existing_nix_config.settings_mut().swap_remove(&to_remove);
-                }
-            }
-
-            // Add the leftover existing nix config
-            for (name, value) in existing_nix_config.settings() {
-                if merged_nix_config.settings().get(name).is_some() {
-                    continue;
-                }
-
-                new_config.push_str(name);
-                new_config.push_str(" = ");
-                new_config.push_str(value);
-                new_config.push('\n');
-            }
-
+            let mut new_config = merge_into_existing_lines(&existing, &mut merged_nix_config);
             new_config.push('\n');
-        }
+            new_config
+        } else {
+            String::new()
+        };
 
         new_config.push_str(&self.header);
         new_config.push('\n');
@@ -499,6 +429,20 @@ impl Action for CreateOrMergeNixConfig {
     }
 
     fn revert_description(&self) -> Vec<ActionDescription> {
+        if let Some(backup_path) = &self.backup_path {
+            return vec![ActionDescription::new(
+                format!(
+                    "Restore file `{}` from its backup `{}`",
+                    self.path.display(),
+                    backup_path.display()
+                ),
+                vec![format!(
+                    "Move `{}` to `{}`",
+                    backup_path.display(),
+                    self.path.display()
+                )],
+            )];
+        }
         vec![ActionDescription::new(
             format!("Delete file `{}`", self.path.display()),
             vec![format!("Delete file `{}`", self.path.display())],
@@ -507,6 +451,20 @@ impl Action for CreateOrMergeNixConfig {
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn revert(&mut self) -> Result<(), ActionError> {
+        if let Some(backup_path) = self.backup_path.as_ref().filter(|path| path.exists()) {
+            tokio::fs::rename(backup_path, &self.path)
+                .await
+                .map_err(|e| {
+                    Self::error(ActionErrorKind::Rename(
+                        backup_path.to_owned(),
+                        self.path.to_owned(),
+                        e,
+                    ))
+                })?;
+            self.backup_path = None;
+            return Ok(());
+        }
+
         crate::util::remove_file(&self.path, OnMissing::Ignore)
             .await
             .map_err(|e| Self::error(ActionErrorKind::Remove(self.path.to_owned(), e)))?;
@@ -515,11 +473,63 @@ impl Action for CreateOrMergeNixConfig {
     }
 }
 
+/// The lines of an existing `nix.conf`, with the settings being merged rewritten in place
+///
+/// Comments, blank lines, and settings we don't manage are kept as they are. Each setting in
+/// `merged_nix_config` which the file already sets is written (and removed from
+/// `merged_nix_config`) where the file last sets it, keeping its inline comment, as Nix uses the
+/// last value. The remaining settings are new, for the caller to add.
+fn merge_into_existing_lines(existing: &str, merged_nix_config: &mut NixConfig) -> String {
+    let setting_name = |line: &str| {
+        let line = line.trim();
+        if line.starts_with(NIX_CONF_COMMENT_CHAR) {
+            return None;
+        }
+        line.split_once('=')
+            .map(|(name, _)| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+    };
+
+    let lines = existing.lines().collect::<Vec<_>>();
+    let mut last_set_at = std::collections::HashMap::new();
+    for (index, line) in lines.iter().enumerate() {
+        if let Some(name) = setting_name(line) {
+            last_set_at.insert(name, index);
+        }
+    }
+
+    let mut new_config = String::new();
+    for (index, line) in lines.iter().enumerate() {
+        // Our header is written again after the existing lines
+        if line.trim().starts_with("# Generated by") {
+            continue;
+        }
+        let rewritten = setting_name(line)
+            .filter(|name| last_set_at.get(name) == Some(&index))
+            .and_then(|name| {
+                let value = merged_nix_config.settings_mut().shift_remove(&name)?;
+                let inline_comment = line
+                    .find(NIX_CONF_COMMENT_CHAR)
+                    .map(|idx| format!(" {}", &line[idx..]))
+                    .unwrap_or_default();
+                Some(format!("{name} = {value}{inline_comment}"))
+            });
+        new_config.push_str(rewritten.as_deref().unwrap_or(line.trim_end()));
+        new_config.push('\n');
+    }
+
+    // A single blank line separates the existing lines from ours
+    while new_config.ends_with("\n\n") {
+        new_config.pop();
+    }
+    new_config
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use color_eyre::eyre::eyre;
-    use tokio::fs::write;
+    use tokio::fs::{read_to_string, write};
 
     #[tokio::test]
     async fn creates_and_deletes_file() -> eyre::Result<()> {
@@ -534,6 +544,8 @@ mod test {
             nix_config,
             "# Generated by".to_string(),
             Some("# opa".into()),
+            vec![],
+            false,
         )
         .await?;
 
@@ -568,6 +580,8 @@ mod test {
             nix_config,
             "# Generated by".to_string(),
             None,
+            vec![],
+            false,
         )
         .await?;
 
@@ -582,6 +596,108 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn backs_up_existing_file_and_restores_it() -> eyre::Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let test_file = temp_dir
+            .path()
+            .join("backs_up_existing_file_and_restores_it");
+
+        let original = "# My comment\nexperimental-features = flakes\n";
+        write(test_file.as_path(), original).await?;
+
+        let mut nix_config = NixConfig::new();
+        nix_config
+            .settings_mut()
+            .insert("warn-dirty".into(), "false".into());
+        let mut action = CreateOrMergeNixConfig::plan(
+            &test_file,
+            nix_config,
+            "# Generated by".to_string(),
+            None,
+            vec![],
+            true,
+        )
+        .await?;
+
+        action.try_execute().await?;
+
+        let backup = action
+            .inner()
+            .backup_path
+            .clone()
+            .ok_or_else(|| eyre!("The existing file should have been backed up"))?;
+        assert!(backup
+            .to_string_lossy()
+            .starts_with(&format!("{}.nix-installer.bak-", test_file.display())));
+        assert_eq!(read_to_string(&backup).await?, original);
+        assert!(read_to_string(&test_file).await?.contains("warn-dirty"));
+
+        action.try_revert().await?;
+
+        assert_eq!(read_to_string(&test_file).await?, original);
+        assert!(!backup.exists(), "Backup should have been moved back");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refuses_to_overwrite_backup() -> eyre::Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let test_file = temp_dir.path().join("refuses_to_overwrite_backup");
+        let original = "experimental-features = flakes\n";
+        write(test_file.as_path(), original).await?;
+
+        // A backup left by an earlier install in the same second, and the seconds after it in
+        // case the clock ticks over before execute
+        let now = std::time::SystemTime::now();
+        let mut earlier_backups = vec![];
+        for secs in 0..5 {
+            let backup = crate::util::backup_path_for(
+                &test_file,
+                now + std::time::Duration::from_secs(secs),
+            );
+            write(&backup, "earlier").await?;
+            earlier_backups.push(backup);
+        }
+
+        let mut nix_config = NixConfig::new();
+        nix_config
+            .settings_mut()
+            .insert("warn-dirty".into(), "false".into());
+        let mut action = CreateOrMergeNixConfig::plan(
+            &test_file,
+            nix_config,
+            "# Generated by".to_string(),
+            None,
+            vec![],
+            true,
+        )
+        .await?;
+
+        match action.try_execute().await {
+            Err(err) => match err.kind() {
+                ActionErrorKind::FileExists(path) => assert!(earlier_backups.contains(path)),
+                _ => {
+                    return Err(eyre!(
+                        "Should have returned an ActionErrorKind::FileExists error"
+                    ))
+                },
+            },
+            _ => {
+                return Err(eyre!(
+                    "Should have returned an ActionErrorKind::FileExists error"
+                ))
+            },
+        }
+        for backup in earlier_backups {
+            assert_eq!(read_to_string(&backup).await?, "earlier");
+        }
+        assert_eq!(read_to_string(&test_file).await?, original);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn recognizes_existing_exact_files_and_reverts_them() -> eyre::Result<()> {
         let temp_dir = tempfile::TempDir::new()?;
@@ -602,6 +718,8 @@ mod test {
             nix_config,
             "# Generated by".to_string(),
             None,
+            vec![],
+            false,
         )
         .await?;
 
@@ -640,6 +758,8 @@ mod test {
             nix_config,
             "# Generated by".to_string(),
             None,
+            vec![],
+            false,
         )
         .await?;
 
@@ -686,7 +806,16 @@ mod test {
         nix_config
             .settings_mut()
             .insert("warn-dirty".into(), "false".into());
-        match CreateOrMergeNixConfig::plan(&test_file, nix_config, "".to_string(), None).await {
+        match CreateOrMergeNixConfig::plan(
+            &test_file,
+            nix_config,
+            "".to_string(),
+            None,
+            vec![],
+            false,
+        )
+        .await
+        {
             Err(err) => {
                 if let ActionErrorKind::Custom(e) = err.kind() {
                     match e.downcast_ref::<CreateOrMergeNixConfigError>() {
@@ -713,6 +842,84 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn merges_caller_provided_mergeable_keys() -> eyre::Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let test_file = temp_dir
+            .path()
+            .join("merges_caller_provided_mergeable_keys");
+
+        write(
+            test_file.as_path(),
+            "substituters = https://cache.nixos.org\ntrusted-public-keys = a:1\nwarn-dirty = true\n",
+        )
+        .await?;
+        tokio::fs::set_permissions(&test_file, PermissionsExt::from_mode(NIX_CONF_MODE)).await?;
+
+        let mut nix_config = NixConfig::new();
+        nix_config.settings_mut().insert(
+            "substituters".into(),
+            "https://cache.example.com https://cache.nixos.org".into(),
+        );
+        nix_config
+            .settings_mut()
+            .insert("trusted-public-keys".into(), "b:2".into());
+        nix_config
+            .settings_mut()
+            .insert("warn-dirty".into(), "false".into());
+
+        let err = CreateOrMergeNixConfig::plan(
+            &test_file,
+            nix_config.clone(),
+            "".to_string(),
+            None,
+            vec!["substituters".into()],
+            false,
+        )
+        .await
+        .expect_err("`trusted-public-keys` and `warn-dirty` are not mergeable");
+        let ActionErrorKind::Custom(e) = err.kind() else {
+            return Err(eyre!("Expected a custom error"));
+        };
+        match e.downcast_ref::<CreateOrMergeNixConfigError>() {
+            Some(CreateOrMergeNixConfigError::UnmergeableConfig(names, _)) => {
+                assert_eq!(names, &["trusted-public-keys", "warn-dirty"])
+            },
+            _ => {
+                return Err(eyre!(
+                    "Should have returned CreateOrMergeNixConfigError::UnmergeableConfig"
+                ))
+            },
+        }
+
+        nix_config.settings_mut().shift_remove("warn-dirty");
+        let mut action = CreateOrMergeNixConfig::plan(
+            &test_file,
+            nix_config,
+            "".to_string(),
+            None,
+            vec!["substituters".into(), "trusted-public-keys".into()],
+            false,
+        )
+        .await?;
+        action.try_execute().await?;
+
+        let merged = NixConfig::parse_file(&test_file)?;
+        assert_eq!(
+            merged.settings().get("substituters").map(String::as_str),
+            Some("https://cache.example.com https://cache.nixos.org")
+        );
+        assert_eq!(
+            merged
+                .settings()
+                .get("trusted-public-keys")
+                .map(String::as_str),
+            Some("b:2 a:1")
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn preserves_comments() -> eyre::Result<()> {
         let temp_dir = tempfile::TempDir::new()?;
@@ -733,6 +940,8 @@ mod test {
             nix_config,
             "# Generated by".to_string(),
             None,
+            vec![],
+            false,
         )
         .await?;
 
@@ -755,6 +964,63 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn preserves_comments_and_blank_lines_around_merged_settings() -> eyre::Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let test_file = temp_dir
+            .path()
+            .join("preserves_comments_and_blank_lines_around_merged_settings");
+
+        let original = "\
+            # Managed by hand, see the wiki\n\
+            \n\
+            # Flakes are needed by our tooling\n\
+            experimental-features = flakes\n\
+            \n\
+            # Keep builds quiet\n\
+            warn-dirty = false\n";
+        write(test_file.as_path(), original).await?;
+        tokio::fs::set_permissions(&test_file, PermissionsExt::from_mode(NIX_CONF_MODE)).await?;
+
+        let mut nix_config = NixConfig::new();
+        nix_config
+            .settings_mut()
+            .insert("experimental-features".into(), "nix-command".into());
+        nix_config
+            .settings_mut()
+            .insert("max-jobs".into(), "auto".into());
+        let mut action = CreateOrMergeNixConfig::plan(
+            &test_file,
+            nix_config,
+            "# Generated by".to_string(),
+            None,
+            vec![],
+            false,
+        )
+        .await?;
+
+        action.try_execute().await?;
+
+        let s = std::fs::read_to_string(&test_file)?;
+        assert_eq!(
+            s,
+            "\
+            # Managed by hand, see the wiki\n\
+            \n\
+            # Flakes are needed by our tooling\n\
+            experimental-features = nix-command flakes\n\
+            \n\
+            # Keep builds quiet\n\
+            warn-dirty = false\n\
+            \n\
+            # Generated by\n\
+            max-jobs = auto\n"
+        );
+        assert!(NixConfig::parse_file(&test_file).is_ok());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn preserves_comments_edge_case() -> eyre::Result<()> {
         let temp_dir = tempfile::TempDir::new()?;
@@ -771,6 +1037,8 @@ mod test {
             nix_config,
             "# Generated by".to_string(),
             None,
+            vec![],
+            false,
         )
         .await?;
 
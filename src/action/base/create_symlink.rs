@@ -0,0 +1,157 @@
+use std::path::{Path, PathBuf};
+
+use tracing::{span, Span};
+
+use crate::action::{
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+};
+use crate::util::OnMissing;
+
+/** Create a symlink at `path` pointing to `target`, removing it on revert
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+#[serde(tag = "action_name", rename = "create_symlink")]
+pub struct CreateSymlink {
+    path: PathBuf,
+    target: PathBuf,
+}
+
+impl CreateSymlink {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(
+        path: impl AsRef<Path>,
+        target: impl AsRef<Path>,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        let this = Self {
+            path: path.as_ref().to_path_buf(),
+            target: target.as_ref().to_path_buf(),
+        };
+
+        if this.path.is_symlink() {
+            let existing_target = tokio::fs::read_link(&this.path)
+                .await
+                .map_err(|e| ActionErrorKind::ReadSymlink(this.path.clone(), e))
+                .map_err(Self::error)?;
+            if existing_target == this.target {
+                tracing::debug!(
+                    "Symlink `{}` to `{}` already complete",
+                    this.path.display(),
+                    this.target.display()
+                );
+                return Ok(StatefulAction::completed(this));
+            }
+            return Err(Self::error(CreateSymlinkError::DifferentTarget {
+                path: this.path,
+                expected: this.target,
+                found: existing_target,
+            }));
+        } else if this.path.exists() {
+            return Err(Self::error(CreateSymlinkError::Exists(this.path)));
+        }
+
+        Ok(StatefulAction::uncompleted(this))
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "create_symlink")]
+impl Action for CreateSymlink {
+    fn action_tag() -> ActionTag {
+        ActionTag("create_symlink")
+    }
+    fn tracing_synopsis(&self) -> String {
+        format!(
+            "Create symlink `{}` to `{}`",
+            self.path.display(),
+            self.target.display()
+        )
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "create_symlink",
+            path = tracing::field::display(self.path.display()),
+            target = tracing::field::display(self.target.display()),
+        )
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(self.tracing_synopsis(), vec![])]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        tokio::fs::symlink(&self.target, &self.path)
+            .await
+            .map_err(|e| ActionErrorKind::Symlink(self.target.clone(), self.path.clone(), e))
+            .map_err(Self::error)?;
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            format!("Remove symlink `{}`", self.path.display()),
+            vec![],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        // Only remove what we created, never what it points to
+        if !self.path.is_symlink() {
+            return Ok(());
+        }
+        crate::util::remove_file(&self.path, OnMissing::Ignore)
+            .await
+            .map_err(|e| ActionErrorKind::Remove(self.path.clone(), e))
+            .map_err(Self::error)?;
+        Ok(())
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum CreateSymlinkError {
+    #[error("`{0}` exists and is not a symlink")]
+    Exists(PathBuf),
+    #[error("`{path}` is a symlink to `{found}`, expected `{expected}`")]
+    DifferentTarget {
+        path: PathBuf,
+        expected: PathBuf,
+        found: PathBuf,
+    },
+}
+
+impl From<CreateSymlinkError> for ActionErrorKind {
+    fn from(val: CreateSymlinkError) -> Self {
+        ActionErrorKind::Custom(Box::new(val))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn creates_and_removes_symlink() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let target = temp_dir.path().join("target");
+        tokio::fs::create_dir(&target).await?;
+        let path = temp_dir.path().join("link");
+
+        let mut action = CreateSymlink::plan(&path, &target).await?;
+        action.try_execute().await?;
+        assert_eq!(tokio::fs::read_link(&path).await?, target);
+
+        // Planning again finds the symlink already in place
+        let replanned = CreateSymlink::plan(&path, &target).await?;
+        assert_eq!(replanned.state, crate::action::ActionState::Completed);
+
+        action.try_revert().await?;
+        assert!(!path.exists(), "Symlink should have been removed");
+        assert!(target.exists(), "Target should have been left alone");
+
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
 
 use nix::unistd::User;
 use target_lexicon::OperatingSystem;
@@ -7,6 +8,7 @@ use tracing::{span, Span};
 
 use crate::action::{ActionError, ActionErrorKind, ActionTag};
 use crate::execute_command;
+use crate::settings::{default_nix_build_user_home, default_nix_build_user_shell};
 
 use crate::action::{Action, ActionDescription, StatefulAction};
 
@@ -23,10 +25,15 @@ pub struct CreateUser {
     pub(crate) uid: u32,
     pub(crate) groupname: String,
     pub(crate) gid: u32,
-    comment: String,
+    pub(crate) comment: String,
+    #[serde(default = "default_nix_build_user_home")]
+    pub(crate) home: PathBuf,
+    #[serde(default = "default_nix_build_user_shell")]
+    pub(crate) shell: PathBuf,
 }
 
 impl CreateUser {
+    #[allow(clippy::too_many_arguments)]
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn plan(
         name: String,
@@ -34,6 +41,8 @@ impl CreateUser {
         groupname: String,
         gid: u32,
         comment: String,
+        home: PathBuf,
+        shell: PathBuf,
         check_completed: bool,
     ) -> Result<StatefulAction<Self>, ActionError> {
         let this = Self {
@@ -42,6 +51,8 @@ impl CreateUser {
             groupname,
             gid,
             comment,
+            home,
+            shell,
         };
 
         match OperatingSystem::host() {
@@ -56,6 +67,12 @@ impl CreateUser {
             },
         }
 
+        if !this.shell.exists() {
+            return Err(Self::error(ActionErrorKind::MissingUserShell(
+                this.shell.clone(),
+            )));
+        }
+
         if check_completed {
             // Ensure user does not exist
             if let Some(user) = User::from_name(name.as_str())
@@ -128,11 +145,13 @@ impl Action for CreateUser {
             groupname,
             gid,
             comment,
+            home,
+            shell,
         } = self;
 
         match OperatingSystem::host() {
             OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => {
-                create_user_macos(name, *uid, *gid)
+                create_user_macos(name, *uid, *gid, home, shell)
                     .await
                     .map_err(Self::error)?;
             },
@@ -143,7 +162,7 @@ impl Action for CreateUser {
                             .process_group(0)
                             .args([
                                 "--home-dir",
-                                "/var/empty",
+                                &home.to_string_lossy(),
                                 "--comment",
                                 comment,
                                 "--gid",
@@ -153,7 +172,7 @@ impl Action for CreateUser {
                                 "--no-user-group",
                                 "--system",
                                 "--shell",
-                                "/sbin/nologin",
+                                &shell.to_string_lossy(),
                                 "--uid",
                                 &uid.to_string(),
                                 "--password",
@@ -170,7 +189,7 @@ impl Action for CreateUser {
                             .process_group(0)
                             .args([
                                 "--home",
-                                "/var/empty",
+                                &home.to_string_lossy(),
                                 "-H", // Don't create a home.
                                 "--gecos",
                                 comment,
@@ -178,7 +197,7 @@ impl Action for CreateUser {
                                 groupname,
                                 "--system",
                                 "--shell",
-                                "/sbin/nologin",
+                                &shell.to_string_lossy(),
                                 "--uid",
                                 &uid.to_string(),
                                 "--disabled-password",
@@ -292,7 +311,13 @@ async fn execute_dscl_retry_on_specific_errors(dscl_args: &[&str]) -> Result<(),
 }
 
 #[tracing::instrument(level = "debug", skip_all)]
-async fn create_user_macos(name: &str, uid: u32, gid: u32) -> Result<(), ActionErrorKind> {
+async fn create_user_macos(
+    name: &str,
+    uid: u32,
+    gid: u32,
+    home: &Path,
+    shell: &Path,
+) -> Result<(), ActionErrorKind> {
     execute_dscl_retry_on_specific_errors(&[".", "-create", &format!("/Users/{name}")]).await?;
 
     execute_dscl_retry_on_specific_errors(&[
@@ -316,7 +341,7 @@ async fn create_user_macos(name: &str, uid: u32, gid: u32) -> Result<(), ActionE
         "-create",
         &format!("/Users/{name}"),
         "NFSHomeDirectory",
-        "/var/empty",
+        &home.to_string_lossy(),
     ])
     .await?;
     execute_dscl_retry_on_specific_errors(&[
@@ -324,7 +349,7 @@ async fn create_user_macos(name: &str, uid: u32, gid: u32) -> Result<(), ActionE
         "-create",
         &format!("/Users/{name}"),
         "UserShell",
-        "/sbin/nologin",
+        &shell.to_string_lossy(),
     ])
     .await?;
     execute_dscl_retry_on_specific_errors(&[
@@ -344,8 +369,8 @@ async fn create_user_macos(name: &str, uid: u32, gid: u32) -> Result<(), ActionE
     ])
     .await
     .or_else(|e| {
-        if let ActionErrorKind::CommandOutput { ref output, .. } = e {
-            if output.status.signal() == Some(9) {
+        if let ActionErrorKind::CommandOutput { ref status, .. } = e {
+            if status.signal() == Some(9) {
                 if !WARNED_USER_HIDDEN.swap(true, std::sync::atomic::Ordering::SeqCst) {
                     tracing::warn!("Failed to automatically mark nixbld users as hidden. See: https://dtr.mn/mark-user-hidden");
                 }
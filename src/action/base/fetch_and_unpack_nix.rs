@@ -1,35 +1,92 @@
-use std::path::PathBuf;
+use std::{
+    io::IsTerminal as _,
+    path::{Path, PathBuf},
+};
 
-use bytes::{Buf, Bytes};
 use reqwest::Url;
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWriteExt as _};
 use tracing::{span, Span};
 
 use crate::{
     action::{Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction},
     parse_ssl_cert,
+    progress::InstallProgress,
     settings::UrlOrPath,
     util::OnMissing,
 };
 
+/// How long a download may take, and how one which fails transiently is retried
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct DownloadPolicy {
+    /// The total number of attempts made, including the first
+    pub attempts: u32,
+    /// The delay before the first retry, doubled for each retry after it
+    pub base_delay_ms: u64,
+    /// How long each attempt may take, `0` for no limit
+    pub timeout_secs: u64,
+}
+
+impl Default for DownloadPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            base_delay_ms: 500,
+            timeout_secs: 0,
+        }
+    }
+}
+
 /**
-Fetch a URL to the given path
+Fetch a URL (or read stdin) and unpack the Nix tarball to the given path
+
+The package is unpacked as it streams in. With a `sha256`, it is unpacked beside the path and
+only moved there once the whole package is verified.
 */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 #[serde(tag = "action_name", rename = "fetch_and_unpack_nix")]
 pub struct FetchAndUnpackNix {
     url_or_path: Option<UrlOrPath>,
+    #[serde(default)]
+    from_stdin: bool,
     dest: PathBuf,
     proxy: Option<Url>,
     ssl_cert_file: Option<PathBuf>,
+    #[serde(default)]
+    sha256: Option<String>,
+    #[serde(default)]
+    download: DownloadPolicy,
+    /// Overrides [`default_user_agent`]
+    #[serde(default)]
+    user_agent: Option<String>,
+    /// Sent as the `From` header, so whoever runs a mirror can reach whoever is installing
+    #[serde(default)]
+    from: Option<String>,
+}
+
+/// The `User-Agent` downloads are made with, like `nix-installer/0.1.0 (linux-x86_64)`
+pub fn default_user_agent() -> String {
+    format!(
+        "nix-installer/{} ({}-{})",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )
 }
 
 impl FetchAndUnpackNix {
+    #[allow(clippy::too_many_arguments)]
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn plan(
         url_or_path: Option<UrlOrPath>,
+        from_stdin: bool,
         dest: PathBuf,
         proxy: Option<Url>,
         ssl_cert_file: Option<PathBuf>,
+        sha256: Option<String>,
+        download: DownloadPolicy,
+        user_agent: Option<String>,
+        from: Option<String>,
     ) -> Result<StatefulAction<Self>, ActionError> {
         // TODO(@hoverbear): Check URL exists?
         // TODO(@hoverbear): Check tempdir exists
@@ -41,6 +98,13 @@ impl FetchAndUnpackNix {
             }
         }
 
+        // A local package can be checked now, rather than failing partway through the install
+        if let Some(path) = url_or_path.as_ref().and_then(local_path) {
+            tokio::fs::File::open(&path)
+                .await
+                .map_err(|e| Self::error(FetchUrlError::LocalPackage(path, e)))?;
+        }
+
         if let Some(proxy) = &proxy {
             match proxy.scheme() {
                 "https" | "http" | "socks5" => (),
@@ -52,14 +116,105 @@ impl FetchAndUnpackNix {
             parse_ssl_cert(ssl_cert_file).await.map_err(Self::error)?;
         }
 
+        if from_stdin && url_or_path.is_some() {
+            return Err(Self::error(FetchUrlError::StdinConflictsWithUrlOrPath));
+        }
+
+        if let Some(from) = &from {
+            if reqwest::header::HeaderValue::from_str(from).is_err() {
+                return Err(Self::error(FetchUrlError::InvalidFromHeader(from.clone())));
+            }
+        }
+
+        let sha256 = sha256.map(|v| v.trim().to_ascii_lowercase());
+        if let Some(sha256) = &sha256 {
+            if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(Self::error(FetchUrlError::InvalidSha256(sha256.clone())));
+            }
+        }
+
         Ok(Self {
             url_or_path,
+            from_stdin,
             dest,
             proxy,
             ssl_cert_file,
+            sha256,
+            download,
+            user_agent,
+            from,
         }
         .into())
     }
+
+    /// A client using the configured proxy, otherwise `HTTPS_PROXY` and friends from the environment
+    ///
+    /// `NO_PROXY` is respected either way.
+    async fn client(&self) -> Result<reqwest::Client, ActionErrorKind> {
+        let mut buildable_client = reqwest::Client::builder()
+            .user_agent(self.user_agent.clone().unwrap_or_else(default_user_agent));
+        if let Some(from) = &self.from {
+            let mut headers = reqwest::header::HeaderMap::new();
+            headers.insert(
+                reqwest::header::FROM,
+                reqwest::header::HeaderValue::from_str(from)
+                    .map_err(|_| FetchUrlError::InvalidFromHeader(from.clone()))?,
+            );
+            buildable_client = buildable_client.default_headers(headers);
+        }
+        if let Some(proxy) = &self.proxy {
+            buildable_client = buildable_client.proxy(
+                reqwest::Proxy::all(proxy.clone())
+                    .map_err(ActionErrorKind::Reqwest)?
+                    .no_proxy(reqwest::NoProxy::from_env()),
+            )
+        }
+        if let Some(ssl_cert_file) = &self.ssl_cert_file {
+            let ssl_cert = parse_ssl_cert(ssl_cert_file).await?;
+            buildable_client = buildable_client.add_root_certificate(ssl_cert);
+        }
+        if self.download.timeout_secs > 0 {
+            buildable_client = buildable_client
+                .timeout(std::time::Duration::from_secs(self.download.timeout_secs));
+        }
+        buildable_client.build().map_err(ActionErrorKind::Reqwest)
+    }
+
+    async fn unpack_local(&self, path: &Path, dest: &Path) -> Result<String, ActionErrorKind> {
+        let file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| FetchUrlError::LocalPackage(path.to_path_buf(), e))?;
+        stream_unpack(Package::Reader(path.to_path_buf(), Box::new(file)), dest).await
+    }
+
+    /// Download and unpack `url`, retrying with exponential backoff on connection, timeout, and
+    /// server errors, and starting over if the download breaks off partway through
+    ///
+    /// Both kinds of failure count towards the same `attempts`.
+    async fn fetch_and_unpack(&self, url: &Url, dest: &Path) -> Result<String, ActionErrorKind> {
+        let client = self.client().await?;
+        let mut attempt = 1;
+        loop {
+            let result = match fetch(&client, url).await {
+                Ok(response) => stream_unpack(Package::Response(response), dest).await,
+                Err(err) => Err(ActionErrorKind::Reqwest(err)),
+            };
+            match result {
+                Err(ActionErrorKind::Reqwest(err))
+                    if attempt < self.download.attempts && is_transient(&err) =>
+                {
+                    let delay = retry_delay(self.download, attempt);
+                    tracing::warn!(
+                        "Downloading `{url}` failed (attempt {attempt} of {}), retrying in {delay}ms: {err}",
+                        self.download.attempts
+                    );
+                    tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+                    attempt += 1;
+                },
+                result => return result,
+            }
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -69,7 +224,9 @@ impl Action for FetchAndUnpackNix {
         ActionTag("fetch_and_unpack_nix")
     }
     fn tracing_synopsis(&self) -> String {
-        if let Some(ref url_or_path) = self.url_or_path {
+        if self.from_stdin {
+            format!("Read Nix from stdin to `{}`", self.dest.display())
+        } else if let Some(ref url_or_path) = self.url_or_path {
             format!("Fetch `{}` to `{}`", url_or_path, self.dest.display())
         } else {
             format!(
@@ -84,6 +241,7 @@ impl Action for FetchAndUnpackNix {
             tracing::Level::DEBUG,
             "fetch_and_unpack_nix",
             url_or_path = self.url_or_path.as_ref().map(tracing::field::display),
+            from_stdin = self.from_stdin,
             proxy = tracing::field::Empty,
             ssl_cert_file = tracing::field::Empty,
             dest = tracing::field::display(self.dest.display()),
@@ -106,84 +264,65 @@ impl Action for FetchAndUnpackNix {
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn execute(&mut self) -> Result<(), ActionError> {
-        let bytes = match &self.url_or_path {
-            &None => Bytes::from(crate::settings::NIX_TARBALL),
-            Some(UrlOrPath::Url(url)) => {
-                let bytes = match url.scheme() {
-                    "https" | "http" => {
-                        let mut buildable_client = reqwest::Client::builder();
-                        if let Some(proxy) = &self.proxy {
-                            buildable_client = buildable_client.proxy(
-                                reqwest::Proxy::all(proxy.clone())
-                                    .map_err(ActionErrorKind::Reqwest)
-                                    .map_err(Self::error)?,
-                            )
-                        }
-                        if let Some(ssl_cert_file) = &self.ssl_cert_file {
-                            let ssl_cert =
-                                parse_ssl_cert(ssl_cert_file).await.map_err(Self::error)?;
-                            buildable_client = buildable_client.add_root_certificate(ssl_cert);
-                        }
-                        let client = buildable_client
-                            .build()
-                            .map_err(ActionErrorKind::Reqwest)
-                            .map_err(Self::error)?;
-                        let req = client
-                            .get(url.clone())
-                            .build()
-                            .map_err(ActionErrorKind::Reqwest)
-                            .map_err(Self::error)?;
-                        let res = client
-                            .execute(req)
-                            .await
-                            .map_err(ActionErrorKind::Reqwest)
-                            .map_err(Self::error)?;
-                        res.bytes()
-                            .await
-                            .map_err(ActionErrorKind::Reqwest)
-                            .map_err(Self::error)?
-                    },
-                    "file" => {
-                        let buf = tokio::fs::read(url.path())
-                            .await
-                            .map_err(|e| ActionErrorKind::Read(PathBuf::from(url.path()), e))
-                            .map_err(Self::error)?;
-                        Bytes::from(buf)
-                    },
-                    _ => return Err(Self::error(ActionErrorKind::UnknownUrlScheme)),
-                };
-                bytes
-            },
-            Some(UrlOrPath::Path(path)) => {
-                let buf = tokio::fs::read(path)
+        // An unverified package is unpacked aside, and only moved into place once its checksum
+        // matches, so later actions never see it
+        let dest = match self.sha256 {
+            Some(_) => staging_path(&self.dest),
+            None => self.dest.clone(),
+        };
+
+        let sha256 = if self.from_stdin {
+            if std::io::stdin().is_terminal() {
+                return Err(Self::error(FetchUrlError::StdinIsTerminal));
+            }
+            stream_unpack(
+                Package::Reader(PathBuf::from("/dev/stdin"), Box::new(tokio::io::stdin())),
+                &dest,
+            )
+            .await
+        } else {
+            match &self.url_or_path {
+                None => {
+                    let tarball = std::io::Cursor::new(crate::settings::NIX_TARBALL);
+                    stream_unpack(
+                        Package::Reader(
+                            PathBuf::from(crate::settings::NIX_TARBALL_PATH),
+                            Box::new(tarball),
+                        ),
+                        &dest,
+                    )
                     .await
-                    .map_err(|e| ActionErrorKind::Read(PathBuf::from(path), e))
-                    .map_err(Self::error)?;
-                Bytes::from(buf)
-            },
+                },
+                Some(UrlOrPath::Url(url)) => match url.scheme() {
+                    "https" | "http" => self.fetch_and_unpack(url, &dest).await,
+                    "file" => self.unpack_local(&PathBuf::from(url.path()), &dest).await,
+                    _ => return Err(Self::error(ActionErrorKind::UnknownUrlScheme)),
+                },
+                Some(UrlOrPath::Path(path)) => self.unpack_local(path, &dest).await,
+            }
         };
 
-        // TODO(@Hoverbear): Pick directory
-        tracing::trace!("Unpacking tar.xz");
+        let Some(expected) = &self.sha256 else {
+            sha256.map_err(Self::error)?;
+            return Ok(());
+        };
+        if let Err(err) =
+            sha256.and_then(|sha256| check_sha256(&sha256, expected).map_err(Into::into))
+        {
+            crate::util::remove_dir_all(&dest, OnMissing::Ignore)
+                .await
+                .map_err(|e| Self::error(ActionErrorKind::Remove(dest.clone(), e)))?;
+            return Err(Self::error(err));
+        }
 
-        // NOTE(cole-h): If the destination exists (because maybe a previous install failed), we
-        // want to remove it so that tar doesn't complain with:
-        //     trying to unpack outside of destination path: /nix/temp-install-dir
         if self.dest.exists() {
             crate::util::remove_dir_all(&self.dest, OnMissing::Ignore)
                 .await
                 .map_err(|e| Self::error(ActionErrorKind::Remove(self.dest.clone(), e)))?;
         }
-
-        let decoder = xz2::read::XzDecoder::new(bytes.reader());
-        let mut archive = tar::Archive::new(decoder);
-        archive.set_preserve_permissions(true);
-        archive.set_preserve_mtime(true);
-        archive.set_unpack_xattrs(true);
-        archive
-            .unpack(&self.dest)
-            .map_err(FetchUrlError::Unarchive)
-            .map_err(Self::error)?;
+        tokio::fs::rename(&dest, &self.dest)
+            .await
+            .map_err(|e| Self::error(ActionErrorKind::Rename(dest, self.dest.clone(), e)))?;
 
         Ok(())
     }
@@ -198,13 +337,220 @@ impl Action for FetchAndUnpackNix {
     }
 }
 
+/// Where a package is unpacked until its checksum is verified, next to `dest` so it can be renamed
+fn staging_path(dest: &Path) -> PathBuf {
+    let mut staging = dest.as_os_str().to_owned();
+    staging.push(".unverified");
+    PathBuf::from(staging)
+}
+
+/// The file a package is read from, if it is not fetched over the network
+fn local_path(url_or_path: &UrlOrPath) -> Option<PathBuf> {
+    match url_or_path {
+        UrlOrPath::Url(url) if url.scheme() == "file" => Some(PathBuf::from(url.path())),
+        UrlOrPath::Url(_) => None,
+        UrlOrPath::Path(path) => Some(path.clone()),
+    }
+}
+
+/// Fetch `url`, failing on an error status
+///
+/// Only the response headers are awaited, the body is left to be streamed.
+async fn fetch(client: &reqwest::Client, url: &Url) -> Result<reqwest::Response, reqwest::Error> {
+    client.get(url.clone()).send().await?.error_for_status()
+}
+
+/// The delay before retrying after failed `attempt`
+fn retry_delay(policy: DownloadPolicy, attempt: u32) -> u64 {
+    policy
+        .base_delay_ms
+        .saturating_mul(1 << (attempt - 1).min(16))
+}
+
+/// Server errors, and failures to connect or to receive the whole response, are worth retrying
+///
+/// Other errors, like a malformed request or a redirect loop, would fail the same way again.
+fn is_transient(err: &reqwest::Error) -> bool {
+    match err.status() {
+        Some(status) => status.is_server_error(),
+        // A body which breaks off while streaming is reported as a decode error
+        None => err.is_connect() || err.is_timeout() || err.is_body() || err.is_decode(),
+    }
+}
+
+/// Check an actual lowercase hex SHA-256 against the expected one
+fn check_sha256(actual: &str, expected: &str) -> Result<(), FetchUrlError> {
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(FetchUrlError::ChecksumMismatch {
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        })
+    }
+}
+
+/// How much of a package may be read ahead of the unpacker
+const STREAM_BUFFER_SIZE: usize = 256 * 1024;
+
+/// How much more of a download there must be before its progress is reported again
+const PROGRESS_INTERVAL: u64 = 1024 * 1024;
+
+/// A compressed Nix package, as it is read
+enum Package {
+    /// Read from the named file
+    Reader(PathBuf, Box<dyn AsyncRead + Unpin + Send>),
+    Response(reqwest::Response),
+}
+
+impl Package {
+    /// Whether this is a download, and how large it is, if the server said
+    fn download_size(&self) -> Option<Option<u64>> {
+        match self {
+            Package::Reader(..) => None,
+            Package::Response(response) => Some(response.content_length()),
+        }
+    }
+
+    async fn next_chunk(&mut self, buf: &mut Vec<u8>) -> Result<bool, ActionErrorKind> {
+        match self {
+            Package::Reader(path, reader) => {
+                buf.resize(STREAM_BUFFER_SIZE / 4, 0);
+                let read = reader
+                    .read(buf)
+                    .await
+                    .map_err(|e| ActionErrorKind::Read(path.clone(), e))?;
+                buf.truncate(read);
+                Ok(read != 0)
+            },
+            Package::Response(response) => {
+                match response.chunk().await.map_err(ActionErrorKind::Reqwest)? {
+                    Some(chunk) => {
+                        buf.clear();
+                        buf.extend_from_slice(&chunk);
+                        Ok(true)
+                    },
+                    None => Ok(false),
+                }
+            },
+        }
+    }
+}
+
+/// Unpack `package` into `dest` while it is read, returning its lowercase hex SHA-256
+///
+/// At most [`STREAM_BUFFER_SIZE`] of the compressed package is held in memory at once. The whole
+/// package is hashed, even past where the unpacker stops reading.
+async fn stream_unpack(mut package: Package, dest: &Path) -> Result<String, ActionErrorKind> {
+    let (mut writer, reader) = tokio::io::duplex(STREAM_BUFFER_SIZE);
+    let read = async move {
+        let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+        let mut buf = Vec::new();
+        let download_size = package.download_size();
+        let (mut downloaded, mut reported) = (0, 0);
+        let mut unpacking = true;
+        while package.next_chunk(&mut buf).await? {
+            context.update(&buf);
+            if let Some(total) = download_size {
+                downloaded += buf.len() as u64;
+                if downloaded - reported >= PROGRESS_INTERVAL || Some(downloaded) == total {
+                    reported = downloaded;
+                    crate::progress::report(InstallProgress::Download { downloaded, total });
+                }
+            }
+            // Only fails once the unpacker stopped, which reports its own error if it failed
+            if unpacking && writer.write_all(&buf).await.is_err() {
+                unpacking = false;
+            }
+        }
+        drop(writer);
+        Ok::<_, ActionErrorKind>(context.finish())
+    };
+
+    let (read, unpacked) = tokio::join!(read, unpack(reader, dest));
+    let digest = read?;
+    unpacked?;
+
+    Ok(digest
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+/// Unpack a `.tar.xz` Nix tarball from `reader` into `dest`
+///
+/// The archive is decompressed and unpacked as it is read, so a corrupt stream is reported as
+/// soon as it is encountered.
+pub(crate) async fn unpack(
+    reader: impl AsyncRead + Unpin + Send + 'static,
+    dest: &Path,
+) -> Result<(), ActionErrorKind> {
+    // TODO(@Hoverbear): Pick directory
+    tracing::trace!("Unpacking tar.xz");
+
+    // NOTE(cole-h): If the destination exists (because maybe a previous install failed), we
+    // want to remove it so that tar doesn't complain with:
+    //     trying to unpack outside of destination path: /nix/temp-install-dir
+    if dest.exists() {
+        crate::util::remove_dir_all(dest, OnMissing::Ignore)
+            .await
+            .map_err(|e| ActionErrorKind::Remove(dest.to_path_buf(), e))?;
+    }
+
+    // `tar` and `xz2` are synchronous, so drive them from a blocking thread which pulls from the
+    // async reader on demand
+    let handle = tokio::runtime::Handle::current();
+    let dest = dest.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let reader = BlockingReader {
+            inner: reader,
+            handle,
+        };
+        let decoder = xz2::read::XzDecoder::new(reader);
+        let mut archive = tar::Archive::new(decoder);
+        archive.set_preserve_permissions(true);
+        archive.set_preserve_mtime(true);
+        archive.set_unpack_xattrs(true);
+        archive.unpack(&dest).map_err(FetchUrlError::Unarchive)
+    })
+    .await
+    .map_err(ActionErrorKind::Join)??;
+
+    Ok(())
+}
+
+/// Adapts an [`AsyncRead`] into a [`std::io::Read`] for use on a blocking thread
+struct BlockingReader<R> {
+    inner: R,
+    handle: tokio::runtime::Handle,
+}
+
+impl<R: AsyncRead + Unpin> std::io::Read for BlockingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.handle.block_on(self.inner.read(buf))
+    }
+}
+
 #[non_exhaustive]
 #[derive(Debug, thiserror::Error)]
 pub enum FetchUrlError {
     #[error("Unarchiving error")]
     Unarchive(#[source] std::io::Error),
+    #[error("Refusing to read the Nix package from stdin, as it is a terminal; pipe the `.tar.xz` in instead")]
+    StdinIsTerminal,
+    #[error("Reading the Nix package from stdin conflicts with also providing a Nix package URL or path")]
+    StdinConflictsWithUrlOrPath,
     #[error("Unknown proxy scheme, `https://`, `socks5://`, and `http://` supported")]
     UnknownProxyScheme,
+    #[error("Could not read the local Nix package `{0}`")]
+    LocalPackage(PathBuf, #[source] std::io::Error),
+    #[error("`{0}` is not a valid SHA-256, expected 64 hexadecimal characters")]
+    InvalidSha256(String),
+    #[error("`{0}` can't be sent as the `From` header")]
+    InvalidFromHeader(String),
+    #[error("The Nix package has SHA-256 `{actual}`, expected `{expected}`; refusing to use it")]
+    ChecksumMismatch { expected: String, actual: String },
 }
 
 impl From<FetchUrlError> for ActionErrorKind {
@@ -212,3 +558,500 @@ impl From<FetchUrlError> for ActionErrorKind {
         ActionErrorKind::Custom(Box::new(val))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write as _;
+
+    fn nix_tarball() -> eyre::Result<Vec<u8>> {
+        let mut builder = tar::Builder::new(xz2::write::XzEncoder::new(Vec::new(), 6));
+        let contents = b"nix";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, "nix-2.0/store-file", &contents[..])?;
+        let mut encoder = builder.into_inner()?;
+        encoder.flush()?;
+        Ok(encoder.finish()?)
+    }
+
+    #[tokio::test]
+    async fn unpacks_from_async_reader() -> eyre::Result<()> {
+        let temp = tempfile::TempDir::new()?;
+        let dest = temp.path().join("unpacked");
+
+        unpack(std::io::Cursor::new(nix_tarball()?), &dest).await?;
+
+        assert_eq!(
+            tokio::fs::read_to_string(dest.join("nix-2.0/store-file")).await?,
+            "nix"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn only_moves_verified_package_into_place() -> eyre::Result<()> {
+        let temp = tempfile::TempDir::new()?;
+        let tarball_path = temp.path().join("nix.tar.xz");
+        let tarball = nix_tarball()?;
+        tokio::fs::write(&tarball_path, &tarball).await?;
+        let sha256 = ring::digest::digest(&ring::digest::SHA256, &tarball)
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+
+        let dest = temp.path().join("right");
+        let mut action = FetchAndUnpackNix::plan(
+            Some(UrlOrPath::Path(tarball_path.clone())),
+            false,
+            dest.clone(),
+            None,
+            None,
+            Some(sha256.to_ascii_uppercase()),
+            DownloadPolicy::default(),
+            None,
+            None,
+        )
+        .await?;
+        action.try_execute().await?;
+        assert!(dest.join("nix-2.0/store-file").exists());
+        assert!(!staging_path(&dest).exists());
+
+        let dest = temp.path().join("wrong");
+        let mut action = FetchAndUnpackNix::plan(
+            Some(UrlOrPath::Path(tarball_path)),
+            false,
+            dest.clone(),
+            None,
+            None,
+            Some("0".repeat(64)),
+            DownloadPolicy::default(),
+            None,
+            None,
+        )
+        .await?;
+        assert!(action.try_execute().await.is_err());
+        assert!(
+            !dest.exists(),
+            "A mismatched package should never be unpacked into place"
+        );
+        assert!(!staging_path(&dest).exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn hashes_whole_package_when_unpacker_stops_early() -> eyre::Result<()> {
+        let temp = tempfile::TempDir::new()?;
+        let dest = temp.path().join("unpacked");
+        // The unpacker stops at the end of the xz stream, before the trailing bytes
+        let mut package = nix_tarball()?;
+        package.extend(vec![0; 4 * STREAM_BUFFER_SIZE]);
+        let sha256 = ring::digest::digest(&ring::digest::SHA256, &package)
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+
+        let hashed = stream_unpack(
+            Package::Reader(
+                temp.path().join("nix.tar.xz"),
+                Box::new(std::io::Cursor::new(package)),
+            ),
+            &dest,
+        )
+        .await?;
+        assert_eq!(hashed, sha256);
+        assert!(dest.join("nix-2.0/store-file").exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unpacks_local_package_for_move_unpacked_nix() -> eyre::Result<()> {
+        let temp = tempfile::TempDir::new()?;
+        let tarball_path = temp.path().join("nix.tar.xz");
+
+        let mut builder = tar::Builder::new(xz2::write::XzEncoder::new(Vec::new(), 6));
+        let contents = b"#!/bin/sh";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder.append_data(
+            &mut header,
+            "nix-2.0/store/abc-nix-2.0/bin/nix",
+            &contents[..],
+        )?;
+        let mut encoder = builder.into_inner()?;
+        encoder.flush()?;
+        tokio::fs::write(&tarball_path, encoder.finish()?).await?;
+
+        let missing = FetchAndUnpackNix::plan(
+            Some(UrlOrPath::Path(temp.path().join("missing.tar.xz"))),
+            false,
+            temp.path().join("unused"),
+            None,
+            None,
+            None,
+            DownloadPolicy::default(),
+            None,
+            None,
+        )
+        .await;
+        assert!(
+            missing.is_err(),
+            "A missing local package should fail to plan"
+        );
+
+        let file_url = Url::from_file_path(&tarball_path).expect("Absolute path");
+        for (index, url_or_path) in [UrlOrPath::Path(tarball_path), UrlOrPath::Url(file_url)]
+            .into_iter()
+            .enumerate()
+        {
+            let unpacked = temp.path().join(format!("unpacked-{index}"));
+            let dest = temp.path().join(format!("nix-{index}"));
+            tokio::fs::create_dir(&dest).await?;
+
+            let mut fetch = FetchAndUnpackNix::plan(
+                Some(url_or_path),
+                false,
+                unpacked.clone(),
+                None,
+                None,
+                None,
+                DownloadPolicy::default(),
+                None,
+                None,
+            )
+            .await?;
+            fetch.try_execute().await?;
+
+            let mut move_unpacked =
+                crate::action::base::MoveUnpackedNix::plan(unpacked, dest.clone()).await?;
+            move_unpacked.try_execute().await?;
+            assert!(dest.join("store/abc-nix-2.0/bin/nix").exists());
+        }
+
+        Ok(())
+    }
+
+    /// Serve each of `responses` to one connection in turn, returning the URL and a hit counter
+    fn serve(
+        responses: Vec<(&'static str, Vec<u8>)>,
+    ) -> eyre::Result<(Url, std::sync::Arc<std::sync::atomic::AtomicUsize>)> {
+        serve_declaring(
+            responses
+                .into_iter()
+                .map(|(status, body)| (status, body, None))
+                .collect(),
+        )
+    }
+
+    /// Like [`serve`], but a response can declare a longer `Content-Length` than it sends, so the
+    /// download breaks off partway through
+    fn serve_declaring(
+        responses: Vec<(&'static str, Vec<u8>, Option<usize>)>,
+    ) -> eyre::Result<(Url, std::sync::Arc<std::sync::atomic::AtomicUsize>)> {
+        use std::io::{BufRead as _, BufReader};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/nix.tar.xz", listener.local_addr()?))?;
+        let hits = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = hits.clone();
+        std::thread::spawn(move || {
+            for (status, body, declared) in responses {
+                let Ok((stream, _)) = listener.accept() else {
+                    return;
+                };
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok() && line != "\r\n" {
+                    line.clear();
+                }
+                let mut stream = reader.into_inner();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    declared.unwrap_or(body.len())
+                );
+                let _ = stream.write_all(&body);
+            }
+        });
+        Ok((url, hits))
+    }
+
+    #[tokio::test]
+    async fn retries_transient_download_failures() -> eyre::Result<()> {
+        let retry = DownloadPolicy {
+            attempts: 3,
+            base_delay_ms: 1,
+            ..Default::default()
+        };
+        let temp = tempfile::TempDir::new()?;
+        let fetch_and_unpack = |url: Url, dest: PathBuf| async move {
+            let action = FetchAndUnpackNix::plan(
+                Some(UrlOrPath::Url(url.clone())),
+                false,
+                dest.clone(),
+                None,
+                None,
+                None,
+                retry,
+                None,
+                None,
+            )
+            .await?;
+            Ok::<_, eyre::Report>(action.action.fetch_and_unpack(&url, &dest).await?)
+        };
+
+        let (url, hits) = serve(vec![
+            ("503 Service Unavailable", vec![]),
+            ("502 Bad Gateway", vec![]),
+            ("200 OK", nix_tarball()?),
+        ])?;
+        let dest = temp.path().join("retried");
+        fetch_and_unpack(url, dest.clone()).await?;
+        assert_eq!(
+            std::fs::read_to_string(dest.join("nix-2.0/store-file"))?,
+            "nix"
+        );
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+        let (url, hits) = serve(vec![("404 Not Found", vec![]), ("200 OK", nix_tarball()?)])?;
+        assert!(fetch_and_unpack(url, temp.path().join("not-found"))
+            .await
+            .is_err());
+        assert_eq!(
+            hits.load(std::sync::atomic::Ordering::SeqCst),
+            1,
+            "A 404 should not be retried"
+        );
+
+        // A download breaking off partway through uses up an attempt like a failed connection
+        let tarball = nix_tarball()?;
+        let (url, hits) = serve_declaring(vec![
+            ("503 Service Unavailable", vec![], None),
+            ("200 OK", tarball[..10].to_vec(), Some(tarball.len())),
+            ("503 Service Unavailable", vec![], None),
+            ("200 OK", tarball, None),
+        ])?;
+        assert!(fetch_and_unpack(url, temp.path().join("broken-off"))
+            .await
+            .is_err());
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+        Ok(())
+    }
+
+    /// The `header` of a request made with `action`'s client
+    async fn sent_header(
+        action: &FetchAndUnpackNix,
+        header: &'static str,
+    ) -> eyre::Result<Option<String>> {
+        use std::io::{BufRead as _, BufReader};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/nix.tar.xz", listener.local_addr()?))?;
+        let server = std::thread::spawn(move || -> std::io::Result<Option<String>> {
+            let (stream, _) = listener.accept()?;
+            let mut reader = BufReader::new(stream);
+            let mut found = None;
+            let mut line = String::new();
+            while reader.read_line(&mut line)? > 0 && line != "\r\n" {
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case(header) {
+                        found = Some(value.trim().to_string());
+                    }
+                }
+                line.clear();
+            }
+            reader
+                .into_inner()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")?;
+            Ok(found)
+        });
+
+        action.client().await?.get(url).send().await?;
+        Ok(server
+            .join()
+            .map_err(|_| eyre::eyre!("Server panicked"))??)
+    }
+
+    #[tokio::test]
+    async fn sends_user_agent() -> eyre::Result<()> {
+        let plan = |user_agent| {
+            FetchAndUnpackNix::plan(
+                None,
+                false,
+                PathBuf::from("/nix/temp-install-dir"),
+                None,
+                None,
+                None,
+                DownloadPolicy::default(),
+                user_agent,
+                None,
+            )
+        };
+
+        let action = plan(None).await?;
+        let user_agent = sent_header(&action.action, "user-agent").await?;
+        assert_eq!(user_agent, Some(default_user_agent()));
+        assert!(default_user_agent().starts_with(concat!(
+            "nix-installer/",
+            env!("CARGO_PKG_VERSION"),
+            " ("
+        )));
+
+        let action = plan(Some("mirror-test/1.0".into())).await?;
+        let user_agent = sent_header(&action.action, "user-agent").await?;
+        assert_eq!(user_agent.as_deref(), Some("mirror-test/1.0"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sends_from_header() -> eyre::Result<()> {
+        let plan = |from| {
+            FetchAndUnpackNix::plan(
+                None,
+                false,
+                PathBuf::from("/nix/temp-install-dir"),
+                None,
+                None,
+                None,
+                DownloadPolicy::default(),
+                None,
+                from,
+            )
+        };
+
+        let action = plan(None).await?;
+        assert_eq!(sent_header(&action.action, "from").await?, None);
+
+        let action = plan(Some("ops@example.com".into())).await?;
+        assert_eq!(
+            sent_header(&action.action, "from").await?.as_deref(),
+            Some("ops@example.com")
+        );
+
+        assert!(plan(Some("ops@example.com\nX-Injected: 1".into()))
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    // A package many times the read-ahead buffer is unpacked as it downloads, and still checked,
+    // reporting its progress along the way. That it is never held in memory all at once is
+    // checked by `tests/bounded_memory.rs`, which can replace the allocator to measure it
+    #[tokio::test]
+    async fn streams_medium_package_from_network() -> eyre::Result<()> {
+        // Incompressible, so the compressed package is about as large
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let contents = (0..(32 * STREAM_BUFFER_SIZE))
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect::<Vec<_>>();
+        let mut builder = tar::Builder::new(xz2::write::XzEncoder::new(Vec::new(), 0));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, "nix-2.0/store-file", &contents[..])?;
+        let tarball = builder.into_inner()?.finish()?;
+        assert!(tarball.len() > 16 * STREAM_BUFFER_SIZE);
+        let sha256 = ring::digest::digest(&ring::digest::SHA256, &tarball)
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+
+        let temp = tempfile::TempDir::new()?;
+        let dest = temp.path().join("unpacked");
+        let size = tarball.len() as u64;
+        let (url, _hits) = serve(vec![("200 OK", tarball)])?;
+
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(64);
+        let mut action = FetchAndUnpackNix::plan(
+            Some(UrlOrPath::Url(url)),
+            false,
+            dest.clone(),
+            None,
+            None,
+            Some(sha256),
+            DownloadPolicy::default(),
+            None,
+            None,
+        )
+        .await?;
+        crate::progress::with_sink(Some(sender), action.try_execute()).await?;
+        assert_eq!(std::fs::read(dest.join("nix-2.0/store-file"))?, contents);
+
+        let mut events = vec![];
+        while let Ok(event) = receiver.try_recv() {
+            events.push(event);
+        }
+        assert!(events.len() > 1);
+        assert_eq!(
+            events.last(),
+            Some(&InstallProgress::Download {
+                downloaded: size,
+                total: Some(size),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_stream() -> eyre::Result<()> {
+        let temp = tempfile::TempDir::new()?;
+        let dest = temp.path().join("unpacked");
+
+        let mut tarball = nix_tarball()?;
+        tarball.truncate(tarball.len() / 2);
+
+        assert!(unpack(std::io::Cursor::new(tarball), &dest).await.is_err());
+        assert!(
+            unpack(std::io::Cursor::new(b"not a tarball".to_vec()), &dest)
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn downloads_through_configured_proxy() -> eyre::Result<()> {
+        let (proxy, hits) = serve(vec![("200 OK", nix_tarball()?)])?;
+        // Only reachable through the proxy
+        let url = Url::parse("http://nix-installer.invalid/nix.tar.xz")?;
+
+        let action = FetchAndUnpackNix::plan(
+            Some(UrlOrPath::Url(url.clone())),
+            false,
+            PathBuf::from("/unused"),
+            Some(proxy),
+            None,
+            None,
+            DownloadPolicy::default(),
+            None,
+            None,
+        )
+        .await?;
+        let client = action.action.client().await?;
+
+        assert_eq!(fetch(&client, &url).await?.bytes().await?, nix_tarball()?);
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+}
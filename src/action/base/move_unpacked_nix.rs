@@ -1,7 +1,4 @@
-use std::{
-    os::unix::prelude::PermissionsExt,
-    path::{Path, PathBuf},
-};
+use std::{os::unix::prelude::PermissionsExt, path::PathBuf};
 
 use tracing::{span, Span};
 use walkdir::WalkDir;
@@ -11,22 +8,42 @@ use crate::{
     util::OnMissing,
 };
 
-pub(crate) const DEST: &str = "/nix/";
-
 /**
-Move an unpacked Nix at `src` to `/nix`
+Move an unpacked Nix at `src` into the store under `dest` (usually `/nix`)
 */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 #[serde(tag = "action_name", rename = "mount_unpacked_nix")]
 pub struct MoveUnpackedNix {
     unpacked_path: PathBuf,
+    #[serde(default = "default_dest")]
+    dest: PathBuf,
+}
+
+fn default_dest() -> PathBuf {
+    PathBuf::from(crate::settings::DEFAULT_NIX_STORE_PATH)
 }
 
 impl MoveUnpackedNix {
     #[tracing::instrument(level = "debug", skip_all)]
-    pub async fn plan(unpacked_path: PathBuf) -> Result<StatefulAction<Self>, ActionError> {
-        // Note: Do NOT try to check for the src/dest since the installer creates those
-        Ok(Self { unpacked_path }.into())
+    pub async fn plan(
+        unpacked_path: PathBuf,
+        dest: PathBuf,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        // Note: Do NOT try to check for the src/dest since the installer creates those, but `dest`
+        // is only created in a directory which is already there
+        if let Some(parent) = dest.parent().filter(|parent| !parent.is_dir()) {
+            return Err(Self::error(
+                MoveUnpackedNixError::MissingDestinationParent {
+                    dest: dest.clone(),
+                    parent: parent.to_path_buf(),
+                },
+            ));
+        }
+        Ok(Self {
+            unpacked_path,
+            dest,
+        }
+        .into())
     }
 }
 
@@ -37,7 +54,7 @@ impl Action for MoveUnpackedNix {
         ActionTag("move_unpacked_nix")
     }
     fn tracing_synopsis(&self) -> String {
-        "Move the downloaded Nix into `/nix`".to_string()
+        format!("Move the downloaded Nix into `{}`", self.dest.display())
     }
 
     fn tracing_span(&self) -> Span {
@@ -45,39 +62,59 @@ impl Action for MoveUnpackedNix {
             tracing::Level::DEBUG,
             "mount_unpacked_nix",
             src = tracing::field::display(self.unpacked_path.display()),
-            dest = DEST,
+            dest = tracing::field::display(self.dest.display()),
         )
     }
 
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
-            "Move the downloaded Nix into `/nix`".to_string(),
+            self.tracing_synopsis(),
             vec![format!(
-                "Nix is being downloaded to `{}` and should be in `/nix`",
+                "Nix is being downloaded to `{}` and should be in `{}`",
                 self.unpacked_path.display(),
+                self.dest.display(),
             )],
         )]
     }
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn execute(&mut self) -> Result<(), ActionError> {
-        let Self { unpacked_path } = self;
+        let Self {
+            unpacked_path,
+            dest,
+        } = self;
+
+        if !dest.is_dir() {
+            return Err(Self::error(MoveUnpackedNixError::MissingDestination(
+                dest.clone(),
+            )));
+        }
 
         // This is the `nix-$VERSION` folder which unpacks from the tarball, not a nix derivation
         let found_nix_paths = glob::glob(&format!("{}/nix-*", unpacked_path.display()))
             .map_err(|e| Self::error(MoveUnpackedNixError::from(e)))?
             .collect::<Result<Vec<_>, _>>()
             .map_err(|e| Self::error(MoveUnpackedNixError::from(e)))?;
-        if found_nix_paths.len() != 1 {
-            return Err(Self::error(ActionErrorKind::MalformedBinaryTarball));
-        }
-        let found_nix_path = found_nix_paths.into_iter().next().unwrap();
+        let found_nix_path = match <[PathBuf; 1]>::try_from(found_nix_paths) {
+            Ok([found_nix_path]) => found_nix_path,
+            Err(found_nix_paths) if found_nix_paths.is_empty() => {
+                return Err(Self::error(MoveUnpackedNixError::NoUnpackedNix(
+                    unpacked_path.clone(),
+                )))
+            },
+            Err(found_nix_paths) => {
+                return Err(Self::error(MoveUnpackedNixError::MultipleUnpackedNix(
+                    unpacked_path.clone(),
+                    found_nix_paths,
+                )))
+            },
+        };
         let src_store = found_nix_path.join("store");
         let mut src_store_listing = tokio::fs::read_dir(src_store.clone())
             .await
             .map_err(|e| ActionErrorKind::ReadDir(src_store.clone(), e))
             .map_err(Self::error)?;
-        let dest_store = Path::new(DEST).join("store");
+        let dest_store = dest.join("store");
         if dest_store.exists() {
             if !dest_store.is_dir() {
                 return Err(Self::error(ActionErrorKind::PathWasNotDirectory(
@@ -181,6 +218,14 @@ pub enum MoveUnpackedNixError {
         #[source]
         glob::GlobError,
     ),
+    #[error("Cannot move Nix into `{0}`, it is not an existing directory")]
+    MissingDestination(PathBuf),
+    #[error("Cannot place the Nix store at `{}`, `{}` does not exist, consider creating it or choosing another `--nix-store-path`", dest.display(), parent.display())]
+    MissingDestinationParent { dest: PathBuf, parent: PathBuf },
+    #[error("No unpacked Nix (a `nix-*` directory) was found in `{0}`, the Nix tarball may be malformed")]
+    NoUnpackedNix(PathBuf),
+    #[error("Found several unpacked Nix (`nix-*` directories) in `{0}`, expected only one: {list}", list = .1.iter().map(|path| format!("`{}`", path.display())).collect::<Vec<_>>().join(", "))]
+    MultipleUnpackedNix(PathBuf, Vec<PathBuf>),
 }
 
 impl From<MoveUnpackedNixError> for ActionErrorKind {
@@ -188,3 +233,121 @@ impl From<MoveUnpackedNixError> for ActionErrorKind {
         ActionErrorKind::Custom(Box::new(val))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::action::base::{CreateDirectory, CreateSymlink};
+
+    #[tokio::test]
+    async fn moves_into_non_default_store_path() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let unpacked = temp_dir.path().join("unpacked");
+        let package = unpacked.join("nix-2.21.2-x86_64-linux/store/abc-nix-2.21.2");
+        tokio::fs::create_dir_all(package.join("bin")).await?;
+        tokio::fs::write(package.join("bin/nix"), "").await?;
+        let dest = temp_dir.path().join("data/nix");
+
+        let err = MoveUnpackedNix::plan(unpacked.clone(), dest.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ActionErrorKind::Custom(e)
+                if matches!(e.downcast_ref(), Some(MoveUnpackedNixError::MissingDestinationParent { .. }))
+        ));
+
+        tokio::fs::create_dir(temp_dir.path().join("data")).await?;
+        let mut action = MoveUnpackedNix::plan(unpacked.clone(), dest.clone()).await?;
+        assert!(
+            action.try_execute().await.is_err(),
+            "A missing destination should be refused"
+        );
+
+        tokio::fs::create_dir_all(&dest).await?;
+        action.try_execute().await?;
+
+        let moved = dest.join("store/abc-nix-2.21.2");
+        assert!(moved.join("bin/nix").exists());
+        assert_eq!(tokio::fs::read_link(&package).await?, moved);
+
+        // Make the moved package writable again so the temp dir can be cleaned up
+        for entry in WalkDir::new(&moved).into_iter().filter_map(Result::ok) {
+            let mut perms = entry.path().metadata()?.permissions();
+            perms.set_mode(perms.mode() | 0o200);
+            std::fs::set_permissions(entry.path(), perms)?;
+        }
+
+        Ok(())
+    }
+
+    /// The store directory, the `/nix` symlink to it, and the move, as the `linux` planner does with
+    /// `--nix-store-path`
+    #[tokio::test]
+    async fn relocated_store_end_to_end() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let unpacked = temp_dir.path().join("unpacked");
+        let package = unpacked.join("nix-2.21.2-x86_64-linux/store/abc-nix-2.21.2");
+        tokio::fs::create_dir_all(package.join("bin")).await?;
+        tokio::fs::write(package.join("bin/nix"), "").await?;
+        tokio::fs::create_dir(temp_dir.path().join("data")).await?;
+        let store_path = temp_dir.path().join("data/nix");
+        let link = temp_dir.path().join("nix");
+
+        let mut create_store = CreateDirectory::plan(&store_path, None, None, 0o0755, true).await?;
+        let mut create_link = CreateSymlink::plan(&link, &store_path).await?;
+        let mut move_unpacked = MoveUnpackedNix::plan(unpacked.clone(), store_path.clone()).await?;
+        create_store.try_execute().await?;
+        create_link.try_execute().await?;
+        move_unpacked.try_execute().await?;
+
+        // Nix is found through the symlink, but lives in the relocated store
+        assert!(link.join("store/abc-nix-2.21.2/bin/nix").exists());
+        let moved = store_path.join("store/abc-nix-2.21.2");
+        assert!(moved.join("bin/nix").exists());
+        assert!(link.is_symlink());
+
+        for entry in WalkDir::new(&moved).into_iter().filter_map(Result::ok) {
+            let mut perms = entry.path().metadata()?.permissions();
+            perms.set_mode(perms.mode() | 0o200);
+            std::fs::set_permissions(entry.path(), perms)?;
+        }
+        create_link.try_revert().await?;
+        assert!(!link.exists(), "The symlink should have been removed");
+        assert!(moved.exists(), "The store should have been left alone");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refuses_several_unpacked_nix() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let unpacked = temp_dir.path().join("unpacked");
+        let dest = temp_dir.path().join("nix");
+        tokio::fs::create_dir_all(&dest).await?;
+
+        tokio::fs::create_dir_all(&unpacked).await?;
+        let mut action = MoveUnpackedNix::plan(unpacked.clone(), dest.clone()).await?;
+        let err = action.try_execute().await.unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ActionErrorKind::Custom(e)
+                if matches!(e.downcast_ref(), Some(MoveUnpackedNixError::NoUnpackedNix(_)))
+        ));
+
+        for version in ["2.21.2", "2.18.1"] {
+            tokio::fs::create_dir_all(unpacked.join(format!("nix-{version}-x86_64-linux/store")))
+                .await?;
+        }
+        let mut action = MoveUnpackedNix::plan(unpacked.clone(), dest.clone()).await?;
+        let err = action.try_execute().await.unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ActionErrorKind::Custom(e)
+                if matches!(e.downcast_ref(), Some(MoveUnpackedNixError::MultipleUnpackedNix(_, found)) if found.len() == 2)
+        ));
+        assert!(!dest.join("store").exists());
+
+        Ok(())
+    }
+}
@@ -1,12 +1,16 @@
+use std::os::unix::fs::MetadataExt as _;
 use std::path::{Path, PathBuf};
 
 use tracing::{span, Span};
 
 use crate::action::{Action, ActionDescription, ActionErrorKind, ActionState};
 use crate::action::{ActionError, StatefulAction};
-use crate::util::OnMissing;
 
 /** Remove a directory, does nothing on revert.
+
+Everything which can be removed is, and what could not be is listed in the error. Mounts inside the
+directory (or the directory itself, if it is a mount, like the Nix volume on macOS) are left alone,
+as they are removed by the action which created them.
 */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 #[serde(tag = "action_name", rename = "remove_directory")]
@@ -22,6 +26,7 @@ impl RemoveDirectory {
         Ok(StatefulAction {
             action: Self { path },
             state: ActionState::Uncompleted,
+            duration: None,
         })
     }
 }
@@ -59,9 +64,7 @@ impl Action for RemoveDirectory {
 
             // At this point, we know the path exists, but just in case it was deleted between then
             // and now, we still ignore the case where it no longer exists.
-            crate::util::remove_dir_all(&self.path, OnMissing::Ignore)
-                .await
-                .map_err(|e| Self::error(ActionErrorKind::Remove(self.path.clone(), e)))?;
+            remove_tree(&self.path).await.map_err(Self::error)?;
         } else {
             tracing::debug!("Directory `{}` not present, skipping", self.path.display(),);
         };
@@ -78,3 +81,228 @@ impl Action for RemoveDirectory {
         Ok(())
     }
 }
+
+/// Remove `path` and everything in it, carrying on past anything which can't be removed
+///
+/// Mounts are not descended into. If `path` is itself a mount, only its contents are removed.
+pub(crate) async fn remove_tree(path: &Path) -> Result<(), RemoveDirectoryError> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || remove_tree_with(&path, &remove_entry))
+        .await
+        .map_err(RemoveDirectoryError::Join)?
+}
+
+fn remove_entry(path: &Path, is_dir: bool) -> std::io::Result<()> {
+    if is_dir {
+        std::fs::remove_dir(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+}
+
+fn remove_tree_with(
+    path: &Path,
+    remove: &dyn Fn(&Path, bool) -> std::io::Result<()>,
+) -> Result<(), RemoveDirectoryError> {
+    let metadata = match path.symlink_metadata() {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => {
+            return Err(RemoveDirectoryError::Undeletable {
+                path: path.to_path_buf(),
+                failures: vec![(path.to_path_buf(), e)],
+            })
+        },
+    };
+    let is_mount = path
+        .parent()
+        .and_then(|parent| parent.metadata().ok())
+        .is_some_and(|parent| parent.dev() != metadata.dev());
+
+    let mut failures = vec![];
+    let removed_contents = remove_contents(path, metadata.dev(), remove, &mut failures);
+    if is_mount {
+        tracing::debug!(
+            "`{}` is a mount, leaving it for the action which created it",
+            path.display()
+        );
+    } else if removed_contents {
+        match remove(path, true) {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => failures.push((path.to_path_buf(), e)),
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(RemoveDirectoryError::Undeletable {
+            path: path.to_path_buf(),
+            failures,
+        })
+    }
+}
+
+/// Remove everything in `dir` on the device `dev`, returning if it was all removed
+fn remove_contents(
+    dir: &Path,
+    dev: u64,
+    remove: &dyn Fn(&Path, bool) -> std::io::Result<()>,
+    failures: &mut Vec<(PathBuf, std::io::Error)>,
+) -> bool {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return true,
+        Err(e) => {
+            failures.push((dir.to_path_buf(), e));
+            return false;
+        },
+    };
+
+    let mut removed_all = true;
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                failures.push((dir.to_path_buf(), e));
+                removed_all = false;
+                continue;
+            },
+        };
+        let entry_path = entry.path();
+        let metadata = match entry_path.symlink_metadata() {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                failures.push((entry_path, e));
+                removed_all = false;
+                continue;
+            },
+        };
+
+        if metadata.is_dir() && metadata.dev() != dev {
+            tracing::warn!(
+                "Not removing `{}`, something is mounted there",
+                entry_path.display()
+            );
+            failures.push((
+                entry_path,
+                std::io::Error::new(
+                    std::io::ErrorKind::ResourceBusy,
+                    "something is mounted here",
+                ),
+            ));
+            removed_all = false;
+            continue;
+        }
+
+        if metadata.is_dir() && !remove_contents(&entry_path, dev, remove, failures) {
+            removed_all = false;
+            continue;
+        }
+        match remove(&entry_path, metadata.is_dir()) {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => {
+                failures.push((entry_path, e));
+                removed_all = false;
+            },
+        }
+    }
+    removed_all
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum RemoveDirectoryError {
+    #[error(
+        "Could not remove everything in `{}`, what remains:\n{}",
+        .path.display(),
+        .failures
+            .iter()
+            .map(|(path, e)| format!("* `{}`: {e}", path.display()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    )]
+    Undeletable {
+        path: PathBuf,
+        failures: Vec<(PathBuf, std::io::Error)>,
+    },
+    #[error("Joining the removal of a directory")]
+    Join(#[source] tokio::task::JoinError),
+}
+
+impl From<RemoveDirectoryError> for ActionErrorKind {
+    fn from(val: RemoveDirectoryError) -> Self {
+        ActionErrorKind::Custom(Box::new(val))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{remove_entry, remove_tree, remove_tree_with, RemoveDirectoryError};
+
+    fn nested_tree(root: &Path) -> eyre::Result<()> {
+        std::fs::create_dir_all(root.join("store/abc-nix/bin"))?;
+        std::fs::create_dir_all(root.join("var/nix/db"))?;
+        std::fs::write(root.join("store/abc-nix/bin/nix"), "")?;
+        std::fs::write(root.join("var/nix/db/db.sqlite"), "")?;
+        std::os::unix::fs::symlink("/nonexistent", root.join("var/nix/gcroot"))?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn removes_nested_tree() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().join("nix");
+        nested_tree(&root)?;
+
+        remove_tree(&root).await?;
+        assert!(!root.exists());
+        // Already gone is fine
+        remove_tree(&root).await?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn lists_what_could_not_be_removed() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().join("nix");
+        nested_tree(&root)?;
+        let busy = root.join("var/nix/db/db.sqlite");
+
+        let err = remove_tree_with(&root, &|path, is_dir| {
+            if path == busy {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::ResourceBusy,
+                    "in use",
+                ))
+            } else {
+                remove_entry(path, is_dir)
+            }
+        })
+        .unwrap_err();
+
+        let RemoveDirectoryError::Undeletable { path, failures } = &err else {
+            eyre::bail!("Unexpected error {err:?}");
+        };
+        assert_eq!(path, &root);
+        assert_eq!(
+            failures.iter().map(|(path, _)| path).collect::<Vec<_>>(),
+            [&busy]
+        );
+        assert!(err
+            .to_string()
+            .contains(&format!("* `{}`: in use", busy.display())));
+
+        // Everything else is gone, and the parents of what remains are kept
+        assert!(busy.exists());
+        assert!(!root.join("store").exists());
+        assert!(!root.join("var/nix/gcroot").exists());
+
+        Ok(())
+    }
+}
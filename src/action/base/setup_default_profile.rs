@@ -1,8 +1,12 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::{
-    action::{common::ConfigureNix, ActionError, ActionErrorKind, ActionTag, StatefulAction},
+    action::{
+        common::{configure_nix::ConfigureNixError, ConfigureNix},
+        ActionError, ActionErrorKind, ActionTag, StatefulAction,
+    },
     execute_command, set_env,
+    settings::SCRATCH_DIR,
 };
 
 use tokio::{io::AsyncWriteExt, process::Command};
@@ -12,17 +16,137 @@ use crate::action::{Action, ActionDescription};
 
 /**
 Setup the default Nix profile with `nss-cacert` and `nix` itself.
+
+Unless `require_cacert` is set, a Nix package without `nss-cacert` only produces a warning.
+
+If installing into another `root`, Nix is run in a `chroot` of it, as its store paths only work
+under `/nix`. The host's `/dev` and `/proc` are mounted into the root while Nix runs, and unmounted
+afterwards.
  */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 #[serde(tag = "action_name", rename = "setup_default_profile")]
 pub struct SetupDefaultProfile {
     unpacked_path: PathBuf,
+    #[serde(default = "default_require_cacert")]
+    require_cacert: bool,
+    #[serde(default)]
+    root: Option<PathBuf>,
+}
+
+fn default_require_cacert() -> bool {
+    true
 }
 
 impl SetupDefaultProfile {
     #[tracing::instrument(level = "debug", skip_all)]
-    pub async fn plan(unpacked_path: PathBuf) -> Result<StatefulAction<Self>, ActionError> {
-        Ok(Self { unpacked_path }.into())
+    pub async fn plan(
+        unpacked_path: PathBuf,
+        require_cacert: bool,
+        root: Option<PathBuf>,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        Ok(Self {
+            unpacked_path,
+            require_cacert,
+            root,
+        }
+        .into())
+    }
+
+    /// A command running `program` (a path in the Nix store), in a `chroot` of the root if there is one
+    fn nix_command(&self, program: &Path) -> Result<Command, ActionError> {
+        let mut command = match &self.root {
+            Some(root) => {
+                let mut command = Command::new("chroot");
+                command.arg(root).arg(self.path_in_root(program));
+                // The scratch directory is removed after the install, unlike a home directory in
+                // the root, which `nix-env` would create its `.nix-defexpr` in
+                command.env("HOME", SCRATCH_DIR);
+                command
+            },
+            None => {
+                let mut command = Command::new(program);
+                command.env(
+                    "HOME",
+                    dirs::home_dir()
+                        .ok_or_else(|| Self::error(SetupDefaultProfileError::NoRootHome))?,
+                );
+                command
+            },
+        };
+        command.process_group(0);
+        Ok(command)
+    }
+
+    /// Outside the running system, name the default profile rather than have `nix-env` find it
+    /// through the (missing) home directory
+    fn profile_args(&self) -> Vec<&'static str> {
+        match self.root {
+            Some(_) => vec!["--profile", "/nix/var/nix/profiles/default"],
+            None => vec![],
+        }
+    }
+
+    /// Mount the host's `/dev` and `/proc` into the root, which Nix needs to run in a `chroot`
+    ///
+    /// Returns the mounted paths, in the order they should be unmounted.
+    async fn mount_host_filesystems(root: &Path) -> Result<Vec<PathBuf>, ActionErrorKind> {
+        let mut mounted = vec![];
+        for (target, args) in [
+            ("dev", &["--bind", "/dev"][..]),
+            ("proc", &["--types", "proc", "proc"][..]),
+        ] {
+            let target = root.join(target);
+            let res = async {
+                tokio::fs::create_dir_all(&target)
+                    .await
+                    .map_err(|e| ActionErrorKind::CreateDirectory(target.clone(), e))?;
+                execute_command(
+                    Command::new("mount")
+                        .process_group(0)
+                        .args(args)
+                        .arg(&target)
+                        .stdin(std::process::Stdio::null()),
+                )
+                .await
+            }
+            .await;
+            if let Err(err) = res {
+                Self::unmount_host_filesystems(mounted).await.ok();
+                return Err(err);
+            }
+            mounted.insert(0, target);
+        }
+        Ok(mounted)
+    }
+
+    /// Unmount what [`Self::mount_host_filesystems`] mounted, trying each even if one fails
+    async fn unmount_host_filesystems(mounted: Vec<PathBuf>) -> Result<(), ActionErrorKind> {
+        let mut first_error = None;
+        for target in mounted {
+            let res = execute_command(
+                Command::new("umount")
+                    .process_group(0)
+                    .arg(&target)
+                    .stdin(std::process::Stdio::null()),
+            )
+            .await;
+            if let Err(err) = res {
+                tracing::warn!(target = %target.display(), "Failed to unmount: {err}");
+                first_error.get_or_insert(err);
+            }
+        }
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// `path` as seen from inside the root, if there is one
+    fn path_in_root(&self, path: &Path) -> PathBuf {
+        match self.root.as_deref().map(|root| path.strip_prefix(root)) {
+            Some(Ok(relative)) => Path::new("/").join(relative),
+            _ => path.to_path_buf(),
+        }
     }
 }
 
@@ -41,6 +165,8 @@ impl Action for SetupDefaultProfile {
             tracing::Level::DEBUG,
             "setup_default_profile",
             unpacked_path = %self.unpacked_path.display(),
+            require_cacert = self.require_cacert,
+            root = self.root.as_ref().map(|root| tracing::field::display(root.display())),
         )
     }
 
@@ -50,8 +176,52 @@ impl Action for SetupDefaultProfile {
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn execute(&mut self) -> Result<(), ActionError> {
+        let mounted = match &self.root {
+            Some(root) => Self::mount_host_filesystems(root)
+                .await
+                .map_err(Self::error)?,
+            None => vec![],
+        };
+        let res = self.setup_profile().await;
+        let unmounted = Self::unmount_host_filesystems(mounted).await;
+        res?;
+        unmounted.map_err(Self::error)
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            "Unset the default Nix profile".to_string(),
+            vec![],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        std::env::remove_var("NIX_SSL_CERT_FILE");
+
+        Ok(())
+    }
+}
+
+impl SetupDefaultProfile {
+    /// Load the store paths of the unpacked package into the database, and install `nix` and
+    /// `nss-cacert` into the default profile
+    async fn setup_profile(&self) -> Result<(), ActionError> {
         let (nix_pkg, nss_ca_cert_pkg) =
             ConfigureNix::find_nix_and_ca_cert(&self.unpacked_path).await?;
+        if nss_ca_cert_pkg.is_none() {
+            if self.require_cacert {
+                return Err(Self::error(ConfigureNixError::NoNssCacert));
+            }
+            tracing::warn!(
+                "The Nix package does not include `nss-cacert`, so Nix may fail to make TLS connections until `ssl-cert-file` is set in `/etc/nix/nix.conf` or `NIX_SSL_CERT_FILE` is set"
+            );
+        }
+        // Without `nss-cacert`, fall back to whatever the caller already had
+        let ssl_cert_file = nss_ca_cert_pkg.as_ref().map(|nss_ca_cert_pkg| {
+            self.path_in_root(nss_ca_cert_pkg)
+                .join("etc/ssl/certs/ca-bundle.crt")
+        });
         let found_nix_paths = glob::glob(&format!("{}/nix-*", self.unpacked_path.display()))
             .map_err(Self::error)?
             .collect::<Result<Vec<_>, _>>()
@@ -65,16 +235,11 @@ impl Action for SetupDefaultProfile {
             .await
             .map_err(|e| ActionErrorKind::Read(reginfo_path.to_path_buf(), e))
             .map_err(Self::error)?;
-        let mut load_db_command = Command::new(nix_pkg.join("bin/nix-store"));
-        load_db_command.process_group(0);
+        let mut load_db_command = self.nix_command(&nix_pkg.join("bin/nix-store"))?;
         load_db_command.arg("--load-db");
         load_db_command.stdin(std::process::Stdio::piped());
         load_db_command.stdout(std::process::Stdio::piped());
         load_db_command.stderr(std::process::Stdio::piped());
-        load_db_command.env(
-            "HOME",
-            dirs::home_dir().ok_or_else(|| Self::error(SetupDefaultProfileError::NoRootHome))?,
-        );
         tracing::trace!(
             "Executing `{:?}` with stdin from `{}`",
             load_db_command.as_std(),
@@ -115,67 +280,45 @@ impl Action for SetupDefaultProfile {
         };
 
         // Install `nix` itself into the store
-        execute_command(
-            Command::new(nix_pkg.join("bin/nix-env"))
-                .process_group(0)
-                .args(["--option", "substitute", "false"])
-                .args(["--option", "post-build-hook", ""])
-                .arg("-i")
-                .arg(&nix_pkg)
-                .stdin(std::process::Stdio::null())
-                .env(
-                    "HOME",
-                    dirs::home_dir()
-                        .ok_or_else(|| Self::error(SetupDefaultProfileError::NoRootHome))?,
-                )
-                .env(
-                    "NIX_SSL_CERT_FILE",
-                    nss_ca_cert_pkg.join("etc/ssl/certs/ca-bundle.crt"),
-                ), /* This is apparently load bearing... */
-        )
-        .await
-        .map_err(Self::error)?;
-
-        // Install `nix` itself into the store
-        execute_command(
-            Command::new(nix_pkg.join("bin/nix-env"))
-                .process_group(0)
-                .args(["--option", "substitute", "false"])
-                .args(["--option", "post-build-hook", ""])
-                .arg("-i")
-                .arg(&nss_ca_cert_pkg)
-                .stdin(std::process::Stdio::null())
-                .env(
-                    "HOME",
-                    dirs::home_dir()
-                        .ok_or_else(|| Self::error(SetupDefaultProfileError::NoRootHome))?,
-                )
-                .env(
-                    "NIX_SSL_CERT_FILE",
-                    nss_ca_cert_pkg.join("etc/ssl/certs/ca-bundle.crt"),
-                ), /* This is apparently load bearing... */
-        )
-        .await
-        .map_err(Self::error)?;
-
-        set_env(
-            "NIX_SSL_CERT_FILE",
-            "/nix/var/nix/profiles/default/etc/ssl/certs/ca-bundle.crt",
-        );
-
-        Ok(())
-    }
+        let mut install_nix_command = self.nix_command(&nix_pkg.join("bin/nix-env"))?;
+        install_nix_command
+            .args(["--option", "substitute", "false"])
+            .args(["--option", "post-build-hook", ""])
+            .args(self.profile_args())
+            .arg("-i")
+            .arg(self.path_in_root(&nix_pkg))
+            .stdin(std::process::Stdio::null());
+        if let Some(ssl_cert_file) = &ssl_cert_file {
+            install_nix_command.env("NIX_SSL_CERT_FILE", ssl_cert_file); /* This is apparently load bearing... */
+        }
+        execute_command(&mut install_nix_command)
+            .await
+            .map_err(Self::error)?;
 
-    fn revert_description(&self) -> Vec<ActionDescription> {
-        vec![ActionDescription::new(
-            "Unset the default Nix profile".to_string(),
-            vec![],
-        )]
-    }
+        if let (Some(nss_ca_cert_pkg), Some(ssl_cert_file)) = (nss_ca_cert_pkg, ssl_cert_file) {
+            // Install `nss-cacert` into the store
+            execute_command(
+                self.nix_command(&nix_pkg.join("bin/nix-env"))?
+                    .args(["--option", "substitute", "false"])
+                    .args(["--option", "post-build-hook", ""])
+                    .args(self.profile_args())
+                    .arg("-i")
+                    .arg(self.path_in_root(&nss_ca_cert_pkg))
+                    .stdin(std::process::Stdio::null())
+                    .env("NIX_SSL_CERT_FILE", ssl_cert_file), /* This is apparently load bearing... */
+            )
+            .await
+            .map_err(Self::error)?;
 
-    #[tracing::instrument(level = "debug", skip_all)]
-    async fn revert(&mut self) -> Result<(), ActionError> {
-        std::env::remove_var("NIX_SSL_CERT_FILE");
+            // The Nix under another root is not used by the rest of the install
+            if self.root.is_some() {
+                return Ok(());
+            }
+            set_env(
+                "NIX_SSL_CERT_FILE",
+                "/nix/var/nix/profiles/default/etc/ssl/certs/ca-bundle.crt",
+            );
+        }
 
         Ok(())
     }
@@ -0,0 +1,409 @@
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+use tracing::{span, Span};
+
+use crate::action::base::{CreateDirectory, CreateFile};
+use crate::action::macos::DARWIN_LAUNCHD_DOMAIN;
+use crate::action::{
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+};
+use crate::execute_command;
+use crate::settings::InitSystem;
+
+const SYSTEMD_UNIT_DIR: &str = "/etc/systemd/system";
+const SYSTEMD_DEFAULT_SERVICE: &str = "nix-daemon.service";
+const SYSTEMD_DROP_IN_NAME: &str = "restart-on-failure.conf";
+const SYSTEMD_DROP_IN_CONTENT: &str = "\
+# Added by the Determinate Nix Installer
+[Service]
+Restart=on-failure
+RestartSec=5s
+";
+
+const LAUNCHD_KEEP_ALIVE: &str = "KeepAlive";
+const LAUNCHD_SUCCESSFUL_EXIT: &str = "SuccessfulExit";
+
+/**
+Configure the Nix daemon to be restarted automatically if it fails
+
+On systemd, this is a drop-in for `nix-daemon.service` (or the service named by `service_name`).
+On launchd, this sets `KeepAlive` in the daemon's plist (if it is not already set).
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+#[serde(tag = "action_name", rename = "configure_daemon_restart")]
+pub struct ConfigureDaemonRestart {
+    init: InitSystem,
+    create_drop_in_dir: Option<StatefulAction<CreateDirectory>>,
+    create_drop_in: Option<StatefulAction<CreateFile>>,
+    plist_path: Option<PathBuf>,
+    service_name: Option<String>,
+    /// If `KeepAlive` was set by us on execute, and should be removed on revert
+    modified_plist: bool,
+}
+
+impl ConfigureDaemonRestart {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(
+        init: InitSystem,
+        plist_path: Option<PathBuf>,
+        service_name: Option<String>,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        let (create_drop_in_dir, create_drop_in) = match init {
+            InitSystem::Systemd => {
+                let drop_in_dir = systemd_drop_in_dir(service_name.as_deref());
+                let create_drop_in_dir =
+                    CreateDirectory::plan(&drop_in_dir, None, None, 0o0755, false)
+                        .await
+                        .map_err(Self::error)?;
+                let create_drop_in = CreateFile::plan(
+                    drop_in_dir.join(SYSTEMD_DROP_IN_NAME),
+                    None,
+                    None,
+                    0o0644,
+                    SYSTEMD_DROP_IN_CONTENT.to_string(),
+                    false,
+                )
+                .await
+                .map_err(Self::error)?;
+                (Some(create_drop_in_dir), Some(create_drop_in))
+            },
+            InitSystem::Launchd => {
+                if plist_path.is_none() || service_name.is_none() {
+                    return Err(Self::error(ConfigureDaemonRestartError::MissingPlist));
+                }
+                (None, None)
+            },
+            InitSystem::None => {
+                return Err(Self::error(ConfigureDaemonRestartError::InitNone));
+            },
+            InitSystem::OpenRc => {
+                return Err(Self::error(ConfigureDaemonRestartError::OpenRc));
+            },
+        };
+
+        Ok(Self {
+            init,
+            create_drop_in_dir,
+            create_drop_in,
+            plist_path,
+            service_name,
+            modified_plist: false,
+        }
+        .into())
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "configure_daemon_restart")]
+impl Action for ConfigureDaemonRestart {
+    fn action_tag() -> ActionTag {
+        ActionTag("configure_daemon_restart")
+    }
+    fn tracing_synopsis(&self) -> String {
+        "Configure the Nix daemon to restart automatically on failure".to_string()
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "configure_daemon_restart",
+            init = ?self.init,
+        )
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        let explanation = match self.init {
+            InitSystem::Systemd => vec![
+                format!(
+                    "Create `{}` setting `Restart=on-failure`",
+                    systemd_drop_in_dir(self.service_name.as_deref())
+                        .join(SYSTEMD_DROP_IN_NAME)
+                        .display()
+                ),
+                "Run `systemctl daemon-reload`".to_string(),
+            ],
+            InitSystem::Launchd => vec![format!(
+                "Set `{LAUNCHD_KEEP_ALIVE}` in `{}` (if it is not already set) and reload the service",
+                self.plist_path
+                    .as_ref()
+                    .expect("plist_path should be defined for launchd")
+                    .display()
+            )],
+            InitSystem::None | InitSystem::OpenRc => vec![],
+        };
+        vec![ActionDescription::new(self.tracing_synopsis(), explanation)]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        match self.init {
+            InitSystem::Systemd => {
+                if let Some(create_drop_in_dir) = &mut self.create_drop_in_dir {
+                    create_drop_in_dir
+                        .try_execute()
+                        .await
+                        .map_err(Self::error)?;
+                }
+                if let Some(create_drop_in) = &mut self.create_drop_in {
+                    create_drop_in.try_execute().await.map_err(Self::error)?;
+                }
+                daemon_reload().await.map_err(Self::error)?;
+            },
+            InitSystem::Launchd => {
+                let plist_path = self
+                    .plist_path
+                    .clone()
+                    .expect("plist_path should be defined for launchd");
+                let service_name = self
+                    .service_name
+                    .clone()
+                    .expect("service_name should be defined for launchd");
+
+                let mut plist = read_plist(&plist_path).map_err(Self::error)?;
+                if !set_keep_alive(&plist_path, &mut plist).map_err(Self::error)? {
+                    tracing::debug!(
+                        "`{}` already sets `{LAUNCHD_KEEP_ALIVE}`, leaving it as-is",
+                        plist_path.display()
+                    );
+                    return Ok(());
+                }
+                write_plist(&plist_path, &plist).map_err(Self::error)?;
+                self.modified_plist = true;
+
+                // launchd only reads the plist when the service is bootstrapped
+                crate::action::macos::retry_bootout(DARWIN_LAUNCHD_DOMAIN, &service_name)
+                    .await
+                    .map_err(Self::error)?;
+                crate::action::macos::retry_bootstrap(
+                    DARWIN_LAUNCHD_DOMAIN,
+                    &service_name,
+                    &plist_path,
+                )
+                .await
+                .map_err(Self::error)?;
+            },
+            InitSystem::None | InitSystem::OpenRc => (),
+        }
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        let explanation = match self.init {
+            InitSystem::Systemd => vec![
+                format!(
+                    "Remove `{}`",
+                    systemd_drop_in_dir(self.service_name.as_deref())
+                        .join(SYSTEMD_DROP_IN_NAME)
+                        .display()
+                ),
+                "Run `systemctl daemon-reload`".to_string(),
+            ],
+            InitSystem::Launchd => vec![format!(
+                "Remove `{LAUNCHD_KEEP_ALIVE}` from `{}` (if it was set by the installer)",
+                self.plist_path
+                    .as_ref()
+                    .expect("plist_path should be defined for launchd")
+                    .display()
+            )],
+            InitSystem::None | InitSystem::OpenRc => vec![],
+        };
+        vec![ActionDescription::new(
+            "Stop restarting the Nix daemon automatically on failure".to_string(),
+            explanation,
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        let mut errors = vec![];
+
+        match self.init {
+            InitSystem::Systemd => {
+                if let Some(create_drop_in) = &mut self.create_drop_in {
+                    if let Err(err) = create_drop_in.try_revert().await {
+                        errors.push(err);
+                    }
+                }
+                if let Some(create_drop_in_dir) = &mut self.create_drop_in_dir {
+                    if let Err(err) = create_drop_in_dir.try_revert().await {
+                        errors.push(err);
+                    }
+                }
+                if let Err(err) = daemon_reload().await.map_err(Self::error) {
+                    errors.push(err);
+                }
+            },
+            InitSystem::Launchd => {
+                if self.modified_plist {
+                    let plist_path = self
+                        .plist_path
+                        .clone()
+                        .expect("plist_path should be defined for launchd");
+                    // The plist itself is removed alongside the service, nothing to do if it's gone
+                    if plist_path.exists() {
+                        let result = read_plist(&plist_path).and_then(|mut plist| {
+                            if let Some(dict) = plist.as_dictionary_mut() {
+                                dict.remove(LAUNCHD_KEEP_ALIVE);
+                            }
+                            write_plist(&plist_path, &plist)
+                        });
+                        if let Err(err) = result {
+                            errors.push(Self::error(err));
+                        }
+                    }
+                }
+            },
+            InitSystem::None | InitSystem::OpenRc => (),
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else if errors.len() == 1 {
+            Err(errors
+                .into_iter()
+                .next()
+                .expect("Expected 1 len Vec to have at least 1 item"))
+        } else {
+            Err(Self::error(ActionErrorKind::MultipleChildren(errors)))
+        }
+    }
+}
+
+async fn daemon_reload() -> Result<(), ActionErrorKind> {
+    // Without a running systemd, the drop-in is picked up whenever it starts
+    if !Path::new("/run/systemd/system").exists() {
+        return Ok(());
+    }
+    execute_command(
+        Command::new("systemctl")
+            .process_group(0)
+            .arg("daemon-reload")
+            .stdin(std::process::Stdio::null()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Have launchd restart the service unless it exits successfully, `false` if `KeepAlive` is already set
+fn set_keep_alive(path: &Path, plist: &mut plist::Value) -> Result<bool, ActionErrorKind> {
+    let dict = plist
+        .as_dictionary_mut()
+        .ok_or_else(|| ConfigureDaemonRestartError::NotADictionary(path.into()))?;
+    if dict.contains_key(LAUNCHD_KEEP_ALIVE) {
+        return Ok(false);
+    }
+
+    let mut keep_alive = plist::Dictionary::new();
+    keep_alive.insert(LAUNCHD_SUCCESSFUL_EXIT.into(), plist::Value::Boolean(false));
+    dict.insert(
+        LAUNCHD_KEEP_ALIVE.into(),
+        plist::Value::Dictionary(keep_alive),
+    );
+    Ok(true)
+}
+
+fn read_plist(path: &Path) -> Result<plist::Value, ActionErrorKind> {
+    plist::Value::from_file(path)
+        .map_err(|e| ConfigureDaemonRestartError::Plist(path.into(), e).into())
+}
+
+fn write_plist(path: &Path, plist: &plist::Value) -> Result<(), ActionErrorKind> {
+    plist
+        .to_file_xml(path)
+        .map_err(|e| ConfigureDaemonRestartError::Plist(path.into(), e).into())
+}
+
+/// The drop-in directory of the systemd service, `nix-daemon.service` unless another is named
+fn systemd_drop_in_dir(service_name: Option<&str>) -> PathBuf {
+    Path::new(SYSTEMD_UNIT_DIR).join(format!(
+        "{}.d",
+        service_name.unwrap_or(SYSTEMD_DEFAULT_SERVICE)
+    ))
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigureDaemonRestartError {
+    #[error("Restarting the Nix daemon on failure requires an init system")]
+    InitNone,
+    #[error("Restarting the Nix daemon on failure with OpenRC is configured in its init script")]
+    OpenRc,
+    #[error("Restarting the Nix daemon on failure with launchd requires the daemon's plist and service name")]
+    MissingPlist,
+    #[error("Reading or writing plist `{0}`")]
+    Plist(PathBuf, #[source] plist::Error),
+    #[error("Plist `{0}` is not a dictionary")]
+    NotADictionary(PathBuf),
+}
+
+impl From<ConfigureDaemonRestartError> for ActionErrorKind {
+    fn from(val: ConfigureDaemonRestartError) -> Self {
+        ActionErrorKind::Custom(Box::new(val))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn systemd_drop_in() -> eyre::Result<()> {
+        let action = ConfigureDaemonRestart::plan(
+            InitSystem::Systemd,
+            None,
+            Some("nix-daemon-2.service".to_string()),
+        )
+        .await?;
+        let drop_in = &serde_json::to_value(action.inner())?["create_drop_in"]["action"];
+        assert_eq!(
+            drop_in["path"],
+            "/etc/systemd/system/nix-daemon-2.service.d/restart-on-failure.conf"
+        );
+        let content = drop_in["buf"].as_str().expect("the drop-in has content");
+        assert!(content.contains("\n[Service]\nRestart=on-failure\n"));
+        assert!(content.contains("\nRestartSec=5s\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn launchd_keep_alive() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let plist_path = temp_dir.path().join("org.nixos.nix-daemon.plist");
+        let mut service = plist::Dictionary::new();
+        service.insert("Label".into(), "org.nixos.nix-daemon".into());
+        write_plist(&plist_path, &plist::Value::Dictionary(service))?;
+
+        let mut plist = read_plist(&plist_path)?;
+        assert!(set_keep_alive(&plist_path, &mut plist)?);
+        write_plist(&plist_path, &plist)?;
+
+        let plist = read_plist(&plist_path)?;
+        let dict = plist.as_dictionary().expect("the plist is a dictionary");
+        assert_eq!(
+            dict.get("Label").and_then(plist::Value::as_string),
+            Some("org.nixos.nix-daemon")
+        );
+        let keep_alive = dict
+            .get(LAUNCHD_KEEP_ALIVE)
+            .and_then(plist::Value::as_dictionary)
+            .expect("`KeepAlive` is set");
+        assert_eq!(
+            keep_alive
+                .get(LAUNCHD_SUCCESSFUL_EXIT)
+                .and_then(plist::Value::as_boolean),
+            Some(false)
+        );
+
+        // Set already, so left as it is
+        let mut plist = plist;
+        assert!(!set_keep_alive(&plist_path, &mut plist)?);
+
+        let mut not_a_dict = plist::Value::Array(vec![]);
+        assert!(set_keep_alive(&plist_path, &mut not_a_dict).is_err());
+
+        Ok(())
+    }
+}
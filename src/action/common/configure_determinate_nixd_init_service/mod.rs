@@ -17,7 +17,7 @@ const LINUX_NIXD_DAEMON_DEST: &str = "/etc/systemd/system/nix-daemon.service";
 // Darwin
 pub(crate) const DARWIN_NIXD_DAEMON_DEST: &str =
     "/Library/LaunchDaemons/systems.determinate.nix-daemon.plist";
-const DARWIN_NIXD_SERVICE_NAME: &str = "systems.determinate.nix-daemon";
+pub(crate) const DARWIN_NIXD_SERVICE_NAME: &str = "systems.determinate.nix-daemon";
 
 /**
 Configure the init to run the Nix daemon
@@ -60,7 +60,7 @@ impl ConfigureDeterminateNixdInitService {
                 Some(DARWIN_NIXD_DAEMON_DEST.into())
             },
             InitSystem::Systemd => Some(LINUX_NIXD_DAEMON_DEST.into()),
-            InitSystem::None => None,
+            InitSystem::None | InitSystem::OpenRc => None,
         };
         let service_name: Option<String> = match init {
             InitSystem::Launchd => Some(DARWIN_NIXD_SERVICE_NAME.into()),
@@ -14,6 +14,8 @@ use crate::util::OnMissing;
 
 const TMPFILES_SRC: &str = "/nix/var/nix/profiles/default/lib/tmpfiles.d/nix-daemon.conf";
 const TMPFILES_DEST: &str = "/etc/tmpfiles.d/nix-daemon.conf";
+const DEFAULT_SERVICE_UNIT: &str = "nix-daemon.service";
+const LAUNCHD_LABEL: &str = "Label";
 
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct SocketFile {
@@ -121,6 +123,12 @@ impl ConfigureInitService {
             InitSystem::None => {
                 // Nothing here, no init system
             },
+            InitSystem::OpenRc => {
+                // OpenRC has no units to link, see `StartOpenrcService`
+                return Err(Self::error(
+                    ConfigureNixDaemonServiceError::InitNotSupported,
+                ));
+            },
         };
 
         Ok(Self {
@@ -147,7 +155,9 @@ impl Action for ConfigureInitService {
             InitSystem::Launchd => {
                 "Configure Nix daemon related settings with launchctl".to_string()
             },
-            InitSystem::None => "Leave the Nix daemon unconfigured".to_string(),
+            InitSystem::None | InitSystem::OpenRc => {
+                "Leave the Nix daemon unconfigured".to_string()
+            },
         }
     }
 
@@ -221,7 +231,7 @@ impl Action for ConfigureInitService {
                 }
                 vec.push(ActionDescription::new(self.tracing_synopsis(), explanation))
             },
-            InitSystem::None => (),
+            InitSystem::None | InitSystem::OpenRc => (),
         }
         vec
     }
@@ -257,6 +267,7 @@ impl Action for ConfigureInitService {
                                 e,
                             ))
                         })?;
+                    set_launchd_label(service_dest, service).map_err(Self::error)?;
                 }
 
                 crate::action::macos::retry_bootstrap(domain, service, service_dest)
@@ -305,18 +316,20 @@ impl Action for ConfigureInitService {
                     }
                 }
 
-                {
-                    let is_active = is_active("nix-daemon.service").await.map_err(Self::error)?;
+                let service_unit = service_unit_name(service_dest);
+                let mut service_units = vec![DEFAULT_SERVICE_UNIT.to_string()];
+                if service_unit != DEFAULT_SERVICE_UNIT {
+                    service_units.push(service_unit);
+                }
+                for service_unit in service_units.iter() {
+                    let is_active = is_active(service_unit).await.map_err(Self::error)?;
 
-                    if is_enabled("nix-daemon.service")
-                        .await
-                        .map_err(Self::error)?
-                    {
-                        disable("nix-daemon.service", is_active)
+                    if is_enabled(service_unit).await.map_err(Self::error)? {
+                        disable(service_unit, is_active)
                             .await
                             .map_err(Self::error)?;
                     } else if is_active {
-                        stop("nix-daemon.service").await.map_err(Self::error)?;
+                        stop(service_unit).await.map_err(Self::error)?;
                     };
                 }
 
@@ -422,7 +435,10 @@ impl Action for ConfigureInitService {
                     let enable_now = *start_daemon || any_socket_was_active;
 
                     match src {
-                        UnitSrc::Path(path) => {
+                        // A unit linked under another name can only be enabled by that name
+                        UnitSrc::Path(path)
+                            if path.file_name() == Some(std::ffi::OsStr::new(name)) =>
+                        {
                             // NOTE(cole-h): we have to enable by path here because older systemd's
                             // (e.g. on our Ubuntu 16.04 test VMs) had faulty (or too- strict)
                             // symlink detection, which causes the symlink chain of
@@ -434,13 +450,13 @@ impl Action for ConfigureInitService {
                                 .await
                                 .map_err(Self::error)?;
                         },
-                        UnitSrc::Literal(_) => {
+                        UnitSrc::Path(_) | UnitSrc::Literal(_) => {
                             enable(name, enable_now).await.map_err(Self::error)?;
                         },
                     }
                 }
             },
-            InitSystem::None => {
+            InitSystem::None | InitSystem::OpenRc => {
                 // Nothing here, no init system
             },
         };
@@ -483,7 +499,7 @@ impl Action for ConfigureInitService {
                     )],
                 )]
             },
-            InitSystem::None => Vec::new(),
+            InitSystem::None | InitSystem::OpenRc => Vec::new(),
         }
     }
 
@@ -556,17 +572,19 @@ impl Action for ConfigureInitService {
                         }
                     }
                 }
-                let service_is_active =
-                    is_active("nix-daemon.service").await.map_err(Self::error)?;
-                let service_is_enabled = is_enabled("nix-daemon.service")
-                    .await
-                    .map_err(Self::error)?;
+                let service_unit = service_unit_name(
+                    self.service_dest
+                        .as_ref()
+                        .expect("service_dest should be defined for systemd"),
+                );
+                let service_is_active = is_active(&service_unit).await.map_err(Self::error)?;
+                let service_is_enabled = is_enabled(&service_unit).await.map_err(Self::error)?;
 
                 if service_is_active {
                     if let Err(err) = execute_command(
                         Command::new("systemctl")
                             .process_group(0)
-                            .args(["stop", "nix-daemon.service"])
+                            .args(["stop", &service_unit])
                             .stdin(std::process::Stdio::null()),
                     )
                     .await
@@ -579,7 +597,7 @@ impl Action for ConfigureInitService {
                     if let Err(err) = execute_command(
                         Command::new("systemctl")
                             .process_group(0)
-                            .args(["disable", "nix-daemon.service"])
+                            .args(["disable", &service_unit])
                             .stdin(std::process::Stdio::null()),
                     )
                     .await
@@ -619,7 +637,7 @@ impl Action for ConfigureInitService {
                     errors.push(err);
                 }
             },
-            InitSystem::None => {
+            InitSystem::None | InitSystem::OpenRc => {
                 // Nothing here, no init
             },
         };
@@ -662,6 +680,41 @@ impl Action for ConfigureInitService {
 pub enum ConfigureNixDaemonServiceError {
     #[error("No supported init system found")]
     InitNotSupported,
+    #[error("Reading or writing plist `{0}`")]
+    Plist(PathBuf, #[source] plist::Error),
+    #[error("Plist `{0}` is not a dictionary")]
+    NotADictionary(PathBuf),
+}
+
+impl From<ConfigureNixDaemonServiceError> for ActionErrorKind {
+    fn from(val: ConfigureNixDaemonServiceError) -> Self {
+        ActionErrorKind::Custom(Box::new(val))
+    }
+}
+
+/// Make the plist's `Label` the service name it is bootstrapped as, if the service was renamed
+fn set_launchd_label(plist_path: &Path, label: &str) -> Result<(), ActionErrorKind> {
+    let mut plist = plist::Value::from_file(plist_path)
+        .map_err(|e| ConfigureNixDaemonServiceError::Plist(plist_path.into(), e))?;
+    let dict = plist
+        .as_dictionary_mut()
+        .ok_or_else(|| ConfigureNixDaemonServiceError::NotADictionary(plist_path.into()))?;
+    if dict.get(LAUNCHD_LABEL).and_then(|value| value.as_string()) == Some(label) {
+        return Ok(());
+    }
+    dict.insert(LAUNCHD_LABEL.into(), plist::Value::String(label.into()));
+    plist
+        .to_file_xml(plist_path)
+        .map_err(|e| ConfigureNixDaemonServiceError::Plist(plist_path.into(), e))?;
+    Ok(())
+}
+
+/// The name systemd knows the service by, which is the name of the file linking it
+fn service_unit_name(service_dest: &Path) -> String {
+    service_dest
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| DEFAULT_SERVICE_UNIT.to_string())
 }
 
 async fn stop(unit: &str) -> Result<(), ActionErrorKind> {
@@ -755,3 +808,36 @@ async fn is_enabled(unit: &str) -> Result<bool, ActionErrorKind> {
         Ok(false)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn renamed_launchd_service_gets_its_label() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let plist_path = temp_dir.path().join("org.example.nix-daemon.plist");
+        let mut dict = plist::Dictionary::new();
+        dict.insert(
+            LAUNCHD_LABEL.into(),
+            plist::Value::String("org.nixos.nix-daemon".into()),
+        );
+        dict.insert("RunAtLoad".into(), plist::Value::Boolean(true));
+        plist::Value::Dictionary(dict).to_file_xml(&plist_path)?;
+
+        set_launchd_label(&plist_path, "org.example.nix-daemon")?;
+
+        let plist = plist::Value::from_file(&plist_path)?;
+        let dict = plist.as_dictionary().expect("the plist is a dictionary");
+        assert_eq!(
+            dict.get(LAUNCHD_LABEL).and_then(|label| label.as_string()),
+            Some("org.example.nix-daemon")
+        );
+        // The rest of the plist is kept
+        assert_eq!(
+            dict.get("RunAtLoad").and_then(|value| value.as_boolean()),
+            Some(true)
+        );
+        Ok(())
+    }
+}
@@ -7,7 +7,7 @@ use crate::{
         Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
     },
     planner::ShellProfileLocations,
-    settings::{CommonSettings, SCRATCH_DIR},
+    settings::{CommonSettings, Component, SCRATCH_DIR},
 };
 use glob::glob;
 
@@ -30,36 +30,38 @@ impl ConfigureNix {
         shell_profile_locations: ShellProfileLocations,
         settings: &CommonSettings,
     ) -> Result<StatefulAction<Self>, ActionError> {
-        let setup_default_profile = SetupDefaultProfile::plan(PathBuf::from(SCRATCH_DIR))
-            .await
-            .map_err(Self::error)?;
+        let setup_default_profile = SetupDefaultProfile::plan(
+            settings.rooted(SCRATCH_DIR),
+            settings.require_cacert,
+            settings.root.clone(),
+        )
+        .await
+        .map_err(Self::error)?;
 
-        let configure_shell_profile = if settings.modify_profile {
-            Some(
-                ConfigureShellProfile::plan(shell_profile_locations)
+        let configure_shell_profile =
+            if settings.modify_profile && settings.installs(Component::Profiles) {
+                Some(
+                    ConfigureShellProfile::plan(
+                        shell_profile_locations,
+                        settings.backup_shell_profiles,
+                    )
                     .await
                     .map_err(Self::error)?,
-            )
-        } else {
-            None
-        };
+                )
+            } else {
+                None
+            };
 
-        let place_nix_configuration = if settings.skip_nix_conf {
-            None
-        } else {
-            Some(
-                PlaceNixConfiguration::plan(
-                    settings.nix_build_group_name.clone(),
-                    settings.proxy.clone(),
-                    settings.ssl_cert_file.clone(),
-                    settings.extra_conf.clone(),
-                    settings.force,
-                    settings.determinate_nix,
+        let place_nix_configuration =
+            if settings.skip_nix_conf || !settings.installs(Component::Config) {
+                None
+            } else {
+                Some(
+                    PlaceNixConfiguration::plan(settings)
+                        .await
+                        .map_err(Self::error)?,
                 )
-                .await
-                .map_err(Self::error)?,
-            )
-        };
+            };
 
         Ok(Self {
             place_nix_configuration,
@@ -69,9 +71,10 @@ impl ConfigureNix {
         .into())
     }
 
+    /// Find the `nix` and `nss-cacert` packages in an unpacked Nix tarball, which may lack the latter
     pub async fn find_nix_and_ca_cert(
         unpacked_path: &Path,
-    ) -> Result<(PathBuf, PathBuf), ActionError> {
+    ) -> Result<(PathBuf, Option<PathBuf>), ActionError> {
         // Find a `nix` package
         let nix_pkg_glob = format!("{}/nix-*/store/*-nix-*.*.*/bin", unpacked_path.display());
         let mut found_nix_pkg = None;
@@ -117,12 +120,14 @@ impl ConfigureNix {
             };
         }
         let nss_ca_cert_pkg = if let Some(nss_ca_cert_pkg) = found_nss_ca_cert_pkg {
-            tokio::fs::read_link(&nss_ca_cert_pkg)
-                .await
-                .map_err(|e| ActionErrorKind::ReadSymlink(nss_ca_cert_pkg, e))
-                .map_err(Self::error)?
+            Some(
+                tokio::fs::read_link(&nss_ca_cert_pkg)
+                    .await
+                    .map_err(|e| ActionErrorKind::ReadSymlink(nss_ca_cert_pkg, e))
+                    .map_err(Self::error)?,
+            )
         } else {
-            return Err(Self::error(ConfigureNixError::NoNssCacert));
+            None
         };
 
         Ok((nix_pkg, nss_ca_cert_pkg))
@@ -240,7 +245,7 @@ impl Action for ConfigureNix {
 #[non_exhaustive]
 #[derive(Debug, thiserror::Error)]
 pub enum ConfigureNixError {
-    #[error("Unarchived Nix store did not appear to include a `nss-cacert` location")]
+    #[error("Unarchived Nix store did not appear to include a `nss-cacert` location, pass `--no-require-cacert` to install without it")]
     NoNssCacert,
     #[error("Unarchived Nix store did not appear to include a `nix` location")]
     NoNix,
@@ -255,3 +260,59 @@ impl From<ConfigureNixError> for ActionErrorKind {
         ActionErrorKind::Custom(Box::new(val))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::ConfigureNix;
+    use crate::{
+        planner::ShellProfileLocations,
+        settings::{CommonSettings, Component},
+    };
+
+    #[tokio::test]
+    async fn excluded_components_are_not_planned() -> eyre::Result<()> {
+        let root = tempfile::tempdir()?;
+        let settings = CommonSettings::builder()
+            .await?
+            .root(root.path())
+            .components(vec![Component::Store])
+            .build()?;
+
+        let action = ConfigureNix::plan(ShellProfileLocations::default(), &settings).await?;
+        assert!(action.inner().configure_shell_profile.is_none());
+        assert!(action.inner().place_nix_configuration.is_none());
+
+        let settings = CommonSettings::builder()
+            .await?
+            .root(root.path())
+            .components(vec![Component::Store, Component::Config])
+            .build()?;
+        let action = ConfigureNix::plan(ShellProfileLocations::default(), &settings).await?;
+        assert!(action.inner().configure_shell_profile.is_none());
+        assert!(action.inner().place_nix_configuration.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn nss_cacert_is_optional() -> eyre::Result<()> {
+        let unpacked = tempfile::tempdir()?;
+        let store = unpacked.path().join("nix-2.24.0-x86_64-linux/store");
+        let nix_pkg = unpacked.path().join("real-nix");
+        std::fs::create_dir_all(nix_pkg.join("bin"))?;
+        std::fs::create_dir_all(&store)?;
+        std::os::unix::fs::symlink(&nix_pkg, store.join("aaaa-nix-2.24.0"))?;
+
+        let (found_nix, found_cacert) = ConfigureNix::find_nix_and_ca_cert(unpacked.path()).await?;
+        assert_eq!(found_nix, nix_pkg);
+        assert_eq!(found_cacert, None);
+
+        let cacert_pkg = unpacked.path().join("real-nss-cacert");
+        std::fs::create_dir_all(&cacert_pkg)?;
+        std::os::unix::fs::symlink(&cacert_pkg, store.join("bbbb-nss-cacert-3.98"))?;
+        let (_, found_cacert) = ConfigureNix::find_nix_and_ca_cert(unpacked.path()).await?;
+        assert_eq!(found_cacert, Some(cacert_pkg));
+
+        Ok(())
+    }
+}
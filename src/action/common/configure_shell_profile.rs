@@ -27,6 +27,7 @@ impl ConfigureShellProfile {
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn plan(
         locations: ShellProfileLocations,
+        backup: bool,
     ) -> Result<StatefulAction<Self>, ActionError> {
         let mut create_or_insert_files = Vec::default();
         let mut create_directories = Vec::default();
@@ -37,7 +38,7 @@ impl ConfigureShellProfile {
             if [ -e '{PROFILE_NIX_FILE_SHELL}' ]; then\n\
             {inde}. '{PROFILE_NIX_FILE_SHELL}'\n\
             fi\n\
-            # End Nix\n
+            # End Nix\n\
         \n",
             inde = "    ", // indent
         );
@@ -63,6 +64,7 @@ impl ConfigureShellProfile {
                             0o644,
                             shell_buf.to_string(),
                             create_or_insert_into_file::Position::Beginning,
+                            backup,
                         )
                         .await
                         .map_err(Self::error)?,
@@ -110,6 +112,7 @@ impl ConfigureShellProfile {
                         0o644,
                         fish_buf.to_string(),
                         create_or_insert_into_file::Position::Beginning,
+                        backup,
                     )
                     .await?,
                 );
@@ -140,6 +143,7 @@ impl ConfigureShellProfile {
                     0o644,
                     fish_buf.to_string(),
                     create_or_insert_into_file::Position::Beginning,
+                    backup,
                 )
                 .await?,
             );
@@ -167,6 +171,7 @@ impl ConfigureShellProfile {
                     0o777,
                     buf,
                     create_or_insert_into_file::Position::End,
+                    false,
                 )
                 .await?,
             );
@@ -221,8 +226,8 @@ impl Action for ConfigureShellProfile {
                     .try_execute()
                     .instrument(span)
                     .await
-                    .map_err(Self::error)?;
-                Result::<_, ActionError>::Ok((idx, create_or_insert_into_file_clone))
+                    .map_err(|e| (idx, Self::error(e)))?;
+                Result::<_, (usize, ActionError)>::Ok((idx, create_or_insert_into_file_clone))
             });
         }
 
@@ -235,6 +240,9 @@ impl Action for ConfigureShellProfile {
                 Err(e) => return Err(Self::error(e))?,
             };
         }
+        // Tasks finish in any order, report failures in the order the profiles were planned
+        errors.sort_by_key(|(idx, _)| *idx);
+        let errors = errors.into_iter().map(|(_, e)| e).collect::<Vec<_>>();
 
         if !errors.is_empty() {
             if errors.len() == 1 {
@@ -266,21 +274,27 @@ impl Action for ConfigureShellProfile {
         {
             let mut create_or_insert_file_clone = create_or_insert_into_file.clone();
             let _abort_handle = set.spawn(async move {
-                create_or_insert_file_clone.try_revert().await?;
-                Result::<_, _>::Ok((idx, create_or_insert_file_clone))
+                create_or_insert_file_clone
+                    .try_revert()
+                    .await
+                    .map_err(|e| (idx, e))?;
+                Result::<_, (usize, ActionError)>::Ok((idx, create_or_insert_file_clone))
             });
         }
 
+        let mut file_errors = vec![];
         while let Some(result) = set.join_next().await {
             match result {
                 Ok(Ok((idx, create_or_insert_into_file))) => {
                     self.create_or_insert_into_files[idx] = create_or_insert_into_file
                 },
-                Ok(Err(e)) => errors.push(e),
+                Ok(Err(e)) => file_errors.push(e),
                 // This is quite rare and generally a very bad sign.
                 Err(e) => return Err(e).map_err(|e| Self::error(ActionErrorKind::from(e)))?,
             };
         }
+        file_errors.sort_by_key(|(idx, _)| *idx);
+        errors.extend(file_errors.into_iter().map(|(_, e)| e));
 
         for create_directory in self.create_directories.iter_mut() {
             if let Err(err) = create_directory.try_revert().await {
@@ -300,3 +314,88 @@ impl Action for ConfigureShellProfile {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::ConfigureShellProfile;
+    use crate::action::ActionErrorKind;
+    use crate::planner::{FishShellProfileLocations, ShellProfileLocations};
+
+    #[tokio::test]
+    async fn failures_are_reported_in_profile_order() -> eyre::Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let targets = ["a", "b", "c"]
+            .iter()
+            .map(|name| temp_dir.path().join(name).join("bashrc"))
+            .collect::<Vec<_>>();
+        for target in &targets {
+            std::fs::create_dir_all(target.parent().expect("Has a parent"))?;
+        }
+
+        let locations = ShellProfileLocations {
+            fish: FishShellProfileLocations {
+                confd_prefixes: vec![],
+                vendor_confd_prefixes: vec![],
+                ..Default::default()
+            },
+            bash: targets.clone(),
+            zsh: vec![],
+        };
+        let mut action = ConfigureShellProfile::plan(locations, false).await?;
+
+        // A directory where the profile is expected can't be written to
+        std::fs::create_dir(&targets[0])?;
+        std::fs::create_dir(&targets[2])?;
+
+        let err = action
+            .try_execute()
+            .await
+            .expect_err("Two profiles can't be written");
+        let ActionErrorKind::MultipleChildren(errors) = err.kind() else {
+            panic!("Expected multiple errors, got {err:?}");
+        };
+        let reported = errors.iter().map(|e| format!("{e:?}")).collect::<Vec<_>>();
+        assert_eq!(reported.len(), 2);
+        assert!(reported[0].contains(&*targets[0].to_string_lossy()));
+        assert!(reported[1].contains(&*targets[2].to_string_lossy()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fish_only_configured_where_fish_exists() -> eyre::Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let fish_prefix = temp_dir.path().join("etc/fish");
+        std::fs::create_dir_all(&fish_prefix)?;
+        let missing_prefix = temp_dir.path().join("opt/fish");
+
+        let locations = ShellProfileLocations {
+            fish: FishShellProfileLocations {
+                confd_prefixes: vec![fish_prefix.clone(), missing_prefix.clone()],
+                vendor_confd_prefixes: vec![],
+                ..Default::default()
+            },
+            bash: vec![],
+            zsh: vec![],
+        };
+        let action = ConfigureShellProfile::plan(locations, false).await?;
+
+        let files = action
+            .inner()
+            .create_or_insert_into_files
+            .iter()
+            .map(|file| serde_json::to_value(file.inner()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let targets = files
+            .iter()
+            .map(|file| file["path"].as_str().unwrap_or_default())
+            .collect::<Vec<_>>();
+        let expected = fish_prefix.join("conf.d/nix.fish");
+        assert_eq!(targets, vec![&*expected.to_string_lossy()]);
+
+        let fish_buf = files[0]["buf"].as_str().unwrap_or_default();
+        assert!(fish_buf.contains(&format!("if test -e '{}'", super::PROFILE_NIX_FILE_FISH)));
+
+        Ok(())
+    }
+}
@@ -11,16 +11,25 @@ use crate::util::OnMissing;
 
 // Linux
 const SERVICE_SRC: &str = "/nix/var/nix/profiles/default/lib/systemd/system/nix-daemon.service";
-const SERVICE_DEST: &str = "/etc/systemd/system/nix-daemon.service";
+const SOCKET_SRC: &str = "/nix/var/nix/profiles/default/lib/systemd/system/nix-daemon.socket";
+const SYSTEMD_UNIT_DIR: &str = "/etc/systemd/system";
 
 // Darwin
 const DARWIN_NIX_DAEMON_SOURCE: &str =
     "/nix/var/nix/profiles/default/Library/LaunchDaemons/org.nixos.nix-daemon.plist";
 pub(crate) const DARWIN_NIX_DAEMON_DEST: &str = "/Library/LaunchDaemons/org.nixos.nix-daemon.plist";
-const DARWIN_LAUNCHD_SERVICE_NAME: &str = "org.nixos.nix-daemon";
+pub(crate) const DARWIN_LAUNCHD_SERVICE_NAME: &str = "org.nixos.nix-daemon";
+
+/// Where the plist of the daemon labelled `label` is placed
+pub(crate) fn darwin_nix_daemon_dest(label: &str) -> PathBuf {
+    Path::new("/Library/LaunchDaemons").join(format!("{label}.plist"))
+}
 
 /**
 Configure the init to run the Nix daemon
+
+On systemd, the units are linked as `{unit_name}.service` and `{unit_name}.socket`. On launchd,
+`unit_name` is the daemon's label, and its plist is placed as `/Library/LaunchDaemons/{unit_name}.plist`.
 */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 #[serde(tag = "action_name", rename = "create_upstream_init_service")]
@@ -33,11 +42,12 @@ impl ConfigureUpstreamInitService {
     pub async fn plan(
         init: InitSystem,
         start_daemon: bool,
+        unit_name: &str,
     ) -> Result<StatefulAction<Self>, ActionError> {
         let service_src: Option<PathBuf> = match init {
             InitSystem::Launchd => Some(DARWIN_NIX_DAEMON_SOURCE.into()),
             InitSystem::Systemd => Some(SERVICE_SRC.into()),
-            InitSystem::None => None,
+            InitSystem::None | InitSystem::OpenRc => None,
         };
         let service_dest: Option<PathBuf> = match init {
             InitSystem::Launchd => {
@@ -61,13 +71,15 @@ impl ConfigureUpstreamInitService {
                     ))
                 })?;
 
-                Some(DARWIN_NIX_DAEMON_DEST.into())
+                Some(darwin_nix_daemon_dest(unit_name))
             },
-            InitSystem::Systemd => Some(SERVICE_DEST.into()),
-            InitSystem::None => None,
+            InitSystem::Systemd => {
+                Some(Path::new(SYSTEMD_UNIT_DIR).join(format!("{unit_name}.service")))
+            },
+            InitSystem::None | InitSystem::OpenRc => None,
         };
         let service_name: Option<String> = match init {
-            InitSystem::Launchd => Some(DARWIN_LAUNCHD_SERVICE_NAME.into()),
+            InitSystem::Launchd => Some(unit_name.into()),
             _ => None,
         };
 
@@ -78,11 +90,9 @@ impl ConfigureUpstreamInitService {
             service_dest,
             service_name,
             vec![SocketFile {
-                name: "nix-daemon.socket".into(),
-                src: UnitSrc::Path(
-                    "/nix/var/nix/profiles/default/lib/systemd/system/nix-daemon.socket".into(),
-                ),
-                dest: "/etc/systemd/system/nix-daemon.socket".into(),
+                name: format!("{unit_name}.socket"),
+                src: UnitSrc::Path(SOCKET_SRC.into()),
+                dest: Path::new(SYSTEMD_UNIT_DIR).join(format!("{unit_name}.socket")),
             }],
         )
         .await
@@ -140,3 +150,29 @@ impl Action for ConfigureUpstreamInitService {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn launchd_label_names_the_plist() -> eyre::Result<()> {
+        let action = ConfigureUpstreamInitService::plan(
+            InitSystem::Launchd,
+            false,
+            "org.example.nix-daemon",
+        )
+        .await?;
+        let configure_init_service =
+            &serde_json::to_value(action.inner())?["configure_init_service"]["action"];
+        assert_eq!(
+            configure_init_service["service_dest"],
+            "/Library/LaunchDaemons/org.example.nix-daemon.plist"
+        );
+        assert_eq!(
+            configure_init_service["service_name"],
+            "org.example.nix-daemon"
+        );
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
 
 use tracing::{span, Span};
 
@@ -6,6 +7,7 @@ use crate::action::base::CreateDirectory;
 use crate::action::{
     Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
 };
+use crate::settings::rooted;
 
 const PATHS: &[&str] = &[
     "/nix/var",
@@ -24,28 +26,34 @@ const PATHS: &[&str] = &[
 ];
 
 /**
-Create the `/nix` tree
+Create the `/nix` tree, under `root` if installing into another root
  */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 #[serde(tag = "action_name", rename = "create_nix_tree")]
 pub struct CreateNixTree {
     create_directories: Vec<StatefulAction<CreateDirectory>>,
+    #[serde(default)]
+    root: Option<PathBuf>,
 }
 
 impl CreateNixTree {
     #[tracing::instrument(level = "debug", skip_all)]
-    pub async fn plan() -> Result<StatefulAction<Self>, ActionError> {
+    pub async fn plan(root: Option<&Path>) -> Result<StatefulAction<Self>, ActionError> {
         let mut create_directories = Vec::default();
         for path in PATHS {
             // We use `create_dir` over `create_dir_all` to ensure we always set permissions right
             create_directories.push(
-                CreateDirectory::plan(path, None, None, 0o0755, true)
+                CreateDirectory::plan(rooted(root, path), None, None, 0o0755, true)
                     .await
                     .map_err(Self::error)?,
             )
         }
 
-        Ok(Self { create_directories }.into())
+        Ok(Self {
+            create_directories,
+            root: root.map(Path::to_path_buf),
+        }
+        .into())
     }
 }
 
@@ -64,7 +72,10 @@ impl Action for CreateNixTree {
     }
 
     fn execute_description(&self) -> Vec<ActionDescription> {
-        let Self { create_directories } = &self;
+        let Self {
+            create_directories,
+            root: _,
+        } = &self;
 
         let mut create_directory_descriptions = Vec::new();
         for create_directory in create_directories {
@@ -90,7 +101,9 @@ impl Action for CreateNixTree {
             create_directory.try_execute().await.map_err(Self::error)?;
         }
 
-        ensure_nix_var_ownership().await.map_err(Self::error)?;
+        ensure_nix_var_ownership(&rooted(self.root.as_deref(), "/nix/var"))
+            .await
+            .map_err(Self::error)?;
 
         Ok(())
     }
@@ -107,7 +120,7 @@ impl Action for CreateNixTree {
                     PATHS
                         .iter()
                         .rev()
-                        .map(|v| format!("`{v}`"))
+                        .map(|v| format!("`{}`", rooted(self.root.as_deref(), v).display()))
                         .collect::<Vec<_>>()
                         .join(", ")
                 ),
@@ -143,9 +156,12 @@ impl Action for CreateNixTree {
 /// * /nix/var/nix/profiles/per-user/*
 /// * /nix/var/nix/gcroots/per-user/*
 ///
-/// This function walks /nix/var and makes sure that is true.
-async fn ensure_nix_var_ownership() -> Result<(), ActionErrorKind> {
-    let entryiter = walkdir::WalkDir::new("/nix/var")
+/// This function walks /nix/var (given as `nix_var`, which differs under another root) and makes
+/// sure that is true.
+async fn ensure_nix_var_ownership(nix_var: &Path) -> Result<(), ActionErrorKind> {
+    let per_user_profiles = nix_var.join("nix/profiles/per-user");
+    let per_user_gcroots = nix_var.join("nix/gcroots/per-user");
+    let entryiter = walkdir::WalkDir::new(nix_var)
         .follow_links(false)
         .same_file_system(true)
         .contents_first(true)
@@ -153,8 +169,8 @@ async fn ensure_nix_var_ownership() -> Result<(), ActionErrorKind> {
         .filter_entry(|entry| {
             let parent = entry.path().parent();
 
-            if parent == Some(std::path::Path::new("/nix/var/nix/profiles/per-user"))
-                || parent == Some(std::path::Path::new("/nix/var/nix/gcroots/per-user"))
+            if parent == Some(per_user_profiles.as_path())
+                || parent == Some(per_user_gcroots.as_path())
             {
                 // False means do *not* descend into this directory
                 // ...which we don't want to do, because the per-user subdirectories are usually owned by that user.
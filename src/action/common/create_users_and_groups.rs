@@ -1,10 +1,12 @@
 use crate::{
     action::{
         base::{AddUserToGroup, CreateGroup, CreateUser},
-        Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+        Action, ActionDescription, ActionError, ActionErrorKind, ActionState, ActionTag,
+        StatefulAction,
     },
     settings::CommonSettings,
 };
+use nix::unistd::{Uid, User};
 use tracing::{span, Span};
 
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
@@ -23,6 +25,19 @@ pub struct CreateUsersAndGroups {
 impl CreateUsersAndGroups {
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn plan(settings: CommonSettings) -> Result<StatefulAction<Self>, ActionError> {
+        // Check every UID up front, so we don't create half the users before finding a conflict
+        check_uid_range(
+            &settings.nix_build_user_prefix,
+            settings.nix_build_user_id_base,
+            settings.nix_build_user_count,
+            |uid| {
+                User::from_uid(Uid::from_raw(uid))
+                    .map(|user| user.map(|user| user.name))
+                    .map_err(|e| ActionErrorKind::GettingUserId(uid.to_string(), e))
+            },
+        )
+        .map_err(Self::error)?;
+
         let create_group = CreateGroup::plan(
             settings.nix_build_group_name.clone(),
             settings.nix_build_group_id,
@@ -37,6 +52,8 @@ impl CreateUsersAndGroups {
                     settings.nix_build_group_name.clone(),
                     settings.nix_build_group_id,
                     format!("Nix build user {index}"),
+                    settings.nix_build_user_home.clone(),
+                    settings.nix_build_user_shell.clone(),
                     true,
                 )
                 .await
@@ -65,6 +82,58 @@ impl CreateUsersAndGroups {
         }
         .into())
     }
+
+    /// Re-plan the group and each build user recorded in this (usually receipt) action, returning only the steps which need to run again
+    ///
+    /// Users and groups which still exist are left untouched, so nothing is renumbered.
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan_repair(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, ActionError> {
+        let mut repairs = Vec::new();
+
+        let create_group = CreateGroup::plan(
+            self.create_group.action.name.clone(),
+            self.create_group.action.gid,
+        )?;
+        if create_group.state != ActionState::Completed {
+            repairs.push(create_group.boxed());
+        }
+
+        for create_user in &self.create_users {
+            let CreateUser {
+                name,
+                uid,
+                groupname,
+                gid,
+                comment,
+                home,
+                shell,
+            } = create_user.action.clone();
+            let create_user =
+                CreateUser::plan(name, uid, groupname, gid, comment, home, shell, true)
+                    .await
+                    .map_err(Self::error)?;
+            if create_user.state != ActionState::Completed {
+                repairs.push(create_user.boxed());
+            }
+        }
+
+        for add_user_to_group in &self.add_users_to_groups {
+            let AddUserToGroup {
+                name,
+                uid,
+                groupname,
+                gid,
+            } = add_user_to_group.action.clone();
+            let add_user_to_group = AddUserToGroup::plan(name, uid, groupname, gid)
+                .await
+                .map_err(Self::error)?;
+            if add_user_to_group.state != ActionState::Completed {
+                repairs.push(add_user_to_group.boxed());
+            }
+        }
+
+        Ok(repairs)
+    }
 }
 
 #[async_trait::async_trait]
@@ -161,14 +230,14 @@ impl Action for CreateUsersAndGroups {
                 patch: _,
             }
             | OperatingSystem::Darwin => {
-                for create_user in create_users.iter_mut() {
-                    create_user.try_execute().await.map_err(Self::error)?;
-                }
+                execute_or_revert_created(create_users)
+                    .await
+                    .map_err(Self::error)?;
             },
             _ => {
-                for create_user in create_users.iter_mut() {
-                    create_user.try_execute().await.map_err(Self::error)?;
-                }
+                execute_or_revert_created(create_users)
+                    .await
+                    .map_err(Self::error)?;
                 // While we may be tempted to do something like this, it can break on many older OSes like Ubuntu 18.04:
                 // ```
                 // useradd: cannot lock /etc/passwd; try again later.
@@ -289,3 +358,212 @@ impl Action for CreateUsersAndGroups {
         }
     }
 }
+
+/// Ensure the build user UIDs, `base + 1` through `base + count`, are either free or already belong to the matching build user
+fn check_uid_range(
+    prefix: &str,
+    base: u32,
+    count: u32,
+    lookup: impl Fn(u32) -> Result<Option<String>, ActionErrorKind>,
+) -> Result<(), ActionErrorKind> {
+    if count == 0 {
+        return Err(CreateUsersAndGroupsError::NoBuildUsers.into());
+    }
+    if base.checked_add(count).is_none() {
+        return Err(CreateUsersAndGroupsError::UidRangeOverflow { base, count }.into());
+    }
+
+    for index in 1..=count {
+        let uid = base + index;
+        let expected = format!("{prefix}{index}");
+        if let Some(existing) = lookup(uid)? {
+            if existing != expected {
+                return Err(CreateUsersAndGroupsError::UidInUse {
+                    uid,
+                    existing,
+                    expected,
+                }
+                .into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Execute `actions` in order, and if one fails, revert the ones this call completed
+///
+/// Reverting is best effort: failures are logged, and the original error is returned. Reverted
+/// actions are left uncompleted, so reverting the plan later does not revert them again.
+async fn execute_or_revert_created<A: Action>(
+    actions: &mut [StatefulAction<A>],
+) -> Result<(), ActionError> {
+    let mut completed = vec![];
+    for (index, action) in actions.iter_mut().enumerate() {
+        let was_completed = action.state == ActionState::Completed;
+        match action.try_execute().await {
+            Ok(()) if !was_completed && action.state == ActionState::Completed => {
+                completed.push(index)
+            },
+            Ok(()) => (),
+            Err(err) => {
+                for index in completed.into_iter().rev() {
+                    let action = &mut actions[index];
+                    if let Err(revert_err) = action.try_revert().await {
+                        tracing::warn!(
+                            error = ?revert_err,
+                            "Could not revert `{}` after a later one failed",
+                            action.tracing_synopsis()
+                        );
+                    }
+                }
+                return Err(err);
+            },
+        }
+    }
+    Ok(())
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum CreateUsersAndGroupsError {
+    #[error("At least one build user is required, consider increasing `--nix-build-user-count`")]
+    NoBuildUsers,
+    #[error("A base UID of {base} with {count} build users runs past the largest possible UID, consider a smaller `--nix-build-user-id-base` or `--nix-build-user-count`")]
+    UidRangeOverflow { base: u32, count: u32 },
+    #[error("UID {uid} is needed for build user `{expected}` but already belongs to `{existing}`, consider a different `--nix-build-user-id-base`")]
+    UidInUse {
+        uid: u32,
+        existing: String,
+        expected: String,
+    },
+}
+
+impl From<CreateUsersAndGroupsError> for ActionErrorKind {
+    fn from(val: CreateUsersAndGroupsError) -> Self {
+        ActionErrorKind::Custom(Box::new(val))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_uid_range, execute_or_revert_created, CreateUsersAndGroupsError};
+    use crate::action::{base::CreateDirectory, ActionErrorKind, ActionState};
+
+    fn uid_in_use(err: ActionErrorKind) -> Option<(u32, String)> {
+        match err {
+            ActionErrorKind::Custom(err) => match err.downcast_ref::<CreateUsersAndGroupsError>() {
+                Some(CreateUsersAndGroupsError::UidInUse { uid, existing, .. }) => {
+                    Some((*uid, existing.clone()))
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn uid_range_conflicts() {
+        let lookup = |uid| {
+            Ok(match uid {
+                301 => Some("nixbld1".to_string()),
+                303 => Some("postgres".to_string()),
+                305 => Some("nobody".to_string()),
+                _ => None,
+            })
+        };
+
+        // Existing build users with the expected UIDs are fine
+        assert!(check_uid_range("nixbld", 300, 2, lookup).is_ok());
+        assert_eq!(
+            uid_in_use(check_uid_range("nixbld", 300, 32, lookup).unwrap_err()),
+            Some((303, "postgres".to_string()))
+        );
+        assert!(check_uid_range("nixbld", 300, 0, lookup).is_err());
+        assert!(check_uid_range("nixbld", u32::MAX - 1, 2, lookup).is_err());
+    }
+
+    // macOS has no `root` group, its GID 0 is `wheel`
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn repair_plans_only_what_is_missing() -> eyre::Result<()> {
+        use super::CreateUsersAndGroups;
+        use crate::action::{
+            base::{AddUserToGroup, CreateUser},
+            StatefulAction,
+        };
+        use crate::settings::CommonSettings;
+
+        // `root` stands in for a build user and group which still exist
+        let settings = CommonSettings::builder()
+            .await?
+            .nix_build_group_name("root")
+            .nix_build_group_id(0)
+            .nix_build_user_prefix("nixbld-repair-test")
+            .nix_build_user_count(1)
+            .nix_build_user_id_base(31990)
+            .build()?;
+        let mut action = CreateUsersAndGroups::plan(settings).await?.action;
+        let existing = action.create_users[0].action.clone();
+        action
+            .create_users
+            .push(StatefulAction::completed(CreateUser {
+                name: "root".into(),
+                uid: 0,
+                gid: 0,
+                ..existing
+            }));
+        action
+            .add_users_to_groups
+            .push(StatefulAction::completed(AddUserToGroup {
+                name: "root".into(),
+                uid: 0,
+                groupname: "root".into(),
+                gid: 0,
+            }));
+
+        let repairs = action
+            .plan_repair()
+            .await?
+            .iter()
+            .map(|repair| serde_json::to_value(repair).map(|repair| repair["action"].clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let planned = repairs
+            .iter()
+            .map(|repair| (repair["action_name"].as_str(), repair["name"].as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            planned,
+            vec![
+                (Some("create_user"), Some("nixbld-repair-test1")),
+                (Some("add_user_to_group"), Some("nixbld-repair-test1")),
+            ]
+        );
+        Ok(())
+    }
+
+    // Creating users needs root, so directories stand in for them
+    #[tokio::test]
+    async fn reverts_created_when_one_fails() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let existing = temp_dir.path().join("existing");
+        let created = temp_dir.path().join("created");
+        let blocked = temp_dir.path().join("file/blocked");
+        std::fs::create_dir(&existing)?;
+        std::fs::write(temp_dir.path().join("file"), "")?;
+
+        let mut actions = vec![];
+        for path in [&existing, &created, &blocked] {
+            actions.push(CreateDirectory::plan(path, None, None, None, false).await?);
+        }
+        assert_eq!(actions[0].state, ActionState::Completed);
+
+        assert!(execute_or_revert_created(&mut actions).await.is_err());
+        // Only what this execution created is reverted
+        assert!(existing.exists());
+        assert_eq!(actions[0].state, ActionState::Completed);
+        assert!(!created.exists());
+        assert_eq!(actions[1].state, ActionState::Uncompleted);
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 //! [`Action`](crate::action::Action)s which only call other base plugins
 
+pub(crate) mod configure_daemon_restart;
 pub(crate) mod configure_determinate_nixd_init_service;
 pub(crate) mod configure_init_service;
 pub(crate) mod configure_nix;
@@ -11,15 +12,18 @@ pub(crate) mod delete_users;
 pub(crate) mod place_nix_configuration;
 pub(crate) mod provision_determinate_nixd;
 pub(crate) mod provision_nix;
+pub(crate) mod run_post_install;
 
+pub use configure_daemon_restart::{ConfigureDaemonRestart, ConfigureDaemonRestartError};
 pub use configure_determinate_nixd_init_service::ConfigureDeterminateNixdInitService;
 pub use configure_init_service::{ConfigureInitService, ConfigureNixDaemonServiceError};
 pub use configure_nix::ConfigureNix;
 pub use configure_shell_profile::ConfigureShellProfile;
 pub use configure_upstream_init_service::ConfigureUpstreamInitService;
 pub use create_nix_tree::CreateNixTree;
-pub use create_users_and_groups::CreateUsersAndGroups;
+pub use create_users_and_groups::{CreateUsersAndGroups, CreateUsersAndGroupsError};
 pub use delete_users::DeleteUsersInGroup;
 pub use place_nix_configuration::PlaceNixConfiguration;
 pub use provision_determinate_nixd::ProvisionDeterminateNixd;
 pub use provision_nix::ProvisionNix;
+pub use run_post_install::{PostInstallOutcome, RunPostInstall, NIX_DAEMON_PROFILE_SCRIPT};
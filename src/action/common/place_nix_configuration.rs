@@ -2,15 +2,17 @@ use tracing::{span, Span};
 use url::Url;
 
 use crate::action::base::create_or_merge_nix_config::{
-    CreateOrMergeNixConfigError, EXPERIMENTAL_FEATURES_CONF_NAME,
-    EXTRA_EXPERIMENTAL_FEATURES_CONF_NAME, TRUSTED_USERS_CONF_NAME,
+    CreateOrMergeNixConfigError, BUILDERS_USE_SUBSTITUTES_CONF_NAME, CORES_CONF_NAME,
+    EXPERIMENTAL_FEATURES_CONF_NAME, EXTRA_EXPERIMENTAL_FEATURES_CONF_NAME,
+    EXTRA_SUBSTITUTERS_CONF_NAME, EXTRA_TRUSTED_PUBLIC_KEYS_CONF_NAME, FLAKE_REGISTRY_CONF_NAME,
+    MAX_JOBS_CONF_NAME, STORE_CONF_NAME, TRUSTED_USERS_CONF_NAME,
 };
 use crate::action::base::{CreateDirectory, CreateOrMergeNixConfig};
 use crate::action::{
     Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
 };
 use crate::parse_ssl_cert;
-use crate::settings::UrlOrPathOrString;
+use crate::settings::{CommonSettings, NixConfSetting, UrlOrPathOrString};
 use std::path::PathBuf;
 
 pub const NIX_CONF_FOLDER: &str = "/etc/nix";
@@ -27,6 +29,152 @@ pub(crate) const CUSTOM_NIX_CONFIG_HEADER: &str = r#"# Written by https://github
 # The contents below are based on options specified at installation time.
 "#;
 
+/// Nix settings holding a space separated list, so a repeated `--nix-conf` adds to them
+///
+/// Their `extra-` prefixed forms, like any `extra-` setting, are lists too.
+const LIST_NIX_CONF_NAMES: &[&str] = &[
+    "allowed-impure-host-deps",
+    "allowed-uris",
+    "allowed-users",
+    "experimental-features",
+    "extra-platforms",
+    "hashed-mirrors",
+    "nix-path",
+    "plugin-files",
+    "sandbox-paths",
+    "secret-key-files",
+    "substituters",
+    "system-features",
+    "trusted-public-keys",
+    "trusted-substituters",
+    "trusted-users",
+];
+
+/// Nix settings which may be passed individually via `--nix-conf`, see `nix config show`
+///
+/// An `extra-` prefixed form of any of these is also accepted.
+pub(crate) const KNOWN_NIX_CONF_NAMES: &[&str] = &[
+    "accept-flake-config",
+    "allow-dirty",
+    "allow-import-from-derivation",
+    "allow-symlinked-store",
+    "allow-unsafe-native-code-during-evaluation",
+    "allowed-impure-host-deps",
+    "allowed-uris",
+    "always-allow-substitutes",
+    "auto-allocate-uids",
+    "auto-optimise-store",
+    "bash-prompt",
+    "bash-prompt-prefix",
+    "bash-prompt-suffix",
+    "build-dir",
+    "build-hook",
+    "build-poll-interval",
+    "build-users-group",
+    "builders",
+    "builders-use-substitutes",
+    "commit-lockfile-summary",
+    "compress-build-log",
+    "connect-timeout",
+    "cores",
+    "diff-hook",
+    "download-attempts",
+    "download-buffer-size",
+    "download-speed",
+    "eval-cache",
+    "experimental-features",
+    "fallback",
+    "filter-syscalls",
+    "flake-registry",
+    "fsync-metadata",
+    "gc-reserved-space",
+    "hashed-mirrors",
+    "http-connections",
+    "http2",
+    "id-count",
+    "ignore-try",
+    "impersonate-linux-26",
+    "keep-build-log",
+    "keep-derivations",
+    "keep-env-derivations",
+    "keep-failed",
+    "keep-going",
+    "keep-outputs",
+    "log-lines",
+    "max-build-log-size",
+    "max-free",
+    "max-jobs",
+    "max-silent-time",
+    "max-substitution-jobs",
+    "min-free",
+    "min-free-check-interval",
+    "narinfo-cache-negative-ttl",
+    "narinfo-cache-positive-ttl",
+    "netrc-file",
+    "nix-path",
+    "plugin-files",
+    "post-build-hook",
+    "pre-build-hook",
+    "preallocate-contents",
+    "print-missing",
+    "pure-eval",
+    "require-drop-supplementary-groups",
+    "require-sigs",
+    "restrict-eval",
+    "run-diff-hook",
+    "sandbox",
+    "sandbox-build-dir",
+    "sandbox-dev-shm-size",
+    "sandbox-fallback",
+    "sandbox-paths",
+    "secret-key-files",
+    "show-trace",
+    "ssl-cert-file",
+    "start-id",
+    "store",
+    "substitute",
+    "substituters",
+    "sync-before-registering",
+    "system",
+    "system-features",
+    "tarball-ttl",
+    "timeout",
+    "trace-function-calls",
+    "trace-verbose",
+    "trusted-public-keys",
+    "trusted-substituters",
+    "trusted-users",
+    "upgrade-nix-store-path-url",
+    "use-case-hack",
+    "use-cgroups",
+    "use-registries",
+    "use-sqlite-wal",
+    "use-xdg-base-directories",
+    "user-agent-suffix",
+    "warn-dirty",
+];
+
+/// Experimental features enabled unless `--experimental-features` is passed
+const DEFAULT_EXPERIMENTAL_FEATURES: &[&str] = &["nix-command", "flakes"];
+
+/// Experimental features, and the first Nix version which understands them
+///
+/// Features not listed here are always written, as there is no way to tell if they are supported.
+const EXPERIMENTAL_FEATURE_MIN_VERSIONS: &[(&str, (u64, u64, u64))] = &[
+    ("nix-command", (2, 4, 0)),
+    ("flakes", (2, 4, 0)),
+    ("ca-derivations", (2, 4, 0)),
+    ("recursive-nix", (2, 4, 0)),
+    ("impure-derivations", (2, 8, 0)),
+    ("fetch-closure", (2, 8, 0)),
+    ("repl-flake", (2, 9, 0)),
+    ("auto-allocate-uids", (2, 12, 0)),
+    ("cgroups", (2, 12, 0)),
+    ("daemon-trust-override", (2, 15, 0)),
+    ("dynamic-derivations", (2, 15, 0)),
+    ("verified-fetches", (2, 19, 0)),
+];
+
 /**
 Place the `/etc/nix/nix.conf` file
  */
@@ -40,15 +188,69 @@ pub struct PlaceNixConfiguration {
 
 impl PlaceNixConfiguration {
     #[tracing::instrument(level = "debug", skip_all)]
-    pub async fn plan(
-        nix_build_group_name: String,
-        proxy: Option<Url>,
-        ssl_cert_file: Option<PathBuf>,
-        extra_conf: Vec<UrlOrPathOrString>,
-        force: bool,
-        determinate_nix: bool,
-    ) -> Result<StatefulAction<Self>, ActionError> {
-        let extra_conf = Self::parse_extra_conf(proxy, ssl_cert_file.as_ref(), extra_conf).await?;
+    pub async fn plan(settings: &CommonSettings) -> Result<StatefulAction<Self>, ActionError> {
+        let CommonSettings {
+            nix_build_group_name,
+            proxy,
+            ssl_cert_file,
+            extra_conf,
+            extra_conf_file,
+            builders_use_substitutes,
+            nix_conf,
+            strict_nix_conf,
+            offline,
+            force,
+            determinate_nix,
+            auto_optimise_store,
+            experimental_features,
+            extra_experimental_features,
+            ..
+        } = settings.clone();
+        let nix_version = settings.nix_version_to_install();
+        settings.check_build_limits().map_err(Self::error)?;
+        settings.check_substituters().map_err(Self::error)?;
+
+        let mut extra_conf = extra_conf;
+        extra_conf.extend(Self::read_extra_conf_files(&extra_conf_file).await?);
+        let mut extra_conf =
+            Self::parse_extra_conf(proxy, ssl_cert_file.as_ref(), extra_conf).await?;
+        if let Some(builders_use_substitutes) = builders_use_substitutes {
+            extra_conf.settings_mut().insert(
+                BUILDERS_USE_SUBSTITUTES_CONF_NAME.to_string(),
+                builders_use_substitutes.to_string(),
+            );
+        }
+        if settings.relocated_nix_store() {
+            // Nix refuses a store reached through a symlink, unless told where it really is
+            extra_conf.settings_mut().insert(
+                STORE_CONF_NAME.to_string(),
+                format!(
+                    "local?real={}",
+                    settings.nix_store_path.join("store").display()
+                ),
+            );
+        }
+        Self::apply_build_limits(
+            &mut extra_conf,
+            settings.max_jobs.as_deref(),
+            settings.cores,
+        );
+        Self::apply_substituters(
+            &mut extra_conf,
+            &settings.extra_substituters,
+            &settings.trusted_public_keys,
+        );
+        Self::apply_nix_conf(&mut extra_conf, nix_conf, strict_nix_conf)?;
+        Self::apply_trusted_users(&mut extra_conf, &settings.trusted_users_to_add());
+        if let Some(nix_version) = &nix_version {
+            Self::retain_supported_experimental_features(&mut extra_conf, nix_version);
+        }
+        if offline {
+            // An empty registry disables fetching the global flake registry
+            extra_conf
+                .settings_mut()
+                .insert(FLAKE_REGISTRY_CONF_NAME.to_string(), String::new());
+        }
 
         let is_macos = matches!(
             target_lexicon::OperatingSystem::host(),
@@ -58,6 +260,9 @@ impl PlaceNixConfiguration {
         let configured_ssl_cert_file = if determinate_nix && is_macos {
             // On macOS, determinate-nixd will handle configuring the ssl-cert-file option for Nix
             None
+        } else if settings.root.is_some() {
+            // The certificate is only used to fetch Nix, it is not part of the other root
+            None
         } else {
             ssl_cert_file
         };
@@ -65,7 +270,25 @@ impl PlaceNixConfiguration {
         let standard_nix_config = if !determinate_nix {
             let maybe_trusted_users = extra_conf.settings().get(TRUSTED_USERS_CONF_NAME);
 
-            Some(Self::setup_standard_config(maybe_trusted_users).await?)
+            let experimental_features =
+                Self::experimental_features(experimental_features, extra_experimental_features);
+
+            let mut standard_nix_config = Self::setup_standard_config(
+                maybe_trusted_users,
+                auto_optimise_store,
+                &experimental_features,
+            )
+            .await?;
+            if offline {
+                // Both of these point at resources which can only be fetched over the network
+                let settings = standard_nix_config.settings_mut();
+                settings.shift_remove("extra-nix-path");
+                settings.shift_remove("upgrade-nix-store-path-url");
+            }
+            if let Some(nix_version) = &nix_version {
+                Self::retain_supported_experimental_features(&mut standard_nix_config, nix_version);
+            }
+            Some(standard_nix_config)
         } else {
             None
         };
@@ -77,18 +300,26 @@ impl PlaceNixConfiguration {
         )
         .await?;
 
-        let create_directory = CreateDirectory::plan(NIX_CONF_FOLDER, None, None, 0o0755, force)
-            .await
-            .map_err(Self::error)?;
+        let create_directory =
+            CreateDirectory::plan(settings.rooted(NIX_CONF_FOLDER), None, None, 0o0755, force)
+                .await
+                .map_err(Self::error)?;
 
         let create_or_merge_standard_nix_config =
             if let Some(standard_nix_config) = standard_nix_config {
                 Some(
                     CreateOrMergeNixConfig::plan(
-                        NIX_CONF,
+                        settings.rooted(NIX_CONF),
                         standard_nix_config,
                         NIX_CONFIG_HEADER.to_string(),
                         Some(NIX_CONFIG_FOOTER.to_string()),
+                        // An existing `nix.conf` may already enable some of the same features, or
+                        // trust some of the same users
+                        vec![
+                            EXTRA_EXPERIMENTAL_FEATURES_CONF_NAME.to_string(),
+                            TRUSTED_USERS_CONF_NAME.to_string(),
+                        ],
+                        settings.backup_nix_conf,
                     )
                     .await
                     .map_err(Self::error)?,
@@ -98,10 +329,12 @@ impl PlaceNixConfiguration {
             };
 
         let create_or_merge_custom_nix_config = CreateOrMergeNixConfig::plan(
-            CUSTOM_NIX_CONF,
+            settings.rooted(CUSTOM_NIX_CONF),
             custom_nix_config,
             CUSTOM_NIX_CONFIG_HEADER.to_string(),
             None,
+            vec![TRUSTED_USERS_CONF_NAME.to_string()],
+            settings.backup_nix_conf,
         )
         .await
         .map_err(Self::error)?;
@@ -114,21 +347,95 @@ impl PlaceNixConfiguration {
         .into())
     }
 
+    /// Where `nix.conf` is placed, which is under the root being installed into (if there is one)
+    fn nix_conf(&self) -> PathBuf {
+        self.create_directory.inner().path.join("nix.conf")
+    }
+
+    /// The configuration for a single-user install, which has no build users group or daemon
+    pub(crate) async fn single_user_config(
+        settings: &CommonSettings,
+    ) -> Result<nix_config_parser::NixConfig, ActionError> {
+        let mut extra_conf = settings.extra_conf.clone();
+        extra_conf.extend(Self::read_extra_conf_files(&settings.extra_conf_file).await?);
+        let mut extra_conf = Self::parse_extra_conf(
+            settings.proxy.clone(),
+            settings.ssl_cert_file.as_ref(),
+            extra_conf,
+        )
+        .await?;
+        settings.check_build_limits().map_err(Self::error)?;
+        settings.check_substituters().map_err(Self::error)?;
+        Self::apply_build_limits(
+            &mut extra_conf,
+            settings.max_jobs.as_deref(),
+            settings.cores,
+        );
+        Self::apply_substituters(
+            &mut extra_conf,
+            &settings.extra_substituters,
+            &settings.trusted_public_keys,
+        );
+        Self::apply_nix_conf(
+            &mut extra_conf,
+            settings.nix_conf.clone(),
+            settings.strict_nix_conf,
+        )?;
+        let mut nix_config = Self::setup_extra_config(
+            extra_conf,
+            crate::settings::DEFAULT_NIX_BUILD_USER_GROUP_NAME.to_string(),
+            settings.ssl_cert_file.as_ref(),
+        )
+        .await?;
+
+        let nix_config_settings = nix_config.settings_mut();
+        let experimental_features = Self::experimental_features(
+            settings.experimental_features.clone(),
+            settings.extra_experimental_features.clone(),
+        );
+        let experimental_features =
+            match nix_config_settings.get(EXTRA_EXPERIMENTAL_FEATURES_CONF_NAME) {
+                Some(existing) => Self::experimental_features(
+                    Some(existing.split_whitespace().map(String::from).collect()),
+                    experimental_features,
+                ),
+                None => experimental_features,
+            };
+        if !experimental_features.is_empty() {
+            nix_config_settings.insert(
+                EXTRA_EXPERIMENTAL_FEATURES_CONF_NAME.to_string(),
+                experimental_features.join(" "),
+            );
+        }
+        // Nix run as `root` otherwise expects a `nixbld` group to build as
+        nix_config_settings.insert("build-users-group".to_string(), String::new());
+
+        if let Some(nix_version) = settings.nix_version_to_install() {
+            Self::retain_supported_experimental_features(&mut nix_config, &nix_version);
+        }
+
+        Ok(nix_config)
+    }
+
     async fn setup_standard_config(
         maybe_trusted_users: Option<&String>,
+        auto_optimise_store: bool,
+        experimental_features: &[String],
     ) -> Result<nix_config_parser::NixConfig, ActionError> {
         let mut nix_config = nix_config_parser::NixConfig::new();
         let settings = nix_config.settings_mut();
 
-        let experimental_features = ["nix-command", "flakes"];
-        settings.insert(
-            "extra-experimental-features".to_string(),
-            experimental_features.join(" "),
-        );
+        if !experimental_features.is_empty() {
+            settings.insert(
+                EXTRA_EXPERIMENTAL_FEATURES_CONF_NAME.to_string(),
+                experimental_features.join(" "),
+            );
+        }
 
         // https://github.com/DeterminateSystems/nix-installer/issues/449#issuecomment-1551782281
-        #[cfg(not(target_os = "macos"))]
-        settings.insert("auto-optimise-store".to_string(), "true".to_string());
+        if cfg!(not(target_os = "macos")) && auto_optimise_store {
+            settings.insert("auto-optimise-store".to_string(), "true".to_string());
+        }
 
         // https://github.com/NixOS/nix/pull/8047
         settings.insert("always-allow-substitutes".to_string(), "true".to_string());
@@ -198,6 +505,24 @@ impl PlaceNixConfiguration {
         Ok(nix_config)
     }
 
+    /// Read each `--extra-conf-file`, failing on the first which is not valid Nix configuration
+    async fn read_extra_conf_files(
+        extra_conf_files: &[PathBuf],
+    ) -> Result<Vec<UrlOrPathOrString>, ActionError> {
+        let mut extra_conf = Vec::with_capacity(extra_conf_files.len());
+        for path in extra_conf_files {
+            let contents = tokio::fs::read_to_string(path)
+                .await
+                .map_err(|e| ActionErrorKind::Read(path.clone(), e))
+                .map_err(Self::error)?;
+            nix_config_parser::NixConfig::parse_string(contents.clone(), Some(path))
+                .map_err(|e| PlaceNixConfigurationError::InvalidExtraConfFile(path.clone(), e))
+                .map_err(Self::error)?;
+            extra_conf.push(UrlOrPathOrString::String(contents));
+        }
+        Ok(extra_conf)
+    }
+
     async fn parse_extra_conf(
         proxy: Option<Url>,
         ssl_cert_file: Option<&PathBuf>,
@@ -263,6 +588,188 @@ impl PlaceNixConfiguration {
         Ok(nix_config)
     }
 
+    /// The experimental features to enable: `overridden` (or `nix-command` and `flakes`) followed by
+    /// `extra`, without duplicates
+    fn experimental_features(overridden: Option<Vec<String>>, extra: Vec<String>) -> Vec<String> {
+        let base = overridden.unwrap_or_else(|| {
+            DEFAULT_EXPERIMENTAL_FEATURES
+                .iter()
+                .map(ToString::to_string)
+                .collect()
+        });
+
+        let mut features: Vec<String> = vec![];
+        for feature in base
+            .iter()
+            .chain(extra.iter())
+            .flat_map(|f| f.split_whitespace())
+        {
+            if !features.iter().any(|existing| existing == feature) {
+                features.push(feature.to_string());
+            }
+        }
+        features
+    }
+
+    /// Drop any experimental features `nix_version` does not understand, which the daemon would reject
+    fn retain_supported_experimental_features(
+        nix_config: &mut nix_config_parser::NixConfig,
+        nix_version: &semver::Version,
+    ) {
+        for name in [
+            EXPERIMENTAL_FEATURES_CONF_NAME,
+            EXTRA_EXPERIMENTAL_FEATURES_CONF_NAME,
+        ] {
+            let Some(features) = nix_config.settings().get(name) else {
+                continue;
+            };
+
+            let (supported, unsupported): (Vec<&str>, Vec<&str>) =
+                features.split_whitespace().partition(|feature| {
+                    EXPERIMENTAL_FEATURE_MIN_VERSIONS
+                        .iter()
+                        .find(|(known, _)| known == feature)
+                        .is_none_or(|(_, (major, minor, patch))| {
+                            *nix_version >= semver::Version::new(*major, *minor, *patch)
+                        })
+                });
+            if unsupported.is_empty() {
+                continue;
+            }
+
+            tracing::warn!(
+                "Nix {nix_version} does not support the experimental feature(s) `{}`, not adding them to `{name}`",
+                unsupported.join(" ")
+            );
+            let supported = supported.join(" ");
+            if supported.is_empty() {
+                nix_config.settings_mut().shift_remove(name);
+            } else {
+                nix_config
+                    .settings_mut()
+                    .insert(name.to_string(), supported);
+            }
+        }
+    }
+
+    /// Merge the individual `--nix-conf` settings, checking each key against [`KNOWN_NIX_CONF_NAMES`]
+    ///
+    /// A list setting passed more than once gets every value, any other setting passed more than
+    /// once is refused rather than only keeping the last value.
+    fn apply_nix_conf(
+        extra_conf: &mut nix_config_parser::NixConfig,
+        nix_conf: Vec<NixConfSetting>,
+        strict_nix_conf: bool,
+    ) -> Result<(), ActionError> {
+        let mut seen: Vec<String> = vec![];
+        for NixConfSetting { name, value } in nix_conf {
+            let base_name = name.strip_prefix("extra-").unwrap_or(&name);
+            if !KNOWN_NIX_CONF_NAMES.contains(&base_name) {
+                if strict_nix_conf {
+                    return Err(Self::error(PlaceNixConfigurationError::UnknownSetting(
+                        name,
+                    )));
+                }
+                tracing::warn!("`{name}` is not a known Nix setting, setting it anyway");
+            }
+            if seen.contains(&name) {
+                if !(name.starts_with("extra-") || LIST_NIX_CONF_NAMES.contains(&base_name)) {
+                    return Err(Self::error(PlaceNixConfigurationError::RepeatedSetting(
+                        name,
+                    )));
+                }
+                let settings = extra_conf.settings_mut();
+                let mut values = settings
+                    .get(&name)
+                    .map(|existing| {
+                        existing
+                            .split_whitespace()
+                            .map(String::from)
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+                for value in value.split_whitespace() {
+                    if !values.iter().any(|existing| existing == value) {
+                        values.push(value.to_string());
+                    }
+                }
+                settings.insert(name, values.join(" "));
+                continue;
+            }
+            seen.push(name.clone());
+            extra_conf.settings_mut().insert(name, value);
+        }
+
+        Ok(())
+    }
+
+    /// Set `max-jobs` and `cores` for build machines, when given
+    fn apply_build_limits(
+        extra_conf: &mut nix_config_parser::NixConfig,
+        max_jobs: Option<&str>,
+        cores: Option<u32>,
+    ) {
+        let settings = extra_conf.settings_mut();
+        if let Some(max_jobs) = max_jobs {
+            settings.insert(MAX_JOBS_CONF_NAME.to_string(), max_jobs.to_string());
+        }
+        if let Some(cores) = cores {
+            settings.insert(CORES_CONF_NAME.to_string(), cores.to_string());
+        }
+    }
+
+    /// Add binary caches and their signing keys, after any already listed
+    ///
+    /// Both are the `extra-` settings, so the default cache and its key stay trusted.
+    fn apply_substituters(
+        extra_conf: &mut nix_config_parser::NixConfig,
+        substituters: &[String],
+        public_keys: &[String],
+    ) {
+        for (name, values) in [
+            (EXTRA_SUBSTITUTERS_CONF_NAME, substituters),
+            (EXTRA_TRUSTED_PUBLIC_KEYS_CONF_NAME, public_keys),
+        ] {
+            if values.is_empty() {
+                continue;
+            }
+            let settings = extra_conf.settings_mut();
+            let mut merged = settings
+                .get(name)
+                .map(|existing| {
+                    existing
+                        .split_whitespace()
+                        .map(String::from)
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            for value in values {
+                if !merged.contains(value) {
+                    merged.push(value.clone());
+                }
+            }
+            settings.insert(name.to_string(), merged.join(" "));
+        }
+    }
+
+    /// Add `users` to the `trusted-users`, which otherwise only trusts `root`, skipping any already listed
+    fn apply_trusted_users(extra_conf: &mut nix_config_parser::NixConfig, users: &[String]) {
+        if users.is_empty() {
+            return;
+        }
+        let settings = extra_conf.settings_mut();
+        let mut trusted_users = settings
+            .get(TRUSTED_USERS_CONF_NAME)
+            .map(|existing| existing.split_whitespace().map(String::from).collect())
+            .unwrap_or_else(|| vec!["root".to_string()]);
+        for user in users {
+            if !trusted_users.contains(user) {
+                trusted_users.push(user.clone());
+            }
+        }
+        settings.insert(TRUSTED_USERS_CONF_NAME.to_string(), trusted_users.join(" "));
+    }
+
     async fn setup_extra_config(
         mut extra_conf: nix_config_parser::NixConfig,
         nix_build_group_name: String,
@@ -317,7 +824,10 @@ impl Action for PlaceNixConfiguration {
         ActionTag("place_nix_configuration")
     }
     fn tracing_synopsis(&self) -> String {
-        format!("Place the Nix configuration in `{NIX_CONF}`")
+        format!(
+            "Place the Nix configuration in `{}`",
+            self.nix_conf().display()
+        )
     }
 
     fn tracing_span(&self) -> Span {
@@ -385,7 +895,10 @@ impl Action for PlaceNixConfiguration {
 
     fn revert_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
-            format!("Remove the Nix configuration in `{NIX_CONF}`"),
+            format!(
+                "Remove the Nix configuration in `{}`",
+                self.nix_conf().display()
+            ),
             vec![
                 "This file is read by the Nix daemon to set its configuration options at runtime."
                     .to_string(),
@@ -423,10 +936,92 @@ impl Action for PlaceNixConfiguration {
     }
 }
 
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum PlaceNixConfigurationError {
+    #[error("`{0}` is not a known Nix setting, pass `--nix-conf` without `--strict-nix-conf` to set it anyway")]
+    UnknownSetting(String),
+    #[error("`--nix-conf {0}=...` was passed more than once, but `{0}` is not a list, so only one value can be kept")]
+    RepeatedSetting(String),
+    #[error("`{}` is not valid Nix configuration", .0.display())]
+    InvalidExtraConfFile(PathBuf, #[source] nix_config_parser::ParseError),
+}
+
+impl From<PlaceNixConfigurationError> for ActionErrorKind {
+    fn from(val: PlaceNixConfigurationError) -> Self {
+        ActionErrorKind::Custom(Box::new(val))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn default_features() -> Vec<String> {
+        PlaceNixConfiguration::experimental_features(None, vec![])
+    }
+
+    #[test]
+    fn experimental_features_are_merged_and_deduplicated() {
+        assert_eq!(default_features(), vec!["nix-command", "flakes"]);
+        assert_eq!(
+            PlaceNixConfiguration::experimental_features(
+                None,
+                vec![
+                    "ca-derivations".into(),
+                    "flakes".into(),
+                    "ca-derivations".into()
+                ]
+            ),
+            vec!["nix-command", "flakes", "ca-derivations"]
+        );
+        assert_eq!(
+            PlaceNixConfiguration::experimental_features(
+                Some(vec!["nix-command".into()]),
+                vec!["nix-command recursive-nix".into()]
+            ),
+            vec!["nix-command", "recursive-nix"]
+        );
+        assert!(PlaceNixConfiguration::experimental_features(Some(vec![]), vec![]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn experimental_features_merge_with_existing_subset() -> eyre::Result<()> {
+        let nix_conf_dir = tempfile::tempdir()?;
+        let nix_conf_path = nix_conf_dir.path().join("nix.conf");
+        tokio::fs::write(
+            &nix_conf_path,
+            "extra-experimental-features = nix-command\n",
+        )
+        .await?;
+
+        let features =
+            PlaceNixConfiguration::experimental_features(None, vec!["ca-derivations".into()]);
+        let standard_nix_config =
+            PlaceNixConfiguration::setup_standard_config(None, true, &features).await?;
+        let mut create_or_merge = CreateOrMergeNixConfig::plan(
+            &nix_conf_path,
+            standard_nix_config,
+            NIX_CONFIG_HEADER.to_string(),
+            Some(NIX_CONFIG_FOOTER.to_string()),
+            vec![EXTRA_EXPERIMENTAL_FEATURES_CONF_NAME.to_string()],
+            false,
+        )
+        .await?;
+        create_or_merge.try_execute().await?;
+
+        let nix_conf = nix_config_parser::NixConfig::parse_file(&nix_conf_path)?;
+        assert_eq!(
+            nix_conf
+                .settings()
+                .get(EXTRA_EXPERIMENTAL_FEATURES_CONF_NAME)
+                .map(String::as_str),
+            Some("nix-command flakes ca-derivations")
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn extra_trusted_cache() -> eyre::Result<()> {
         let extra_conf = PlaceNixConfiguration::parse_extra_conf(
@@ -464,6 +1059,30 @@ mod tests {
         Ok(())
     }
 
+    #[cfg(not(target_os = "macos"))]
+    #[tokio::test]
+    async fn auto_optimise_store_can_be_disabled() -> eyre::Result<()> {
+        let enabled =
+            PlaceNixConfiguration::setup_standard_config(None, true, &default_features()).await?;
+        assert_eq!(
+            enabled
+                .settings()
+                .get("auto-optimise-store")
+                .map(String::as_str),
+            Some("true")
+        );
+
+        let disabled =
+            PlaceNixConfiguration::setup_standard_config(None, false, &default_features()).await?;
+        assert!(disabled.settings().get("auto-optimise-store").is_none());
+        assert_eq!(
+            disabled.settings().get("extra-experimental-features"),
+            enabled.settings().get("extra-experimental-features")
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn experimental_features() -> eyre::Result<()> {
         let nix_conf_dir = tempfile::tempdir()?;
@@ -479,7 +1098,8 @@ mod tests {
         )
         .await?;
 
-        let standard_nix_config = PlaceNixConfiguration::setup_standard_config(None).await?;
+        let standard_nix_config =
+            PlaceNixConfiguration::setup_standard_config(None, true, &default_features()).await?;
         let custom_nix_config =
             PlaceNixConfiguration::setup_extra_config(extra_conf, String::from("foo"), None)
                 .await?;
@@ -513,6 +1133,8 @@ mod tests {
                 mode: None,
                 is_mountpoint: false,
                 force_prune_on_revert: false,
+                created: vec![],
+                adopted: None,
             }),
             create_or_merge_standard_nix_config: Some(
                 CreateOrMergeNixConfig::plan(
@@ -520,6 +1142,8 @@ mod tests {
                     standard_nix_config,
                     NIX_CONFIG_HEADER.to_string(),
                     Some(NIX_CONFIG_FOOTER.to_string()),
+                    vec![],
+                    false,
                 )
                 .await
                 .map_err(PlaceNixConfiguration::error)?,
@@ -529,6 +1153,8 @@ mod tests {
                 custom_nix_config,
                 CUSTOM_NIX_CONFIG_HEADER.to_string(),
                 None,
+                vec![],
+                false,
             )
             .await
             .map_err(PlaceNixConfiguration::error)?,
@@ -551,6 +1177,118 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn extra_conf_files_are_merged_and_checked() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let valid = temp_dir.path().join("valid.conf");
+        std::fs::write(&valid, "max-jobs = 4\n# Comments are fine\ncores = 2\n")?;
+        let invalid = temp_dir.path().join("invalid.conf");
+        std::fs::write(&invalid, "max-jobs\n")?;
+
+        let mut extra_conf = vec![UrlOrPathOrString::String("sandbox = true".to_string())];
+        extra_conf.extend(PlaceNixConfiguration::read_extra_conf_files(&[valid]).await?);
+        let extra_conf = PlaceNixConfiguration::parse_extra_conf(None, None, extra_conf).await?;
+        assert_eq!(
+            extra_conf.settings().get("sandbox").map(String::as_str),
+            Some("true")
+        );
+        assert_eq!(
+            extra_conf.settings().get("max-jobs").map(String::as_str),
+            Some("4")
+        );
+        assert_eq!(
+            extra_conf.settings().get("cores").map(String::as_str),
+            Some("2")
+        );
+
+        let err = PlaceNixConfiguration::read_extra_conf_files(std::slice::from_ref(&invalid))
+            .await
+            .unwrap_err();
+        assert!(
+            err.kind()
+                .to_string()
+                .contains(&invalid.display().to_string()),
+            "{err:?}"
+        );
+
+        Ok(())
+    }
+
+    // The build users, their group, and `build-users-group` in `nix.conf` must all agree
+    #[tokio::test]
+    async fn custom_build_group_name() -> eyre::Result<()> {
+        let root = tempfile::tempdir()?;
+
+        let mut settings = CommonSettings::builder().await?.root(root.path()).build()?;
+        settings.nix_build_group_name = "nixbld-custom".to_string();
+        settings.nix_build_group_id = 64000;
+        settings.nix_build_user_prefix = "nixbldcustom".to_string();
+        settings.nix_build_user_id_base = 64000;
+        settings.nix_build_user_count = 2;
+        settings.nix_build_user_shell = PathBuf::from("/bin/sh");
+
+        let create_users_and_groups =
+            crate::action::common::CreateUsersAndGroups::plan(settings.clone()).await?;
+        let create_users_and_groups = &create_users_and_groups.action;
+        assert_eq!(
+            create_users_and_groups.create_group.action.name,
+            "nixbld-custom"
+        );
+        assert!(create_users_and_groups
+            .create_users
+            .iter()
+            .all(|create_user| create_user.action.groupname == "nixbld-custom"));
+        assert!(create_users_and_groups
+            .add_users_to_groups
+            .iter()
+            .all(|add_user_to_group| add_user_to_group.action.groupname == "nixbld-custom"));
+
+        let mut place = PlaceNixConfiguration::plan(&settings).await?;
+        place.try_execute().await?;
+
+        let nix_conf = std::fs::read_to_string(root.path().join("etc/nix/nix.conf"))?;
+        let custom_conf = std::fs::read_to_string(root.path().join("etc/nix/nix.custom.conf"))?;
+        let build_users_groups = nix_conf
+            .lines()
+            .chain(custom_conf.lines())
+            .filter(|line| line.starts_with("build-users-group ="))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            build_users_groups,
+            ["build-users-group = nixbld-custom"],
+            "{nix_conf}\n{custom_conf}"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn builders_use_substitutes() -> eyre::Result<()> {
+        for (builders_use_substitutes, expected) in [
+            (None, vec![]),
+            (Some(true), vec!["builders-use-substitutes = true"]),
+            (Some(false), vec!["builders-use-substitutes = false"]),
+        ] {
+            let root = tempfile::tempdir()?;
+            let mut settings = CommonSettings::builder().await?.root(root.path()).build()?;
+            settings.builders_use_substitutes = builders_use_substitutes;
+
+            let mut place = PlaceNixConfiguration::plan(&settings).await?;
+            place.try_execute().await?;
+
+            let nix_conf = std::fs::read_to_string(root.path().join("etc/nix/nix.conf"))?;
+            let custom_conf = std::fs::read_to_string(root.path().join("etc/nix/nix.custom.conf"))?;
+            let found = nix_conf
+                .lines()
+                .chain(custom_conf.lines())
+                .filter(|line| line.starts_with("builders-use-substitutes"))
+                .collect::<Vec<_>>();
+            assert_eq!(found, expected, "{nix_conf}\n{custom_conf}");
+        }
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn extra_trusted_users() -> eyre::Result<()> {
         let nix_conf_dir = tempfile::tempdir()?;
@@ -568,8 +1306,12 @@ mod tests {
 
         let maybe_trusted_users = extra_conf.settings().get(TRUSTED_USERS_CONF_NAME);
 
-        let standard_nix_config =
-            PlaceNixConfiguration::setup_standard_config(maybe_trusted_users).await?;
+        let standard_nix_config = PlaceNixConfiguration::setup_standard_config(
+            maybe_trusted_users,
+            true,
+            &default_features(),
+        )
+        .await?;
         let custom_nix_config =
             PlaceNixConfiguration::setup_extra_config(extra_conf, String::from("foo"), None)
                 .await?;
@@ -599,6 +1341,8 @@ mod tests {
                 mode: None,
                 is_mountpoint: false,
                 force_prune_on_revert: false,
+                created: vec![],
+                adopted: None,
             }),
             create_or_merge_standard_nix_config: Some(
                 CreateOrMergeNixConfig::plan(
@@ -606,6 +1350,8 @@ mod tests {
                     standard_nix_config,
                     NIX_CONFIG_HEADER.to_string(),
                     Some(NIX_CONFIG_FOOTER.to_string()),
+                    vec![],
+                    false,
                 )
                 .await
                 .map_err(PlaceNixConfiguration::error)?,
@@ -615,6 +1361,8 @@ mod tests {
                 custom_nix_config,
                 CUSTOM_NIX_CONFIG_HEADER.to_string(),
                 None,
+                vec![],
+                false,
             )
             .await
             .map_err(PlaceNixConfiguration::error)?,
@@ -638,4 +1386,242 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn build_limits_are_set_when_given() {
+        let mut extra_conf = nix_config_parser::NixConfig::new();
+        PlaceNixConfiguration::apply_build_limits(&mut extra_conf, None, None);
+        assert!(extra_conf.settings().is_empty());
+
+        PlaceNixConfiguration::apply_build_limits(&mut extra_conf, Some("8"), Some(4));
+        assert_eq!(
+            extra_conf
+                .settings()
+                .get(MAX_JOBS_CONF_NAME)
+                .map(String::as_str),
+            Some("8")
+        );
+        assert_eq!(
+            extra_conf
+                .settings()
+                .get(CORES_CONF_NAME)
+                .map(String::as_str),
+            Some("4")
+        );
+    }
+
+    #[tokio::test]
+    async fn max_jobs_auto_is_written() -> eyre::Result<()> {
+        let root = tempfile::tempdir()?;
+        let settings = CommonSettings::builder()
+            .await?
+            .root(root.path())
+            .max_jobs("auto")
+            .cores(0)
+            .build()?;
+
+        let mut place = PlaceNixConfiguration::plan(&settings).await?;
+        place.try_execute().await?;
+
+        let custom = std::fs::read_to_string(root.path().join("etc/nix/nix.custom.conf"))?;
+        assert!(custom.contains("max-jobs = auto\n"), "{custom}");
+        assert!(custom.contains("cores = 0\n"), "{custom}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn substituters_are_appended() {
+        let mut extra_conf = nix_config_parser::NixConfig::new();
+        extra_conf.settings_mut().insert(
+            EXTRA_SUBSTITUTERS_CONF_NAME.to_string(),
+            "https://a.example.com".to_string(),
+        );
+        PlaceNixConfiguration::apply_substituters(
+            &mut extra_conf,
+            &[
+                "https://b.example.com".to_string(),
+                "https://a.example.com".to_string(),
+            ],
+            &["b.example.com-1:AAAA".to_string()],
+        );
+        assert_eq!(
+            extra_conf
+                .settings()
+                .get(EXTRA_SUBSTITUTERS_CONF_NAME)
+                .map(String::as_str),
+            Some("https://a.example.com https://b.example.com")
+        );
+        assert_eq!(
+            extra_conf
+                .settings()
+                .get(EXTRA_TRUSTED_PUBLIC_KEYS_CONF_NAME)
+                .map(String::as_str),
+            Some("b.example.com-1:AAAA")
+        );
+    }
+
+    #[test]
+    fn trusted_users_are_added_once() {
+        let mut extra_conf = nix_config_parser::NixConfig::new();
+        PlaceNixConfiguration::apply_trusted_users(&mut extra_conf, &[]);
+        assert!(extra_conf.settings().get(TRUSTED_USERS_CONF_NAME).is_none());
+
+        PlaceNixConfiguration::apply_trusted_users(&mut extra_conf, &["alice".to_string()]);
+        assert_eq!(
+            extra_conf
+                .settings()
+                .get(TRUSTED_USERS_CONF_NAME)
+                .map(String::as_str),
+            Some("root alice")
+        );
+
+        // Users already trusted by `--extra-conf` are not listed again
+        PlaceNixConfiguration::apply_trusted_users(
+            &mut extra_conf,
+            &["bob".to_string(), "alice".to_string()],
+        );
+        assert_eq!(
+            extra_conf
+                .settings()
+                .get(TRUSTED_USERS_CONF_NAME)
+                .map(String::as_str),
+            Some("root alice bob")
+        );
+    }
+
+    #[tokio::test]
+    async fn trusted_users_merge_with_existing() -> eyre::Result<()> {
+        let nix_conf_dir = tempfile::tempdir()?;
+        let nix_conf_path = nix_conf_dir.path().join("nix.conf");
+        tokio::fs::write(&nix_conf_path, "trusted-users = root bob\n").await?;
+
+        let mut extra_conf = nix_config_parser::NixConfig::new();
+        PlaceNixConfiguration::apply_trusted_users(
+            &mut extra_conf,
+            &["alice".to_string(), "bob".to_string()],
+        );
+        for _ in 0..2 {
+            let mut create_or_merge = CreateOrMergeNixConfig::plan(
+                &nix_conf_path,
+                extra_conf.clone(),
+                CUSTOM_NIX_CONFIG_HEADER.to_string(),
+                None,
+                vec![TRUSTED_USERS_CONF_NAME.to_string()],
+                false,
+            )
+            .await?;
+            create_or_merge.try_execute().await?;
+        }
+
+        let nix_conf = nix_config_parser::NixConfig::parse_file(&nix_conf_path)?;
+        assert_eq!(
+            nix_conf
+                .settings()
+                .get(TRUSTED_USERS_CONF_NAME)
+                .map(String::as_str),
+            Some("root alice bob")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn nix_conf_settings() -> eyre::Result<()> {
+        let mut extra_conf = nix_config_parser::NixConfig::new();
+        let nix_conf = vec![
+            "keep-failed = true".parse::<NixConfSetting>()?,
+            "extra-substituters=https://cache.example.com".parse()?,
+            "not-a-setting = 1".parse()?,
+        ];
+
+        assert!(
+            PlaceNixConfiguration::apply_nix_conf(&mut extra_conf, nix_conf.clone(), true).is_err(),
+            "Unknown settings are refused when strict"
+        );
+
+        PlaceNixConfiguration::apply_nix_conf(&mut extra_conf, nix_conf, false)?;
+        let settings = extra_conf.settings();
+        assert_eq!(
+            settings.get("keep-failed").map(String::as_str),
+            Some("true")
+        );
+        assert_eq!(
+            settings.get("extra-substituters").map(String::as_str),
+            Some("https://cache.example.com")
+        );
+        assert_eq!(settings.get("not-a-setting").map(String::as_str), Some("1"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn repeated_nix_conf_settings() -> eyre::Result<()> {
+        let mut extra_conf = nix_config_parser::NixConfig::new();
+        let nix_conf = vec![
+            "extra-substituters=https://a.example.com".parse::<NixConfSetting>()?,
+            "trusted-users = alice".parse()?,
+            "extra-substituters=https://b.example.com https://a.example.com".parse()?,
+            "trusted-users = bob".parse()?,
+        ];
+        PlaceNixConfiguration::apply_nix_conf(&mut extra_conf, nix_conf, true)?;
+        let settings = extra_conf.settings();
+        assert_eq!(
+            settings.get("extra-substituters").map(String::as_str),
+            Some("https://a.example.com https://b.example.com")
+        );
+        assert_eq!(
+            settings.get("trusted-users").map(String::as_str),
+            Some("alice bob")
+        );
+
+        let mut extra_conf = nix_config_parser::NixConfig::new();
+        let nix_conf = vec![
+            "keep-failed = true".parse::<NixConfSetting>()?,
+            "keep-failed = false".parse()?,
+        ];
+        assert!(
+            PlaceNixConfiguration::apply_nix_conf(&mut extra_conf, nix_conf, true).is_err(),
+            "A setting which is not a list can only be given once"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn experimental_features_filtered_by_version() {
+        let mut nix_config = nix_config_parser::NixConfig::new();
+        nix_config.settings_mut().insert(
+            "extra-experimental-features".to_string(),
+            "nix-command flakes dynamic-derivations some-future-feature".to_string(),
+        );
+        nix_config
+            .settings_mut()
+            .insert("experimental-features".to_string(), "flakes".to_string());
+
+        PlaceNixConfiguration::retain_supported_experimental_features(
+            &mut nix_config,
+            &semver::Version::new(2, 13, 0),
+        );
+        assert_eq!(
+            nix_config
+                .settings()
+                .get("extra-experimental-features")
+                .map(String::as_str),
+            Some("nix-command flakes some-future-feature")
+        );
+
+        PlaceNixConfiguration::retain_supported_experimental_features(
+            &mut nix_config,
+            &semver::Version::new(2, 3, 16),
+        );
+        assert_eq!(
+            nix_config
+                .settings()
+                .get("extra-experimental-features")
+                .map(String::as_str),
+            Some("some-future-feature")
+        );
+        assert!(nix_config.settings().get("experimental-features").is_none());
+    }
 }
@@ -3,13 +3,13 @@ use tracing::{span, Span};
 use super::CreateNixTree;
 use crate::{
     action::{
-        base::{FetchAndUnpackNix, MoveUnpackedNix},
+        base::{DownloadPolicy, FetchAndUnpackNix, MoveUnpackedNix},
         Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
     },
     settings::{CommonSettings, SCRATCH_DIR},
 };
 use std::os::unix::fs::MetadataExt as _;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 pub(crate) const NIX_STORE_LOCATION: &str = "/nix/store";
 
@@ -24,25 +24,43 @@ pub struct ProvisionNix {
     pub(crate) fetch_nix: StatefulAction<FetchAndUnpackNix>,
     pub(crate) create_nix_tree: StatefulAction<CreateNixTree>,
     pub(crate) move_unpacked_nix: StatefulAction<MoveUnpackedNix>,
+    /// The directory being installed into as if it were `/`, if not the running system
+    #[serde(default)]
+    root: Option<PathBuf>,
 }
 
 impl ProvisionNix {
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn plan(settings: &CommonSettings) -> Result<StatefulAction<Self>, ActionError> {
         let fetch_nix = FetchAndUnpackNix::plan(
-            settings.nix_package_url.clone(),
-            PathBuf::from(SCRATCH_DIR),
+            settings.nix_package().map_err(Self::error)?,
+            settings.nix_package_stdin,
+            settings.rooted(SCRATCH_DIR),
             settings.proxy.clone(),
             settings.ssl_cert_file.clone(),
+            settings.nix_package_sha256.clone(),
+            DownloadPolicy {
+                attempts: settings.download_attempts,
+                timeout_secs: settings.download_timeout_secs,
+                ..Default::default()
+            },
+            settings.user_agent.clone(),
+            settings.from_header.clone(),
         )
         .await?;
 
-        let create_nix_tree = CreateNixTree::plan().await.map_err(Self::error)?;
-        let move_unpacked_nix = MoveUnpackedNix::plan(PathBuf::from(SCRATCH_DIR))
+        let create_nix_tree = CreateNixTree::plan(settings.root.as_deref())
             .await
             .map_err(Self::error)?;
+        let move_unpacked_nix = MoveUnpackedNix::plan(
+            settings.rooted(SCRATCH_DIR),
+            settings.rooted(&settings.nix_store_path),
+        )
+        .await
+        .map_err(Self::error)?;
         Ok(Self {
             nix_store_gid: settings.nix_build_group_id,
+            root: settings.root.clone(),
             fetch_nix,
             create_nix_tree,
             move_unpacked_nix,
@@ -71,6 +89,7 @@ impl Action for ProvisionNix {
             create_nix_tree,
             move_unpacked_nix,
             nix_store_gid,
+            root: _,
         } = &self;
 
         let mut buf = Vec::default();
@@ -93,10 +112,12 @@ impl Action for ProvisionNix {
     async fn execute(&mut self) -> Result<(), ActionError> {
         // We fetch nix while doing the rest, then move it over.
         let mut fetch_nix_clone = self.fetch_nix.clone();
-        let fetch_nix_handle = tokio::task::spawn(async {
+        // The download reports its progress to the install, from its own task
+        let progress = crate::progress::sink();
+        let fetch_nix_handle = tokio::task::spawn(crate::progress::with_sink(progress, async {
             fetch_nix_clone.try_execute().await.map_err(Self::error)?;
             Result::<_, ActionError>::Ok(fetch_nix_clone)
-        });
+        }));
 
         self.create_nix_tree
             .try_execute()
@@ -112,9 +133,12 @@ impl Action for ProvisionNix {
             .await
             .map_err(Self::error)?;
 
-        ensure_nix_store_group(self.nix_store_gid)
-            .await
-            .map_err(Self::error)?;
+        ensure_nix_store_group(
+            &crate::settings::rooted(self.root.as_deref(), NIX_STORE_LOCATION),
+            self.nix_store_gid,
+        )
+        .await
+        .map_err(Self::error)?;
 
         Ok(())
     }
@@ -125,6 +149,7 @@ impl Action for ProvisionNix {
             create_nix_tree,
             move_unpacked_nix,
             nix_store_gid: _,
+            root: _,
         } = &self;
 
         let mut buf = Vec::default();
@@ -164,16 +189,19 @@ impl Action for ProvisionNix {
     }
 }
 
-/// If there is an existing /nix/store directory, ensure that the group ID we're going to use for
-/// the nix build group matches the group that owns /nix/store to prevent weird mismatched-ownership
-/// issues.
-async fn ensure_nix_store_group(desired_nix_build_group_id: u32) -> Result<(), ActionErrorKind> {
-    let previous_store_metadata = tokio::fs::metadata(NIX_STORE_LOCATION)
+/// If there is an existing /nix/store directory (`nix_store`, which differs under another root),
+/// ensure that the group ID we're going to use for the nix build group matches the group that owns
+/// /nix/store to prevent weird mismatched-ownership issues.
+async fn ensure_nix_store_group(
+    nix_store: &Path,
+    desired_nix_build_group_id: u32,
+) -> Result<(), ActionErrorKind> {
+    let previous_store_metadata = tokio::fs::metadata(nix_store)
         .await
-        .map_err(|e| ActionErrorKind::GettingMetadata(NIX_STORE_LOCATION.into(), e))?;
+        .map_err(|e| ActionErrorKind::GettingMetadata(nix_store.into(), e))?;
     let previous_store_group_id = previous_store_metadata.gid();
     if previous_store_group_id != desired_nix_build_group_id {
-        let entryiter = walkdir::WalkDir::new(NIX_STORE_LOCATION)
+        let entryiter = walkdir::WalkDir::new(nix_store)
             .follow_links(false)
             .same_file_system(true)
             // chown all of the contents of the dir before NIX_STORE_LOCATION,
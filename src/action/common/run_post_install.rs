@@ -0,0 +1,201 @@
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+use tracing::{span, Span};
+
+use crate::action::{
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+};
+use crate::settings::CommonSettings;
+
+/// The profile script which sets up a shell for a multi-user (daemon) install
+pub const NIX_DAEMON_PROFILE_SCRIPT: &str =
+    "/nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh";
+
+/// The result of a single post-install command, kept in the receipt
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq, Eq)]
+pub struct PostInstallOutcome {
+    pub command: String,
+    pub success: bool,
+    /// `None` if the command was killed by a signal
+    pub exit_code: Option<i32>,
+}
+
+/**
+Run the user provided `--post-install-command`s, each in a fresh shell which has sourced the Nix profile
+
+Failures are logged and recorded, and only fail the action if `abort_on_failure` is set.
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+#[serde(tag = "action_name", rename = "run_post_install")]
+pub struct RunPostInstall {
+    profile_script: PathBuf,
+    commands: Vec<String>,
+    abort_on_failure: bool,
+    #[serde(default)]
+    pub(crate) outcomes: Vec<PostInstallOutcome>,
+}
+
+impl RunPostInstall {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(
+        profile_script: impl AsRef<Path>,
+        settings: &CommonSettings,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        Ok(StatefulAction::uncompleted(Self {
+            profile_script: profile_script.as_ref().to_path_buf(),
+            commands: settings.post_install_commands.clone(),
+            abort_on_failure: settings.post_install_abort_on_failure,
+            outcomes: vec![],
+        }))
+    }
+
+    fn command(&self, command: &str) -> Command {
+        let mut shell = Command::new("/bin/sh");
+        shell
+            .process_group(0)
+            .arg("-c")
+            .arg(r#". "$0" && eval "$1""#)
+            .arg(&self.profile_script)
+            .arg(command)
+            .stdin(std::process::Stdio::null());
+        shell
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "run_post_install")]
+impl Action for RunPostInstall {
+    fn action_tag() -> ActionTag {
+        ActionTag("run_post_install")
+    }
+    fn tracing_synopsis(&self) -> String {
+        format!(
+            "Run {} post-install command{}",
+            self.commands.len(),
+            if self.commands.len() == 1 { "" } else { "s" }
+        )
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "run_post_install",
+            profile_script = tracing::field::display(self.profile_script.display()),
+            abort_on_failure = self.abort_on_failure,
+        )
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            self.tracing_synopsis(),
+            self.commands
+                .iter()
+                .map(|command| format!("Run `{command}`"))
+                .collect(),
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        self.outcomes.clear();
+
+        for command in self.commands.clone() {
+            let mut shell = self.command(&command);
+            let output = shell
+                .output()
+                .await
+                .map_err(|e| Self::error(ActionErrorKind::command(&shell, e)))?;
+
+            self.outcomes.push(PostInstallOutcome {
+                command: command.clone(),
+                success: output.status.success(),
+                exit_code: output.status.code(),
+            });
+
+            if output.status.success() {
+                tracing::debug!(%command, "Post-install command succeeded");
+            } else if self.abort_on_failure {
+                return Err(Self::error(ActionErrorKind::command_output(&shell, output)));
+            } else {
+                tracing::warn!(
+                    %command,
+                    status = %output.status,
+                    stderr = %String::from_utf8_lossy(&output.stderr),
+                    "Post-install command failed, continuing"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        vec![]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        // Whatever the commands did is theirs to undo
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PostInstallOutcome, RunPostInstall};
+    use crate::action::Action;
+
+    fn run_post_install(
+        profile_script: &std::path::Path,
+        commands: &[&str],
+        abort_on_failure: bool,
+    ) -> RunPostInstall {
+        RunPostInstall {
+            profile_script: profile_script.to_path_buf(),
+            commands: commands.iter().map(|command| command.to_string()).collect(),
+            abort_on_failure,
+            outcomes: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_commands_with_profile_sourced() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let profile_script = temp_dir.path().join("nix-daemon.sh");
+        std::fs::write(&profile_script, "export FROM_PROFILE=yes\n")?;
+
+        let mut action = run_post_install(
+            &profile_script,
+            &["test \"$FROM_PROFILE\" = yes", "exit 3", "true | true"],
+            false,
+        );
+        action.execute().await?;
+        assert_eq!(
+            action.outcomes,
+            vec![
+                PostInstallOutcome {
+                    command: "test \"$FROM_PROFILE\" = yes".into(),
+                    success: true,
+                    exit_code: Some(0),
+                },
+                PostInstallOutcome {
+                    command: "exit 3".into(),
+                    success: false,
+                    exit_code: Some(3),
+                },
+                PostInstallOutcome {
+                    command: "true | true".into(),
+                    success: true,
+                    exit_code: Some(0),
+                },
+            ]
+        );
+
+        let mut action = run_post_install(&profile_script, &["exit 3", "true"], true);
+        assert!(action.execute().await.is_err());
+        assert_eq!(action.outcomes.len(), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+use tracing::{span, Span};
+
+use crate::action::base::CreateFile;
+use crate::action::{ActionError, ActionErrorKind, ActionTag, StatefulAction};
+use crate::execute_command;
+
+use crate::action::{Action, ActionDescription};
+
+pub const DAEMON_SOCKET_TMPFILES_DEST: &str = "/etc/tmpfiles.d/nix-daemon-socket.conf";
+/// Recreate the directory holding the daemon socket at boot, for when `/nix/var` is not persistent
+pub const DAEMON_SOCKET_TMPFILES_RULE: &str = "d /nix/var/nix/daemon-socket 0755 root root - -";
+
+/**
+Write a `tmpfiles.d` rule, and have `systemd-tmpfiles` apply it right away
+
+The rule is applied again by systemd on every boot.
+
+Nix ships its own `nix-daemon.conf` rule for the daemon socket, which
+[`ConfigureUpstreamInitService`](crate::action::common::ConfigureUpstreamInitService) links into
+`/etc/tmpfiles.d` from the default profile. That link resolves into `/nix/var`, so when `/nix` is
+mounted late or `/nix/var` is not persistent, `systemd-tmpfiles` cannot read it at boot, and the
+socket directory is never made. A plain file in `/etc/tmpfiles.d` is always readable, so the
+[`DAEMON_SOCKET_TMPFILES_RULE`] is written alongside it rather than in place of it.
+ */
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+#[serde(tag = "action_name", rename = "create_tmpfiles_rule")]
+pub struct CreateTmpfilesRule {
+    path: PathBuf,
+    create_file: StatefulAction<CreateFile>,
+}
+
+impl CreateTmpfilesRule {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(
+        path: impl AsRef<Path>,
+        rule: &str,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        let path = path.as_ref().to_path_buf();
+        if which::which("systemd-tmpfiles").is_err() {
+            return Err(Self::error(ActionErrorKind::SystemdMissing));
+        }
+
+        let create_file = CreateFile::plan(
+            &path,
+            None,
+            None,
+            0o0644,
+            format!("# Generated by the Nix installer\n{rule}\n"),
+            false,
+        )
+        .await
+        .map_err(Self::error)?;
+
+        Ok(Self { path, create_file }.into())
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "create_tmpfiles_rule")]
+impl Action for CreateTmpfilesRule {
+    fn action_tag() -> ActionTag {
+        ActionTag("create_tmpfiles_rule")
+    }
+    fn tracing_synopsis(&self) -> String {
+        format!("Create the tmpfiles rule `{}`", self.path.display())
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "create_tmpfiles_rule",
+            path = %self.path.display(),
+        )
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            self.tracing_synopsis(),
+            vec![
+                format!("Create `{}`", self.path.display()),
+                format!("Run `systemd-tmpfiles --create {}`", self.path.display()),
+            ],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        self.create_file.try_execute().await.map_err(Self::error)?;
+
+        execute_command(
+            Command::new("systemd-tmpfiles")
+                .process_group(0)
+                .arg("--create")
+                .arg(&self.path)
+                .stdin(std::process::Stdio::null()),
+        )
+        .await
+        .map_err(Self::error)?;
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            format!("Remove the tmpfiles rule `{}`", self.path.display()),
+            vec![format!("Remove `{}`", self.path.display())],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        // What the rule created is removed along with the store
+        self.create_file.try_revert().await.map_err(Self::error)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn writes_and_applies_the_rule() -> eyre::Result<()> {
+        if which::which("systemd-tmpfiles").is_err() {
+            return Ok(());
+        }
+        let temp_dir = tempfile::tempdir()?;
+        let dest = temp_dir.path().join("nix-daemon-socket.conf");
+        let socket_dir = temp_dir.path().join("daemon-socket");
+
+        let mut action =
+            CreateTmpfilesRule::plan(&dest, &format!("d {} 0755 - - - -", socket_dir.display()))
+                .await?;
+        action.try_execute().await?;
+        assert!(tokio::fs::read_to_string(&dest)
+            .await?
+            .ends_with(&format!("\nd {} 0755 - - - -\n", socket_dir.display())));
+        assert!(socket_dir.is_dir());
+
+        action.try_revert().await?;
+        assert!(!dest.exists());
+
+        Ok(())
+    }
+}
@@ -1,11 +1,17 @@
+pub(crate) mod create_tmpfiles_rule;
 pub(crate) mod ensure_steamos_nix_directory;
 pub(crate) mod provision_selinux;
 pub(crate) mod revert_clean_steamos_nix_offload;
+pub(crate) mod start_openrc_service;
 pub(crate) mod start_systemd_unit;
 pub(crate) mod systemctl_daemon_reload;
 
+pub use create_tmpfiles_rule::{
+    CreateTmpfilesRule, DAEMON_SOCKET_TMPFILES_DEST, DAEMON_SOCKET_TMPFILES_RULE,
+};
 pub use ensure_steamos_nix_directory::EnsureSteamosNixDirectory;
 pub use provision_selinux::ProvisionSelinux;
 pub use revert_clean_steamos_nix_offload::RevertCleanSteamosNixOffload;
+pub use start_openrc_service::{StartOpenrcService, StartOpenrcServiceError};
 pub use start_systemd_unit::{StartSystemdUnit, StartSystemdUnitError};
 pub use systemctl_daemon_reload::SystemctlDaemonReload;
@@ -0,0 +1,244 @@
+use tokio::process::Command;
+use tracing::{span, Span};
+use which::which;
+
+use crate::action::base::CreateFile;
+use crate::action::{ActionError, ActionErrorKind, ActionTag, StatefulAction};
+use crate::execute_command;
+
+use crate::action::{Action, ActionDescription};
+
+pub const OPENRC_SERVICE_NAME: &str = "nix-daemon";
+pub const OPENRC_INIT_SCRIPT: &str = "/etc/init.d/nix-daemon";
+const OPENRC_RUNLEVEL: &str = "default";
+
+/**
+Run the Nix daemon with OpenRC, as on Alpine or Gentoo
+
+Writes an init script to `/etc/init.d/nix-daemon`, adds it to the `default` runlevel, and
+(optionally) starts it.
+ */
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+#[serde(tag = "action_name", rename = "start_openrc_service")]
+pub struct StartOpenrcService {
+    create_init_script: StatefulAction<CreateFile>,
+    start_daemon: bool,
+}
+
+impl StartOpenrcService {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(
+        start_daemon: bool,
+        restart_on_failure: bool,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        for command in ["rc-update", "rc-service"] {
+            if which(command).is_err() {
+                return Err(Self::error(StartOpenrcServiceError::MissingCommand(
+                    command,
+                )));
+            }
+        }
+
+        let create_init_script = CreateFile::plan(
+            OPENRC_INIT_SCRIPT,
+            None,
+            None,
+            0o0755,
+            init_script(restart_on_failure),
+            false,
+        )
+        .await
+        .map_err(Self::error)?;
+
+        Ok(Self {
+            create_init_script,
+            start_daemon,
+        }
+        .into())
+    }
+}
+
+/// An OpenRC init script for `nix-daemon`
+///
+/// `supervise-daemon` is used to restart the daemon if it fails, otherwise OpenRC only starts it.
+fn init_script(restart_on_failure: bool) -> String {
+    let supervisor = if restart_on_failure {
+        "supervisor=supervise-daemon\nrespawn_delay=5\nrespawn_max=0\n"
+    } else {
+        "command_background=true\npidfile=\"/run/${RC_SVCNAME}.pid\"\n"
+    };
+    format!(
+        "\
+#!/sbin/openrc-run
+# Generated by the Nix installer
+
+description=\"Nix package manager daemon\"
+command=\"/nix/var/nix/profiles/default/bin/nix-daemon\"
+{supervisor}
+depend() {{
+    need localmount
+    after net
+}}
+"
+    )
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "start_openrc_service")]
+impl Action for StartOpenrcService {
+    fn action_tag() -> ActionTag {
+        ActionTag("start_openrc_service")
+    }
+    fn tracing_synopsis(&self) -> String {
+        if self.start_daemon {
+            format!("Enable (and start) the OpenRC service `{OPENRC_SERVICE_NAME}`")
+        } else {
+            format!("Enable the OpenRC service `{OPENRC_SERVICE_NAME}`")
+        }
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "start_openrc_service",
+            start_daemon = self.start_daemon,
+        )
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        let mut explanation = vec![
+            format!("Create `{OPENRC_INIT_SCRIPT}`"),
+            format!("Run `rc-update add {OPENRC_SERVICE_NAME} {OPENRC_RUNLEVEL}`"),
+        ];
+        if self.start_daemon {
+            explanation.push(format!("Run `rc-service {OPENRC_SERVICE_NAME} start`"));
+        }
+        vec![ActionDescription::new(self.tracing_synopsis(), explanation)]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        self.create_init_script
+            .try_execute()
+            .await
+            .map_err(Self::error)?;
+
+        execute_command(
+            Command::new("rc-update")
+                .process_group(0)
+                .args(["add", OPENRC_SERVICE_NAME, OPENRC_RUNLEVEL])
+                .stdin(std::process::Stdio::null()),
+        )
+        .await
+        .map_err(Self::error)?;
+
+        if self.start_daemon {
+            execute_command(
+                Command::new("rc-service")
+                    .process_group(0)
+                    .args([OPENRC_SERVICE_NAME, "start"])
+                    .stdin(std::process::Stdio::null()),
+            )
+            .await
+            .map_err(Self::error)?;
+        }
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            format!("Disable (and stop) the OpenRC service `{OPENRC_SERVICE_NAME}`"),
+            vec![
+                format!("Run `rc-service {OPENRC_SERVICE_NAME} stop`"),
+                format!("Run `rc-update del {OPENRC_SERVICE_NAME} {OPENRC_RUNLEVEL}`"),
+                format!("Remove `{OPENRC_INIT_SCRIPT}`"),
+            ],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        let mut errors = vec![];
+
+        // The service may already be stopped, or never have been started
+        let status = Command::new("rc-service")
+            .process_group(0)
+            .args([OPENRC_SERVICE_NAME, "status"])
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .await;
+        if matches!(status, Ok(status) if status.success()) {
+            if let Err(e) = execute_command(
+                Command::new("rc-service")
+                    .process_group(0)
+                    .args([OPENRC_SERVICE_NAME, "stop"])
+                    .stdin(std::process::Stdio::null()),
+            )
+            .await
+            .map_err(Self::error)
+            {
+                errors.push(e);
+            }
+        }
+
+        if let Err(e) = execute_command(
+            Command::new("rc-update")
+                .process_group(0)
+                .args(["del", OPENRC_SERVICE_NAME, OPENRC_RUNLEVEL])
+                .stdin(std::process::Stdio::null()),
+        )
+        .await
+        .map_err(Self::error)
+        {
+            errors.push(e);
+        }
+
+        if let Err(e) = self.create_init_script.try_revert().await {
+            errors.push(e);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else if errors.len() == 1 {
+            Err(errors
+                .into_iter()
+                .next()
+                .expect("Expected 1 len Vec to have at least 1 item"))
+        } else {
+            Err(Self::error(ActionErrorKind::MultipleChildren(errors)))
+        }
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum StartOpenrcServiceError {
+    #[error("OpenRC was selected, but `{0}` was not found")]
+    MissingCommand(&'static str),
+}
+
+impl From<StartOpenrcServiceError> for ActionErrorKind {
+    fn from(val: StartOpenrcServiceError) -> Self {
+        ActionErrorKind::Custom(Box::new(val))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::init_script;
+
+    #[test]
+    fn init_script_supervises_only_when_restarting() {
+        let script = init_script(true);
+        assert!(script.starts_with("#!/sbin/openrc-run\n"));
+        assert!(script.contains("supervisor=supervise-daemon"));
+        assert!(!script.contains("command_background"));
+
+        let script = init_script(false);
+        assert!(script.contains("command_background=true"));
+        assert!(!script.contains("supervise-daemon"));
+    }
+}
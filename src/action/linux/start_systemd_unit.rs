@@ -23,15 +23,11 @@ impl StartSystemdUnit {
         enable: bool,
     ) -> Result<StatefulAction<Self>, ActionError> {
         let unit = unit.as_ref();
-        let mut command = Command::new("systemctl");
-        command.arg("is-active");
-        command.arg(unit);
-        let output = command
-            .output()
-            .await
-            .map_err(|e| Self::error(ActionErrorKind::command(&command, e)))?;
+        let active = systemctl_succeeds("is-active", unit).await?;
+        // An active unit which is not enabled would not come back after a reboot
+        let enabled = !enable || systemctl_succeeds("is-enabled", unit).await?;
 
-        let state = if output.status.success() {
+        let state = if active && enabled {
             tracing::debug!("Starting systemd unit `{}` already complete", unit);
             ActionState::Skipped
         } else {
@@ -44,8 +40,55 @@ impl StartSystemdUnit {
                 enable,
             },
             state,
+            duration: None,
         })
     }
+
+    /// `systemctl enable --now` when the unit should persist across reboots, otherwise `systemctl start`
+    fn start_command(&self) -> Command {
+        let mut command = Command::new("systemctl");
+        command.process_group(0);
+        if self.enable {
+            command.arg("enable").arg("--now");
+        } else {
+            command.arg("start");
+        }
+        command.arg(&self.unit).stdin(std::process::Stdio::null());
+        command
+    }
+
+    /// `systemctl disable` (if the unit was enabled), then `systemctl stop`
+    fn stop_commands(&self) -> Vec<Command> {
+        // We do both to avoid an error doing `disable --now` if the user did stop it already somehow.
+        let verbs: &[&str] = if self.enable {
+            &["disable", "stop"]
+        } else {
+            &["stop"]
+        };
+        verbs
+            .iter()
+            .map(|verb| {
+                let mut command = Command::new("systemctl");
+                command
+                    .process_group(0)
+                    .arg(verb)
+                    .arg(&self.unit)
+                    .stdin(std::process::Stdio::null());
+                command
+            })
+            .collect()
+    }
+}
+
+async fn systemctl_succeeds(verb: &str, unit: &str) -> Result<bool, ActionError> {
+    let mut command = Command::new("systemctl");
+    command.arg(verb);
+    command.arg(unit);
+    let output = command
+        .output()
+        .await
+        .map_err(|e| StartSystemdUnit::error(ActionErrorKind::command(&command, e)))?;
+    Ok(output.status.success())
 }
 
 #[async_trait::async_trait]
@@ -55,7 +98,11 @@ impl Action for StartSystemdUnit {
         ActionTag("start_systemd_unit")
     }
     fn tracing_synopsis(&self) -> String {
-        format!("Enable (and start) the systemd unit `{}`", self.unit)
+        if self.enable {
+            format!("Enable (and start) the systemd unit `{}`", self.unit)
+        } else {
+            format!("Start the systemd unit `{}`", self.unit)
+        }
     }
 
     fn tracing_span(&self) -> Span {
@@ -63,6 +110,7 @@ impl Action for StartSystemdUnit {
             tracing::Level::DEBUG,
             "start_systemd_unit",
             unit = %self.unit,
+            enable = self.enable,
         )
     }
 
@@ -72,77 +120,31 @@ impl Action for StartSystemdUnit {
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn execute(&mut self) -> Result<(), ActionError> {
-        let Self { unit, enable } = self;
-
-        match enable {
-            true => {
-                // TODO(@Hoverbear): Handle proxy vars
-                execute_command(
-                    Command::new("systemctl")
-                        .process_group(0)
-                        .arg("enable")
-                        .arg("--now")
-                        .arg(unit)
-                        .stdin(std::process::Stdio::null()),
-                )
-                .await
-                .map_err(Self::error)?;
-            },
-            false => {
-                // TODO(@Hoverbear): Handle proxy vars
-                execute_command(
-                    Command::new("systemctl")
-                        .process_group(0)
-                        .arg("start")
-                        .arg(unit)
-                        .stdin(std::process::Stdio::null()),
-                )
-                .await
-                .map_err(Self::error)?;
-            },
-        }
+        // TODO(@Hoverbear): Handle proxy vars
+        execute_command(&mut self.start_command())
+            .await
+            .map_err(Self::error)?;
 
         Ok(())
     }
 
     fn revert_description(&self) -> Vec<ActionDescription> {
-        vec![ActionDescription::new(
-            format!("Disable (and stop) the systemd unit `{}`", self.unit),
-            vec![],
-        )]
+        let synopsis = if self.enable {
+            format!("Disable (and stop) the systemd unit `{}`", self.unit)
+        } else {
+            format!("Stop the systemd unit `{}`", self.unit)
+        };
+        vec![ActionDescription::new(synopsis, vec![])]
     }
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn revert(&mut self) -> Result<(), ActionError> {
         let mut errors = vec![];
 
-        if self.enable {
-            if let Err(e) = execute_command(
-                Command::new("systemctl")
-                    .process_group(0)
-                    .arg("disable")
-                    .arg(&self.unit)
-                    .stdin(std::process::Stdio::null()),
-            )
-            .await
-            .map_err(Self::error)
-            {
+        for mut command in self.stop_commands() {
+            if let Err(e) = execute_command(&mut command).await.map_err(Self::error) {
                 errors.push(e);
             }
-        };
-
-        // We do both to avoid an error doing `disable --now` if the user did stop it already somehow.
-        if let Err(e) = execute_command(
-            Command::new("systemctl")
-                .process_group(0)
-                .arg("stop")
-                .arg(&self.unit)
-                .stdin(std::process::Stdio::null()),
-        )
-        .await
-        .map_err(Self::error)
-        {
-            errors.push(e);
         }
 
         if errors.is_empty() {
@@ -164,3 +166,47 @@ pub enum StartSystemdUnitError {
     #[error("Failed to execute command")]
     Command(#[source] std::io::Error),
 }
+
+#[cfg(test)]
+mod test {
+    use super::StartSystemdUnit;
+
+    fn args(command: &tokio::process::Command) -> Vec<String> {
+        std::iter::once(command.as_std().get_program())
+            .chain(command.as_std().get_args())
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn enable_persists_the_unit() {
+        let enabled = StartSystemdUnit {
+            unit: "nix-daemon.socket".into(),
+            enable: true,
+        };
+        assert_eq!(
+            args(&enabled.start_command()),
+            ["systemctl", "enable", "--now", "nix-daemon.socket"]
+        );
+        assert_eq!(
+            enabled.stop_commands().iter().map(args).collect::<Vec<_>>(),
+            [
+                ["systemctl", "disable", "nix-daemon.socket"],
+                ["systemctl", "stop", "nix-daemon.socket"],
+            ]
+        );
+
+        let started = StartSystemdUnit {
+            unit: "nix.mount".into(),
+            enable: false,
+        };
+        assert_eq!(
+            args(&started.start_command()),
+            ["systemctl", "start", "nix.mount"]
+        );
+        assert_eq!(
+            started.stop_commands().iter().map(args).collect::<Vec<_>>(),
+            [["systemctl", "stop", "nix.mount"]]
+        );
+    }
+}
@@ -41,6 +41,7 @@ fi
                     0o644,
                     shell_buf.to_string(),
                     create_or_insert_into_file::Position::Beginning,
+                    false,
                 )
                 .await
                 .map_err(Self::error)?,
@@ -16,6 +16,9 @@ pub struct CreateApfsVolume {
     disk: PathBuf,
     name: String,
     case_sensitive: bool,
+    /// A size like `50g`, passed to `diskutil apfs addVolume -quota`
+    #[serde(default)]
+    quota: Option<String>,
 }
 
 impl CreateApfsVolume {
@@ -24,7 +27,16 @@ impl CreateApfsVolume {
         disk: impl AsRef<Path>,
         name: String,
         case_sensitive: bool,
+        quota: Option<String>,
     ) -> Result<StatefulAction<Self>, ActionError> {
+        if let Some(quota) = &quota {
+            if !is_valid_size(quota) {
+                return Err(Self::error(CreateApfsVolumeError::InvalidQuota(
+                    quota.clone(),
+                )));
+            }
+        }
+
         let output =
             execute_command(Command::new("/usr/sbin/diskutil").args(["apfs", "list", "-plist"]))
                 .await
@@ -32,24 +44,76 @@ impl CreateApfsVolume {
 
         let parsed: DiskUtilApfsListOutput =
             plist::from_bytes(&output.stdout).map_err(Self::error)?;
-        for container in parsed.containers {
-            for volume in container.volumes {
-                if volume.name.as_ref() == Some(&name) {
-                    return Ok(StatefulAction::completed(Self {
-                        disk: disk.as_ref().to_path_buf(),
-                        name,
-                        case_sensitive,
-                    }));
-                }
-            }
-        }
+        let exists = volume_exists(&parsed, disk.as_ref(), &name).map_err(Self::error)?;
 
-        Ok(StatefulAction::uncompleted(Self {
+        let action = Self {
             disk: disk.as_ref().to_path_buf(),
             name,
             case_sensitive,
-        }))
+            quota,
+        };
+        if exists {
+            tracing::debug!("Volume `{}` already exists", action.name);
+            Ok(StatefulAction::completed(action))
+        } else {
+            Ok(StatefulAction::uncompleted(action))
+        }
+    }
+}
+
+impl CreateApfsVolume {
+    /// The filesystem `diskutil` formats the volume with
+    fn filesystem(&self) -> &'static str {
+        if self.case_sensitive {
+            "Case-sensitive APFS"
+        } else {
+            "APFS"
+        }
+    }
+}
+
+/// Whether a volume named `name` exists on `disk`, refusing one of that name on another disk
+fn volume_exists(
+    list: &DiskUtilApfsListOutput,
+    disk: &Path,
+    name: &str,
+) -> Result<bool, CreateApfsVolumeError> {
+    let disk_name = disk.to_string_lossy();
+    let (on_disk, elsewhere): (Vec<_>, Vec<_>) = list
+        .containers
+        .iter()
+        .filter(|container| {
+            container
+                .volumes
+                .iter()
+                .any(|volume| volume.name.as_deref() == Some(name))
+        })
+        .partition(|container| container.is_on(&disk_name));
+    if !on_disk.is_empty() {
+        return Ok(true);
     }
+    match elsewhere.first() {
+        Some(container) => Err(CreateApfsVolumeError::ExistsOnOtherDisk {
+            name: name.to_string(),
+            disk: disk.to_path_buf(),
+            other_disk: container
+                .container_reference
+                .clone()
+                .unwrap_or_else(|| "an unknown disk".to_string()),
+        }),
+        None => Ok(false),
+    }
+}
+
+/// Whether `size` is a size `diskutil` understands, like `50g`, `1.5T`, or `1073741824`
+fn is_valid_size(size: &str) -> bool {
+    let number = size
+        .strip_suffix(|c: char| "bkmgtpeBKMGTPE".contains(c))
+        .unwrap_or(size);
+    !number.is_empty()
+        && !number.starts_with('.')
+        && number.parse::<f64>().is_ok_and(|n| n > 0.0)
+        && number.chars().all(|c| c.is_ascii_digit() || c == '.')
 }
 
 #[async_trait::async_trait]
@@ -60,9 +124,13 @@ impl Action for CreateApfsVolume {
     }
     fn tracing_synopsis(&self) -> String {
         format!(
-            "Create an APFS volume on `{}` named `{}`",
+            "Create an APFS volume on `{}` named `{}`{}",
             self.disk.display(),
-            self.name
+            self.name,
+            match &self.quota {
+                Some(quota) => format!(" with a quota of `{quota}`"),
+                None => String::new(),
+            }
         )
     }
 
@@ -73,40 +141,40 @@ impl Action for CreateApfsVolume {
             disk = %self.disk.display(),
             name = %self.name,
             case_sensitive = %self.case_sensitive,
+            quota = self.quota,
         )
     }
 
     fn execute_description(&self) -> Vec<ActionDescription> {
-        vec![ActionDescription::new(self.tracing_synopsis(), vec![])]
+        vec![ActionDescription::new(
+            self.tracing_synopsis(),
+            vec![format!("Format it as `{}`", self.filesystem())],
+        )]
     }
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn execute(&mut self) -> Result<(), ActionError> {
+        let filesystem = self.filesystem();
         let Self {
             disk,
             name,
-            case_sensitive,
+            case_sensitive: _,
+            quota,
         } = self;
 
-        execute_command(
-            Command::new("/usr/sbin/diskutil")
-                .process_group(0)
-                .args([
-                    "apfs",
-                    "addVolume",
-                    &format!("{}", disk.display()),
-                    if !*case_sensitive {
-                        "APFS"
-                    } else {
-                        "Case-sensitive APFS"
-                    },
-                    name,
-                    "-nomount",
-                ])
-                .stdin(std::process::Stdio::null()),
-        )
-        .await
-        .map_err(Self::error)?;
+        let mut command = Command::new("/usr/sbin/diskutil");
+        command.process_group(0).args([
+            "apfs",
+            "addVolume",
+            &format!("{}", disk.display()),
+            filesystem,
+            name,
+        ]);
+        if let Some(quota) = quota {
+            command.args(["-quota", quota]);
+        }
+        command.arg("-nomount").stdin(std::process::Stdio::null());
+        execute_command(&mut command).await.map_err(Self::error)?;
 
         Ok(())
     }
@@ -186,3 +254,162 @@ impl Action for CreateApfsVolume {
         Ok(())
     }
 }
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum CreateApfsVolumeError {
+    #[error("`{0}` is not a volume size `diskutil` understands, expected a number with an optional unit like `50g` or `1.5T`")]
+    InvalidQuota(String),
+    #[error("A volume named `{name}` already exists on `{other_disk}`, not on `{disk}` where it would be created; remove it or pick another `--volume-label`", disk = disk.display())]
+    ExistsOnOtherDisk {
+        name: String,
+        disk: PathBuf,
+        other_disk: String,
+    },
+}
+
+impl From<CreateApfsVolumeError> for ActionErrorKind {
+    fn from(val: CreateApfsVolumeError) -> Self {
+        ActionErrorKind::Custom(Box::new(val))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_valid_size, volume_exists, CreateApfsVolume, CreateApfsVolumeError};
+    use crate::action::Action;
+    use crate::os::darwin::DiskUtilApfsListOutput;
+
+    #[test]
+    fn validates_quota_sizes() {
+        for valid in ["50g", "50G", "1.5T", "1073741824", "512m"] {
+            assert!(is_valid_size(valid), "{valid}");
+        }
+        for invalid in ["", "g", "50gb", "fifty", "-5g", "0", ".5g", "5 g"] {
+            assert!(!is_valid_size(invalid), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn case_sensitive_selects_filesystem() {
+        let mut volume = CreateApfsVolume {
+            disk: "disk3".into(),
+            name: "Nix Store".into(),
+            case_sensitive: true,
+            quota: None,
+        };
+        assert_eq!(volume.filesystem(), "Case-sensitive APFS");
+        assert!(volume.execute_description()[0]
+            .explanation
+            .contains(&"Format it as `Case-sensitive APFS`".to_string()));
+
+        volume.case_sensitive = false;
+        assert_eq!(volume.filesystem(), "APFS");
+    }
+
+    #[test]
+    fn detects_existing_volume() -> Result<(), Box<dyn std::error::Error>> {
+        let list: DiskUtilApfsListOutput = plist::from_bytes(
+            br#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Containers</key>
+    <array>
+        <dict>
+            <key>ContainerReference</key>
+            <string>disk3</string>
+            <key>PhysicalStores</key>
+            <array>
+                <dict>
+                    <key>DeviceIdentifier</key>
+                    <string>disk0s2</string>
+                </dict>
+            </array>
+            <key>Volumes</key>
+            <array>
+                <dict>
+                    <key>Name</key>
+                    <string>Macintosh HD</string>
+                </dict>
+                <dict>
+                    <key>Name</key>
+                    <string>Nix Store</string>
+                </dict>
+            </array>
+        </dict>
+    </array>
+</dict>
+</plist>"#,
+        )?;
+
+        assert!(volume_exists(&list, "disk3".as_ref(), "Nix Store")?);
+        assert!(volume_exists(&list, "/dev/disk0".as_ref(), "Nix Store")?);
+        assert!(!volume_exists(&list, "disk3".as_ref(), "Other Store")?);
+        assert!(matches!(
+            volume_exists(&list, "disk4".as_ref(), "Nix Store"),
+            Err(CreateApfsVolumeError::ExistsOnOtherDisk { other_disk, .. }) if other_disk == "disk3"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn checks_every_container() -> Result<(), Box<dyn std::error::Error>> {
+        // An external disk holding a volume of the same name is listed first
+        let list: DiskUtilApfsListOutput = plist::from_bytes(
+            br#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Containers</key>
+    <array>
+        <dict>
+            <key>ContainerReference</key>
+            <string>disk5</string>
+            <key>PhysicalStores</key>
+            <array>
+                <dict>
+                    <key>DeviceIdentifier</key>
+                    <string>disk4s2</string>
+                </dict>
+            </array>
+            <key>Volumes</key>
+            <array>
+                <dict>
+                    <key>Name</key>
+                    <string>Nix Store</string>
+                </dict>
+            </array>
+        </dict>
+        <dict>
+            <key>ContainerReference</key>
+            <string>disk3</string>
+            <key>PhysicalStores</key>
+            <array>
+                <dict>
+                    <key>DeviceIdentifier</key>
+                    <string>disk0s2</string>
+                </dict>
+            </array>
+            <key>Volumes</key>
+            <array>
+                <dict>
+                    <key>Name</key>
+                    <string>Nix Store</string>
+                </dict>
+            </array>
+        </dict>
+    </array>
+</dict>
+</plist>"#,
+        )?;
+
+        assert!(volume_exists(&list, "disk3".as_ref(), "Nix Store")?);
+        assert!(volume_exists(&list, "disk5".as_ref(), "Nix Store")?);
+        assert!(matches!(
+            volume_exists(&list, "disk6".as_ref(), "Nix Store"),
+            Err(CreateApfsVolumeError::ExistsOnOtherDisk { other_disk, .. }) if other_disk == "disk5"
+        ));
+        Ok(())
+    }
+}
@@ -6,7 +6,10 @@ use std::{
 use tokio::process::Command;
 use tracing::{span, Span};
 
-use super::{create_fstab_entry::CreateFstabEntry, DARWIN_LAUNCHD_DOMAIN};
+use super::{
+    create_fstab_entry::CreateFstabEntry, create_synthetic_objects::SYNTHETIC_CONF_REBOOT_NOTE,
+    DARWIN_LAUNCHD_DOMAIN,
+};
 use crate::action::macos::{
     BootstrapLaunchctlService, CreateDeterminateVolumeService, KickstartLaunchctlService,
 };
@@ -53,6 +56,7 @@ impl CreateDeterminateNixVolume {
         case_sensitive: bool,
         force: bool,
         use_ec2_instance_store: bool,
+        quota: Option<String>,
     ) -> Result<StatefulAction<Self>, ActionError> {
         let disk = disk.as_ref();
         let create_or_append_synthetic_conf = CreateOrInsertIntoFile::plan(
@@ -62,6 +66,7 @@ impl CreateDeterminateNixVolume {
             None,
             "nix\n".into(), /* The newline is required otherwise it segfaults */
             create_or_insert_into_file::Position::End,
+            false,
         )
         .await
         .map_err(Self::error)?;
@@ -72,7 +77,7 @@ impl CreateDeterminateNixVolume {
 
         let create_synthetic_objects = CreateSyntheticObjects::plan().await.map_err(Self::error)?;
 
-        let create_volume = CreateApfsVolume::plan(disk, name.clone(), case_sensitive)
+        let create_volume = CreateApfsVolume::plan(disk, name.clone(), case_sensitive, quota)
             .await
             .map_err(Self::error)?;
 
@@ -272,7 +277,7 @@ impl Action for CreateDeterminateNixVolume {
     }
 
     fn revert_description(&self) -> Vec<ActionDescription> {
-        let explanation = vec![
+        let mut explanation = vec![
             self.create_directory.tracing_synopsis(),
             self.create_or_append_synthetic_conf.tracing_synopsis(),
             self.create_synthetic_objects.tracing_synopsis(),
@@ -286,6 +291,8 @@ impl Action for CreateDeterminateNixVolume {
             self.enable_ownership.tracing_synopsis(),
         ];
 
+        explanation.push(SYNTHETIC_CONF_REBOOT_NOTE.to_string());
+
         vec![ActionDescription::new(
             format!(
                 "Remove the APFS volume `{}` on `{}`",
@@ -341,7 +348,8 @@ impl Action for CreateDeterminateNixVolume {
             errors.push(err);
         }
 
-        // Purposefully not reversed
+        // Purposefully not reversed, the `nix` line is removed from `/etc/synthetic.conf` before
+        // refreshing the synthetic objects
         if let Err(err) = self.create_or_append_synthetic_conf.try_revert().await {
             errors.push(err);
         }
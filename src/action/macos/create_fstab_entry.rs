@@ -26,7 +26,36 @@ pub struct CreateFstabEntry {
 impl CreateFstabEntry {
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn plan(apfs_volume_label: String) -> Result<StatefulAction<Self>, ActionError> {
-        Ok(StatefulAction::uncompleted(Self { apfs_volume_label }))
+        let fstab_path = Path::new(FSTAB_PATH);
+        let this = Self { apfs_volume_label };
+
+        if !fstab_path.exists() {
+            return Ok(StatefulAction::uncompleted(this));
+        }
+
+        let fstab_buf = tokio::fs::read_to_string(fstab_path)
+            .await
+            .map_err(|e| Self::error(ActionErrorKind::Read(fstab_path.to_owned(), e)))?;
+
+        if !fstab_buf.lines().any(is_nix_mount_line) {
+            return Ok(StatefulAction::uncompleted(this));
+        }
+
+        // The volume may not exist yet (eg. a fresh install), in which case no entry can match it
+        let uuid = get_disk_info_for_label(&this.apfs_volume_label)
+            .await
+            .map_err(Self::error)?
+            .map(|diskutil_info| diskutil_info.volume_uuid);
+
+        if existing_fstab_entry(&fstab_buf, uuid.as_ref()).map_err(Self::error)? {
+            tracing::debug!(
+                "Entry for the APFS volume `{}` in `{FSTAB_PATH}` already exists",
+                this.apfs_volume_label
+            );
+            return Ok(StatefulAction::completed(this));
+        }
+
+        Ok(StatefulAction::uncompleted(this))
     }
 }
 
@@ -91,13 +120,17 @@ impl Action for CreateFstabEntry {
                     Some(line)
                 }
             })
-            .map(|line| {
-                if line.split(&[' ', '\t']).nth(1) == Some("/nix") {
-                    // Replace the existing line with an updated version
-                    line_present = true;
-                    fstab_entry(&uuid)
+            .filter_map(|line| {
+                if is_nix_mount_line(line) {
+                    // Replace the existing line with an updated version, dropping any duplicates
+                    if line_present {
+                        None
+                    } else {
+                        line_present = true;
+                        Some(fstab_entry(&uuid))
+                    }
                 } else {
-                    line.to_owned()
+                    Some(line.to_owned())
                 }
             })
             .collect::<Vec<String>>();
@@ -153,7 +186,7 @@ impl Action for CreateFstabEntry {
                 }
             })
             .filter_map(|line| {
-                if line.split(&[' ', '\t']).nth(1) == Some("/nix") {
+                if is_nix_mount_line(line) {
                     // Delete the mount line for /nix
                     None
                 } else {
@@ -175,8 +208,37 @@ impl Action for CreateFstabEntry {
     }
 }
 
+const FSTAB_ENTRY_MARKER: &str = "# Added by the Determinate Nix Installer";
+
 fn fstab_entry(uuid: &Uuid) -> String {
-    format!("UUID={uuid} /nix apfs rw,noatime,noauto,nobrowse,nosuid,owners # Added by the Determinate Nix Installer")
+    format!("UUID={uuid} /nix apfs rw,noatime,noauto,nobrowse,nosuid,owners {FSTAB_ENTRY_MARKER}")
+}
+
+fn is_nix_mount_line(line: &str) -> bool {
+    line.split(&[' ', '\t']).nth(1) == Some("/nix")
+}
+
+/// Check an `/etc/fstab` buffer for an entry mounting the volume with `uuid` on `/nix`
+///
+/// Returns `true` if the exact entry is already present. Entries for `/nix` we previously wrote
+/// (eg. for a since deleted volume) are considered stale and will be replaced, any other entry
+/// for `/nix` is a conflict.
+fn existing_fstab_entry(
+    fstab_buf: &str,
+    uuid: Option<&Uuid>,
+) -> Result<bool, CreateFstabEntryError> {
+    let expected_entry = uuid.map(fstab_entry);
+    let mut found = false;
+
+    for line in fstab_buf.lines().filter(|line| is_nix_mount_line(line)) {
+        if Some(line) == expected_entry.as_deref() {
+            found = true;
+        } else if !line.ends_with(FSTAB_ENTRY_MARKER) {
+            return Err(CreateFstabEntryError::ConflictingEntry(line.to_string()));
+        }
+    }
+
+    Ok(found)
 }
 
 #[non_exhaustive]
@@ -184,6 +246,8 @@ fn fstab_entry(uuid: &Uuid) -> String {
 pub enum CreateFstabEntryError {
     #[error("Unable to determine how to add APFS volume `{0}` the `/etc/fstab` line, likely the volume is not yet created or there is some synchronization issue, please report this")]
     CannotDetermineUuid(String),
+    #[error("An existing entry in `/etc/fstab` already mounts something on `/nix`: `{0}`, consider removing it in your editor")]
+    ConflictingEntry(String),
 }
 
 impl From<CreateFstabEntryError> for ActionErrorKind {
@@ -191,3 +255,48 @@ impl From<CreateFstabEntryError> for ActionErrorKind {
         ActionErrorKind::Custom(Box::new(val))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recognizes_existing_entry() -> eyre::Result<()> {
+        let uuid = Uuid::new_v4();
+        let fstab_buf = format!(
+            "UUID={} /Volumes/Other apfs rw\n{}\n",
+            Uuid::new_v4(),
+            fstab_entry(&uuid)
+        );
+
+        assert!(existing_fstab_entry(&fstab_buf, Some(&uuid))?);
+        assert!(!existing_fstab_entry(&fstab_buf, None)?);
+        Ok(())
+    }
+
+    #[test]
+    fn recognizes_stale_entry() -> eyre::Result<()> {
+        let fstab_buf = format!("{}\n", fstab_entry(&Uuid::new_v4()));
+
+        assert!(!existing_fstab_entry(&fstab_buf, Some(&Uuid::new_v4()))?);
+        Ok(())
+    }
+
+    #[test]
+    fn errors_on_conflicting_entry() -> eyre::Result<()> {
+        let uuid = Uuid::new_v4();
+        let fstab_buf = format!("LABEL=Other /nix apfs rw\n{}\n", fstab_entry(&uuid));
+
+        match existing_fstab_entry(&fstab_buf, Some(&uuid)) {
+            Err(CreateFstabEntryError::ConflictingEntry(line)) => {
+                assert_eq!(line, "LABEL=Other /nix apfs rw")
+            },
+            _ => {
+                return Err(eyre::eyre!(
+                    "Should have returned a conflicting entry error"
+                ))
+            },
+        }
+        Ok(())
+    }
+}
@@ -4,7 +4,8 @@ use crate::action::{
         BootstrapLaunchctlService, CreateApfsVolume, CreateSyntheticObjects, EnableOwnership,
         EncryptApfsVolume, UnmountApfsVolume,
     },
-    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionState, ActionTag,
+    StatefulAction,
 };
 use std::{
     path::{Path, PathBuf},
@@ -14,13 +15,44 @@ use tokio::process::Command;
 use tracing::{span, Span};
 
 use super::{
-    create_fstab_entry::CreateFstabEntry, CreateVolumeService, KickstartLaunchctlService,
-    DARWIN_LAUNCHD_DOMAIN,
+    create_fstab_entry::CreateFstabEntry, create_synthetic_objects::SYNTHETIC_CONF_REBOOT_NOTE,
+    get_disk_info_for_label, CreateVolumeService, KickstartLaunchctlService, DARWIN_LAUNCHD_DOMAIN,
 };
 
 pub const NIX_VOLUME_MOUNTD_DEST: &str = "/Library/LaunchDaemons/org.nixos.darwin-store.plist";
 pub const NIX_VOLUME_MOUNTD_NAME: &str = "org.nixos.darwin-store";
 
+fn default_volume_appearance_attempts() -> usize {
+    50
+}
+
+fn default_volume_appearance_interval_ms() -> u64 {
+    100
+}
+
+/// The longest wait between checks for the volume, as the wait doubles after each check
+const VOLUME_APPEARANCE_MAX_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The waits between checks for a volume, doubling from `initial` up to `max`, and adding up to
+/// `timeout` (the last wait is cut short to fit)
+fn volume_appearance_delays(
+    initial: Duration,
+    max: Duration,
+    timeout: Duration,
+) -> impl Iterator<Item = Duration> {
+    let mut next = initial;
+    let mut remaining = timeout;
+    std::iter::from_fn(move || {
+        if remaining.is_zero() || next.is_zero() {
+            return None;
+        }
+        let delay = next.min(remaining);
+        remaining -= delay;
+        next = (next * 2).min(max);
+        Some(delay)
+    })
+}
+
 /// Create an APFS volume
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 #[serde(tag = "action_name", rename = "create_nix_volume")]
@@ -39,6 +71,13 @@ pub struct CreateNixVolume {
     bootstrap_volume: StatefulAction<BootstrapLaunchctlService>,
     kickstart_launchctl_service: StatefulAction<KickstartLaunchctlService>,
     enable_ownership: StatefulAction<EnableOwnership>,
+    /// How long to wait for the volume after creating it, in multiples of
+    /// `volume_appearance_interval_ms`, slow machines may need more
+    #[serde(default = "default_volume_appearance_attempts")]
+    pub(crate) volume_appearance_attempts: usize,
+    /// How long to wait before the second check for the volume, later waits are longer
+    #[serde(default = "default_volume_appearance_interval_ms")]
+    pub(crate) volume_appearance_interval_ms: u64,
 }
 
 impl CreateNixVolume {
@@ -48,6 +87,7 @@ impl CreateNixVolume {
         name: String,
         case_sensitive: bool,
         encrypt: bool,
+        quota: Option<String>,
     ) -> Result<StatefulAction<Self>, ActionError> {
         let disk = disk.as_ref();
         let create_or_append_synthetic_conf = CreateOrInsertIntoFile::plan(
@@ -57,13 +97,14 @@ impl CreateNixVolume {
             None,
             "nix\n".into(), /* The newline is required otherwise it segfaults */
             create_or_insert_into_file::Position::End,
+            false,
         )
         .await
         .map_err(Self::error)?;
 
         let create_synthetic_objects = CreateSyntheticObjects::plan().await.map_err(Self::error)?;
 
-        let create_volume = CreateApfsVolume::plan(disk, name.clone(), case_sensitive)
+        let create_volume = CreateApfsVolume::plan(disk, name.clone(), case_sensitive, quota)
             .await
             .map_err(Self::error)?;
 
@@ -81,6 +122,17 @@ impl CreateNixVolume {
             .await
             .map_err(Self::error)?;
 
+        // A volume made by an unencrypted install is encrypted in place, rather than recreated
+        let encrypting_existing_volume = encrypt
+            && create_volume.state == ActionState::Completed
+            && !get_disk_info_for_label(&name)
+                .await
+                .map_err(Self::error)?
+                .is_some_and(|disk_info| disk_info.file_vault);
+        if encrypting_existing_volume {
+            tracing::info!("Existing volume `{name}` is not encrypted, it will be encrypted");
+        }
+
         let encrypt_volume = if encrypt {
             Some(EncryptApfsVolume::plan(false, disk, &name, &create_volume).await?)
         } else {
@@ -93,6 +145,7 @@ impl CreateNixVolume {
             name.clone(),
             "/nix",
             encrypt,
+            encrypting_existing_volume,
         )
         .await
         .map_err(Self::error)?;
@@ -122,6 +175,8 @@ impl CreateNixVolume {
             bootstrap_volume,
             kickstart_launchctl_service,
             enable_ownership,
+            volume_appearance_attempts: default_volume_appearance_attempts(),
+            volume_appearance_interval_ms: default_volume_appearance_interval_ms(),
         }
         .into())
     }
@@ -185,29 +240,41 @@ impl Action for CreateNixVolume {
             .await
             .map_err(Self::error)?;
 
-        let mut retry_tokens: usize = 50;
+        let interval = Duration::from_millis(self.volume_appearance_interval_ms);
+        let timeout = interval * self.volume_appearance_attempts as u32;
+        let mut delays =
+            volume_appearance_delays(interval, VOLUME_APPEARANCE_MAX_INTERVAL, timeout);
+        let mut waited = Duration::ZERO;
         loop {
             let mut command = Command::new("/usr/sbin/diskutil");
+            // In its own process group, so a Ctrl-C is handled by the installer alone
+            command.process_group(0);
             command.args(["info", "-plist"]);
             command.arg(&self.name);
             command.stderr(std::process::Stdio::null());
             command.stdout(std::process::Stdio::null());
-            tracing::debug!(%retry_tokens, command = ?command.as_std(), "Checking for Nix Store volume existence");
+            tracing::debug!(waited_ms = waited.as_millis(), command = ?command.as_std(), "Checking for Nix Store volume existence");
             let output = command
                 .output()
                 .await
                 .map_err(|e| ActionErrorKind::command(&command, e))
                 .map_err(Self::error)?;
             if output.status.success() {
+                tracing::debug!(waited_ms = waited.as_millis(), "Nix Store volume appeared");
                 break;
-            } else if retry_tokens == 0 {
-                return Err(Self::error(ActionErrorKind::command_output(
-                    &command, output,
-                )));
-            } else {
-                retry_tokens = retry_tokens.saturating_sub(1);
             }
-            tokio::time::sleep(Duration::from_millis(100)).await;
+            match delays.next() {
+                Some(delay) => {
+                    tokio::time::sleep(delay).await;
+                    waited += delay;
+                },
+                None => {
+                    return Err(Self::error(CreateNixVolumeError::VolumeNeverAppeared {
+                        name: self.name.clone(),
+                        waited,
+                    }))
+                },
+            }
         }
 
         self.create_fstab_entry
@@ -259,6 +326,8 @@ impl Action for CreateNixVolume {
         explanation.push(self.bootstrap_volume.tracing_synopsis());
         explanation.push(self.enable_ownership.tracing_synopsis());
 
+        explanation.push(SYNTHETIC_CONF_REBOOT_NOTE.to_string());
+
         vec![ActionDescription::new(
             format!(
                 "Remove the APFS volume `{}` on `{}`",
@@ -316,7 +385,8 @@ impl Action for CreateNixVolume {
             }
         }
 
-        // Purposefully not reversed
+        // Purposefully not reversed, the `nix` line is removed from `/etc/synthetic.conf` before
+        // refreshing the synthetic objects
         if let Err(err) = self.create_or_append_synthetic_conf.try_revert().await {
             errors.push(err);
         }
@@ -337,3 +407,43 @@ impl Action for CreateNixVolume {
         }
     }
 }
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum CreateNixVolumeError {
+    #[error("The APFS volume `{name}` did not appear after waiting {}s, a slow machine may need a higher `volume_appearance_attempts` in the plan", waited.as_secs_f32())]
+    VolumeNeverAppeared { name: String, waited: Duration },
+}
+
+impl From<CreateNixVolumeError> for ActionErrorKind {
+    fn from(val: CreateNixVolumeError) -> Self {
+        ActionErrorKind::Custom(Box::new(val))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volume_appearance_delays_back_off_within_timeout() {
+        let delays = volume_appearance_delays(
+            Duration::from_millis(100),
+            VOLUME_APPEARANCE_MAX_INTERVAL,
+            Duration::from_secs(5),
+        )
+        .map(|delay| delay.as_millis())
+        .collect::<Vec<_>>();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1000, 1000, 1000, 500]);
+
+        assert_eq!(
+            volume_appearance_delays(
+                Duration::from_millis(100),
+                VOLUME_APPEARANCE_MAX_INTERVAL,
+                Duration::ZERO
+            )
+            .count(),
+            0
+        );
+    }
+}
@@ -5,6 +5,9 @@ use crate::execute_command;
 
 use crate::action::{Action, ActionDescription, ActionError, ActionTag, StatefulAction};
 
+/// `/etc/synthetic.conf` is only read at boot, `apfs.util` can create new objects but not remove old ones
+pub(crate) const SYNTHETIC_CONF_REBOOT_NOTE: &str = "The `/nix` mount point is defined in `/etc/synthetic.conf`, removing it there takes effect after the next reboot";
+
 /// Create the synthetic objects defined in `/etc/synthetic.conf`
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct CreateSyntheticObjects;
@@ -63,7 +66,10 @@ impl Action for CreateSyntheticObjects {
     fn revert_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
             "Refresh the objects defined in `/etc/synthetic.conf`".to_string(),
-            vec!["Will remove the `/nix` path".to_string()],
+            vec![
+                "Will remove the `/nix` path".to_string(),
+                SYNTHETIC_CONF_REBOOT_NOTE.to_string(),
+            ],
         )]
     }
 
@@ -39,6 +39,7 @@ impl CreateVolumeService {
         apfs_volume_label: String,
         mount_point: impl AsRef<Path>,
         encrypt: bool,
+        replace_existing: bool,
     ) -> Result<StatefulAction<Self>, ActionError> {
         let path = path.as_ref().to_path_buf();
         let mount_point = mount_point.as_ref().to_path_buf();
@@ -94,7 +95,13 @@ impl CreateVolumeService {
                     )
                     .await
                     .map_err(Self::error)?;
-                    if discovered_plist != expected_plist {
+                    if discovered_plist != expected_plist && replace_existing {
+                        tracing::debug!(
+                            "Existing `{}` will be replaced, as the volume's encryption is changing",
+                            this.path.display()
+                        );
+                        return Ok(StatefulAction::uncompleted(this));
+                    } else if discovered_plist != expected_plist {
                         tracing::trace!(
                             ?discovered_plist,
                             ?expected_plist,
@@ -220,7 +227,8 @@ impl Action for CreateVolumeService {
         .map_err(Self::error)?;
 
         let mut options = OpenOptions::new();
-        options.create(true).write(true).read(true);
+        // An existing plist may be longer than the new one
+        options.create(true).write(true).truncate(true).read(true);
 
         let mut file = options
             .open(&path)
@@ -315,3 +323,64 @@ impl From<CreateVolumeServiceError> for ActionErrorKind {
         ActionErrorKind::Custom(Box::new(val))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{generate_mount_plist, LaunchctlMountPlist};
+
+    #[tokio::test]
+    async fn mount_plist_uses_volume_label() -> eyre::Result<()> {
+        let uuid = uuid::Uuid::new_v4();
+
+        let encrypted = generate_mount_plist(
+            "org.nixos.darwin-store",
+            "Custom Store",
+            uuid,
+            Path::new("/nix"),
+            true,
+        )
+        .await?;
+        assert!(encrypted.program_arguments[2].contains("unlockVolume \"Custom Store\""));
+        assert!(encrypted.program_arguments[2].contains("-a \"Custom Store\""));
+
+        let unencrypted = generate_mount_plist(
+            "org.nixos.darwin-store",
+            "Custom Store",
+            uuid,
+            Path::new("/nix"),
+            false,
+        )
+        .await?;
+        assert_eq!(
+            unencrypted.program_arguments.last(),
+            Some(&uuid.to_string().to_uppercase())
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mount_plist_escapes_volume_label() -> eyre::Result<()> {
+        let plist = generate_mount_plist(
+            "org.nixos.darwin-store",
+            "Nix & <Store>",
+            uuid::Uuid::new_v4(),
+            Path::new("/nix"),
+            true,
+        )
+        .await?;
+
+        let mut buf = Vec::new();
+        plist::to_writer_xml(&mut buf, &plist)?;
+        let xml = String::from_utf8(buf.clone())?;
+        assert!(xml.contains("Nix &amp; &lt;Store&gt;"));
+        assert!(!xml.contains("Nix & <Store>"));
+
+        let parsed: LaunchctlMountPlist = plist::from_bytes(&buf)?;
+        assert_eq!(parsed, plist);
+
+        Ok(())
+    }
+}
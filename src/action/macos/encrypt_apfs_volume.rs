@@ -5,7 +5,7 @@ use crate::{
         macos::NIX_VOLUME_MOUNTD_DEST, Action, ActionDescription, ActionError, ActionErrorKind,
         ActionState, ActionTag, StatefulAction,
     },
-    execute_command,
+    execute_command, execute_command_with_secret,
     os::darwin::DiskUtilApfsListOutput,
 };
 use rand::Rng;
@@ -232,7 +232,9 @@ impl Action for EncryptApfsVolume {
         cmd.arg("/Library/Keychains/System.keychain");
 
         // Add the password to the user keychain so they can unlock it later.
-        execute_command(&mut cmd).await.map_err(Self::error)?;
+        execute_command_with_secret(&mut cmd, &password)
+            .await
+            .map_err(Self::error)?;
 
         // Encrypt the mounted volume
         {
@@ -23,11 +23,11 @@ use std::time::Duration;
 
 pub use bootstrap_launchctl_service::BootstrapLaunchctlService;
 pub use configure_remote_building::ConfigureRemoteBuilding;
-pub use create_apfs_volume::CreateApfsVolume;
+pub use create_apfs_volume::{CreateApfsVolume, CreateApfsVolumeError};
 pub use create_determinate_nix_volume::CreateDeterminateNixVolume;
 pub use create_determinate_volume_service::CreateDeterminateVolumeService;
 pub use create_nix_hook_service::CreateNixHookService;
-pub use create_nix_volume::{CreateNixVolume, NIX_VOLUME_MOUNTD_DEST};
+pub use create_nix_volume::{CreateNixVolume, CreateNixVolumeError, NIX_VOLUME_MOUNTD_DEST};
 pub use create_synthetic_objects::CreateSyntheticObjects;
 pub use create_volume_service::CreateVolumeService;
 pub use enable_ownership::{EnableOwnership, EnableOwnershipError};
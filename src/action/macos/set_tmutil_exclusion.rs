@@ -80,8 +80,8 @@ impl Action for SetTmutilExclusion {
         match tmutil_ret {
             Ok(_) => Ok(()),
             Err(err) => {
-                if let crate::action::ActionErrorKind::CommandOutput { ref output, .. } = err {
-                    if output.status.signal() == Some(9) {
+                if let crate::action::ActionErrorKind::CommandOutput { ref status, .. } = err {
+                    if status.signal() == Some(9) {
                         tracing::debug!(%err, "tmutil failed because it was killed with signal 9; ignoring");
                         return Ok(());
                     }
@@ -110,8 +110,8 @@ impl Action for SetTmutilExclusion {
         match tmutil_ret {
             Ok(_) => Ok(()),
             Err(err) => {
-                if let crate::action::ActionErrorKind::CommandOutput { ref output, .. } = err {
-                    if output.status.signal() == Some(9) {
+                if let crate::action::ActionErrorKind::CommandOutput { ref status, .. } = err {
+                    if status.signal() == Some(9) {
                         tracing::debug!(%err, "tmutil failed because it was killed with signal 9; ignoring");
                         return Ok(());
                     }
@@ -198,7 +198,11 @@ pub mod macos;
 mod stateful;
 
 pub use stateful::{ActionState, StatefulAction};
-use std::{error::Error, os::unix::process::ExitStatusExt as _, process::Output};
+use std::{
+    error::Error,
+    os::unix::process::ExitStatusExt as _,
+    process::{ExitStatus, Output},
+};
 use tokio::task::JoinError;
 use tracing::Span;
 
@@ -257,6 +261,7 @@ pub trait Action: Send + Sync + std::fmt::Debug + dyn_clone::DynClone {
         StatefulAction {
             action: self,
             state: ActionState::Uncompleted,
+            duration: None,
         }
     }
 
@@ -495,36 +500,40 @@ pub enum ActionErrorKind {
     ),
     /// Failed to execute command
     #[error("Failed to execute command `{command}`",
-        command = .command,
+        command = command_line(.program, .args),
     )]
     Command {
-        #[cfg(feature = "diagnostics")]
         program: String,
-        command: String,
+        args: Vec<String>,
         #[source]
         error: std::io::Error,
     },
+    /// A command ran, but did not succeed
     #[error(
-        "Failed to execute command `{command}`\nstdout: {stdout}\nstderr: {stderr}\n{maybe_status}{maybe_signal}",
-        command = .command,
-        stdout = String::from_utf8_lossy(&.output.stdout),
-        stderr = String::from_utf8_lossy(&.output.stderr),
-        maybe_status = if let Some(status) = .output.status.code() {
-            format!("exited with status code: {status}\n")
+        "`{command}` {outcome}{maybe_stderr}{maybe_stdout}",
+        command = command_line(.program, .args),
+        outcome = match (.status.code(), .status.signal()) {
+            (Some(code), _) => format!("exited {code}"),
+            (None, Some(signal)) => format!("was terminated by signal {signal}"),
+            (None, None) => format!("failed ({})", .status),
+        },
+        maybe_stderr = if .stderr.trim().is_empty() {
+            String::new()
         } else {
-            "".to_string()
+            format!(": {}", .stderr.trim())
         },
-        maybe_signal = if let Some(signal) = .output.status.signal() {
-            format!("terminated by signal: {signal}\n")
+        maybe_stdout = if .stdout.trim().is_empty() {
+            String::new()
         } else {
-            "".to_string()
+            format!("\nstdout: {}", .stdout.trim())
         },
     )]
     CommandOutput {
-        #[cfg(feature = "diagnostics")]
         program: String,
-        command: String,
-        output: Output,
+        args: Vec<String>,
+        status: ExitStatus,
+        stdout: String,
+        stderr: String,
     },
     #[error("Joining spawned async task")]
     Join(
@@ -551,6 +560,8 @@ pub enum ActionErrorKind {
         "Could not find a supported command to create users in PATH; please install `useradd` or `adduser`"
     )]
     MissingUserCreationCommand,
+    #[error("The build user shell `{0}` does not exist, consider passing `--nix-build-user-shell` with one that does, like `/usr/sbin/nologin`")]
+    MissingUserShell(std::path::PathBuf),
     #[error("Could not find a supported command to create groups in PATH; please install `groupadd` or `addgroup`")]
     MissingGroupCreationCommand,
     #[error("Could not find a supported command to add users to groups in PATH; please install `gpasswd` or `addgroup`")]
@@ -584,21 +595,92 @@ pub enum ActionErrorKind {
 
 impl ActionErrorKind {
     pub fn command(command: &tokio::process::Command, error: std::io::Error) -> Self {
+        let (program, args) = program_and_args(command);
         Self::Command {
-            #[cfg(feature = "diagnostics")]
-            program: command.as_std().get_program().to_string_lossy().into(),
-            command: format!("{:?}", command.as_std()),
+            program,
+            args,
             error,
         }
     }
-    pub fn command_output(command: &tokio::process::Command, output: std::process::Output) -> Self {
+    pub fn command_output(command: &tokio::process::Command, output: Output) -> Self {
+        let (program, args) = program_and_args(command);
         Self::CommandOutput {
-            #[cfg(feature = "diagnostics")]
-            program: command.as_std().get_program().to_string_lossy().into(),
-            command: format!("{:?}", command.as_std()),
-            output,
+            program,
+            args,
+            status: output.status,
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+    }
+
+    /// The exit code of a command which ran but did not succeed, if it exited rather than being
+    /// terminated by a signal
+    ///
+    /// Some tools report a specific failure (like "already exists") with a documented code, which
+    /// an action may treat as success.
+    pub fn exit_code(&self) -> Option<i32> {
+        match self {
+            Self::CommandOutput { status, .. } => status.code(),
+            Self::Child(child) => child.kind().exit_code(),
+            _ => None,
         }
     }
+
+    /// Replace `secret` in any command this error reports, so it doesn't end up in logs
+    pub(crate) fn redact(self, secret: &str) -> Self {
+        let redact = |s: String| s.replace(secret, "<redacted>");
+        match self {
+            Self::Command {
+                program,
+                args,
+                error,
+            } => Self::Command {
+                program,
+                args: args.into_iter().map(redact).collect(),
+                error,
+            },
+            Self::CommandOutput {
+                program,
+                args,
+                status,
+                stdout,
+                stderr,
+            } => Self::CommandOutput {
+                program,
+                args: args.into_iter().map(redact).collect(),
+                status,
+                stdout: redact(stdout),
+                stderr: redact(stderr),
+            },
+            other => other,
+        }
+    }
+}
+
+fn program_and_args(command: &tokio::process::Command) -> (String, Vec<String>) {
+    let command = command.as_std();
+    (
+        command.get_program().to_string_lossy().into_owned(),
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect(),
+    )
+}
+
+/// The command as a user could paste it into a shell, like `/usr/sbin/diskutil apfs list`
+fn command_line(program: &str, args: &[String]) -> String {
+    std::iter::once(program)
+        .chain(args.iter().map(String::as_str))
+        .map(|word| {
+            if word.is_empty() || word.contains(|c: char| c.is_whitespace() || c == '\'') {
+                format!("'{}'", word.replace('\'', r"'\''"))
+            } else {
+                word.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
 impl HasExpectedErrors for ActionErrorKind {
@@ -608,6 +690,7 @@ impl HasExpectedErrors for ActionErrorKind {
             | Self::PathGroupMismatch(_, _, _)
             | Self::PathModeMismatch(_, _, _) => Some(Box::new(self)),
             Self::SystemdMissing => Some(Box::new(self)),
+            Self::MissingUserShell(_) => Some(Box::new(self)),
             _ => None,
         }
     }
@@ -644,16 +727,7 @@ impl crate::diagnostics::ErrorDiagnostic for ActionErrorKind {
             Self::NoGroup(name) | Self::NoUser(name) => {
                 vec![name.clone()]
             },
-            Self::Command {
-                program,
-                command: _,
-                error: _,
-            }
-            | Self::CommandOutput {
-                program,
-                command: _,
-                output: _,
-            } => {
+            Self::Command { program, .. } | Self::CommandOutput { program, .. } => {
                 vec![program.clone()]
             },
             _ => vec![],
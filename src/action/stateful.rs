@@ -1,3 +1,5 @@
+use std::time::{Duration, Instant};
+
 use serde::{Deserialize, Serialize};
 use tracing::{Instrument, Span};
 
@@ -9,6 +11,10 @@ use super::{Action, ActionDescription, ActionError, ActionTag};
 pub struct StatefulAction<A> {
     pub(crate) action: A,
     pub(crate) state: ActionState,
+    /// How long the last [`try_execute`](StatefulAction::try_execute) took, absent from receipts
+    /// written before this was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) duration: Option<Duration>,
 }
 
 impl<A> From<A> for StatefulAction<A>
@@ -19,6 +25,7 @@ where
         Self {
             action,
             state: ActionState::Uncompleted,
+            duration: None,
         }
     }
 }
@@ -30,6 +37,14 @@ impl StatefulAction<Box<dyn Action>> {
     pub fn tracing_synopsis(&self) -> String {
         self.action.tracing_synopsis()
     }
+    /// Whether the action has been executed, reverted, or skipped
+    pub fn state(&self) -> ActionState {
+        self.state
+    }
+    /// How long the action took to execute, if it was executed
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
     /// A tracing span suitable for the action
     pub fn tracing_span(&self) -> Span {
         self.action.tracing_span()
@@ -72,7 +87,9 @@ impl StatefulAction<Box<dyn Action>> {
             _ => {
                 self.state = ActionState::Progress;
                 tracing::debug!("Executing: {}", self.action.tracing_synopsis());
+                let started = Instant::now();
                 self.action.execute().await?;
+                self.duration = Some(started.elapsed());
                 self.state = ActionState::Completed;
                 tracing::debug!("Completed: {}", self.action.tracing_synopsis());
                 Ok(())
@@ -138,6 +155,7 @@ where
         StatefulAction {
             action: Box::new(self.action),
             state: self.state,
+            duration: self.duration,
         }
     }
     /// A description of what this action would do during execution
@@ -179,7 +197,9 @@ where
                     "Executing: {}",
                     self.action.tracing_synopsis()
                 );
+                let started = Instant::now();
                 self.action.execute().instrument(span.clone()).await?;
+                self.duration = Some(started.elapsed());
                 self.state = ActionState::Completed;
                 tracing::debug!(
                     parent: &span,
@@ -231,6 +251,7 @@ where
         Self {
             state: ActionState::Completed,
             action,
+            duration: None,
         }
     }
 
@@ -238,6 +259,7 @@ where
         Self {
             state: ActionState::Skipped,
             action,
+            duration: None,
         }
     }
 
@@ -245,6 +267,7 @@ where
         Self {
             state: ActionState::Uncompleted,
             action,
+            duration: None,
         }
     }
 }
@@ -28,13 +28,17 @@ async fn main() -> eyre::Result<ExitCode> {
     let (feedback, feedback_worker) = nix_installer::feedback::devnull::dev_null();
 
     #[cfg(feature = "diagnostics")]
-    let (feedback, feedback_worker) = nix_installer::diagnostics::diagnostics(
-        cli.diagnostic_attribution.clone(),
-        cli.diagnostic_endpoint.clone(),
-        cli.ssl_cert_file.clone(),
-        cli.proxy.clone(),
-    )
-    .await;
+    let (feedback, feedback_worker) = if cli.is_offline() {
+        nix_installer::feedback::devnull::dev_null()
+    } else {
+        nix_installer::diagnostics::diagnostics(
+            cli.diagnostic_attribution.clone(),
+            cli.diagnostic_endpoint.clone(),
+            cli.ssl_cert_file.clone(),
+            cli.proxy.clone(),
+        )
+        .await
+    };
 
     let err = cli.execute(feedback).await;
 
@@ -1,9 +1,11 @@
 use eyre::WrapErr;
 use std::error::Error;
 use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::sync::Mutex;
 use tracing_error::ErrorLayer;
 use tracing_subscriber::{
-    filter::Directive, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter,
+    filter::Directive, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
 };
 
 #[derive(Clone, Default, Debug, clap::ValueEnum)]
@@ -40,6 +42,11 @@ pub struct Instrumentation {
     /// See https://docs.rs/tracing-subscriber/latest/tracing_subscriber/filter/struct.EnvFilter.html#directives
     #[clap(long = "log-directive", global = true, env = "NIX_INSTALLER_LOG_DIRECTIVES", value_delimiter = ',', num_args = 0..)]
     pub log_directives: Vec<Directive>,
+    /// Also write logs to this file, appending if it exists
+    ///
+    /// Lines are JSON when `--logger json` is used, otherwise they are in the `full` format without colors.
+    #[clap(long, env = "NIX_INSTALLER_LOG_FILE", global = true)]
+    pub log_file: Option<PathBuf>,
 }
 
 impl Instrumentation {
@@ -55,9 +62,12 @@ impl Instrumentation {
     pub fn setup(&self) -> eyre::Result<()> {
         let filter_layer = self.filter_layer()?;
 
+        let file_layer = self.file_layer()?;
+
         let registry = tracing_subscriber::registry()
             .with(filter_layer)
-            .with(ErrorLayer::default());
+            .with(ErrorLayer::default())
+            .with(file_layer);
 
         match self.logger {
             Logger::Compact => {
@@ -126,6 +136,33 @@ impl Instrumentation {
             .with_line_number(false)
     }
 
+    pub fn file_layer<S>(
+        &self,
+    ) -> eyre::Result<Option<Box<dyn tracing_subscriber::layer::Layer<S> + Send + Sync>>>
+    where
+        S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+    {
+        let Some(log_file) = &self.log_file else {
+            return Ok(None);
+        };
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_file)
+            .wrap_err_with(|| format!("opening log file `{}`", log_file.display()))?;
+
+        let fmt_layer = tracing_subscriber::fmt::Layer::new()
+            .with_ansi(false)
+            .with_writer(Mutex::new(file));
+
+        let layer = match self.logger {
+            Logger::Json => fmt_layer.json().boxed(),
+            Logger::Compact | Logger::Full | Logger::Pretty => fmt_layer.boxed(),
+        };
+
+        Ok(Some(layer))
+    }
+
     pub fn filter_layer(&self) -> eyre::Result<EnvFilter> {
         let mut filter_layer = match EnvFilter::try_from_default_env() {
             Ok(layer) => layer,
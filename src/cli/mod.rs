@@ -13,7 +13,7 @@ use std::{ffi::CString, path::PathBuf, process::ExitCode};
 use tokio::sync::broadcast::{Receiver, Sender};
 use url::Url;
 
-use self::subcommand::NixInstallerSubcommand;
+use self::subcommand::{NixInstallerSubcommand, PlanSubcommand};
 
 const FAIL_PKG_SUGGEST: &str = "\
 The Determinate Nix Installer failed.
@@ -83,6 +83,30 @@ pub struct NixInstallerCli {
     pub subcommand: NixInstallerSubcommand,
 }
 
+impl NixInstallerCli {
+    /// If the invocation asked for no network access (`--offline`)
+    pub fn is_offline(&self) -> bool {
+        match &self.subcommand {
+            NixInstallerSubcommand::Install(install) => {
+                install.settings.offline
+                    || install
+                        .planner
+                        .as_ref()
+                        .is_some_and(|planner| planner.common_settings().offline)
+            },
+            NixInstallerSubcommand::Plan(plan) => matches!(
+                &plan.subcommand,
+                Some(PlanSubcommand::Planner(planner)) if planner.common_settings().offline
+            ),
+            NixInstallerSubcommand::Reinstall(reinstall) => reinstall
+                .planner
+                .as_ref()
+                .is_some_and(|planner| planner.common_settings().offline),
+            _ => false,
+        }
+    }
+}
+
 #[async_trait::async_trait]
 impl CommandExecute for NixInstallerCli {
     #[tracing::instrument(level = "trace", skip_all)]
@@ -96,10 +120,13 @@ impl CommandExecute for NixInstallerCli {
 
         let ret = match self.subcommand {
             NixInstallerSubcommand::Plan(plan) => plan.execute(feedback_clone).await,
+            NixInstallerSubcommand::Planners(planners) => planners.execute(feedback_clone).await,
             NixInstallerSubcommand::SelfTest(self_test) => self_test.execute(feedback_clone).await,
+            NixInstallerSubcommand::Doctor(doctor) => doctor.execute(feedback_clone).await,
             NixInstallerSubcommand::Install(install) => install.execute(feedback_clone).await,
             NixInstallerSubcommand::Repair(repair) => repair.execute(feedback_clone).await,
             NixInstallerSubcommand::Uninstall(revert) => revert.execute(feedback_clone).await,
+            NixInstallerSubcommand::Reinstall(reinstall) => reinstall.execute(feedback_clone).await,
             NixInstallerSubcommand::SplitReceipt(split_receipt) => {
                 split_receipt.execute(feedback_clone).await
             },
@@ -109,7 +136,10 @@ impl CommandExecute for NixInstallerCli {
             err.root_cause()
                 .downcast_ref::<crate::NixInstallerError>()
                 .and_then(|err| {
-                    if matches!(err, crate::NixInstallerError::Cancelled) {
+                    if matches!(
+                        err,
+                        crate::NixInstallerError::Cancelled | crate::NixInstallerError::Terminated
+                    ) {
                         return Some(err);
                     }
                     None
@@ -152,7 +182,9 @@ impl CommandExecute for NixInstallerCli {
     }
 }
 
-pub(crate) async fn signal_channel() -> eyre::Result<(Sender<()>, Receiver<()>)> {
+/// Forward `SIGINT` and `SIGTERM` as [`CancelSignal`](crate::CancelSignal)s
+pub(crate) async fn signal_channel(
+) -> eyre::Result<(Sender<crate::CancelSignal>, Receiver<crate::CancelSignal>)> {
     let (sender, receiver) = tokio::sync::broadcast::channel(100);
 
     let sender_cloned = sender.clone();
@@ -168,11 +200,11 @@ pub(crate) async fn signal_channel() -> eyre::Result<(Sender<()>, Receiver<()>)>
             tokio::select! {
                     Some(()) = ctrl_c.recv() => {
                         tracing::warn!("Got SIGINT signal");
-                        sender_cloned.send(()).ok();
+                        sender_cloned.send(crate::CancelSignal::Interrupt).ok();
                     },
                     Some(()) = terminate.recv() => {
-                        tracing::warn!("Got SIGTERM signal");
-                        sender_cloned.send(()).ok();
+                        tracing::warn!("Got SIGTERM signal, stopping after the current action");
+                        sender_cloned.send(crate::CancelSignal::Terminate).ok();
                     },
             }
         }
@@ -214,7 +246,8 @@ pub fn ensure_root() -> eyre::Result<()> {
                 // Used for detecting what command to suggest for sourcing Nix
                 "SHELL" => true,
                 // Proxy settings (automatically picked up by Reqwest)
-                "HTTP_PROXY" | "http_proxy" | "HTTPS_PROXY" | "https_proxy" => true,
+                "HTTP_PROXY" | "http_proxy" | "HTTPS_PROXY" | "https_proxy" | "ALL_PROXY"
+                | "all_proxy" | "NO_PROXY" | "no_proxy" => true,
                 // Our own environments
                 key if key.starts_with("NIX_INSTALLER") => true,
                 // Our own environments
@@ -0,0 +1,455 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::Parser;
+use nix_config_parser::NixConfig;
+use owo_colors::OwoColorize;
+
+use crate::{
+    cli::CommandExecute,
+    drift::{recorded_actions, recorded_paths, Drift},
+    plan::RECEIPT_LOCATION,
+    planner::ShellProfileLocations,
+    self_test::DAEMON_SOCKET,
+    settings::DEFAULT_NIX_STORE_PATH,
+    InstallPlan,
+};
+
+const NIX_CONF: &str = "/etc/nix/nix.conf";
+/// What a shell profile sources to load a multi-user install, in POSIX shells and in fish
+const DAEMON_PROFILE_SCRIPTS: &[&str] = &["nix-daemon.sh", "nix-daemon.fish"];
+/// What a shell profile sources to load a single-user install
+const SINGLE_USER_PROFILE_SCRIPTS: &[&str] = &["nix.sh"];
+
+/**
+Check the health of an existing install, for sharing when asking for help
+
+Each check reports OK, WARN, or FAIL. Exits non-zero if any check fails.
+*/
+#[derive(Debug, Parser)]
+pub struct Doctor {
+    /// The receipt of the install to check
+    #[clap(long, env = "NIX_INSTALLER_RECEIPT", default_value = RECEIPT_LOCATION)]
+    pub receipt: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Status::Ok => write!(f, "{}", "OK  ".green()),
+            Status::Warn => write!(f, "{}", "WARN".yellow()),
+            Status::Fail => write!(f, "{}", "FAIL".red()),
+        }
+    }
+}
+
+struct Check {
+    name: &'static str,
+    status: Status,
+    detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl CommandExecute for Doctor {
+    #[tracing::instrument(level = "debug", skip_all, fields())]
+    async fn execute<T>(self, _feedback: T) -> eyre::Result<ExitCode>
+    where
+        T: crate::feedback::Feedback,
+    {
+        let Self { receipt } = self;
+
+        let (receipt_check, plan) = check_receipt(&receipt).await;
+        let recorded = plan
+            .as_ref()
+            .and_then(|plan| serde_json::to_value(plan).ok());
+        let nix_store_path = receipt_setting(plan.as_ref(), "nix_store_path")
+            .and_then(|v| v.as_str().map(PathBuf::from))
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_NIX_STORE_PATH));
+        let single_user = plan
+            .as_ref()
+            .is_some_and(|plan| plan.planner.typetag_name() == "single-user");
+        let checks = vec![
+            receipt_check,
+            check_nix_store(&nix_store_path),
+            check_daemon().await,
+            check_build_users(recorded.as_ref()),
+            check_nix_conf(&nix_conf_path(recorded.as_ref())),
+            check_shell_profiles(
+                &shell_profile_paths(recorded.as_ref(), &ShellProfileLocations::default()),
+                if single_user {
+                    SINGLE_USER_PROFILE_SCRIPTS
+                } else {
+                    DAEMON_PROFILE_SCRIPTS
+                },
+            ),
+        ];
+
+        for Check {
+            name,
+            status,
+            detail,
+        } in &checks
+        {
+            println!("{status} {}: {detail}", name.bold());
+        }
+
+        Ok(if checks.iter().any(|check| check.status == Status::Fail) {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        })
+    }
+}
+
+/// A setting of the planner which wrote the receipt
+fn receipt_setting(plan: Option<&InstallPlan>, key: &str) -> Option<serde_json::Value> {
+    plan?.planner.settings().ok()?.remove(key)
+}
+
+async fn check_receipt(receipt: &Path) -> (Check, Option<InstallPlan>) {
+    const NAME: &str = "Receipt";
+    let contents = match tokio::fs::read_to_string(receipt).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return (
+                Check::new(
+                    NAME,
+                    Status::Warn,
+                    format!(
+                        "No receipt at `{}`, so Nix was not installed by `nix-installer`",
+                        receipt.display()
+                    ),
+                ),
+                None,
+            )
+        },
+        Err(e) => {
+            return (
+                Check::new(
+                    NAME,
+                    Status::Fail,
+                    format!("Could not read `{}`: {e}", receipt.display()),
+                ),
+                None,
+            )
+        },
+    };
+
+    match InstallPlan::from_json(&contents) {
+        Ok(plan) => (
+            Check::new(
+                NAME,
+                Status::Ok,
+                format!(
+                    "`{}` was written by `nix-installer` {} using the `{}` planner",
+                    receipt.display(),
+                    plan.version,
+                    plan.planner.typetag_name()
+                ),
+            ),
+            Some(plan),
+        ),
+        Err(e) => (
+            Check::new(
+                NAME,
+                Status::Fail,
+                format!("Could not parse `{}`: {e}", receipt.display()),
+            ),
+            None,
+        ),
+    }
+}
+
+fn check_nix_store(nix_store_path: &Path) -> Check {
+    const NAME: &str = "Nix store";
+    let store = nix_store_path.join("store");
+    if store.is_dir() {
+        Check::new(NAME, Status::Ok, format!("`{}` exists", store.display()))
+    } else {
+        Check::new(
+            NAME,
+            Status::Fail,
+            format!("`{}` does not exist", store.display()),
+        )
+    }
+}
+
+async fn check_daemon() -> Check {
+    const NAME: &str = "Nix daemon";
+    // A root-only or single-user install has no daemon, so no socket
+    if !Path::new(DAEMON_SOCKET).exists() {
+        return Check::new(
+            NAME,
+            Status::Warn,
+            format!("No socket at `{DAEMON_SOCKET}`, expected only for installs without a daemon"),
+        );
+    }
+    match crate::self_test::daemon_reachable().await {
+        Ok(()) => Check::new(
+            NAME,
+            Status::Ok,
+            format!("Accepting connections on `{DAEMON_SOCKET}`"),
+        ),
+        Err(e) => Check::new(NAME, Status::Fail, e.to_string()),
+    }
+}
+
+/// The build users and group the receipt records creating, as `uninstall` would find them
+fn check_build_users(recorded: Option<&serde_json::Value>) -> Check {
+    const NAME: &str = "Build users";
+    let Some(recorded) = recorded else {
+        return Check::new(
+            NAME,
+            Status::Warn,
+            "Without a receipt, the build users to expect are unknown",
+        );
+    };
+    if recorded_actions(recorded, "create_group").is_empty() {
+        return Check::new(NAME, Status::Ok, "None were created by this install");
+    }
+    let drift = crate::drift::detect(recorded)
+        .into_iter()
+        .filter(|drift| {
+            matches!(
+                drift,
+                Drift::MissingUser { .. }
+                    | Drift::UserChanged { .. }
+                    | Drift::MissingGroup { .. }
+                    | Drift::GroupChanged { .. }
+            )
+        })
+        .map(|drift| drift.to_string())
+        .collect::<Vec<_>>();
+    if drift.is_empty() {
+        Check::new(
+            NAME,
+            Status::Ok,
+            "The build users and group in the receipt exist as created",
+        )
+    } else {
+        Check::new(NAME, Status::Fail, drift.join(", "))
+    }
+}
+
+fn check_nix_conf(nix_conf: &Path) -> Check {
+    const NAME: &str = "nix.conf";
+    if !nix_conf.exists() {
+        return Check::new(
+            NAME,
+            Status::Warn,
+            format!("`{}` does not exist", nix_conf.display()),
+        );
+    }
+    match NixConfig::parse_file(nix_conf) {
+        Ok(config) => Check::new(
+            NAME,
+            Status::Ok,
+            format!(
+                "`{}` parses, with {} settings",
+                nix_conf.display(),
+                config.settings().len()
+            ),
+        ),
+        Err(e) => Check::new(
+            NAME,
+            Status::Fail,
+            format!("Could not parse `{}`: {e}", nix_conf.display()),
+        ),
+    }
+}
+
+/// The `nix.conf` the install wrote, like `~/.config/nix/nix.conf` for a single-user install
+/// without `root`
+fn nix_conf_path(recorded: Option<&serde_json::Value>) -> PathBuf {
+    recorded
+        .into_iter()
+        .flat_map(|recorded| recorded_paths(recorded, "create_or_merge_nix_config"))
+        .find(|path| path.file_name().is_some_and(|name| name == "nix.conf"))
+        .unwrap_or_else(|| PathBuf::from(NIX_CONF))
+}
+
+/// The shell profiles the install inserted into, or without a receipt, everywhere it would have
+fn shell_profile_paths(
+    recorded: Option<&serde_json::Value>,
+    locations: &ShellProfileLocations,
+) -> Vec<PathBuf> {
+    if let Some(recorded) = recorded {
+        return recorded_paths(recorded, "create_or_insert_into_file");
+    }
+    let fish = &locations.fish;
+    locations
+        .bash
+        .iter()
+        .chain(locations.zsh.iter())
+        .cloned()
+        .chain(
+            fish.confd_prefixes
+                .iter()
+                .map(|prefix| prefix.join(&fish.confd_suffix)),
+        )
+        .chain(
+            fish.vendor_confd_prefixes
+                .iter()
+                .map(|prefix| prefix.join(&fish.vendor_confd_suffix)),
+        )
+        .collect()
+}
+
+fn check_shell_profiles(paths: &[PathBuf], profile_scripts: &[&str]) -> Check {
+    const NAME: &str = "Shell profiles";
+    let configured = paths
+        .iter()
+        .filter(|path| {
+            std::fs::read_to_string(path).is_ok_and(|contents| {
+                profile_scripts
+                    .iter()
+                    .any(|script| contents.contains(script))
+            })
+        })
+        .map(|path| format!("`{}`", path.display()))
+        .collect::<Vec<_>>();
+    if configured.is_empty() {
+        Check::new(
+            NAME,
+            Status::Warn,
+            format!(
+                "No shell profile sources `{}`, try `nix-installer repair`",
+                profile_scripts.join("` or `")
+            ),
+        )
+    } else {
+        Check::new(
+            NAME,
+            Status::Ok,
+            format!("Nix is loaded by {}", configured.join(", ")),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn completed(action: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({ "action": action, "state": "Completed" })
+    }
+
+    #[test]
+    fn nix_conf_is_found_in_the_receipt() {
+        assert_eq!(nix_conf_path(None), Path::new(NIX_CONF));
+
+        let recorded = serde_json::json!({
+            "actions": [completed(serde_json::json!({
+                "action_name": "create_or_merge_nix_config",
+                "path": "/home/alice/.config/nix/nix.conf",
+            }))],
+        });
+        assert_eq!(
+            nix_conf_path(Some(&recorded)),
+            Path::new("/home/alice/.config/nix/nix.conf")
+        );
+    }
+
+    #[test]
+    fn nix_conf_is_parsed() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let nix_conf = temp_dir.path().join("nix.conf");
+        assert_eq!(check_nix_conf(&nix_conf).status, Status::Warn);
+
+        std::fs::write(&nix_conf, "max-jobs = auto\n")?;
+        assert_eq!(check_nix_conf(&nix_conf).status, Status::Ok);
+
+        std::fs::write(&nix_conf, "include\n")?;
+        assert_eq!(check_nix_conf(&nix_conf).status, Status::Fail);
+        Ok(())
+    }
+
+    #[test]
+    fn shell_profiles_source_the_install_scripts() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let fish = temp_dir.path().join("nix.fish");
+        std::fs::write(
+            &fish,
+            ". /nix/var/nix/profiles/default/etc/profile.d/nix-daemon.fish\n",
+        )?;
+        let profile = temp_dir.path().join(".profile");
+        std::fs::write(&profile, ". \"$HOME/.nix-profile/etc/profile.d/nix.sh\"\n")?;
+
+        let check = check_shell_profiles(&[fish], DAEMON_PROFILE_SCRIPTS);
+        assert_eq!(check.status, Status::Ok, "{}", check.detail);
+        assert_eq!(
+            check_shell_profiles(std::slice::from_ref(&profile), DAEMON_PROFILE_SCRIPTS).status,
+            Status::Warn
+        );
+        assert_eq!(
+            check_shell_profiles(&[profile], SINGLE_USER_PROFILE_SCRIPTS).status,
+            Status::Ok
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn shell_profiles_include_fish_without_a_receipt() {
+        let locations = ShellProfileLocations::default();
+        let paths = shell_profile_paths(None, &locations);
+        let fish_confd = locations.fish.confd_prefixes[0].join(&locations.fish.confd_suffix);
+        assert!(paths.contains(&fish_confd), "{paths:?}");
+
+        let recorded = serde_json::json!({
+            "actions": [completed(serde_json::json!({
+                "action_name": "create_or_insert_into_file",
+                "path": "/home/alice/.profile",
+            }))],
+        });
+        assert_eq!(
+            shell_profile_paths(Some(&recorded), &locations),
+            [PathBuf::from("/home/alice/.profile")]
+        );
+    }
+
+    #[test]
+    fn build_users_are_checked_against_the_receipt() {
+        assert_eq!(check_build_users(None).status, Status::Warn);
+
+        let no_users = serde_json::json!({ "actions": [] });
+        assert_eq!(check_build_users(Some(&no_users)).status, Status::Ok);
+
+        let existing = serde_json::json!({
+            "actions": [completed(serde_json::json!({
+                "action_name": "create_group",
+                "name": "root",
+                "gid": 0,
+            }))],
+        });
+        assert_eq!(check_build_users(Some(&existing)).status, Status::Ok);
+
+        let missing = serde_json::json!({
+            "actions": [completed(serde_json::json!({
+                "action_name": "create_group",
+                "name": "nix-installer-doctor-missing",
+                "gid": 30000,
+            }))],
+        });
+        assert_eq!(check_build_users(Some(&missing)).status, Status::Fail);
+    }
+}
@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use indicatif::{ProgressBar, ProgressStyle};
+use tokio::{sync::mpsc::Sender, task::JoinHandle};
+
+use crate::progress::InstallProgress;
+
+const DOWNLOAD_BAR_TEMPLATE: &str =
+    "{spinner} Downloading Nix {bytes}/{total_bytes} [{bar:30}] {bytes_per_sec}";
+const DOWNLOAD_SPINNER_TEMPLATE: &str = "{spinner} Downloading Nix {bytes} {bytes_per_sec}";
+
+/// Draw the download of the Nix package on stderr, from the events sent to the returned sender
+///
+/// The task finishes once the sender is dropped. Nothing is drawn when stderr is not a terminal.
+pub(crate) fn spawn_download_progress() -> (Sender<InstallProgress>, JoinHandle<()>) {
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(64);
+
+    let handle = tokio::spawn(async move {
+        let mut bar: Option<ProgressBar> = None;
+        while let Some(event) = receiver.recv().await {
+            match event {
+                InstallProgress::Download { downloaded, total } => {
+                    bar.get_or_insert_with(|| download_bar(total))
+                        .set_position(downloaded);
+                    if Some(downloaded) == total {
+                        finish(&mut bar);
+                    }
+                },
+                // A download which failed part way through ends with its action
+                InstallProgress::ActionCompleted { .. } | InstallProgress::ActionFailed { .. } => {
+                    finish(&mut bar)
+                },
+                InstallProgress::ActionStarted { .. } => (),
+            }
+        }
+        finish(&mut bar);
+    });
+
+    (sender, handle)
+}
+
+/// A bar when the size of the download is known, otherwise a spinner
+fn download_bar(total: Option<u64>) -> ProgressBar {
+    let bar = match total {
+        Some(total) => ProgressBar::new(total).with_style(
+            ProgressStyle::with_template(DOWNLOAD_BAR_TEMPLATE)
+                .unwrap_or_else(|_| ProgressStyle::default_bar())
+                .progress_chars("=> "),
+        ),
+        None => ProgressBar::new_spinner().with_style(
+            ProgressStyle::with_template(DOWNLOAD_SPINNER_TEMPLATE)
+                .unwrap_or_else(|_| ProgressStyle::default_spinner()),
+        ),
+    };
+    bar.enable_steady_tick(Duration::from_millis(100));
+    bar
+}
+
+fn finish(bar: &mut Option<ProgressBar>) {
+    if let Some(bar) = bar.take() {
+        bar.finish_and_clear();
+    }
+}
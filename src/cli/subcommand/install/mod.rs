@@ -1,4 +1,5 @@
 mod determinate;
+mod download_progress;
 
 use std::{
     os::unix::prelude::PermissionsExt,
@@ -16,7 +17,7 @@ use crate::{
     },
     error::HasExpectedErrors,
     plan::RECEIPT_LOCATION,
-    settings::CommonSettings,
+    settings::{rooted, CommonSettings},
     util::OnMissing,
     BuiltinPlanner, InstallPlan, NixInstallerError,
 };
@@ -66,6 +67,70 @@ pub struct Install {
     )]
     pub explain: bool,
 
+    /// After a successful install, print the next steps as JSON instead of a message
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_NEXT_STEPS_JSON",
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub next_steps_json: bool,
+
+    /// After a successful install, print how long each action took, slowest first
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_TIMINGS",
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub timings: bool,
+
+    /// Plan the install and print what it would do, without changing anything
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_DRY_RUN",
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub dry_run: bool,
+
+    /// Skip the actions with this action name, like `configure_init_service`, even when nested in another action, for debugging (can be passed multiple times)
+    #[clap(
+        long,
+        value_name = "ACTION_NAME",
+        action(ArgAction::Append),
+        global = true
+    )]
+    pub skip: Vec<String>,
+
+    /// Only run the top level actions which are, or contain, an action with this action name, for debugging (can be passed multiple times)
+    #[clap(
+        long,
+        value_name = "ACTION_NAME",
+        action(ArgAction::Append),
+        global = true
+    )]
+    pub only: Vec<String>,
+
+    /// When Nix was already installed with these settings, plan again and redo only what has drifted
+    ///
+    /// Distinct from `--force`, which recreates existing files while installing.
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_FORCE_REINSTALL",
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub force_reinstall: bool,
+
+    /// A label recorded in the receipt, like the name of the provisioning system running the install
+    #[clap(long, env = "NIX_INSTALLER_TAG", global = true)]
+    pub tag: Option<String>,
+
     /// A path to a non-default installer plan
     #[clap(env = "NIX_INSTALLER_PLAN")]
     pub plan: Option<PathBuf>,
@@ -87,24 +152,19 @@ impl CommandExecute for Install {
             planner: maybe_planner,
             settings,
             explain,
+            next_steps_json,
+            timings,
+            tag,
+            dry_run,
+            skip,
+            only,
+            force_reinstall,
         } = self;
 
-        ensure_root()?;
-
-        let existing_receipt: Option<InstallPlan> = match Path::new(RECEIPT_LOCATION).exists() {
-            true => {
-                tracing::trace!("Reading existing receipt");
-                let install_plan_string = tokio::fs::read_to_string(&RECEIPT_LOCATION)
-                    .await
-                    .wrap_err("Reading plan")?;
-                Some(
-                    serde_json::from_str(&install_plan_string).wrap_err_with(|| {
-                        format!("Unable to parse existing receipt `{RECEIPT_LOCATION}`, it may be from an incompatible version of `nix-installer`. Try running `/nix/nix-installer uninstall`, then installing again.")
-                    })?,
-                )
-            },
-            false => None,
-        };
+        // Single-user installs are done by the user who will own `/nix`
+        if !matches!(maybe_planner, Some(BuiltinPlanner::SingleUser(_))) {
+            ensure_root()?;
+        }
 
         let uninstall_command = match Path::new("/nix/nix-installer").exists() {
             true => "/nix/nix-installer uninstall".into(),
@@ -123,7 +183,15 @@ impl CommandExecute for Install {
             let install_plan_string = tokio::fs::read_to_string(&plan_path)
                 .await
                 .wrap_err("Reading plan")?;
-            serde_json::from_str(&install_plan_string)?
+            let install_plan = InstallPlan::from_json(&install_plan_string)?;
+            let nix_package_stdin = install_plan
+                .planner
+                .settings()
+                .map_err(|e| eyre!(e))?
+                .get("nix_package_stdin")
+                == Some(&serde_json::Value::Bool(true));
+            check_nix_package_stdin(nix_package_stdin, no_confirm)?;
+            install_plan
         } else {
             let mut planner = match maybe_planner {
                 Some(planner) => planner,
@@ -131,6 +199,12 @@ impl CommandExecute for Install {
                     .await
                     .map_err(|e| eyre::eyre!(e))?,
             };
+            check_nix_package_stdin(planner.common_settings().nix_package_stdin, no_confirm)?;
+
+            // An install into another root is only compared with the receipt in that root
+            let receipt_location = planner.common_settings().rooted(RECEIPT_LOCATION);
+            let existing_receipt = read_existing_receipt(&receipt_location).await?;
+            let receipt_location = receipt_location.display();
 
             if let Some(existing_receipt) = existing_receipt {
                 if let Err(e) = existing_receipt.check_compatible() {
@@ -139,7 +213,7 @@ impl CommandExecute for Install {
                         format!("\
                             {e}\n\
                             \n\
-                            Found existing plan in `{RECEIPT_LOCATION}` which was created by a version incompatible `nix-installer`.\n\
+                            Found existing plan in `{receipt_location}` which was created by a version incompatible `nix-installer`.\n\
                             {EXISTING_INCOMPATIBLE_PLAN_GUIDANCE}\n\
                         ").red()
                         );
@@ -147,24 +221,37 @@ impl CommandExecute for Install {
                 }
 
                 if existing_receipt.planner.typetag_name() != planner.typetag_name() {
-                    eprintln!("{}", format!("Found existing plan in `{RECEIPT_LOCATION}` which used a different planner, try uninstalling the existing install with `{uninstall_command}`").red());
+                    eprintln!("{}", format!("Found existing plan in `{receipt_location}` which used a different planner, try uninstalling the existing install with `{uninstall_command}`").red());
                     return Ok(ExitCode::FAILURE);
                 }
 
                 if existing_receipt.planner.settings().map_err(|e| eyre!(e))?
                     != planner.settings().map_err(|e| eyre!(e))?
                 {
-                    eprintln!("{}", format!("Found existing plan in `{RECEIPT_LOCATION}` which used different planner settings, try uninstalling the existing install with `{uninstall_command}`").red());
+                    eprintln!("{}", format!("Found existing plan in `{receipt_location}` which used different planner settings, try uninstalling the existing install with `{uninstall_command}`").red());
                     return Ok(ExitCode::FAILURE);
                 }
 
-                eprintln!("{}", format!("Found existing plan in `{RECEIPT_LOCATION}`, with the same settings, already completed. Try uninstalling (`{uninstall_command}`) and reinstalling if Nix isn't working").red());
-                return Ok(ExitCode::SUCCESS);
+                if force_reinstall {
+                    eprintln!("{}", format!("Found existing plan in `{receipt_location}`, with the same settings. `--force-reinstall` was passed, so planning again over the existing install and redoing every step which is no longer in place").yellow().bold());
+                    planner.common_settings_mut().reconcile_existing = true;
+                } else {
+                    eprintln!("{}", format!("Found existing plan in `{receipt_location}`, with the same settings, already completed. Try uninstalling (`{uninstall_command}`) and reinstalling, or installing again with `--force-reinstall`, if Nix isn't working").red());
+                    // There is nothing a dry run could show, which is a failure for anything relying on it
+                    return Ok(if dry_run {
+                        ExitCode::FAILURE
+                    } else {
+                        ExitCode::SUCCESS
+                    });
+                }
             }
 
-            post_install_message =
-                determinate::prompt_for_determinate(&mut feedback, &mut planner, no_confirm)
-                    .await?;
+            post_install_message = determinate::prompt_for_determinate(
+                &mut feedback,
+                &mut planner,
+                no_confirm || dry_run,
+            )
+            .await?;
 
             feedback.set_planner(&planner).await?;
 
@@ -182,6 +269,18 @@ impl CommandExecute for Install {
             }
         };
 
+        if tag.is_some() {
+            install_plan.set_tag(tag);
+        }
+
+        if let Err(err) = install_plan.filter_actions(&only, &skip) {
+            if let Some(expected) = err.expected() {
+                eprintln!("{}", expected.red());
+                return Ok(ExitCode::FAILURE);
+            }
+            Err(err)?
+        }
+
         feedback.planning_succeeded().await;
 
         if let Err(err) = install_plan.pre_install_check().await {
@@ -192,6 +291,17 @@ impl CommandExecute for Install {
             Err(err)?
         }
 
+        if dry_run {
+            println!(
+                "{}",
+                install_plan
+                    .describe_install(explain)
+                    .await
+                    .map_err(|e| eyre!(e))?
+            );
+            return Ok(ExitCode::SUCCESS);
+        }
+
         if !no_confirm {
             let mut currently_explaining = explain;
             loop {
@@ -219,19 +329,37 @@ impl CommandExecute for Install {
 
         let (tx, rx1) = signal_channel().await?;
 
-        match install_plan.install(feedback.clone(), rx1).await {
+        let (progress, download_progress) = download_progress::spawn_download_progress();
+        let res = install_plan
+            .install_with_progress(feedback.clone(), rx1, progress)
+            .await;
+        // The download bar is cleared before anything else is printed
+        download_progress.await.ok();
+
+        match res {
             Err(err) => {
                 // Attempt to copy self to the store if possible, but since the install failed, this might not work, that's ok.
-                copy_self_to_nix_dir().await.ok();
+                copy_self_to_nix_dir(install_plan.root().as_deref())
+                    .await
+                    .ok();
 
-                if !no_confirm {
+                // A service manager is stopping us, leave the partial install for a later
+                // `nix-installer uninstall` rather than prompting or reverting
+                if matches!(err, NixInstallerError::Terminated) {
+                    eprintln!("{}", err.red());
+                    return Ok(ExitCode::FAILURE);
+                }
+
+                // An interrupted install is reverted even without confirmation, so a Ctrl-C
+                // part way through an action (like creating the Nix volume) leaves no trace
+                let was_cancelled = matches!(err, NixInstallerError::Cancelled);
+                if !no_confirm || was_cancelled {
                     let mut was_expected = false;
                     if let Some(expected) = err.expected() {
                         was_expected = true;
                         eprintln!("{}", expected.red())
                     }
 
-                    let was_cancelled = matches!(err, NixInstallerError::Cancelled);
                     if was_cancelled {
                         eprintln!("{}", err.red());
                     }
@@ -241,27 +369,35 @@ impl CommandExecute for Install {
                         tracing::error!("{:?}", error);
                     };
 
-                    eprintln!("{}", "Installation failure, offering to revert...".red());
-                    let mut currently_explaining = explain;
-                    loop {
-                        match interaction::prompt(
-                            install_plan
-                                .describe_uninstall(currently_explaining)
-                                .await
-                                .map_err(|e| eyre!(e))?,
-                            PromptChoice::Yes,
-                            currently_explaining,
-                        )
-                        .await?
-                        {
-                            PromptChoice::Yes => break,
-                            PromptChoice::Explain => currently_explaining = true,
-                            PromptChoice::No => {
-                                interaction::clean_exit_with_message(
-                                    "Okay, didn't do anything! Bye!",
-                                )
-                                .await
-                            },
+                    if no_confirm {
+                        eprintln!(
+                            "{}",
+                            "Installation interrupted, reverting the steps which had started..."
+                                .red()
+                        );
+                    } else {
+                        eprintln!("{}", "Installation failure, offering to revert...".red());
+                        let mut currently_explaining = explain;
+                        loop {
+                            match interaction::prompt(
+                                install_plan
+                                    .describe_uninstall(currently_explaining)
+                                    .await
+                                    .map_err(|e| eyre!(e))?,
+                                PromptChoice::Yes,
+                                currently_explaining,
+                            )
+                            .await?
+                            {
+                                PromptChoice::Yes => break,
+                                PromptChoice::Explain => currently_explaining = true,
+                                PromptChoice::No => {
+                                    interaction::clean_exit_with_message(
+                                        "Okay, didn't do anything! Bye!",
+                                    )
+                                    .await
+                                },
+                            }
                         }
                     }
                     let rx2 = tx.subscribe();
@@ -311,7 +447,7 @@ impl CommandExecute for Install {
                 }
             },
             Ok(_) => {
-                copy_self_to_nix_dir()
+                copy_self_to_nix_dir(install_plan.root().as_deref())
                     .await
                     .wrap_err("Copying `nix-installer` to `/nix/nix-installer`")?;
 
@@ -331,23 +467,31 @@ impl CommandExecute for Install {
                         .wrap_err_with(|| format!("Failed to remove uninstall phase 2 receipt at {PHASE2_RECEIPT_LOCATION}"))?;
                 }
 
-                println!(
-                    "\
-                    {success}\n\
-                    To get started using Nix, open a new shell or run `{shell_reminder}`\n\
-                    ",
-                    success = "Nix was installed successfully!".green().bold(),
-                    shell_reminder = match std::env::var("SHELL") {
-                        Ok(val) if val.contains("fish") =>
-                            ". /nix/var/nix/profiles/default/etc/profile.d/nix-daemon.fish".bold(),
-                        Ok(_) | Err(_) =>
-                            ". /nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh".bold(),
-                    },
-                );
+                let next_steps = install_plan
+                    .next_steps(std::env::var("SHELL").ok().as_deref())
+                    .map_err(|e| eyre!(e))?;
+                if next_steps_json {
+                    println!(
+                        "{}",
+                        serde_json::to_string(&next_steps).wrap_err("Serializing next steps")?
+                    );
+                } else {
+                    println!(
+                        "\
+                        {success}\n\
+                        {next_steps}\
+                        ",
+                        success = "Nix was installed successfully!".green().bold(),
+                    );
+                }
 
                 if let Some(msg) = post_install_message {
                     println!("{}\n", msg.trim());
                 }
+
+                if timings {
+                    print_timings(&install_plan);
+                }
             },
         }
 
@@ -355,10 +499,93 @@ impl CommandExecute for Install {
     }
 }
 
+/// Refuse to read the Nix package from stdin when stdin is also needed to answer prompts
+///
+/// This is checked on the planner (or plan) being used, as a planner subcommand or plan file
+/// carries its own settings.
+pub(crate) fn check_nix_package_stdin(
+    nix_package_stdin: bool,
+    no_confirm: bool,
+) -> eyre::Result<()> {
+    if nix_package_stdin && !no_confirm {
+        return Err(eyre!("`--nix-package-stdin` requires `--no-confirm`, as stdin is used to read the Nix package and cannot also be used for prompts"));
+    }
+    Ok(())
+}
+
+/// Print the actions which ran, slowest first
+fn print_timings(install_plan: &InstallPlan) {
+    let mut timings = install_plan
+        .actions
+        .iter()
+        .filter_map(|action| Some((action.duration()?, action.tracing_synopsis())))
+        .collect::<Vec<_>>();
+    timings.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    let total: std::time::Duration = timings.iter().map(|(duration, _)| *duration).sum();
+    println!("{}", "Timings".bold());
+    for (duration, synopsis) in timings {
+        println!("{:>9.2}s  {synopsis}", duration.as_secs_f64());
+    }
+    println!("{:>9.2}s  {}\n", total.as_secs_f64(), "Total".bold());
+}
+
 #[tracing::instrument(level = "debug")]
-async fn copy_self_to_nix_dir() -> Result<(), std::io::Error> {
+/// Copy `nix-installer` to `/nix/nix-installer` (under `root`, if installing into another root)
+pub(crate) async fn copy_self_to_nix_dir(root: Option<&Path>) -> Result<(), std::io::Error> {
     let path = std::env::current_exe()?;
-    tokio::fs::copy(path, "/nix/nix-installer").await?;
-    tokio::fs::set_permissions("/nix/nix-installer", PermissionsExt::from_mode(0o0755)).await?;
+    let dest = rooted(root, "/nix/nix-installer");
+    tokio::fs::copy(path, &dest).await?;
+    tokio::fs::set_permissions(&dest, PermissionsExt::from_mode(0o0755)).await?;
     Ok(())
 }
+
+async fn read_existing_receipt(receipt_location: &Path) -> eyre::Result<Option<InstallPlan>> {
+    if !receipt_location.exists() {
+        return Ok(None);
+    }
+    tracing::trace!("Reading existing receipt");
+    let install_plan_string = tokio::fs::read_to_string(receipt_location)
+        .await
+        .wrap_err("Reading plan")?;
+    Ok(Some(InstallPlan::from_json(&install_plan_string).wrap_err_with(|| {
+        format!("Unable to parse existing receipt `{}`, it may be from an incompatible version of `nix-installer`. Try running `/nix/nix-installer uninstall`, then installing again.", receipt_location.display())
+    })?))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use clap::Parser;
+
+    use super::{check_nix_package_stdin, eyre, Install};
+    use crate::cli::{subcommand::NixInstallerSubcommand, NixInstallerCli};
+
+    fn parse_install(args: &[&str]) -> eyre::Result<Install> {
+        let cli = NixInstallerCli::try_parse_from(
+            std::iter::once("nix-installer").chain(args.iter().copied()),
+        )?;
+        match cli.subcommand {
+            NixInstallerSubcommand::Install(install) => Ok(install),
+            subcommand => Err(eyre!("Expected `install`, got {subcommand:?}")),
+        }
+    }
+
+    #[test]
+    fn planner_nix_package_stdin_requires_no_confirm() -> eyre::Result<()> {
+        let install = parse_install(&["install", "linux", "--nix-package-stdin"])?;
+        let planner = install.planner.expect("a planner was passed");
+        assert!(check_nix_package_stdin(
+            planner.common_settings().nix_package_stdin,
+            install.no_confirm
+        )
+        .is_err());
+
+        let install = parse_install(&["install", "linux", "--nix-package-stdin", "--no-confirm"])?;
+        let planner = install.planner.expect("a planner was passed");
+        check_nix_package_stdin(
+            planner.common_settings().nix_package_stdin,
+            install.no_confirm,
+        )?;
+        Ok(())
+    }
+}
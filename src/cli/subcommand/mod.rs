@@ -1,12 +1,20 @@
+mod doctor;
 mod install;
 mod plan;
+mod plan_diff;
+mod planners;
+mod reinstall;
 mod repair;
 mod self_test;
 mod split_receipt;
 mod uninstall;
 
+use doctor::Doctor;
 use install::Install;
 use plan::Plan;
+pub(crate) use plan::PlanSubcommand;
+use planners::Planners;
+use reinstall::Reinstall;
 use repair::Repair;
 use self_test::SelfTest;
 use split_receipt::SplitReceipt;
@@ -18,7 +26,10 @@ pub enum NixInstallerSubcommand {
     Install(Install),
     Repair(Repair),
     Uninstall(Uninstall),
+    Reinstall(Reinstall),
     SelfTest(SelfTest),
+    Doctor(Doctor),
     Plan(Plan),
+    Planners(Planners),
     SplitReceipt(SplitReceipt),
 }
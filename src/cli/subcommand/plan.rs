@@ -1,13 +1,28 @@
-use std::{path::PathBuf, process::ExitCode};
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
 use crate::{cli::ensure_root, error::HasExpectedErrors, BuiltinPlanner};
 use clap::Parser;
 
 use eyre::WrapErr;
 use owo_colors::OwoColorize;
+use tokio::io::AsyncWriteExt;
 
 use crate::cli::CommandExecute;
 
+use super::plan_diff::PlanDiff;
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, clap::Subcommand)]
+pub enum PlanSubcommand {
+    /// Compare two install plans (or receipts)
+    Diff(PlanDiff),
+    #[clap(flatten)]
+    Planner(BuiltinPlanner),
+}
+
 /**
 Emit a JSON install plan that can be manually edited before execution
 
@@ -16,12 +31,13 @@ Primarily intended for development, debugging, and handling install cases.
 #[derive(Debug, Parser)]
 pub struct Plan {
     #[clap(subcommand)]
-    pub planner: Option<BuiltinPlanner>,
-    /// Where to write the generated plan (in JSON format)
+    pub subcommand: Option<PlanSubcommand>,
+    /// Where to write the generated plan (in JSON format, for `install --plan`), or `-` for stdout
     #[clap(
         long = "out-file",
+        alias = "output",
         env = "NIX_INSTALLER_PLAN_OUT_FILE",
-        default_value = "/dev/stdout"
+        default_value = "-"
     )]
     pub output: PathBuf,
 }
@@ -33,15 +49,18 @@ impl CommandExecute for Plan {
     where
         T: crate::feedback::Feedback,
     {
-        let Self { planner, output } = self;
+        let Self { subcommand, output } = self;
 
-        ensure_root()?;
-
-        let planner = match planner {
-            Some(planner) => planner,
+        let planner = match subcommand {
+            Some(PlanSubcommand::Diff(diff)) => return diff.execute(feedback).await,
+            Some(PlanSubcommand::Planner(planner)) => planner,
             None => BuiltinPlanner::default().await?,
         };
 
+        if !matches!(planner, BuiltinPlanner::SingleUser(_)) {
+            ensure_root()?;
+        }
+
         feedback.set_planner(&planner).await?;
 
         let res = planner.plan().await;
@@ -60,10 +79,20 @@ impl CommandExecute for Plan {
 
         feedback.planning_succeeded().await;
 
-        let json = serde_json::to_string_pretty(&install_plan)?;
-        tokio::fs::write(output, format!("{json}\n"))
-            .await
-            .wrap_err("Writing plan")?;
+        // Pretty printed, so edits to a plan make for readable diffs
+        let json = format!("{}\n", install_plan.to_json()?);
+        if output == Path::new("-") {
+            let mut stdout = tokio::io::stdout();
+            stdout
+                .write_all(json.as_bytes())
+                .await
+                .wrap_err("Writing plan")?;
+            stdout.flush().await.wrap_err("Writing plan")?;
+        } else {
+            tokio::fs::write(&output, json)
+                .await
+                .wrap_err_with(|| format!("Writing plan to `{}`", output.display()))?;
+        }
 
         Ok(ExitCode::SUCCESS)
     }
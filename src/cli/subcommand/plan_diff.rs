@@ -0,0 +1,208 @@
+use std::{collections::BTreeSet, path::PathBuf, process::ExitCode};
+
+use clap::Parser;
+use eyre::WrapErr;
+use owo_colors::OwoColorize;
+
+use crate::{cli::CommandExecute, InstallPlan};
+
+/**
+Compare two install plans (or receipts)
+
+Prints the planner settings which differ, and the actions which were added, removed, or
+reordered. Exits with a failure if the plans differ.
+*/
+#[derive(Debug, Parser)]
+pub struct PlanDiff {
+    /// The original plan
+    pub a: PathBuf,
+    /// The plan to compare to it
+    pub b: PathBuf,
+}
+
+#[async_trait::async_trait]
+impl CommandExecute for PlanDiff {
+    #[tracing::instrument(level = "debug", skip_all, fields())]
+    async fn execute<T>(self, _feedback: T) -> eyre::Result<ExitCode>
+    where
+        T: crate::feedback::Feedback,
+    {
+        let Self { a, b } = self;
+
+        let a = read_plan(&a).await?;
+        let b = read_plan(&b).await?;
+
+        let mut differs = false;
+
+        let (a_planner, b_planner) = (a.planner.typetag_name(), b.planner.typetag_name());
+        if a_planner != b_planner {
+            differs = true;
+            println!("{}", "Planner".bold());
+            println!("{}", format!("- {a_planner}").red());
+            println!("{}", format!("+ {b_planner}").green());
+        }
+
+        let a_settings = a.planner.settings()?;
+        let b_settings = b.planner.settings()?;
+        let keys = a_settings
+            .keys()
+            .chain(b_settings.keys())
+            .collect::<BTreeSet<_>>();
+        let mut settings_lines = vec![];
+        for key in keys {
+            let (a_value, b_value) = (a_settings.get(key), b_settings.get(key));
+            if a_value == b_value {
+                continue;
+            }
+            if let Some(a_value) = a_value {
+                settings_lines.push(format!("- {key}: {a_value}").red().to_string());
+            }
+            if let Some(b_value) = b_value {
+                settings_lines.push(format!("+ {key}: {b_value}").green().to_string());
+            }
+        }
+        if !settings_lines.is_empty() {
+            differs = true;
+            println!("{}", "Settings".bold());
+            for line in settings_lines {
+                println!("{line}");
+            }
+        }
+
+        let a_actions = synopses(&a);
+        let b_actions = synopses(&b);
+        if a_actions != b_actions {
+            differs = true;
+            println!("{}", "Actions".bold());
+            for line in diff_lines(&a_actions, &b_actions) {
+                match line {
+                    DiffLine::Same(synopsis) => println!("  {}", synopsis.dimmed()),
+                    DiffLine::Removed(synopsis) => println!("{}", format!("- {synopsis}").red()),
+                    DiffLine::Added(synopsis) => println!("{}", format!("+ {synopsis}").green()),
+                    DiffLine::Moved(synopsis) => {
+                        println!("{}", format!("~ {synopsis} (reordered)").yellow())
+                    },
+                }
+            }
+        }
+
+        if differs {
+            Ok(ExitCode::FAILURE)
+        } else {
+            println!("The plans are the same");
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+async fn read_plan(path: &PathBuf) -> eyre::Result<InstallPlan> {
+    let json = tokio::fs::read_to_string(path)
+        .await
+        .wrap_err_with(|| format!("Reading plan `{}`", path.display()))?;
+    let plan = serde_json::from_str(&json)
+        .wrap_err_with(|| format!("Parsing plan `{}`", path.display()))?;
+    Ok(plan)
+}
+
+fn synopses(plan: &InstallPlan) -> Vec<String> {
+    plan.actions
+        .iter()
+        .map(|action| action.tracing_synopsis())
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+    /// In both lists, but not in the same order
+    Moved(&'a str),
+}
+
+/// A line diff of `a` and `b` via their longest common subsequence
+fn diff_lines<'a>(a: &'a [String], b: &'a [String]) -> Vec<DiffLine<'a>> {
+    // `lcs[i][j]` is the length of the longest common subsequence of `a[i..]` and `b[j..]`
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = vec![];
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            lines.push(DiffLine::Same(&a[i]));
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            // A reordered action is reported where it now is, in `b`
+            if !b.contains(&a[i]) {
+                lines.push(DiffLine::Removed(&a[i]));
+            }
+            i += 1;
+        } else {
+            if a.contains(&b[j]) {
+                lines.push(DiffLine::Moved(&b[j]));
+            } else {
+                lines.push(DiffLine::Added(&b[j]));
+            }
+            j += 1;
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::{diff_lines, DiffLine};
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn diffs_added_and_removed() {
+        let a = lines(&["a", "b", "c"]);
+        let b = lines(&["a", "c", "d"]);
+        assert_eq!(
+            diff_lines(&a, &b),
+            vec![
+                DiffLine::Same("a"),
+                DiffLine::Removed("b"),
+                DiffLine::Same("c"),
+                DiffLine::Added("d"),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_moved_where_it_now_is() {
+        let a = lines(&["a", "b", "c"]);
+        let b = lines(&["b", "c", "a"]);
+        assert_eq!(
+            diff_lines(&a, &b),
+            vec![
+                DiffLine::Same("b"),
+                DiffLine::Same("c"),
+                DiffLine::Moved("a"),
+            ]
+        );
+    }
+
+    #[test]
+    fn same_plans_have_no_changes() {
+        let a = lines(&["a", "b"]);
+        assert_eq!(
+            diff_lines(&a, &a),
+            vec![DiffLine::Same("a"), DiffLine::Same("b")]
+        );
+        assert_eq!(diff_lines(&[], &[]), vec![]);
+    }
+}
@@ -0,0 +1,143 @@
+use std::{collections::BTreeMap, process::ExitCode};
+
+use clap::{ArgAction, Parser, Subcommand};
+use owo_colors::OwoColorize;
+
+use crate::{cli::CommandExecute, BuiltinPlanner};
+
+/**
+List the built-in planners, with their default settings
+
+Planners which do not suit this host are listed too, noting why. Pass `--json` for a
+machine-readable catalog, like for generating a form of the settings.
+*/
+#[derive(Debug, Parser)]
+pub struct Planners {
+    /// Print the catalog as JSON
+    #[clap(long, action(ArgAction::SetTrue), default_value = "false")]
+    pub json: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct PlannerEntry {
+    name: &'static str,
+    description: Option<String>,
+    /// Whether the planner can install on this host
+    applicable: bool,
+    /// Why the planner cannot install on this host, or find its defaults here
+    reason: Option<String>,
+    /// The default settings, unless the planner cannot find them on this host
+    settings: Option<BTreeMap<String, SettingEntry>>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct SettingEntry {
+    default: serde_json::Value,
+    description: Option<String>,
+}
+
+#[async_trait::async_trait]
+impl CommandExecute for Planners {
+    #[tracing::instrument(level = "debug", skip_all, fields())]
+    async fn execute<T>(self, _feedback: T) -> eyre::Result<ExitCode>
+    where
+        T: crate::feedback::Feedback,
+    {
+        let Self { json } = self;
+
+        let command = BuiltinPlanner::augment_subcommands(clap::Command::new("planners"));
+        let mut entries = Vec::with_capacity(BuiltinPlanner::NAMES.len());
+        for name in BuiltinPlanner::NAMES {
+            let subcommand = command.find_subcommand(name);
+            let description = subcommand
+                .and_then(|subcommand| subcommand.get_about())
+                .map(|about| about.to_string());
+
+            let planner = match BuiltinPlanner::default_named(name).await {
+                Ok(planner) => planner.expect("every name is a built-in planner"),
+                Err(e) => {
+                    entries.push(PlannerEntry {
+                        name,
+                        description,
+                        applicable: false,
+                        reason: Some(error_chain(&e)),
+                        settings: None,
+                    });
+                    continue;
+                },
+            };
+            let reason = planner
+                .platform_check()
+                .await
+                .err()
+                .map(|e| error_chain(&e));
+            let settings = planner
+                .settings()?
+                .into_iter()
+                .map(|(key, default)| {
+                    let description = subcommand
+                        .and_then(|subcommand| {
+                            subcommand
+                                .get_arguments()
+                                .find(|arg| arg.get_id().as_str() == key)
+                        })
+                        .and_then(|arg| arg.get_help())
+                        .map(|help| help.to_string());
+                    (
+                        key,
+                        SettingEntry {
+                            default,
+                            description,
+                        },
+                    )
+                })
+                .collect();
+            entries.push(PlannerEntry {
+                name,
+                description,
+                applicable: reason.is_none(),
+                reason,
+                settings: Some(settings),
+            });
+        }
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        for PlannerEntry {
+            name,
+            description,
+            applicable,
+            reason,
+            settings: _,
+        } in &entries
+        {
+            let description = description.as_deref().unwrap_or_default();
+            if *applicable {
+                println!("{}: {description}", name.bold());
+            } else {
+                println!(
+                    "{}: {description}\n  {}",
+                    name.bold().dimmed(),
+                    format!(
+                        "Not for this host: {}",
+                        reason.as_deref().unwrap_or_default()
+                    )
+                    .dimmed()
+                );
+            }
+        }
+
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+/// An error with its sources, as the planner errors wrap the reason a planner does not suit the host
+fn error_chain(err: &(dyn std::error::Error + 'static)) -> String {
+    std::iter::successors(Some(err), |err| err.source())
+        .map(|err| err.to_string())
+        .collect::<Vec<_>>()
+        .join(": ")
+}
@@ -0,0 +1,260 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use crate::{
+    cli::{
+        ensure_root,
+        interaction::{self, PromptChoice},
+        signal_channel, CommandExecute,
+    },
+    error::HasExpectedErrors,
+    plan::RECEIPT_LOCATION,
+    BuiltinPlanner, InstallPlan, NixInstallerError,
+};
+use clap::{ArgAction, Parser};
+use color_eyre::eyre::{eyre, WrapErr};
+use owo_colors::OwoColorize;
+
+use super::install::{check_nix_package_stdin, copy_self_to_nix_dir};
+
+/**
+Uninstall a previously `nix-installer` installed Nix, then install it again
+
+The install reuses the receipt's planner and settings, unless a planner (with its settings) is
+passed. If the uninstall fails, nothing is installed.
+*/
+#[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Reinstall {
+    /// Run the reinstall without requiring explicit user confirmation
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_NO_CONFIRM",
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub no_confirm: bool,
+
+    /// Provide an explanation of the changes the reinstall will make to your system
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_EXPLAIN",
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub explain: bool,
+
+    /// Leave the build users and their group in place while uninstalling, for the install to reuse
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_KEEP_USERS",
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub keep_users: bool,
+
+    /// The receipt of the install to replace
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_RECEIPT",
+        default_value = RECEIPT_LOCATION,
+        global = true
+    )]
+    pub receipt: PathBuf,
+
+    /// Install with this planner and its settings, instead of those in the receipt
+    #[clap(subcommand)]
+    pub planner: Option<BuiltinPlanner>,
+}
+
+#[async_trait::async_trait]
+impl CommandExecute for Reinstall {
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute<T>(self, feedback: T) -> eyre::Result<ExitCode>
+    where
+        T: crate::feedback::Feedback,
+    {
+        let Self {
+            no_confirm,
+            explain,
+            keep_users,
+            receipt,
+            planner: maybe_planner,
+        } = self;
+
+        ensure_root()?;
+
+        if !receipt.exists() {
+            return Err(eyre!(
+                "No receipt found at `{}`, to install Nix use `nix-installer install`",
+                receipt.display()
+            ));
+        }
+        let receipt_string = tokio::fs::read_to_string(&receipt)
+            .await
+            .wrap_err_with(|| format!("Reading receipt `{}`", receipt.display()))?;
+        let mut existing_plan = InstallPlan::from_json(&receipt_string)?;
+
+        let planner = match maybe_planner {
+            Some(planner) => planner,
+            None => match BuiltinPlanner::from_planner(&*existing_plan.planner)
+                .wrap_err("Reading the planner of the receipt")?
+            {
+                Some(planner) => planner,
+                None => {
+                    return Err(eyre!(
+                        "The receipt at `{}` was made by the custom `{}` planner, pass a planner to install with",
+                        receipt.display(),
+                        existing_plan.planner.typetag_name()
+                    ))
+                },
+            },
+        };
+
+        // Refuse settings the new install would reject while the existing install is still there
+        if let Err(err) = planner.check_settings() {
+            if let Some(expected) = err.expected() {
+                eprintln!("{}", expected.red());
+                return Ok(ExitCode::FAILURE);
+            }
+            Err(err)?
+        }
+
+        if let Err(err) = existing_plan.pre_uninstall_check().await {
+            if let Some(expected) = err.expected() {
+                eprintln!("{}", expected.red());
+                return Ok(ExitCode::FAILURE);
+            }
+            Err(err)?
+        }
+
+        check_nix_package_stdin(planner.common_settings().nix_package_stdin, no_confirm)?;
+
+        if keep_users {
+            let kept = existing_plan.keep_build_users();
+            tracing::debug!(kept, "Keeping the build users and group");
+        }
+
+        if !no_confirm {
+            let mut currently_explaining = explain;
+            loop {
+                let description = format!(
+                    "{uninstall}\n{install}",
+                    uninstall = existing_plan
+                        .describe_uninstall(currently_explaining)
+                        .await
+                        .map_err(|e| eyre!(e))?,
+                    install = describe_reinstall(&planner).await?,
+                );
+                match interaction::prompt(description, PromptChoice::Yes, currently_explaining)
+                    .await?
+                {
+                    PromptChoice::Yes => break,
+                    PromptChoice::Explain => currently_explaining = true,
+                    PromptChoice::No => {
+                        interaction::clean_exit_with_message(
+                            "Okay, not continuing with the reinstallation. Bye!",
+                        )
+                        .await
+                    },
+                }
+            }
+        }
+
+        let (tx, rx) = signal_channel().await?;
+
+        if let Err(err) = existing_plan.uninstall(feedback.clone(), rx).await {
+            eprintln!(
+                "{}",
+                "Uninstalling failed, so Nix was not installed again".red()
+            );
+            if let Some(expected) = err.expected() {
+                eprintln!("{}", expected.red());
+                return Ok(ExitCode::FAILURE);
+            }
+            return Err(err)?;
+        }
+        println!("{}", "Nix was uninstalled, installing again".bold());
+
+        // The install can only be planned once the existing one is gone
+        let mut install_plan = match planner.plan().await {
+            Ok(install_plan) => install_plan,
+            Err(err) => {
+                eprintln!(
+                    "{}",
+                    "Nix was uninstalled, but planning the new install failed".red()
+                );
+                if let Some(expected) = err.expected() {
+                    eprintln!("{}", expected.red());
+                    return Ok(ExitCode::FAILURE);
+                }
+                return Err(err)?;
+            },
+        };
+
+        if let Err(err) = install_plan.install(feedback.clone(), tx.subscribe()).await {
+            // Leave the machine without Nix rather than half installed, as after an uninstall
+            eprintln!(
+                "{}",
+                "Installing failed, reverting the partial install...".red()
+            );
+            match install_plan.uninstall(feedback, tx.subscribe()).await {
+                Err(NixInstallerError::ActionRevert(errs)) => {
+                    tracing::error!(errors = ?errs, "Reverting the partial install failed");
+                },
+                Err(revert_err) => tracing::error!("{:?}", eyre!(revert_err)),
+                Ok(()) => (),
+            }
+            if let Some(expected) = err.expected() {
+                eprintln!("{}", expected.red());
+                return Ok(ExitCode::FAILURE);
+            }
+            return Err(eyre!(err).wrap_err("Install failure"))?;
+        }
+
+        copy_self_to_nix_dir(install_plan.root().as_deref())
+            .await
+            .wrap_err("Copying `nix-installer` to `/nix/nix-installer`")?;
+
+        let next_steps = install_plan
+            .next_steps(std::env::var("SHELL").ok().as_deref())
+            .map_err(|e| eyre!(e))?;
+        println!(
+            "\
+            {success}\n\
+            {next_steps}\
+            ",
+            success = "Nix was reinstalled successfully!".green().bold(),
+        );
+
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+/// What will be installed once the existing install is reverted, which cannot be planned yet
+async fn describe_reinstall(planner: &BuiltinPlanner) -> eyre::Result<String> {
+    let mut settings = planner
+        .configured_settings()
+        .await
+        .map_err(|e| eyre!(e))?
+        .into_iter()
+        .map(|(k, v)| format!("* {k}: {v}", k = k.bold()))
+        .collect::<Vec<_>>();
+    // Stabilize output order
+    settings.sort();
+
+    Ok(if settings.is_empty() {
+        format!(
+            "Then install Nix again with the `{}` planner (with default settings)\n",
+            planner.typetag_name()
+        )
+    } else {
+        format!(
+            "Then install Nix again with the `{}` planner, with settings:\n{}\n",
+            planner.typetag_name(),
+            settings.join("\n")
+        )
+    })
+}
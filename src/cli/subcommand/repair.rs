@@ -86,7 +86,7 @@ pub enum RepairKind {
             feature = "cli",
             clap(
                 long,
-                default_value = "nixbld",
+                default_value = crate::settings::DEFAULT_NIX_BUILD_USER_GROUP_NAME,
                 env = "NIX_INSTALLER_NIX_BUILD_GROUP_NAME",
                 global = true
             )
@@ -107,6 +107,10 @@ pub enum RepairKind {
         )]
         move_existing_users: bool,
     },
+    /// Re-create any Nix build users or group recorded in the receipt which no longer exist.
+    ///
+    /// Users and groups which still exist are left as they are, so nothing is renumbered.
+    Users,
 }
 
 impl Repair {
@@ -132,6 +136,10 @@ impl CommandExecute for Repair {
                 false,
                 String::from("Will ensure the Nix shell profiles are still being sourced"),
             ),
+            RepairKind::Users => (
+                false,
+                String::from("Will re-create any missing Nix build users and group"),
+            ),
             RepairKind::Sequoia {
                 ref nix_build_user_prefix,
                 nix_build_user_count,
@@ -188,10 +196,11 @@ impl CommandExecute for Repair {
         // TODO(cole-h): if we add another repair command, make this whole thing more generic
         let updated_receipt = match command.clone() {
             RepairKind::Hooks => {
-                let reconfigure = ConfigureShellProfile::plan(ShellProfileLocations::default())
-                    .await
-                    .map_err(PlannerError::Action)?
-                    .boxed();
+                let reconfigure =
+                    ConfigureShellProfile::plan(ShellProfileLocations::default(), false)
+                        .await
+                        .map_err(PlannerError::Action)?
+                        .boxed();
                 repair_actions.push(reconfigure);
 
                 match OperatingSystem::host() {
@@ -209,6 +218,26 @@ impl CommandExecute for Repair {
 
                 None
             },
+            RepairKind::Users => {
+                let Some((_receipt, _action_idx, create_users_and_groups)) =
+                    find_users_and_groups(get_existing_receipt().await)?
+                else {
+                    return Err(color_eyre::eyre::eyre!(
+                        "Unable to find {} in the receipt at {RECEIPT_LOCATION}, so the build \
+                        users and group to re-create are unknown",
+                        CreateUsersAndGroups::action_tag()
+                    ));
+                };
+
+                let repairs = create_users_and_groups.plan_repair().await?;
+                if repairs.is_empty() {
+                    tracing::info!("Nothing to do! All users appear to be in place!");
+                    return Ok(ExitCode::SUCCESS);
+                }
+                repair_actions.extend(repairs);
+
+                None
+            },
             RepairKind::Sequoia {
                 nix_build_user_prefix,
                 nix_build_user_count,
@@ -333,6 +362,8 @@ impl CommandExecute for Repair {
                         group_name.clone(),
                         group_gid,
                         format!("Nix build user {idx}"),
+                        crate::settings::default_nix_build_user_home(),
+                        crate::settings::default_nix_build_user_shell(),
                         false,
                     )
                     .await?;
@@ -459,7 +490,7 @@ async fn get_existing_receipt() -> Option<InstallPlan> {
             let install_plan_string = tokio::fs::read_to_string(RECEIPT_LOCATION).await.ok();
 
             match install_plan_string {
-                Some(s) => match serde_json::from_str::<InstallPlan>(s.as_str()) {
+                Some(s) => match InstallPlan::from_json(s.as_str()) {
                     Ok(plan) => {
                         tracing::debug!(plan_version = %plan.version, "Able to parse receipt");
                         Some(plan)
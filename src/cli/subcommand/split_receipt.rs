@@ -107,16 +107,15 @@ impl CommandExecute for SplitReceipt {
         if self.force_naive_json_method {
             two_phased_cannot_parse_receipt_perfectly(&self, &install_receipt_string).await?;
         } else {
-            let maybe_compatible_plan =
-                serde_json::from_str::<InstallPlan>(&install_receipt_string)
-                    .ok()
-                    .and_then(|plan| {
-                        if plan.check_compatible().is_ok() {
-                            Some(plan)
-                        } else {
-                            None
-                        }
-                    });
+            let maybe_compatible_plan = InstallPlan::from_json(&install_receipt_string)
+                .ok()
+                .and_then(|plan| {
+                    if plan.check_compatible().is_ok() {
+                        Some(plan)
+                    } else {
+                        None
+                    }
+                });
             match maybe_compatible_plan {
                 Some(plan) => {
                     two_phased_can_parse_receipt_perfectly(&self, plan).await?;
@@ -168,6 +167,8 @@ async fn two_phased_can_parse_receipt_perfectly(
         version: phase1_plan.version.clone(),
         actions: Vec::new(),
         planner: phase1_plan.planner.clone(),
+        provenance: phase1_plan.provenance.clone(),
+        receipt_path: phase1_plan.receipt_path.clone(),
     };
 
     for action in phase1_plan.actions.iter_mut() {
@@ -38,8 +38,38 @@ pub struct Uninstall {
     )]
     pub explain: bool,
 
+    /// Report how the system has drifted from the receipt, then exit without uninstalling
+    #[clap(long, action(ArgAction::SetTrue), default_value = "false")]
+    pub diff_only: bool,
+
+    /// Print what the uninstall would revert, without changing anything
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_DRY_RUN",
+        action(ArgAction::SetTrue),
+        default_value = "false"
+    )]
+    pub dry_run: bool,
+
+    /// Leave the build users and their group in place, so a reinstall can reuse them
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_KEEP_USERS",
+        action(ArgAction::SetTrue),
+        default_value = "false"
+    )]
+    pub keep_users: bool,
+
     #[clap(default_value = RECEIPT_LOCATION)]
     pub receipt: PathBuf,
+
+    /// The receipt to uninstall, if it is not at the default location (overrides the positional receipt)
+    #[clap(
+        long = "receipt",
+        env = "NIX_INSTALLER_RECEIPT",
+        conflicts_with = "receipt"
+    )]
+    pub receipt_override: Option<PathBuf>,
 }
 
 #[async_trait::async_trait]
@@ -53,9 +83,17 @@ impl CommandExecute for Uninstall {
             no_confirm,
             receipt,
             explain,
+            diff_only,
+            dry_run,
+            keep_users,
+            receipt_override,
         } = self;
+        let receipt = receipt_override.unwrap_or(receipt);
 
-        ensure_root()?;
+        // Nothing is changed when only reporting drift or previewing
+        if !diff_only && !dry_run {
+            ensure_root()?;
+        }
 
         if let Ok(current_dir) = std::env::current_dir() {
             let mut components = current_dir.components();
@@ -74,7 +112,7 @@ impl CommandExecute for Uninstall {
         // well, we have a problem, since the binary would delete itself.
         // Instead, detect if we're in that location, if so, move the binary and `execv` it.
         if let Ok(current_exe) = std::env::current_exe() {
-            if current_exe.as_path() == Path::new("/nix/nix-installer") {
+            if !diff_only && !dry_run && current_exe.as_path() == Path::new("/nix/nix-installer") {
                 tracing::debug!(
                     "Detected uninstall from `/nix/nix-installer`, moving executable and re-executing"
                 );
@@ -111,11 +149,17 @@ impl CommandExecute for Uninstall {
             }
         }
 
-        let install_receipt_string = tokio::fs::read_to_string(receipt)
+        if !receipt.exists() {
+            return Err(eyre!(
+                "No receipt found at `{}`, pass the receipt of the install to uninstall with `--receipt`",
+                receipt.display()
+            ));
+        }
+        let install_receipt_string = tokio::fs::read_to_string(&receipt)
             .await
-            .wrap_err("Reading receipt")?;
+            .wrap_err_with(|| format!("Reading receipt `{}`", receipt.display()))?;
 
-        let mut plan: InstallPlan = match serde_json::from_str(&install_receipt_string) {
+        let mut plan = match InstallPlan::from_json(&install_receipt_string) {
             Ok(plan) => plan,
             Err(plan_err) => {
                 #[derive(serde::Deserialize)]
@@ -148,15 +192,34 @@ impl CommandExecute for Uninstall {
                 format!("\
                     {e}\n\
                     \n\
-                    Found existing plan in `{RECEIPT_LOCATION}` which was created by a version incompatible `nix-installer`.\n\
+                    Found existing plan in `{receipt}` which was created by a version incompatible `nix-installer`.\n\
                     \n
                     To uninstall, either run `/nix/nix-installer uninstall` or `curl --proto '=https' --tlsv1.2 -sSf -L https://install.determinate.systems/nix/tag/v${version} | sh -s -- uninstall`\n\
                     \n\
-                ").red()
+                ", receipt = receipt.display()).red()
             );
             return Ok(ExitCode::FAILURE);
         }
 
+        if diff_only {
+            let drift = plan.drift()?;
+            if drift.is_empty() {
+                println!(
+                    "{}",
+                    "The system matches the receipt, nothing has drifted".green()
+                );
+            } else {
+                println!(
+                    "{}",
+                    format!("Found {} difference(s) from the receipt:", drift.len()).yellow()
+                );
+                for drift in drift {
+                    println!("* {drift}");
+                }
+            }
+            return Ok(ExitCode::SUCCESS);
+        }
+
         if let Err(err) = plan.pre_uninstall_check().await {
             if let Some(expected) = err.expected() {
                 eprintln!("{}", expected.red());
@@ -165,6 +228,21 @@ impl CommandExecute for Uninstall {
             Err(err)?
         }
 
+        if keep_users {
+            let kept = plan.keep_build_users();
+            tracing::debug!(kept, "Keeping the build users and group");
+        }
+
+        if dry_run {
+            println!(
+                "{}",
+                plan.describe_uninstall(explain)
+                    .await
+                    .map_err(|e| eyre!(e))?
+            );
+            return Ok(ExitCode::SUCCESS);
+        }
+
         if !no_confirm {
             let mut currently_explaining = explain;
             loop {
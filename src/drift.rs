@@ -0,0 +1,353 @@
+/*! How a system has drifted from what an install receipt says was done
+
+Over time, users and groups get edited, and `nix.conf` is changed by hand. Before uninstalling, a
+[`Drift`] report shows which artifacts recorded in the receipt are missing or no longer match.
+
+The receipt is inspected as JSON, so this works across every [`Action`](crate::action::Action),
+including those nested inside others.
+*/
+use std::path::{Path, PathBuf};
+
+use nix::unistd::{Group, User};
+use nix_config_parser::NixConfig;
+use serde_json::Value;
+
+/// An artifact recorded in the receipt which no longer matches the system
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Drift {
+    /// A user which was created is gone
+    MissingUser { name: String },
+    /// A user which was created has a different UID
+    UserChanged {
+        name: String,
+        expected: u32,
+        actual: u32,
+    },
+    /// A group which was created is gone
+    MissingGroup { name: String },
+    /// A group which was created has a different GID
+    GroupChanged {
+        name: String,
+        expected: u32,
+        actual: u32,
+    },
+    /// A file or directory which was created is gone
+    MissingPath { path: PathBuf },
+    /// A file which was created or inserted into no longer contains what was written
+    ContentChanged { path: PathBuf },
+    /// A setting written to a Nix configuration file is gone
+    MissingNixConfSetting { path: PathBuf, name: String },
+    /// A setting written to a Nix configuration file has a different value
+    NixConfSettingChanged {
+        path: PathBuf,
+        name: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl std::fmt::Display for Drift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Drift::MissingUser { name } => write!(f, "User `{name}` is missing"),
+            Drift::UserChanged {
+                name,
+                expected,
+                actual,
+            } => write!(f, "User `{name}` has UID {actual}, expected {expected}"),
+            Drift::MissingGroup { name } => write!(f, "Group `{name}` is missing"),
+            Drift::GroupChanged {
+                name,
+                expected,
+                actual,
+            } => write!(f, "Group `{name}` has GID {actual}, expected {expected}"),
+            Drift::MissingPath { path } => write!(f, "`{}` is missing", path.display()),
+            Drift::ContentChanged { path } => write!(
+                f,
+                "`{}` no longer contains what was written during install",
+                path.display()
+            ),
+            Drift::MissingNixConfSetting { path, name } => {
+                write!(f, "`{name}` is missing from `{}`", path.display())
+            },
+            Drift::NixConfSettingChanged {
+                path,
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "`{name}` in `{}` is `{actual}`, expected `{expected}`",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// Compare the artifacts recorded in a serialized [`InstallPlan`](crate::InstallPlan) to the system
+pub fn detect(receipt: &Value) -> Vec<Drift> {
+    let mut drift = vec![];
+    walk(receipt, &mut |action| check_action(action, &mut drift));
+    drift
+}
+
+/// Each action named `action_name` which the receipt records as done
+pub(crate) fn recorded_actions<'a>(receipt: &'a Value, action_name: &str) -> Vec<&'a Value> {
+    let mut actions = vec![];
+    walk(receipt, &mut |action| {
+        if action.get("action_name").and_then(Value::as_str) == Some(action_name) {
+            actions.push(action);
+        }
+    });
+    actions
+}
+
+/// The `path` of each action named `action_name` which the receipt records as done
+pub(crate) fn recorded_paths(receipt: &Value, action_name: &str) -> Vec<PathBuf> {
+    recorded_actions(receipt, action_name)
+        .into_iter()
+        .filter_map(|action| action.get("path").and_then(Value::as_str))
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Visit each action, however nested, which is not recorded as uncompleted
+fn walk<'a>(value: &'a Value, visit: &mut impl FnMut(&'a Value)) {
+    match value {
+        Value::Object(map) => {
+            // A `StatefulAction`, only artifacts which were actually created are expected to exist
+            if let (Some(action), Some(state)) = (map.get("action"), map.get("state")) {
+                if state.as_str() == Some("Uncompleted") {
+                    return;
+                }
+                visit(action);
+            }
+            for value in map.values() {
+                walk(value, visit);
+            }
+        },
+        Value::Array(values) => {
+            for value in values {
+                walk(value, visit);
+            }
+        },
+        _ => (),
+    }
+}
+
+fn check_action(action: &Value, drift: &mut Vec<Drift>) {
+    let str_field = |name: &str| action.get(name).and_then(Value::as_str);
+    let id_field = |name: &str| {
+        action
+            .get(name)
+            .and_then(Value::as_u64)
+            .and_then(|v| u32::try_from(v).ok())
+    };
+
+    match str_field("action_name") {
+        Some("create_user") => {
+            let (Some(name), Some(expected)) = (str_field("name"), id_field("uid")) else {
+                return;
+            };
+            match User::from_name(name) {
+                Ok(Some(user)) if user.uid.as_raw() != expected => drift.push(Drift::UserChanged {
+                    name: name.to_string(),
+                    expected,
+                    actual: user.uid.as_raw(),
+                }),
+                Ok(Some(_)) => (),
+                Ok(None) | Err(_) => drift.push(Drift::MissingUser {
+                    name: name.to_string(),
+                }),
+            }
+        },
+        Some("create_group") => {
+            let (Some(name), Some(expected)) = (str_field("name"), id_field("gid")) else {
+                return;
+            };
+            match Group::from_name(name) {
+                Ok(Some(group)) if group.gid.as_raw() != expected => {
+                    drift.push(Drift::GroupChanged {
+                        name: name.to_string(),
+                        expected,
+                        actual: group.gid.as_raw(),
+                    })
+                },
+                Ok(Some(_)) => (),
+                Ok(None) | Err(_) => drift.push(Drift::MissingGroup {
+                    name: name.to_string(),
+                }),
+            }
+        },
+        Some("create_directory") => {
+            if let Some(path) = str_field("path") {
+                check_exists(Path::new(path), drift);
+            }
+        },
+        Some("create_file") => {
+            let (Some(path), Some(buf)) = (str_field("path"), str_field("buf")) else {
+                return;
+            };
+            check_contents(Path::new(path), |contents| contents == buf, drift);
+        },
+        Some("create_or_insert_into_file") => {
+            let (Some(path), Some(buf)) = (str_field("path"), str_field("buf")) else {
+                return;
+            };
+            check_contents(Path::new(path), |contents| contents.contains(buf), drift);
+        },
+        Some("create_or_merge_nix_config") => {
+            let Some(path) = str_field("path") else {
+                return;
+            };
+            let Some(pending) = action
+                .get("pending_nix_config")
+                .and_then(|v| serde_json::from_value::<NixConfig>(v.clone()).ok())
+            else {
+                return;
+            };
+            check_nix_config(Path::new(path), &pending, drift);
+        },
+        _ => (),
+    }
+}
+
+fn check_exists(path: &Path, drift: &mut Vec<Drift>) -> bool {
+    if path.exists() {
+        true
+    } else {
+        drift.push(Drift::MissingPath {
+            path: path.to_path_buf(),
+        });
+        false
+    }
+}
+
+fn check_contents(path: &Path, matches: impl Fn(&str) -> bool, drift: &mut Vec<Drift>) {
+    if !check_exists(path, drift) {
+        return;
+    }
+    match std::fs::read_to_string(path) {
+        Ok(contents) if matches(&contents) => (),
+        _ => drift.push(Drift::ContentChanged {
+            path: path.to_path_buf(),
+        }),
+    }
+}
+
+fn check_nix_config(path: &Path, pending: &NixConfig, drift: &mut Vec<Drift>) {
+    if !check_exists(path, drift) {
+        return;
+    }
+    let Ok(existing) = NixConfig::parse_file(path) else {
+        drift.push(Drift::ContentChanged {
+            path: path.to_path_buf(),
+        });
+        return;
+    };
+    for (name, expected) in pending.settings() {
+        match existing.settings().get(name) {
+            None => drift.push(Drift::MissingNixConfSetting {
+                path: path.to_path_buf(),
+                name: name.clone(),
+            }),
+            Some(actual) if !setting_satisfied(name, expected, actual) => {
+                drift.push(Drift::NixConfSettingChanged {
+                    path: path.to_path_buf(),
+                    name: name.clone(),
+                    expected: expected.clone(),
+                    actual: actual.clone(),
+                })
+            },
+            Some(_) => (),
+        }
+    }
+}
+
+/// Merged settings may have been extended with values beyond those the installer wrote
+fn setting_satisfied(name: &str, expected: &str, actual: &str) -> bool {
+    if name.starts_with("extra-") || name == "trusted-users" {
+        let actual: Vec<&str> = actual.split_whitespace().collect();
+        expected
+            .split_whitespace()
+            .all(|value| actual.contains(&value))
+    } else {
+        expected == actual
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{detect, Drift};
+
+    #[test]
+    fn detects_missing_and_changed_artifacts() -> eyre::Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let file = temp_dir.path().join("file");
+        std::fs::write(&file, "edited by hand")?;
+        let nix_conf = temp_dir.path().join("nix.conf");
+        std::fs::write(
+            &nix_conf,
+            "extra-experimental-features = nix-command flakes ca-derivations\nmax-jobs = 4\n",
+        )?;
+        let missing = temp_dir.path().join("missing");
+
+        let receipt = serde_json::json!({
+            "actions": [
+                {
+                    "action": { "action_name": "create_file", "path": file, "buf": "original" },
+                    "state": "Completed",
+                },
+                {
+                    "action": {
+                        "action_name": "wrapper",
+                        "inner": {
+                            "action": { "action_name": "create_directory", "path": missing },
+                            "state": "Completed",
+                        },
+                        "not_done": {
+                            "action": { "action_name": "create_directory", "path": "/not/done" },
+                            "state": "Uncompleted",
+                        },
+                    },
+                    "state": "Completed",
+                },
+                {
+                    "action": {
+                        "action_name": "create_or_merge_nix_config",
+                        "path": nix_conf,
+                        "pending_nix_config": {
+                            "settings": {
+                                "extra-experimental-features": "nix-command flakes",
+                                "max-jobs": "auto",
+                                "sandbox": "true",
+                            },
+                        },
+                    },
+                    "state": "Completed",
+                },
+            ],
+        });
+
+        assert_eq!(
+            detect(&receipt),
+            vec![
+                Drift::ContentChanged { path: file },
+                Drift::MissingPath { path: missing },
+                Drift::NixConfSettingChanged {
+                    path: nix_conf.clone(),
+                    name: "max-jobs".into(),
+                    expected: "auto".into(),
+                    actual: "4".into(),
+                },
+                Drift::MissingNixConfSetting {
+                    path: nix_conf,
+                    name: "sandbox".into(),
+                },
+            ]
+        );
+
+        Ok(())
+    }
+}
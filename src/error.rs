@@ -52,6 +52,9 @@ pub enum NixInstallerError {
     /// An error occurring when a signal is issued along [`InstallPlan::install`](crate::InstallPlan::install)'s `cancel_channel` argument
     #[error("Cancelled by user")]
     Cancelled,
+    /// Like [`Cancelled`](Self::Cancelled), but from a [`CancelSignal::Terminate`](crate::CancelSignal::Terminate)
+    #[error("Terminated, the receipt records the partial progress")]
+    Terminated,
     /// Semver error
     #[error("Semantic Versioning error")]
     SemVer(
@@ -88,9 +91,27 @@ pub enum NixInstallerError {
     /// Could not parse `nix-installer`'s version as a valid version according to Semantic Versioning, therefore the plan version compatibility cannot be checked
     #[error("Could not parse `nix-installer`'s version `{0}` as a valid version according to Semantic Versioning, therefore the plan version compatibility cannot be checked")]
     InvalidCurrentVersion(String, semver::Error),
+    /// An error while parsing an [`InstallPlan`](crate::InstallPlan)
+    #[error("Parsing plan")]
+    ParsingPlan(#[source] serde_json::Error),
+    /// The plan's `version` was not a valid version according to Semantic Versioning
+    #[error("Could not parse the plan's version `{0}` as a valid version according to Semantic Versioning")]
+    InvalidPlanVersion(String),
     /// This version of `nix-installer` is not compatible with this plan's version
     #[error("`nix-installer` version `{}` is not compatible with this plan's version `{}`", .binary, .plan)]
     IncompatibleVersion { binary: Version, plan: Version },
+    /// [`InstallPlan::revert_action`](crate::InstallPlan::revert_action) was given an index past the end of the plan
+    #[error("The plan has no action {index}, it has {len}")]
+    NoSuchAction { index: usize, len: usize },
+    /// [`InstallPlan::revert_action`](crate::InstallPlan::revert_action) was asked to revert an action which later actions may depend on
+    #[error("Action {index} can't be reverted before the later actions which may depend on it: {}", .later.join(", "))]
+    LaterActionsNotReverted { index: usize, later: Vec<String> },
+    /// [`InstallPlan::filter_actions`](crate::InstallPlan::filter_actions) was given action names which are not in the plan
+    #[error("The plan has no {} action, its actions (including nested ones) are: {}", .unknown.join(", "), .known.join(", "))]
+    UnknownActionNames {
+        unknown: Vec<String>,
+        known: Vec<String>,
+    },
 }
 
 pub(crate) trait HasExpectedErrors: std::error::Error + Sized + Send + Sync {
@@ -107,14 +128,20 @@ impl HasExpectedErrors for NixInstallerError {
             NixInstallerError::CopyingSelf(_) => None,
             NixInstallerError::SerializingReceipt(_) => None,
             NixInstallerError::Cancelled => None,
+            NixInstallerError::Terminated => None,
             NixInstallerError::SemVer(_) => None,
             NixInstallerError::Planner(planner_error) => planner_error.expected(),
             NixInstallerError::InstallSettings(_) => None,
             this @ NixInstallerError::InvalidVersionRequirement(_, _) => Some(Box::new(this)),
             this @ NixInstallerError::InvalidCurrentVersion(_, _) => Some(Box::new(this)),
+            NixInstallerError::ParsingPlan(_) => None,
+            this @ NixInstallerError::InvalidPlanVersion(_) => Some(Box::new(this)),
             this @ NixInstallerError::IncompatibleVersion { binary: _, plan: _ } => {
                 Some(Box::new(this))
             },
+            this @ NixInstallerError::NoSuchAction { .. } => Some(Box::new(this)),
+            this @ NixInstallerError::LaterActionsNotReverted { .. } => Some(Box::new(this)),
+            this @ NixInstallerError::UnknownActionNames { .. } => Some(Box::new(this)),
             #[cfg(feature = "diagnostics")]
             NixInstallerError::Diagnostic(_) => None,
         }
@@ -74,11 +74,14 @@ pub mod action;
 pub mod cli;
 #[cfg(feature = "diagnostics")]
 pub mod diagnostics;
+pub mod drift;
 mod error;
 pub mod feedback;
+pub mod next_steps;
 mod os;
 mod plan;
 pub mod planner;
+pub mod progress;
 pub mod self_test;
 pub mod settings;
 mod util;
@@ -86,7 +89,7 @@ mod util;
 use std::{ffi::OsStr, path::Path, process::Output};
 
 pub use error::NixInstallerError;
-pub use plan::InstallPlan;
+pub use plan::{ActionSummary, CancelSignal, InstallPlan, Provenance, TargetPlatform};
 use planner::BuiltinPlanner;
 
 use reqwest::Certificate;
@@ -96,6 +99,19 @@ use crate::action::{Action, ActionErrorKind};
 
 #[tracing::instrument(level = "debug", skip_all, fields(command = %format!("{:?}", command.as_std())))]
 async fn execute_command(command: &mut Command) -> Result<Output, ActionErrorKind> {
+    run_command(command).await
+}
+
+/// Like [`execute_command`], but `secret` is replaced in the traced command and any error
+#[tracing::instrument(level = "debug", skip_all, fields(command = %format!("{:?}", command.as_std()).replace(secret, "<redacted>")))]
+async fn execute_command_with_secret(
+    command: &mut Command,
+    secret: &str,
+) -> Result<Output, ActionErrorKind> {
+    run_command(command).await.map_err(|e| e.redact(secret))
+}
+
+async fn run_command(command: &mut Command) -> Result<Output, ActionErrorKind> {
     tracing::trace!("Executing");
     let output = command
         .output()
@@ -127,23 +143,181 @@ async fn parse_ssl_cert(ssl_cert_file: &Path) -> Result<Certificate, Certificate
     let cert_buf = tokio::fs::read(ssl_cert_file)
         .await
         .map_err(|e| CertificateError::Read(ssl_cert_file.to_path_buf(), e))?;
-    // We actually try them since things could be `.crt` and `pem` format or `der` format
-    let cert = if let Ok(cert) = Certificate::from_pem(cert_buf.as_slice()) {
-        cert
-    } else if let Ok(cert) = Certificate::from_der(cert_buf.as_slice()) {
-        cert
-    } else {
-        return Err(CertificateError::UnknownCertFormat);
-    };
-    Ok(cert)
+    // Things could be `.crt` and `pem` format or `der` format. `reqwest` only reads the
+    // certificates when building a client, and silently trusts nothing from a file without any,
+    // so decode and parse each of them now.
+    let pem_certs = rustls_pemfile::certs(&mut cert_buf.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| CertificateError::InvalidPem(ssl_cert_file.to_path_buf(), e))?;
+    if !pem_certs.is_empty() {
+        for cert in &pem_certs {
+            check_x509(ssl_cert_file, cert)?;
+        }
+        return Certificate::from_pem(cert_buf.as_slice()).map_err(CertificateError::Reqwest);
+    }
+
+    // A DER certificate is an ASN.1 `SEQUENCE`
+    if cert_buf.first() == Some(&0x30) {
+        check_x509(ssl_cert_file, &cert_buf.as_slice().into())?;
+        return Certificate::from_der(cert_buf.as_slice()).map_err(CertificateError::Reqwest);
+    }
+
+    Err(CertificateError::UnknownCertFormat(
+        ssl_cert_file.to_path_buf(),
+    ))
+}
+
+/// Parse the X.509 certificate the way it would be as a trust anchor
+fn check_x509(
+    ssl_cert_file: &Path,
+    cert: &webpki::types::CertificateDer<'_>,
+) -> Result<(), CertificateError> {
+    webpki::anchor_from_trusted_cert(cert)
+        .map(|_| ())
+        .map_err(|e| CertificateError::InvalidX509(ssl_cert_file.to_path_buf(), e))
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum CertificateError {
     #[error(transparent)]
     Reqwest(reqwest::Error),
-    #[error("Read path `{0}`")]
+    #[error("Reading SSL certificate `{0}`")]
     Read(std::path::PathBuf, #[source] std::io::Error),
-    #[error("Unknown certificate format, `der` and `pem` supported")]
-    UnknownCertFormat,
+    #[error("`{0}` holds no certificates, `der` and `pem` supported")]
+    UnknownCertFormat(std::path::PathBuf),
+    #[error("`{0}` is not a valid `pem` certificate bundle")]
+    InvalidPem(std::path::PathBuf, #[source] std::io::Error),
+    #[error("`{0}` holds a certificate which is not valid X.509: {1:?}")]
+    InvalidX509(std::path::PathBuf, webpki::Error),
+}
+
+#[cfg(all(test, feature = "cli"))]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::process::Command;
+
+    use super::{execute_command, execute_command_with_secret, parse_ssl_cert, CertificateError};
+    use crate::action::ActionErrorKind;
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn secrets_are_not_logged() {
+        const SECRET: &str = "hunter2-hunter2";
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let mut command = Command::new("false");
+        command.arg(SECRET);
+        let err = execute_command_with_secret(&mut command, SECRET)
+            .await
+            .expect_err("`false` should fail");
+        tracing::error!(error = %err, "Command failed");
+
+        let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("<redacted>"), "{logs}");
+        assert!(!logs.contains(SECRET), "{logs}");
+    }
+
+    #[tokio::test]
+    async fn failed_commands_keep_their_output() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo progress; echo oops >&2; exit 3"]);
+        let err = execute_command(&mut command)
+            .await
+            .expect_err("the command should fail");
+
+        assert_eq!(
+            err.to_string(),
+            "`sh -c 'echo progress; echo oops >&2; exit 3'` exited 3: oops\nstdout: progress"
+        );
+        let ActionErrorKind::CommandOutput {
+            program,
+            args,
+            status,
+            stderr,
+            ..
+        } = err
+        else {
+            panic!("expected `CommandOutput`, got {err:?}");
+        };
+        assert_eq!(program, "sh");
+        assert_eq!(args.len(), 2);
+        assert_eq!(status.code(), Some(3));
+        assert_eq!(stderr, "oops\n");
+    }
+
+    #[tokio::test]
+    async fn parse_ssl_cert_rejects_files_without_certificates() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let cert = temp_dir.path().join("cert.pem");
+
+        let valid = include_str!("../tests/fixtures/ssl-cert.pem");
+        tokio::fs::write(&cert, format!("# A bundle\n{valid}")).await?;
+        parse_ssl_cert(&cert).await?;
+
+        // The DER inside the PEM
+        let der = rustls_pemfile::certs(&mut valid.as_bytes())
+            .next()
+            .expect("the fixture holds a certificate")?;
+        tokio::fs::write(&cert, der.as_ref()).await?;
+        parse_ssl_cert(&cert).await?;
+
+        // Base64, but not a certificate
+        tokio::fs::write(
+            &cert,
+            "# A bundle\n-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----\n",
+        )
+        .await?;
+        assert!(matches!(
+            parse_ssl_cert(&cert).await,
+            Err(CertificateError::InvalidX509(path, _)) if path == cert
+        ));
+        tokio::fs::write(&cert, [0x30, 0x03, 0x02, 0x01, 0x00]).await?;
+        assert!(matches!(
+            parse_ssl_cert(&cert).await,
+            Err(CertificateError::InvalidX509(..))
+        ));
+
+        tokio::fs::write(&cert, "not a certificate\n").await?;
+        assert!(matches!(
+            parse_ssl_cert(&cert).await,
+            Err(CertificateError::UnknownCertFormat(path)) if path == cert
+        ));
+
+        tokio::fs::write(
+            &cert,
+            "-----BEGIN CERTIFICATE-----\n!!!\n-----END CERTIFICATE-----\n",
+        )
+        .await?;
+        assert!(matches!(
+            parse_ssl_cert(&cert).await,
+            Err(CertificateError::InvalidPem(..))
+        ));
+
+        assert!(matches!(
+            parse_ssl_cert(&temp_dir.path().join("missing.pem")).await,
+            Err(CertificateError::Read(..))
+        ));
+
+        Ok(())
+    }
 }
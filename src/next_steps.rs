@@ -0,0 +1,128 @@
+/*! What a user should do after a successful install
+
+[`NextSteps`] is rendered for humans via [`Display`](std::fmt::Display), and can be serialized for
+embedders which present their own onboarding.
+*/
+use std::{collections::HashMap, path::PathBuf};
+
+use crate::{action::common::place_nix_configuration::NIX_CONF, settings::InitSystem};
+
+pub const NIX_DAEMON_PROFILE_SH: &str = "/nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh";
+pub const NIX_DAEMON_PROFILE_FISH: &str =
+    "/nix/var/nix/profiles/default/etc/profile.d/nix-daemon.fish";
+
+/// The state of the Nix daemon after an install
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DaemonStatus {
+    /// The daemon was configured and started
+    Started,
+    /// The daemon was configured, but it will not run until the init system starts it
+    NotStarted,
+    /// No daemon was configured, Nix is `root`-only
+    NotConfigured,
+}
+
+/// Structured post-install guidance
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct NextSteps {
+    /// A command which loads Nix into the user's current shell
+    pub shell_source_command: String,
+    /// The state of the Nix daemon
+    pub daemon: DaemonStatus,
+    /// Where the Nix configuration was written, if it was
+    pub nix_conf: Option<PathBuf>,
+}
+
+impl NextSteps {
+    /// Derive the next steps from a planner's [`settings`](crate::planner::Planner::settings)
+    ///
+    /// `shell` is the user's shell (typically `$SHELL`), used to pick the right profile script.
+    pub fn from_settings(
+        settings: &HashMap<String, serde_json::Value>,
+        shell: Option<&str>,
+    ) -> Self {
+        let shell_source_command = match shell {
+            Some(shell) if shell.contains("fish") => format!(". {NIX_DAEMON_PROFILE_FISH}"),
+            _ => format!(". {NIX_DAEMON_PROFILE_SH}"),
+        };
+
+        let init = settings
+            .get("init")
+            .and_then(|v| serde_json::from_value::<InitSystem>(v.clone()).ok());
+        let start_daemon = settings
+            .get("start_daemon")
+            .and_then(serde_json::Value::as_bool)
+            .unwrap_or(true);
+        let daemon = match (init, start_daemon) {
+            (Some(InitSystem::None), _) => DaemonStatus::NotConfigured,
+            (_, false) => DaemonStatus::NotStarted,
+            (_, true) => DaemonStatus::Started,
+        };
+
+        let skip_nix_conf = settings
+            .get("skip_nix_conf")
+            .and_then(serde_json::Value::as_bool)
+            .unwrap_or(false);
+        let nix_conf = (!skip_nix_conf).then(|| PathBuf::from(NIX_CONF));
+
+        Self {
+            shell_source_command,
+            daemon,
+            nix_conf,
+        }
+    }
+}
+
+impl std::fmt::Display for NextSteps {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "To get started using Nix, open a new shell or run `{}`",
+            self.shell_source_command
+        )?;
+        match self.daemon {
+            DaemonStatus::Started => (),
+            DaemonStatus::NotStarted => writeln!(
+                f,
+                "The Nix daemon was configured, but not started. It will start along with your init system."
+            )?,
+            DaemonStatus::NotConfigured => writeln!(
+                f,
+                "No Nix daemon was configured, so Nix can only be used by `root`."
+            )?,
+        }
+        if let Some(nix_conf) = &self.nix_conf {
+            writeln!(f, "Nix is configured in `{}`", nix_conf.display())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{DaemonStatus, NextSteps, NIX_DAEMON_PROFILE_FISH};
+
+    #[test]
+    fn next_steps_from_settings() {
+        let mut settings = HashMap::new();
+        settings.insert("init".to_string(), serde_json::json!("None"));
+        settings.insert("skip_nix_conf".to_string(), serde_json::json!(true));
+
+        let next_steps = NextSteps::from_settings(&settings, Some("/usr/bin/fish"));
+        assert_eq!(
+            next_steps,
+            NextSteps {
+                shell_source_command: format!(". {NIX_DAEMON_PROFILE_FISH}"),
+                daemon: DaemonStatus::NotConfigured,
+                nix_conf: None,
+            }
+        );
+
+        let next_steps = NextSteps::from_settings(&HashMap::new(), None);
+        assert_eq!(next_steps.daemon, DaemonStatus::Started);
+        assert!(next_steps.nix_conf.is_some());
+    }
+}
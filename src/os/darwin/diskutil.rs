@@ -49,9 +49,35 @@ pub struct DiskUtilApfsListOutput {
 #[derive(serde::Deserialize, Clone, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct DiskUtilApfsContainer {
+    /// The synthesized disk of the container, like `disk3`
+    pub container_reference: Option<String>,
+    #[serde(default)]
+    pub physical_stores: Vec<DiskUtilApfsPhysicalStore>,
     pub volumes: Vec<DiskUtilApfsListVolume>,
 }
 
+impl DiskUtilApfsContainer {
+    /// Whether the container is `disk` (like `disk3` or `/dev/disk3`), or is stored on it
+    pub fn is_on(&self, disk: &str) -> bool {
+        let disk = disk.strip_prefix("/dev/").unwrap_or(disk);
+        self.container_reference.as_deref() == Some(disk)
+            || self.physical_stores.iter().any(|store| {
+                store.device_identifier == disk
+                    || store
+                        .device_identifier
+                        .strip_prefix(disk)
+                        .is_some_and(|partition| partition.starts_with('s'))
+            })
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct DiskUtilApfsPhysicalStore {
+    /// Like `disk0s2`
+    pub device_identifier: String,
+}
+
 #[derive(serde::Deserialize, Clone, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct DiskUtilApfsListVolume {
@@ -0,0 +1,148 @@
+use std::path::{Path, PathBuf};
+
+/// The init system a Linux machine was booted with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DetectedInit {
+    Systemd,
+    OpenRc,
+    SysVInit,
+    /// Not a known init, with the name of PID 1 if it could be read
+    Unknown(Option<String>),
+}
+
+impl std::fmt::Display for DetectedInit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DetectedInit::Systemd => write!(f, "systemd"),
+            DetectedInit::OpenRc => write!(f, "OpenRC"),
+            DetectedInit::SysVInit => write!(f, "SysV init"),
+            DetectedInit::Unknown(Some(comm)) => write!(f, "an unknown init (`{comm}`)"),
+            DetectedInit::Unknown(None) => write!(f, "an unknown init"),
+        }
+    }
+}
+
+/// Detect the init system which booted this machine
+pub fn detect_init() -> DetectedInit {
+    detect_init_in(Path::new("/"))
+}
+
+/// Detect the init system of the system rooted at `root`
+pub(crate) fn detect_init_in(root: &Path) -> DetectedInit {
+    // If /run/systemd/system exists, we can be reasonably sure the machine is booted
+    // with systemd: https://www.freedesktop.org/software/systemd/man/sd_booted.html
+    if root.join("run/systemd/system").exists() {
+        return DetectedInit::Systemd;
+    }
+    // OpenRC creates `/run/openrc` once it has booted the system, whether or not it is PID 1
+    if root.join("run/openrc").exists() {
+        return DetectedInit::OpenRc;
+    }
+
+    let comm = std::fs::read_to_string(root.join("proc/1/comm"))
+        .ok()
+        .map(|comm| comm.trim().to_string());
+    match comm.as_deref() {
+        Some("systemd") => DetectedInit::Systemd,
+        Some("openrc-init") => DetectedInit::OpenRc,
+        Some("init") => DetectedInit::SysVInit,
+        _ => DetectedInit::Unknown(comm),
+    }
+}
+
+/// Whether this machine is a Windows Subsystem for Linux (WSL) instance
+pub fn is_wsl() -> bool {
+    std::fs::read_to_string("/proc/sys/kernel/osrelease")
+        .is_ok_and(|osrelease| is_wsl_osrelease(&osrelease))
+}
+
+/// WSL kernels report themselves as `...-Microsoft` (WSL1) or `...-microsoft-standard-WSL2` (WSL2)
+fn is_wsl_osrelease(osrelease: &str) -> bool {
+    osrelease.to_lowercase().contains("microsoft")
+}
+
+/// The mount point of the read-only filesystem holding `path` (or its nearest existing ancestor), if it is read-only
+pub fn read_only_mount(path: &Path) -> Option<PathBuf> {
+    let existing = path
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(Path::new("/"));
+    let stat = nix::sys::statvfs::statvfs(existing).ok()?;
+    if !stat.flags().contains(nix::sys::statvfs::FsFlags::ST_RDONLY) {
+        return None;
+    }
+
+    let mounts = std::fs::read_to_string("/proc/mounts").unwrap_or_default();
+    Some(mount_point_in(&mounts, existing).unwrap_or_else(|| existing.to_path_buf()))
+}
+
+/// The most specific mount point in `mounts` (formatted like `/proc/mounts`) which contains `path`
+fn mount_point_in(mounts: &str, path: &Path) -> Option<PathBuf> {
+    mounts
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        // `/proc/mounts` escapes whitespace in paths as octal
+        .map(|mount_point| PathBuf::from(mount_point.replace("\\040", " ").replace("\\011", "\t")))
+        .filter(|mount_point| path.starts_with(mount_point))
+        .max_by_key(|mount_point| mount_point.components().count())
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use super::{detect_init_in, is_wsl_osrelease, mount_point_in, DetectedInit};
+
+    #[test]
+    fn finds_most_specific_mount_point() {
+        let mounts = "\
+            /dev/sda1 / ext4 ro,relatime 0 0\n\
+            tmpfs /run tmpfs rw,nosuid 0 0\n\
+            /dev/sda2 /nix ext4 ro,relatime 0 0\n\
+            /dev/sda3 /mnt/my\\040disk ext4 rw 0 0\n";
+        assert_eq!(
+            mount_point_in(mounts, Path::new("/nix/store")),
+            Some(PathBuf::from("/nix"))
+        );
+        assert_eq!(
+            mount_point_in(mounts, Path::new("/nixos")),
+            Some(PathBuf::from("/"))
+        );
+        assert_eq!(
+            mount_point_in(mounts, Path::new("/mnt/my disk/nix")),
+            Some(PathBuf::from("/mnt/my disk"))
+        );
+        assert_eq!(mount_point_in("", Path::new("/nix")), None);
+    }
+
+    #[test]
+    fn detects_wsl_from_osrelease() {
+        assert!(is_wsl_osrelease("5.15.153.1-microsoft-standard-WSL2\n"));
+        assert!(is_wsl_osrelease("4.4.0-19041-Microsoft\n"));
+        assert!(!is_wsl_osrelease("6.8.0-45-generic\n"));
+    }
+
+    #[test]
+    fn detects_init_from_markers_and_pid_1() -> eyre::Result<()> {
+        let root = tempfile::TempDir::new()?;
+        assert_eq!(detect_init_in(root.path()), DetectedInit::Unknown(None));
+
+        std::fs::create_dir_all(root.path().join("proc/1"))?;
+        std::fs::write(root.path().join("proc/1/comm"), "tini\n")?;
+        assert_eq!(
+            detect_init_in(root.path()),
+            DetectedInit::Unknown(Some("tini".into()))
+        );
+
+        std::fs::write(root.path().join("proc/1/comm"), "init\n")?;
+        assert_eq!(detect_init_in(root.path()), DetectedInit::SysVInit);
+
+        std::fs::create_dir_all(root.path().join("run/openrc"))?;
+        assert_eq!(detect_init_in(root.path()), DetectedInit::OpenRc);
+
+        std::fs::create_dir_all(root.path().join("run/systemd/system"))?;
+        assert_eq!(detect_init_in(root.path()), DetectedInit::Systemd);
+
+        Ok(())
+    }
+}
@@ -1 +1,119 @@
 pub mod darwin;
+pub mod linux;
+
+use std::path::Path;
+
+use tokio::process::Command;
+
+/// Signs of a Nix install which is already present, found before planning another
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExistingNix {
+    /// `nix-env` runs
+    pub nix_command: bool,
+    /// The Nix store has something in it
+    pub store_populated: bool,
+    /// A `nix-daemon` answers on its socket
+    pub daemon_running: bool,
+    /// A `nix-installer` receipt is present
+    pub receipt: bool,
+}
+
+impl ExistingNix {
+    /// Whether anything was found
+    pub fn found(&self) -> bool {
+        *self != Self::default()
+    }
+}
+
+impl std::fmt::Display for ExistingNix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let findings = [
+            (self.nix_command, "`nix-env` is a valid command"),
+            (self.store_populated, "the Nix store is not empty"),
+            (self.daemon_running, "a `nix-daemon` is running"),
+            (
+                self.receipt,
+                "a receipt from a previous install is at `/nix/receipt.json`",
+            ),
+        ];
+        let found = findings
+            .iter()
+            .filter(|(found, _)| *found)
+            .map(|(_, finding)| *finding)
+            .collect::<Vec<_>>();
+        f.write_str(&found.join(", "))
+    }
+}
+
+/// Look for an existing Nix install, whose store is in `nix_store_path`
+pub async fn detect_existing_nix(nix_store_path: &Path) -> ExistingNix {
+    let nix_command = Command::new("nix-env")
+        .arg("--version")
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .await
+        .is_ok();
+
+    ExistingNix {
+        nix_command,
+        ..detect_existing_nix_files(
+            nix_store_path,
+            Path::new(crate::plan::RECEIPT_LOCATION),
+            Path::new(crate::self_test::DAEMON_SOCKET),
+        )
+    }
+}
+
+fn detect_existing_nix_files(nix_store_path: &Path, receipt: &Path, socket: &Path) -> ExistingNix {
+    let store_populated = match std::fs::read_dir(nix_store_path.join("store")) {
+        Ok(mut entries) => entries.next().is_some(),
+        Err(_) => false,
+    };
+
+    ExistingNix {
+        nix_command: false,
+        store_populated,
+        daemon_running: std::os::unix::net::UnixStream::connect(socket).is_ok(),
+        receipt: receipt.exists(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{detect_existing_nix_files, ExistingNix};
+
+    #[test]
+    fn detects_existing_nix_files() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let nix = temp_dir.path().join("nix");
+        let receipt = nix.join("receipt.json");
+        let socket = nix.join("var/nix/daemon-socket/socket");
+
+        let existing = detect_existing_nix_files(&nix, &receipt, &socket);
+        assert!(!existing.found());
+
+        // An empty store is what a fresh install leaves behind before unpacking
+        std::fs::create_dir_all(nix.join("store"))?;
+        assert!(!detect_existing_nix_files(&nix, &receipt, &socket).found());
+
+        std::fs::create_dir(nix.join("store/abc-hello"))?;
+        std::fs::write(&receipt, "{}")?;
+        let existing = detect_existing_nix_files(&nix, &receipt, &socket);
+        assert_eq!(
+            existing,
+            ExistingNix {
+                nix_command: false,
+                store_populated: true,
+                daemon_running: false,
+                receipt: true,
+            }
+        );
+        assert_eq!(
+            existing.to_string(),
+            "the Nix store is not empty, a receipt from a previous install is at `/nix/receipt.json`"
+        );
+        Ok(())
+    }
+}
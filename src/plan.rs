@@ -1,22 +1,50 @@
 use std::{
+    fmt::Display,
     path::{Path, PathBuf},
     str::FromStr,
 };
 
 use crate::{
-    action::{Action, ActionDescription, StatefulAction},
+    action::{
+        common::CreateUsersAndGroups, Action, ActionDescription, ActionState, StatefulAction,
+    },
+    drift::Drift,
+    next_steps::NextSteps,
     planner::{BuiltinPlanner, Planner},
+    progress::InstallProgress,
+    settings::{rooted, CommonSettings},
     NixInstallerError,
 };
 use owo_colors::OwoColorize;
 use semver::{Version, VersionReq};
-use tokio::sync::broadcast::Receiver;
+use tokio::sync::{broadcast::Receiver, mpsc::Sender};
 
 pub const RECEIPT_LOCATION: &str = "/nix/receipt.json";
 
+/**
+A request to stop an [`InstallPlan::install`] or [`InstallPlan::uninstall`] which is underway
+
+Either way, the action currently running is finished, and the receipt is written before returning.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelSignal {
+    /// Interactive interruption (`SIGINT`), the caller may revert
+    ///
+    /// Actions which had started, including the sub-actions of one which was interrupted part
+    /// way, are left in a state [`InstallPlan::uninstall`] reverts.
+    Interrupt,
+    /// Termination by a service manager (`SIGTERM`), the caller should exit promptly
+    Terminate,
+}
+
 /**
 A set of [`Action`]s, along with some metadata, which can be carried out to drive an install or
 revert
+
+Neither [`install`](Self::install) nor [`uninstall`](Self::uninstall) ever prompt, so they are
+safe to call without a TTY. Asking the user to confirm a plan (as `nix-installer install` does
+unless passed `--no-confirm`) is up to the caller, using [`describe_install`](Self::describe_install)
+and [`describe_uninstall`](Self::describe_uninstall).
 */
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct InstallPlan {
@@ -25,6 +53,89 @@ pub struct InstallPlan {
     pub(crate) actions: Vec<StatefulAction<Box<dyn Action>>>,
 
     pub(crate) planner: Box<dyn Planner>,
+
+    #[serde(default)]
+    pub(crate) provenance: Provenance,
+
+    /// Where to write the receipt instead of [`RECEIPT_LOCATION`], not part of the receipt itself
+    #[serde(skip)]
+    pub(crate) receipt_path: Option<PathBuf>,
+}
+
+/**
+Who and what carried out an install, recorded in the receipt for fleet audits
+
+The [`tag`](Self::tag) is chosen when planning, the rest is filled in by [`InstallPlan::install`].
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Provenance {
+    /// A caller-supplied label, like the name of the provisioning system
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// The user who invoked the installer (before any `sudo`)
+    #[serde(default)]
+    pub user: Option<String>,
+    /// The version of `nix-installer` which ran the install
+    #[serde(default)]
+    pub installer_version: Option<Version>,
+    /// When the install started, in seconds since the Unix epoch
+    #[serde(default)]
+    pub installed_at: Option<u64>,
+}
+
+impl Provenance {
+    fn record(&mut self) -> Result<(), NixInstallerError> {
+        self.user = std::env::var("SUDO_USER").ok().or_else(|| {
+            nix::unistd::User::from_uid(nix::unistd::Uid::current())
+                .ok()
+                .flatten()
+                .map(|user| user.name)
+        });
+        self.installer_version = Some(current_version()?);
+        self.installed_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()
+            .map(|elapsed| elapsed.as_secs());
+        Ok(())
+    }
+}
+
+/**
+The machine a plan was made on, and the system of the Nix package it installs
+
+Shown by [`InstallPlan::describe_install`], so a mismatch (like an `x86_64` `nix-installer` running
+under Rosetta) stands out.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TargetPlatform {
+    /// The target triple `nix-installer` was built for, like `aarch64-apple-darwin`
+    pub host_triple: String,
+    /// The Nix `system` of the package to install, like `aarch64-darwin`, if it can be told from
+    /// the package's file name
+    pub nix_package_system: Option<&'static str>,
+}
+
+impl Display for TargetPlatform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.host_triple)?;
+        match self.nix_package_system {
+            Some(system) => write!(f, " (Nix package for `{system}`)"),
+            None => write!(f, " (Nix package for an unknown system)"),
+        }
+    }
+}
+
+/**
+An action of an [`InstallPlan`], as listed by [`InstallPlan::action_summaries`]
+*/
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct ActionSummary {
+    /// The name of the action, like `provision_nix`
+    pub name: &'static str,
+    /// A one line description of what the action does
+    pub synopsis: String,
+    /// Whether the action has been carried out yet
+    pub state: ActionState,
 }
 
 impl InstallPlan {
@@ -38,6 +149,8 @@ impl InstallPlan {
             planner,
             actions,
             version: current_version()?,
+            provenance: Provenance::default(),
+            receipt_path: None,
         })
     }
 
@@ -55,6 +168,8 @@ impl InstallPlan {
             planner: planner.boxed(),
             actions,
             version: current_version()?,
+            provenance: Provenance::default(),
+            receipt_path: None,
         })
     }
 
@@ -70,6 +185,28 @@ impl InstallPlan {
         Ok(())
     }
 
+    /// The machine this plan was made on, and the system of the Nix package it installs
+    pub fn target_platform(&self) -> TargetPlatform {
+        let nix_package_system = self
+            .common_settings()
+            .and_then(|settings| settings.nix_package_system());
+        TargetPlatform {
+            host_triple: target_lexicon::HOST.to_string(),
+            nix_package_system,
+        }
+    }
+
+    /// The directory this plan installs into as if it were `/`, see [`CommonSettings::root`]
+    pub fn root(&self) -> Option<PathBuf> {
+        self.common_settings().and_then(|settings| settings.root)
+    }
+
+    fn common_settings(&self) -> Option<CommonSettings> {
+        // Planners record all of their common settings, custom planners may not
+        let settings = self.planner.settings().ok()?;
+        serde_json::from_value(serde_json::Value::Object(settings.into_iter().collect())).ok()
+    }
+
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn describe_install(&self, explain: bool) -> Result<String, NixInstallerError> {
         let Self {
@@ -93,16 +230,39 @@ impl InstallPlan {
         // Stabilize output order
         plan_settings.sort();
 
+        let skipped = actions
+            .iter()
+            .filter(|action| action.state == ActionState::Skipped)
+            .map(|action| {
+                format!(
+                    "* {} ({})",
+                    action.tracing_synopsis(),
+                    action.inner_typetag_name()
+                )
+            })
+            .collect::<Vec<_>>();
+
         let buf = format!(
             "\
             Nix install plan (v{version})\n\
             Planner: {planner}{maybe_default_setting_note}\n\
+            Host: {target_platform}\n\
             \n\
             {maybe_plan_settings}\
             Planned actions:\n\
             {actions}\n\
+            {maybe_skipped}\
         ",
+            maybe_skipped = if skipped.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    "\nSkipped actions:\n{skipped}\n",
+                    skipped = skipped.join("\n").yellow()
+                )
+            },
             planner = planner.typetag_name(),
+            target_platform = self.target_platform(),
             maybe_default_setting_note = if plan_settings.is_empty() {
                 String::from(" (with default settings)")
             } else {
@@ -144,41 +304,91 @@ impl InstallPlan {
         Ok(buf)
     }
 
+    /// Execute each action of the plan in order, writing the receipt as it goes
+    ///
+    /// This never prompts. If `cancel_channel` receives a [`CancelSignal`], the receipt is written
+    /// and an error returned, leaving it to the caller to [`uninstall`](Self::uninstall).
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn install<T>(
+        &mut self,
+        feedback: T,
+        cancel_channel: impl Into<Option<Receiver<CancelSignal>>>,
+    ) -> Result<(), NixInstallerError>
+    where
+        T: crate::feedback::Feedback,
+    {
+        self.install_with_progress(feedback, cancel_channel, None)
+            .await
+    }
+
+    /// Like [`install`](Self::install), also sending an [`InstallProgress`] event to `progress` as
+    /// each action runs
+    ///
+    /// A closed `progress` channel does not stop the install.
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn install_with_progress<T>(
         &mut self,
         mut feedback: T,
-        cancel_channel: impl Into<Option<Receiver<()>>>,
+        cancel_channel: impl Into<Option<Receiver<CancelSignal>>>,
+        progress: impl Into<Option<Sender<InstallProgress>>>,
     ) -> Result<(), NixInstallerError>
     where
         T: crate::feedback::Feedback,
     {
         self.check_compatible()?;
         self.pre_install_check().await?;
+        self.provenance.record()?;
 
         let Self { actions, .. } = self;
         let mut cancel_channel = cancel_channel.into();
+        let progress = progress.into();
+        let send_progress = |event| async {
+            if let Some(progress) = &progress {
+                // The receiver going away must not interrupt the install
+                let _ = progress.send(event).await;
+            }
+        };
+        let total = actions.len();
 
         // This is **deliberately sequential**.
         // Actions which are parallelizable are represented by "group actions" like CreateUsers
         // The plan itself represents the concept of the sequence of stages.
-        for action in actions {
+        for (index, action) in actions.iter_mut().enumerate() {
             if let Some(ref mut cancel_channel) = cancel_channel {
-                if cancel_channel.try_recv()
-                    != Err(tokio::sync::broadcast::error::TryRecvError::Empty)
-                {
+                let signal = cancel_channel.try_recv();
+                if signal != Err(tokio::sync::broadcast::error::TryRecvError::Empty) {
                     if let Err(err) = self.write_receipt().await {
                         tracing::error!("Error saving receipt: {:?}", err);
                     }
 
                     feedback.install_cancelled().await;
 
-                    return Err(NixInstallerError::Cancelled);
+                    return Err(if signal == Ok(CancelSignal::Terminate) {
+                        NixInstallerError::Terminated
+                    } else {
+                        NixInstallerError::Cancelled
+                    });
                 }
             }
 
-            tracing::info!("Step: {}", action.tracing_synopsis());
-            if let Err(err) = action.try_execute().await {
+            let synopsis = action.tracing_synopsis();
+            tracing::info!("Step: {synopsis}");
+            send_progress(InstallProgress::ActionStarted {
+                index,
+                total,
+                synopsis: synopsis.clone(),
+            })
+            .await;
+            if let Err(err) =
+                crate::progress::with_sink(progress.clone(), action.try_execute()).await
+            {
+                send_progress(InstallProgress::ActionFailed {
+                    index,
+                    synopsis,
+                    error: err.to_string(),
+                })
+                .await;
+
                 if let Err(err) = self.write_receipt().await {
                     tracing::error!("Error saving receipt: {:?}", err);
                 }
@@ -189,17 +399,21 @@ impl InstallPlan {
 
                 return Err(err);
             }
+            send_progress(InstallProgress::ActionCompleted { index, synopsis }).await;
         }
 
         self.write_receipt().await?;
 
-        if let Err(err) = crate::self_test::self_test()
-            .await
-            .map_err(NixInstallerError::SelfTest)
-        {
-            feedback.self_test_failed(&err).await;
+        // Nix installed into another root cannot be run from here
+        if self.root().is_none() {
+            if let Err(err) = crate::self_test::self_test()
+                .await
+                .map_err(NixInstallerError::SelfTest)
+            {
+                feedback.self_test_failed(&err).await;
 
-            tracing::warn!("{err:?}")
+                tracing::warn!("{err:?}")
+            }
         }
 
         feedback.install_succeeded().await;
@@ -283,11 +497,14 @@ impl InstallPlan {
         Ok(buf)
     }
 
+    /// Revert each action of the plan in reverse order
+    ///
+    /// Like [`install`](Self::install), this never prompts.
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn uninstall<T>(
         &mut self,
         mut feedback: T,
-        cancel_channel: impl Into<Option<Receiver<()>>>,
+        cancel_channel: impl Into<Option<Receiver<CancelSignal>>>,
     ) -> Result<(), NixInstallerError>
     where
         T: crate::feedback::Feedback,
@@ -304,16 +521,19 @@ impl InstallPlan {
         // The plan itself represents the concept of the sequence of stages.
         for action in actions.iter_mut().rev() {
             if let Some(ref mut cancel_channel) = cancel_channel {
-                if cancel_channel.try_recv()
-                    != Err(tokio::sync::broadcast::error::TryRecvError::Empty)
-                {
+                let signal = cancel_channel.try_recv();
+                if signal != Err(tokio::sync::broadcast::error::TryRecvError::Empty) {
                     if let Err(err) = self.write_receipt().await {
                         tracing::error!("Error saving receipt: {:?}", err);
                     }
 
                     feedback.uninstall_cancelled().await;
 
-                    return Err(NixInstallerError::Cancelled);
+                    return Err(if signal == Ok(CancelSignal::Terminate) {
+                        NixInstallerError::Terminated
+                    } else {
+                        NixInstallerError::Cancelled
+                    });
                 }
             }
 
@@ -333,23 +553,227 @@ impl InstallPlan {
         }
     }
 
-    pub fn check_compatible(&self) -> Result<(), NixInstallerError> {
-        let self_version_string = self.version.to_string();
-        let req = VersionReq::parse(&self_version_string)
-            .map_err(|e| NixInstallerError::InvalidVersionRequirement(self_version_string, e))?;
-        let nix_installer_version = current_version()?;
-        if req.matches(&nix_installer_version) {
-            Ok(())
-        } else {
-            Err(NixInstallerError::IncompatibleVersion {
-                binary: nix_installer_version,
-                plan: self.version.clone(),
+    /**
+    Skip actions by their action name (like `configure_init_service`), for debugging an install
+
+    Actions named in `skip` are skipped, wherever they are nested. If `only` is not empty, every
+    top level action which neither is nor contains an action it names is skipped too. Skipped
+    actions are listed by [`describe_install`](Self::describe_install) and are neither executed
+    nor reverted.
+    */
+    pub fn filter_actions(
+        &mut self,
+        only: &[String],
+        skip: &[String],
+    ) -> Result<(), NixInstallerError> {
+        // Nested actions are only reachable through their serialized form, as with the receipt
+        let mut actions = serde_json::to_value(&self.actions)?;
+        let mut known = vec![];
+        action_names(&actions, &mut known);
+        known.sort();
+        known.dedup();
+        let unknown = only
+            .iter()
+            .chain(skip)
+            .filter(|name| !known.contains(name))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !unknown.is_empty() {
+            return Err(NixInstallerError::UnknownActionNames { unknown, known });
+        }
+
+        for action in actions.as_array_mut().into_iter().flatten() {
+            let mut names = vec![];
+            action_names(action, &mut names);
+            if !only.is_empty() && !names.iter().any(|name| only.contains(name)) {
+                action["state"] = serde_json::to_value(ActionState::Skipped)?;
+            }
+            skip_named(action, skip)?;
+        }
+        self.actions = serde_json::from_value(actions).map_err(NixInstallerError::ParsingPlan)?;
+        Ok(())
+    }
+
+    /**
+    Leave the build users and their group in place on [`uninstall`](Self::uninstall), returning how many actions were skipped
+
+    A later install finds the existing users and treats them as already created. This avoids deleting users
+    on macOS, which can need a secure token.
+    */
+    pub fn keep_build_users(&mut self) -> usize {
+        let mut kept = 0;
+        for action in self.actions.iter_mut() {
+            if action.inner_typetag_name() == CreateUsersAndGroups::action_tag().0
+                && action.state != ActionState::Skipped
+            {
+                action.state = ActionState::Skipped;
+                kept += 1;
+            }
+        }
+        kept
+    }
+
+    /**
+    Revert only the plan's action at `index` (counting from 0), such as from a recovery tool
+
+    Later actions may depend on this one, so they must already be reverted (or skipped). Beyond that,
+    ordering is the caller's responsibility: nothing checks whether an earlier action still relies
+    on this one. The receipt is not written, use [`to_json`](Self::to_json) to keep the new state.
+    */
+    #[tracing::instrument(level = "debug", skip_all, fields(index))]
+    pub async fn revert_action(
+        &mut self,
+        index: usize,
+        cancel_channel: impl Into<Option<Receiver<CancelSignal>>>,
+    ) -> Result<(), NixInstallerError> {
+        self.check_compatible()?;
+
+        let len = self.actions.len();
+        if index >= len {
+            return Err(NixInstallerError::NoSuchAction { index, len });
+        }
+        let later = self.actions[index + 1..]
+            .iter()
+            .filter(|action| {
+                !matches!(
+                    action.state,
+                    ActionState::Uncompleted | ActionState::Skipped
+                )
             })
+            .map(|action| action.tracing_synopsis())
+            .collect::<Vec<_>>();
+        if !later.is_empty() {
+            return Err(NixInstallerError::LaterActionsNotReverted { index, later });
+        }
+
+        if let Some(mut cancel_channel) = cancel_channel.into() {
+            match cancel_channel.try_recv() {
+                Err(tokio::sync::broadcast::error::TryRecvError::Empty) => (),
+                Ok(CancelSignal::Terminate) => return Err(NixInstallerError::Terminated),
+                _ => return Err(NixInstallerError::Cancelled),
+            }
+        }
+
+        let action = &mut self.actions[index];
+        tracing::info!("Revert: {}", action.tracing_synopsis());
+        action
+            .try_revert()
+            .await
+            .map_err(|err| NixInstallerError::ActionRevert(vec![err]))
+    }
+
+    pub fn check_compatible(&self) -> Result<(), NixInstallerError> {
+        check_compatible(&self.version)
+    }
+
+    /// Serialize the plan as a receipt, which [`from_json`](Self::from_json) can read back
+    pub fn to_json(&self) -> Result<String, NixInstallerError> {
+        serde_json::to_string_pretty(self).map_err(NixInstallerError::SerializingReceipt)
+    }
+
+    /**
+    Read a plan (or receipt) written by [`to_json`](Self::to_json)
+
+    The plan's `version` is checked before the rest is parsed, so a plan from an incompatible
+    `nix-installer` is reported as such rather than as whichever field changed. A plan without a
+    `version` is assumed to be from this `nix-installer`.
+    */
+    pub fn from_json(json: &str) -> Result<Self, NixInstallerError> {
+        let mut value: serde_json::Value =
+            serde_json::from_str(json).map_err(NixInstallerError::ParsingPlan)?;
+
+        match value.get("version") {
+            Some(version) => {
+                let version = version
+                    .as_str()
+                    .ok_or_else(|| NixInstallerError::InvalidPlanVersion(version.to_string()))?;
+                let version = Version::from_str(version)
+                    .map_err(|_| NixInstallerError::InvalidPlanVersion(version.to_string()))?;
+                check_compatible(&version)?;
+            },
+            None => {
+                let version = current_version()?;
+                tracing::warn!(
+                    "Plan has no `version`, assuming it is from this `nix-installer` ({version})"
+                );
+                if let Some(object) = value.as_object_mut() {
+                    object.insert(
+                        "version".into(),
+                        serde_json::Value::String(version.to_string()),
+                    );
+                }
+            },
         }
+
+        serde_json::from_value(value).map_err(NixInstallerError::ParsingPlan)
+    }
+
+    /// Who and what carried out the install
+    pub fn provenance(&self) -> &Provenance {
+        &self.provenance
+    }
+
+    /// Label the install, see [`Provenance::tag`]
+    pub fn set_tag(&mut self, tag: Option<String>) {
+        self.provenance.tag = tag;
+    }
+
+    /// Write the receipt to `path` instead of [`RECEIPT_LOCATION`] (under the [`root`](Self::root),
+    /// if there is one)
+    pub fn set_receipt_path(&mut self, path: impl Into<Option<PathBuf>>) {
+        self.receipt_path = path.into();
+    }
+
+    /// What the user should do now that Nix is installed
+    ///
+    /// `shell` is the user's shell (typically `$SHELL`).
+    pub fn next_steps(&self, shell: Option<&str>) -> Result<NextSteps, NixInstallerError> {
+        let settings = self.planner.settings()?;
+        Ok(NextSteps::from_settings(&settings, shell))
+    }
+
+    /// Each planned action, in the order [`install`](Self::install) runs them, with its current state
+    ///
+    /// This runs nothing, so it suits rendering a checklist before, during, or after an install, or
+    /// from a receipt.
+    ///
+    /// ```rust,no_run
+    /// use nix_installer::{action::ActionState, InstallPlan};
+    ///
+    /// # async fn checklist() -> color_eyre::Result<()> {
+    /// let plan = InstallPlan::default().await?;
+    /// for summary in plan.action_summaries() {
+    ///     let mark = match summary.state {
+    ///         ActionState::Completed => "x",
+    ///         _ => " ",
+    ///     };
+    ///     println!("[{mark}] {}", summary.synopsis);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn action_summaries(&self) -> Vec<ActionSummary> {
+        self.actions
+            .iter()
+            .map(|action| ActionSummary {
+                name: action.inner_typetag_name(),
+                synopsis: action.tracing_synopsis(),
+                state: action.state(),
+            })
+            .collect()
+    }
+
+    /// How the system has drifted from the artifacts this plan created
+    pub fn drift(&self) -> Result<Vec<Drift>, NixInstallerError> {
+        let receipt = serde_json::to_value(self).map_err(NixInstallerError::SerializingReceipt)?;
+        Ok(crate::drift::detect(&receipt))
     }
 
     pub(crate) async fn write_receipt(&self) -> Result<(), NixInstallerError> {
-        let install_receipt_path = PathBuf::from(RECEIPT_LOCATION);
+        let install_receipt_path = self
+            .receipt_path
+            .clone()
+            .unwrap_or_else(|| rooted(self.root().as_deref(), RECEIPT_LOCATION));
         write_receipt(self, &install_receipt_path).await?;
 
         Ok(())
@@ -368,9 +792,10 @@ pub(crate) async fn write_receipt(
     let self_json =
         serde_json::to_string_pretty(plan).map_err(NixInstallerError::SerializingReceipt)?;
 
-    tokio::fs::create_dir_all("/nix")
+    let receipt_dir = install_receipt_path.parent().unwrap_or(Path::new("/nix"));
+    tokio::fs::create_dir_all(receipt_dir)
         .await
-        .map_err(|e| NixInstallerError::RecordingReceipt(PathBuf::from("/nix"), e))?;
+        .map_err(|e| NixInstallerError::RecordingReceipt(receipt_dir.to_path_buf(), e))?;
     tokio::fs::write(&install_receipt_path_tmp, format!("{self_json}\n"))
         .await
         .map_err(|e| NixInstallerError::RecordingReceipt(install_receipt_path_tmp.clone(), e))?;
@@ -381,6 +806,21 @@ pub(crate) async fn write_receipt(
     Ok(())
 }
 
+fn check_compatible(plan_version: &Version) -> Result<(), NixInstallerError> {
+    let plan_version_string = plan_version.to_string();
+    let req = VersionReq::parse(&plan_version_string)
+        .map_err(|e| NixInstallerError::InvalidVersionRequirement(plan_version_string, e))?;
+    let nix_installer_version = current_version()?;
+    if req.matches(&nix_installer_version) {
+        Ok(())
+    } else {
+        Err(NixInstallerError::IncompatibleVersion {
+            binary: nix_installer_version,
+            plan: plan_version.clone(),
+        })
+    }
+}
+
 pub fn current_version() -> Result<Version, NixInstallerError> {
     let nix_installer_version_str = env!("CARGO_PKG_VERSION");
     Version::from_str(nix_installer_version_str).map_err(|e| {
@@ -388,11 +828,266 @@ pub fn current_version() -> Result<Version, NixInstallerError> {
     })
 }
 
+/// Collect the action name of every serialized action in `value`, including nested ones
+fn action_names(value: &serde_json::Value, names: &mut Vec<String>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            if let Some(name) = map.get("action_name").and_then(serde_json::Value::as_str) {
+                names.push(name.to_string());
+            }
+            for value in map.values() {
+                action_names(value, names);
+            }
+        },
+        serde_json::Value::Array(values) => {
+            for value in values {
+                action_names(value, names);
+            }
+        },
+        _ => (),
+    }
+}
+
+/// Mark every serialized `StatefulAction` in `value` whose action is named in `skip` as skipped
+fn skip_named(value: &mut serde_json::Value, skip: &[String]) -> Result<(), serde_json::Error> {
+    match value {
+        serde_json::Value::Object(map) => {
+            let named = map
+                .get("action")
+                .and_then(|action| action.get("action_name"))
+                .and_then(serde_json::Value::as_str)
+                .is_some_and(|name| skip.iter().any(|skip| skip == name));
+            if named && map.contains_key("state") {
+                map.insert(
+                    "state".to_string(),
+                    serde_json::to_value(ActionState::Skipped)?,
+                );
+            }
+            for value in map.values_mut() {
+                skip_named(value, skip)?;
+            }
+        },
+        serde_json::Value::Array(values) => {
+            for value in values {
+                skip_named(value, skip)?;
+            }
+        },
+        _ => (),
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use semver::Version;
 
-    use crate::{planner::BuiltinPlanner, InstallPlan, NixInstallerError};
+    use crate::{planner::BuiltinPlanner, InstallPlan, NixInstallerError, Provenance};
+
+    // The running action is left `Progress` by an interruption (or failure), and must be reverted
+    // along with those before it
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn uninstall_reverts_started_actions() -> eyre::Result<()> {
+        use crate::{
+            action::{base::CreateDirectory, ActionState},
+            feedback::devnull::DevNull,
+            planner::{single_user::SingleUser, Planner},
+        };
+
+        let temp_dir = tempfile::tempdir()?;
+        let completed = temp_dir.path().join("completed");
+        let interrupted = temp_dir.path().join("interrupted");
+        let not_started = temp_dir.path().join("not-started");
+
+        let mut actions = vec![];
+        for path in [&completed, &interrupted, &not_started] {
+            actions.push(
+                CreateDirectory::plan(path, None, None, None, false)
+                    .await?
+                    .boxed(),
+            );
+        }
+        let mut plan = InstallPlan {
+            version: super::current_version()?,
+            actions,
+            planner: SingleUser::default().await?.boxed(),
+            provenance: Provenance::default(),
+            receipt_path: Some(temp_dir.path().join("receipt.json")),
+        };
+        plan.actions[0].try_execute().await?;
+        plan.actions[1].try_execute().await?;
+        plan.actions[1].state = ActionState::Progress;
+        // Made by someone else after planning, so not ours to remove
+        std::fs::create_dir(&not_started)?;
+
+        plan.uninstall(DevNull, None).await?;
+        assert!(!completed.exists());
+        assert!(!interrupted.exists());
+        assert!(not_started.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn target_platform_reads_nix_package() -> eyre::Result<()> {
+        let fixture = include_str!("../tests/fixtures/linux/linux.json");
+        let mut value: serde_json::Value = serde_json::from_str(fixture)?;
+        value["planner"]["settings"]["nix_package_url"] = serde_json::json!({
+            "Url": "https://releases.nixos.org/nix/nix-2.18.1/nix-2.18.1-aarch64-darwin.tar.xz"
+        });
+        let plan = InstallPlan::from_json(&value.to_string())?;
+
+        let platform = plan.target_platform();
+        assert_eq!(platform.host_triple, target_lexicon::HOST.to_string());
+        assert_eq!(platform.nix_package_system, Some("aarch64-darwin"));
+        assert!(platform
+            .to_string()
+            .ends_with(" (Nix package for `aarch64-darwin`)"));
+        Ok(())
+    }
+
+    #[test]
+    fn from_json_checks_version() -> eyre::Result<()> {
+        let fixture = include_str!("../tests/fixtures/linux/linux.json");
+        let plan = InstallPlan::from_json(fixture)?;
+        let round_tripped = InstallPlan::from_json(&plan.to_json()?)?;
+        assert_eq!(round_tripped.version, plan.version);
+
+        let mut value: serde_json::Value = serde_json::from_str(fixture)?;
+        value["version"] = "9999999999999.0.0".into();
+        // An incompatible plan is reported as such, even if its actions no longer parse
+        value["actions"] = serde_json::json!([{ "action_name": "from_the_future" }]);
+        assert!(matches!(
+            InstallPlan::from_json(&value.to_string()),
+            Err(NixInstallerError::IncompatibleVersion { .. })
+        ));
+
+        // Plans without a version load, as the current version
+        let object = value.as_object_mut().unwrap();
+        object.remove("version");
+        object.insert(
+            "actions".into(),
+            serde_json::from_str::<serde_json::Value>(fixture)?["actions"].clone(),
+        );
+        let plan = InstallPlan::from_json(&value.to_string())?;
+        assert_eq!(plan.version, Version::parse(env!("CARGO_PKG_VERSION"))?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn revert_action_checks_later_actions() -> eyre::Result<()> {
+        use crate::action::ActionState;
+
+        let mut plan = InstallPlan::from_json(include_str!("../tests/fixtures/linux/linux.json"))?;
+        let last = plan.actions.len() - 1;
+
+        assert!(matches!(
+            plan.revert_action(last + 1, None).await,
+            Err(NixInstallerError::NoSuchAction { .. })
+        ));
+        assert!(matches!(
+            plan.revert_action(last - 1, None).await,
+            Err(NixInstallerError::LaterActionsNotReverted { index, ref later }) if index == last - 1 && later.len() == 1
+        ));
+        assert_eq!(plan.actions[last - 1].state, ActionState::Completed);
+
+        // Reverting the scratch directory removal does nothing to the system
+        plan.revert_action(last, None).await?;
+        assert_eq!(plan.actions[last].state, ActionState::Uncompleted);
+        assert!(plan.actions[..last]
+            .iter()
+            .all(|action| action.state == ActionState::Completed));
+        Ok(())
+    }
+
+    #[test]
+    fn filter_actions_skips_by_name() -> eyre::Result<()> {
+        use crate::action::ActionState;
+
+        let fixture = include_str!("../tests/fixtures/linux/linux.json");
+        let skipped = |plan: &InstallPlan| {
+            plan.actions
+                .iter()
+                .filter(|action| action.state == ActionState::Skipped)
+                .map(|action| action.inner_typetag_name())
+                .collect::<Vec<_>>()
+        };
+
+        let mut plan = InstallPlan::from_json(fixture)?;
+        plan.filter_actions(&[], &["remove_directory".to_string()])?;
+        assert_eq!(skipped(&plan), vec!["remove_directory"]);
+
+        let mut plan = InstallPlan::from_json(fixture)?;
+        plan.filter_actions(&["remove_directory".to_string()], &[])?;
+        assert_eq!(skipped(&plan).len(), plan.actions.len() - 1);
+        assert!(!skipped(&plan).contains(&"remove_directory"));
+
+        // Nested actions are skipped in place, leaving their parent to run the rest
+        let mut plan = InstallPlan::from_json(fixture)?;
+        plan.filter_actions(&[], &["configure_init_service".to_string()])?;
+        assert!(skipped(&plan).is_empty());
+        let receipt = serde_json::to_value(&plan.actions)?;
+        let init_service = receipt
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|action| action["action"]["action_name"] == "create_upstream_init_service")
+            .expect("the fixture configures the init service");
+        assert_eq!(
+            init_service["action"]["configure_init_service"]["state"],
+            serde_json::json!("Skipped")
+        );
+
+        let mut plan = InstallPlan::from_json(fixture)?;
+        plan.filter_actions(&["configure_init_service".to_string()], &[])?;
+        assert_eq!(skipped(&plan).len(), plan.actions.len() - 1);
+        assert!(!skipped(&plan).contains(&"create_upstream_init_service"));
+
+        let mut plan = InstallPlan::from_json(fixture)?;
+        assert!(matches!(
+            plan.filter_actions(&[], &["start_the_engines".to_string()]),
+            Err(NixInstallerError::UnknownActionNames { ref unknown, .. }) if unknown == &["start_the_engines"]
+        ));
+        assert!(skipped(&plan).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn keep_build_users_skips_their_revert() -> eyre::Result<()> {
+        use crate::action::{common::CreateUsersAndGroups, Action, ActionState};
+
+        let mut plan = InstallPlan::from_json(include_str!("../tests/fixtures/linux/linux.json"))?;
+        assert_eq!(plan.keep_build_users(), 1);
+        assert_eq!(plan.keep_build_users(), 0);
+        for action in &plan.actions {
+            let is_users = action.inner_typetag_name() == CreateUsersAndGroups::action_tag().0;
+            assert_eq!(action.state == ActionState::Skipped, is_users);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn action_durations_round_trip() -> eyre::Result<()> {
+        let mut plan: InstallPlan =
+            serde_json::from_str(include_str!("../tests/fixtures/linux/linux.json"))?;
+        assert!(plan
+            .actions
+            .iter()
+            .all(|action| action.duration().is_none()));
+
+        plan.actions[0].duration = Some(std::time::Duration::from_millis(1500));
+        let plan: InstallPlan = serde_json::from_str(&serde_json::to_string(&plan)?)?;
+        assert_eq!(
+            plan.actions[0].duration(),
+            Some(std::time::Duration::from_millis(1500))
+        );
+        assert!(plan.actions[1..]
+            .iter()
+            .all(|action| action.duration().is_none()));
+        Ok(())
+    }
 
     #[tokio::test]
     async fn ensure_version_allows_compatible() -> Result<(), NixInstallerError> {
@@ -421,4 +1116,27 @@ mod test {
         assert!(maybe_plan.check_compatible().is_err());
         Ok(())
     }
+
+    #[tokio::test]
+    async fn provenance_round_trips() -> Result<(), NixInstallerError> {
+        let planner = BuiltinPlanner::default().await?;
+        let value = serde_json::json!({
+            "planner": planner.boxed(),
+            "version": Version::parse(env!("CARGO_PKG_VERSION"))?,
+            "actions": [],
+        });
+        let mut plan: InstallPlan = serde_json::from_value(value)?;
+        assert_eq!(plan.provenance(), &Provenance::default());
+
+        plan.set_tag(Some("provisioner-v3".into()));
+        plan.provenance.record()?;
+        let round_tripped: InstallPlan = serde_json::from_str(&serde_json::to_string(&plan)?)?;
+        assert_eq!(round_tripped.provenance(), plan.provenance());
+        assert_eq!(
+            round_tripped.provenance().tag.as_deref(),
+            Some("provisioner-v3")
+        );
+        assert!(round_tripped.provenance().installer_version.is_some());
+        Ok(())
+    }
 }
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+use super::{
+    linux::{check_nix_not_already_installed, check_no_running_nix_daemon, check_not_nixos},
+    ShellProfileLocations,
+};
+use crate::{
+    action::{
+        base::{CheckFreeSpace, CreateDirectory, RemoveDirectory},
+        common::{
+            ConfigureDaemonRestart, ConfigureDeterminateNixdInitService, ConfigureNix,
+            ConfigureUpstreamInitService, CreateUsersAndGroups, ProvisionDeterminateNixd,
+            ProvisionNix, RunPostInstall, NIX_DAEMON_PROFILE_SCRIPT,
+        },
+        linux::{CreateTmpfilesRule, DAEMON_SOCKET_TMPFILES_DEST, DAEMON_SOCKET_TMPFILES_RULE},
+        StatefulAction,
+    },
+    error::HasExpectedErrors,
+    planner::{Planner, PlannerError},
+    settings::{
+        CommonSettings, Component, InitSystem, InstallSettingsError, DEFAULT_DAEMON_UNIT_NAME,
+    },
+    Action, BuiltinPlanner,
+};
+
+/// The states `systemctl is-system-running` reports while systemd is managing the system
+///
+/// `degraded` (some unit failed) and the transitional states all exit non-zero, but systemd can
+/// still start the daemon.
+const USABLE_SYSTEMD_STATES: &[&str] = &[
+    "initializing",
+    "starting",
+    "running",
+    "degraded",
+    "maintenance",
+];
+
+/**
+A planner for containers, like those run by `systemd-nspawn`, Docker, or Podman, including unprivileged ones
+
+Nix is installed with build users as on [`Linux`](super::linux::Linux). The daemon is only set up
+when `systemctl is-system-running` shows systemd is managing the container, otherwise it is left
+for the container's entrypoint or init to start.
+*/
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct Container {
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub settings: CommonSettings,
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "container")]
+impl Planner for Container {
+    async fn default() -> Result<Self, PlannerError> {
+        Ok(Self {
+            settings: CommonSettings::default().await?,
+        })
+    }
+
+    async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        super::check_no_existing_nix(&self.settings).await?;
+
+        let has_systemd = systemd_usable().await;
+
+        let mut plan = vec![];
+
+        plan.push(
+            CreateDirectory::plan(&self.settings.nix_store_path, None, None, 0o0755, true)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        if self.settings.determinate_nix {
+            if !has_systemd {
+                return Err(ContainerErrorKind::DeterminateNixdWithoutSystemd.into());
+            }
+            plan.push(
+                ProvisionDeterminateNixd::plan()
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
+
+        if self.settings.required_free_space > 0 {
+            plan.push(
+                CheckFreeSpace::plan(
+                    &self.settings.nix_store_path,
+                    self.settings.required_free_space,
+                )
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+            );
+        }
+
+        plan.push(
+            ProvisionNix::plan(&self.settings.clone())
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            CreateUsersAndGroups::plan(self.settings.clone())
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            ConfigureNix::plan(ShellProfileLocations::default(), &self.settings)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        if self.settings.installs(Component::Daemon) {
+            if has_systemd {
+                plan.push(
+                    CreateDirectory::plan("/etc/tmpfiles.d", None, None, 0o0755, false)
+                        .await
+                        .map_err(PlannerError::Action)?
+                        .boxed(),
+                );
+                plan.push(
+                    CreateTmpfilesRule::plan(
+                        DAEMON_SOCKET_TMPFILES_DEST,
+                        DAEMON_SOCKET_TMPFILES_RULE,
+                    )
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+                );
+                if self.settings.determinate_nix {
+                    plan.push(
+                        ConfigureDeterminateNixdInitService::plan(InitSystem::Systemd, true)
+                            .await
+                            .map_err(PlannerError::Action)?
+                            .boxed(),
+                    );
+                } else {
+                    plan.push(
+                        ConfigureUpstreamInitService::plan(
+                            InitSystem::Systemd,
+                            true,
+                            DEFAULT_DAEMON_UNIT_NAME,
+                        )
+                        .await
+                        .map_err(PlannerError::Action)?
+                        .boxed(),
+                    );
+                }
+                if self.settings.daemon_restart_on_failure {
+                    plan.push(
+                        ConfigureDaemonRestart::plan(InitSystem::Systemd, None, None)
+                            .await
+                            .map_err(PlannerError::Action)?
+                            .boxed(),
+                    );
+                }
+            } else {
+                tracing::info!(
+                    "systemd is not managing this container, so the Nix daemon is left for the container to start with `/nix/var/nix/profiles/default/bin/nix-daemon`"
+                );
+            }
+        }
+
+        if !self.settings.post_install_commands.is_empty() {
+            plan.push(
+                RunPostInstall::plan(NIX_DAEMON_PROFILE_SCRIPT, &self.settings)
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
+        plan.push(
+            RemoveDirectory::plan(crate::settings::SCRATCH_DIR)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        Ok(plan)
+    }
+
+    fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
+        let Self { settings } = self;
+        let mut map = HashMap::default();
+
+        map.extend(settings.settings()?);
+
+        Ok(map)
+    }
+
+    async fn configured_settings(
+        &self,
+    ) -> Result<HashMap<String, serde_json::Value>, PlannerError> {
+        let default = Self::default().await?.settings()?;
+        let configured = self.settings()?;
+
+        let mut settings: HashMap<String, serde_json::Value> = HashMap::new();
+        for (key, value) in configured.iter() {
+            if default.get(key) != Some(value) {
+                settings.insert(key.clone(), value.clone());
+            }
+        }
+
+        Ok(settings)
+    }
+
+    async fn platform_check(&self) -> Result<(), PlannerError> {
+        use target_lexicon::OperatingSystem;
+        match target_lexicon::OperatingSystem::host() {
+            OperatingSystem::Linux => Ok(()),
+            host_os => Err(PlannerError::IncompatibleOperatingSystem {
+                planner: self.typetag_name(),
+                host_os,
+            }),
+        }
+    }
+
+    async fn pre_install_check(&self) -> Result<(), PlannerError> {
+        self.settings.check_offline()?;
+        self.settings.check_components()?;
+        self.settings.check_nix_package_system()?;
+        self.settings.check_default_nix_store_path()?;
+        self.settings.check_no_root()?;
+        check_not_nixos()?;
+
+        check_nix_not_already_installed(&self.settings).await?;
+
+        if systemd_usable().await {
+            check_no_running_nix_daemon(&self.settings, false, DEFAULT_DAEMON_UNIT_NAME).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl From<Container> for BuiltinPlanner {
+    fn from(val: Container) -> Self {
+        BuiltinPlanner::Container(val)
+    }
+}
+
+/// Whether systemd is managing this container, so it can run the daemon
+///
+/// Asks `systemctl is-system-running` rather than looking for `/run/systemd/system`, which a
+/// container image can ship or bind mount without systemd being its init.
+pub(crate) async fn systemd_usable() -> bool {
+    let output = tokio::process::Command::new("systemctl")
+        .arg("is-system-running")
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await;
+    match output {
+        Ok(output) => {
+            let state = String::from_utf8_lossy(&output.stdout);
+            let usable = is_usable_systemd_state(&state);
+            tracing::debug!(state = %state.trim(), usable, "Probed systemd with `systemctl is-system-running`");
+            usable
+        },
+        Err(e) => {
+            tracing::debug!(%e, "Could not run `systemctl is-system-running`, so systemd is not usable");
+            false
+        },
+    }
+}
+
+fn is_usable_systemd_state(state: &str) -> bool {
+    USABLE_SYSTEMD_STATES.contains(&state.trim())
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum ContainerErrorKind {
+    #[error("Determinate Nixd requires systemd, but `systemctl is-system-running` shows systemd is not managing this container")]
+    DeterminateNixdWithoutSystemd,
+}
+
+impl HasExpectedErrors for ContainerErrorKind {
+    fn expected<'a>(&'a self) -> Option<Box<dyn std::error::Error + 'a>> {
+        match self {
+            ContainerErrorKind::DeterminateNixdWithoutSystemd => Some(Box::new(self)),
+        }
+    }
+}
+
+impl From<ContainerErrorKind> for PlannerError {
+    fn from(v: ContainerErrorKind) -> PlannerError {
+        PlannerError::Custom(Box::new(v))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::is_usable_systemd_state;
+
+    #[test]
+    fn systemd_states_are_classified() {
+        assert!(is_usable_systemd_state("running\n"));
+        assert!(is_usable_systemd_state("degraded\n"));
+        assert!(is_usable_systemd_state("starting\n"));
+        // Printed when systemd is not PID 1, as in most containers
+        assert!(!is_usable_systemd_state("offline\n"));
+        assert!(!is_usable_systemd_state("unknown\n"));
+        assert!(!is_usable_systemd_state(""));
+    }
+}
@@ -1,4 +1,7 @@
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use tokio::process::Command;
 use which::which;
@@ -6,20 +9,29 @@ use which::which;
 use super::ShellProfileLocations;
 use crate::{
     action::{
-        base::{CreateDirectory, RemoveDirectory},
+        base::{CheckFreeSpace, CreateDirectory, CreateSymlink, RemoveDirectory},
         common::{
-            ConfigureDeterminateNixdInitService, ConfigureNix, ConfigureUpstreamInitService,
-            CreateUsersAndGroups, ProvisionDeterminateNixd, ProvisionNix,
+            ConfigureDaemonRestart, ConfigureDeterminateNixdInitService, ConfigureNix,
+            ConfigureUpstreamInitService, CreateUsersAndGroups, ProvisionDeterminateNixd,
+            ProvisionNix, RunPostInstall, NIX_DAEMON_PROFILE_SCRIPT,
         },
         linux::{
             provision_selinux::{DETERMINATE_SELINUX_POLICY_PP_CONTENT, SELINUX_POLICY_PP_CONTENT},
-            ProvisionSelinux,
+            CreateTmpfilesRule, ProvisionSelinux, StartOpenrcService, DAEMON_SOCKET_TMPFILES_DEST,
+            DAEMON_SOCKET_TMPFILES_RULE,
         },
         StatefulAction,
     },
     error::HasExpectedErrors,
+    os::{
+        detect_existing_nix,
+        linux::{detect_init, read_only_mount, DetectedInit},
+    },
     planner::{Planner, PlannerError},
-    settings::{CommonSettings, InitSettings, InitSystem, InstallSettingsError},
+    settings::{
+        CommonSettings, Component, InitSettings, InitSystem, InstallSettingsError,
+        DEFAULT_DAEMON_UNIT_NAME, DEFAULT_NIX_STORE_PATH,
+    },
     Action, BuiltinPlanner,
 };
 
@@ -46,16 +58,31 @@ impl Planner for Linux {
     }
 
     async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        if let Some(root) = &self.settings.root {
+            return self.plan_into_root(root).await;
+        }
+        super::check_no_existing_nix(&self.settings).await?;
+        check_daemon_unit_name(&self.init, &self.settings)?;
+        check_nix_store_path_writable(&self.settings.nix_store_path)?;
+
         let has_selinux = detect_selinux().await?;
 
         let mut plan = vec![];
 
         plan.push(
-            CreateDirectory::plan("/nix", None, None, 0o0755, true)
+            CreateDirectory::plan(&self.settings.nix_store_path, None, None, 0o0755, true)
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
         );
+        if self.settings.relocated_nix_store() {
+            plan.push(
+                CreateSymlink::plan("/nix", &self.settings.nix_store_path)
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
 
         if self.settings.determinate_nix {
             plan.push(
@@ -66,6 +93,18 @@ impl Planner for Linux {
             );
         }
 
+        if self.settings.required_free_space > 0 {
+            plan.push(
+                CheckFreeSpace::plan(
+                    &self.settings.nix_store_path,
+                    self.settings.required_free_space,
+                )
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+            );
+        }
+
         plan.push(
             ProvisionNix::plan(&self.settings.clone())
                 .await
@@ -101,23 +140,79 @@ impl Planner for Linux {
             );
         }
 
-        plan.push(
-            CreateDirectory::plan("/etc/tmpfiles.d", None, None, 0o0755, false)
-                .await
-                .map_err(PlannerError::Action)?
-                .boxed(),
-        );
-
-        if self.settings.determinate_nix {
+        if self.settings.installs(Component::Daemon) {
             plan.push(
-                ConfigureDeterminateNixdInitService::plan(self.init.init, self.init.start_daemon)
+                CreateDirectory::plan("/etc/tmpfiles.d", None, None, 0o0755, false)
                     .await
                     .map_err(PlannerError::Action)?
                     .boxed(),
             );
-        } else {
+            if self.init.init == InitSystem::Systemd {
+                plan.push(
+                    CreateTmpfilesRule::plan(
+                        DAEMON_SOCKET_TMPFILES_DEST,
+                        DAEMON_SOCKET_TMPFILES_RULE,
+                    )
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+                );
+            }
+
+            if self.init.init == InitSystem::OpenRc {
+                if self.settings.determinate_nix {
+                    return Err(LinuxErrorKind::DeterminateNixdOpenRc.into());
+                }
+                plan.push(
+                    StartOpenrcService::plan(
+                        self.init.start_daemon,
+                        self.settings.daemon_restart_on_failure,
+                    )
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+                );
+            } else if self.settings.determinate_nix {
+                plan.push(
+                    ConfigureDeterminateNixdInitService::plan(
+                        self.init.init,
+                        self.init.start_daemon,
+                    )
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+                );
+            } else {
+                plan.push(
+                    ConfigureUpstreamInitService::plan(
+                        self.init.init,
+                        self.init.start_daemon,
+                        &self.init.daemon_unit_name,
+                    )
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+                );
+            }
+            // OpenRC restarts the daemon via the `supervise-daemon` in its init script
+            if self.settings.daemon_restart_on_failure && self.init.init != InitSystem::OpenRc {
+                plan.push(
+                    ConfigureDaemonRestart::plan(
+                        self.init.init,
+                        None,
+                        self.init
+                            .custom_daemon_unit_name()
+                            .then(|| format!("{}.service", self.init.daemon_unit_name)),
+                    )
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+                );
+            }
+        }
+        if !self.settings.post_install_commands.is_empty() {
             plan.push(
-                ConfigureUpstreamInitService::plan(self.init.init, self.init.start_daemon)
+                RunPostInstall::plan(NIX_DAEMON_PROFILE_SCRIPT, &self.settings)
                     .await
                     .map_err(PlannerError::Action)?
                     .boxed(),
@@ -181,26 +276,121 @@ impl Planner for Linux {
     }
 
     async fn pre_install_check(&self) -> Result<(), PlannerError> {
+        self.settings.check_offline()?;
+        self.settings.check_components()?;
+        self.settings.check_nix_package_system()?;
+        self.settings.check_root()?;
+        // Nothing about the running system matters to an install into another root
+        if let Some(root) = &self.settings.root {
+            return check_root_has_no_nix(root, &self.settings);
+        }
         check_not_nixos()?;
 
-        check_nix_not_already_installed().await?;
+        check_nix_not_already_installed(&self.settings).await?;
 
         check_not_wsl1()?;
 
-        if self.init.init == InitSystem::Systemd && self.init.start_daemon {
-            check_systemd_active()?;
+        check_init_supported(self.init.init, self.init.start_daemon)?;
+        check_daemon_unit_name(&self.init, &self.settings)?;
+
+        if self.init.init == InitSystem::Systemd {
+            check_no_running_nix_daemon(
+                &self.settings,
+                self.init.replace_running_daemon,
+                &self.init.daemon_unit_name,
+            )
+            .await?;
         }
 
         Ok(())
     }
 }
 
+impl Linux {
+    /// Install the store and Nix's configuration under `root`, like for a system image
+    ///
+    /// Build users and the daemon's init service are left for the system booted from `root`, as
+    /// creating them would change the running system instead.
+    async fn plan_into_root(
+        &self,
+        root: &Path,
+    ) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        tracing::warn!(
+            root = %root.display(),
+            "Installing into another root is experimental, build users and the Nix daemon are not set up"
+        );
+        let mut plan = vec![];
+
+        let nix_store_path = self.settings.rooted(&self.settings.nix_store_path);
+        plan.push(
+            CreateDirectory::plan(&nix_store_path, None, None, 0o0755, true)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        if self.settings.required_free_space > 0 {
+            plan.push(
+                CheckFreeSpace::plan(&nix_store_path, self.settings.required_free_space)
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
+        plan.push(
+            ProvisionNix::plan(&self.settings)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            ConfigureNix::plan(
+                ShellProfileLocations::default().rooted(root),
+                &self.settings,
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
+        );
+        plan.push(
+            RemoveDirectory::plan(self.settings.rooted(crate::settings::SCRATCH_DIR))
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        Ok(plan)
+    }
+}
+
 impl From<Linux> for BuiltinPlanner {
     fn from(val: Linux) -> Self {
         BuiltinPlanner::Linux(val)
     }
 }
 
+/// Only the upstream daemon's systemd units can be renamed
+pub(crate) fn check_daemon_unit_name(
+    init: &InitSettings,
+    settings: &CommonSettings,
+) -> Result<(), InstallSettingsError> {
+    init.check_daemon_unit_name()?;
+    if init.custom_daemon_unit_name()
+        && (init.init != InitSystem::Systemd || settings.determinate_nix)
+    {
+        return Err(InstallSettingsError::DaemonUnitNameUnsupported);
+    }
+    Ok(())
+}
+
+/// Refuse to install into a root which already has a Nix store, unless reconciling with it
+fn check_root_has_no_nix(root: &Path, settings: &CommonSettings) -> Result<(), PlannerError> {
+    let store = settings.rooted(Path::new(DEFAULT_NIX_STORE_PATH).join("store"));
+    if store.exists() && !settings.reconcile_existing {
+        return Err(LinuxErrorKind::RootHasNix(root.to_path_buf()).into());
+    }
+    Ok(())
+}
+
 // If on NixOS, running `nix_installer` is pointless
 pub(crate) fn check_not_nixos() -> Result<(), PlannerError> {
     // NixOS always sets up this file as part of setting up /etc itself: https://github.com/NixOS/nixpkgs/blob/bdd39e5757d858bd6ea58ed65b4a2e52c8ed11ca/nixos/modules/system/etc/setup-etc.pl#L145
@@ -210,6 +400,18 @@ pub(crate) fn check_not_nixos() -> Result<(), PlannerError> {
     Ok(())
 }
 
+/// Refuse to plan when the store would be created on a read-only filesystem, as on immutable distributions
+pub(crate) fn check_nix_store_path_writable(nix_store_path: &Path) -> Result<(), PlannerError> {
+    match read_only_mount(nix_store_path) {
+        Some(mount_point) => Err(LinuxErrorKind::ReadOnlyNixStorePath {
+            nix_store_path: nix_store_path.to_path_buf(),
+            mount_point,
+        }
+        .into()),
+        None => Ok(()),
+    }
+}
+
 pub(crate) fn check_not_wsl1() -> Result<(), PlannerError> {
     // Detection strategies: https://patrickwu.space/wslconf/
     if std::env::var("WSL_DISTRO_NAME").is_ok() && std::env::var("WSL_INTEROP").is_err() {
@@ -233,16 +435,17 @@ pub(crate) async fn detect_selinux() -> Result<bool, PlannerError> {
     }
 }
 
-pub(crate) async fn check_nix_not_already_installed() -> Result<(), PlannerError> {
+pub(crate) async fn check_nix_not_already_installed(
+    settings: &CommonSettings,
+) -> Result<(), PlannerError> {
+    if settings.reconcile_existing {
+        return Ok(());
+    }
     // For now, we don't try to repair the user's Nix install or anything special.
-    if Command::new("nix-env")
-        .arg("--version")
-        .stdin(std::process::Stdio::null())
-        .status()
-        .await
-        .is_ok()
-    {
-        return Err(PlannerError::NixExists);
+    let existing = detect_existing_nix(Path::new(DEFAULT_NIX_STORE_PATH)).await;
+    // A resumed install has a store and receipt already, only a working `nix` rules it out
+    if existing.nix_command {
+        return Err(PlannerError::NixExists(existing));
     }
 
     Ok(())
@@ -260,6 +463,122 @@ pub(crate) fn check_systemd_active() -> Result<(), PlannerError> {
     Ok(())
 }
 
+/// Refuse to configure an init system other than the one this machine is running, as it would
+/// fail late with a confusing error
+pub(crate) fn check_init_supported(
+    init: InitSystem,
+    start_daemon: bool,
+) -> Result<(), PlannerError> {
+    init_conflict(init, start_daemon, detect_init())?;
+    Ok(())
+}
+
+fn init_conflict(
+    init: InitSystem,
+    start_daemon: bool,
+    found: DetectedInit,
+) -> Result<(), LinuxErrorKind> {
+    // An init which isn't started now only needs to be running later
+    if !start_daemon {
+        return Ok(());
+    }
+    match (init, found) {
+        (InitSystem::None | InitSystem::Launchd, _)
+        | (InitSystem::Systemd, DetectedInit::Systemd)
+        | (InitSystem::OpenRc, DetectedInit::OpenRc) => Ok(()),
+        (InitSystem::Systemd, _) if std::env::var("WSL_DISTRO_NAME").is_ok() => {
+            Err(LinuxErrorKind::Wsl2SystemdNotActive)
+        },
+        (init, found) => Err(LinuxErrorKind::UnsupportedInit { init, found }),
+    }
+}
+
+const NIX_DAEMON_SOCKET: &str = "/nix/var/nix/daemon-socket/socket";
+const NIX_DAEMON_UNITS: &[&str] = &[
+    "nix-daemon.socket",
+    "nix-daemon.service",
+    "determinate-nixd.socket",
+];
+
+/// Refuse to install alongside an already running `nix-daemon`, as two daemons would race on the
+/// socket
+///
+/// A daemon run by one of the well-known systemd units is stopped by
+/// [`ConfigureInitService`](crate::action::common::ConfigureInitService) if `replace_running_daemon`
+/// is set. A daemon answering on the socket without any such unit can't be safely stopped by us.
+///
+/// When reconciling an existing install (`--force-reinstall`), the running daemon is the one
+/// being reinstalled, so it is not a conflict.
+pub(crate) async fn check_no_running_nix_daemon(
+    settings: &CommonSettings,
+    replace_running_daemon: bool,
+    daemon_unit_name: &str,
+) -> Result<(), PlannerError> {
+    let mut active_units = vec![];
+    if which("systemctl").is_ok() {
+        for unit in nix_daemon_units(daemon_unit_name) {
+            let output = Command::new("systemctl")
+                .arg("is-active")
+                .arg(&unit)
+                .stdin(std::process::Stdio::null())
+                .output()
+                .await
+                .map_err(|e| PlannerError::Command(format!("systemctl is-active {unit}"), e))?;
+            if String::from_utf8(output.stdout)?.starts_with("active") {
+                active_units.push(unit);
+            }
+        }
+    }
+
+    let socket_live = std::os::unix::net::UnixStream::connect(NIX_DAEMON_SOCKET).is_ok();
+
+    running_nix_daemon_conflict(
+        active_units,
+        socket_live,
+        replace_running_daemon,
+        settings.reconcile_existing,
+    )?;
+    Ok(())
+}
+
+/// The well-known units, and those of a daemon renamed with `--daemon-unit-name`
+fn nix_daemon_units(daemon_unit_name: &str) -> Vec<String> {
+    let mut units = NIX_DAEMON_UNITS
+        .iter()
+        .map(|unit| unit.to_string())
+        .collect::<Vec<_>>();
+    if daemon_unit_name != DEFAULT_DAEMON_UNIT_NAME {
+        units.push(format!("{daemon_unit_name}.socket"));
+        units.push(format!("{daemon_unit_name}.service"));
+    }
+    units
+}
+
+fn running_nix_daemon_conflict(
+    active_units: Vec<String>,
+    socket_live: bool,
+    replace_running_daemon: bool,
+    reconcile_existing: bool,
+) -> Result<(), LinuxErrorKind> {
+    if reconcile_existing {
+        Ok(())
+    } else if !active_units.is_empty() {
+        if replace_running_daemon {
+            tracing::warn!(
+                units = active_units.join(", "),
+                "Replacing the running Nix daemon"
+            );
+            Ok(())
+        } else {
+            Err(LinuxErrorKind::NixDaemonRunning(active_units))
+        }
+    } else if socket_live {
+        Err(LinuxErrorKind::UnmanagedNixDaemonRunning)
+    } else {
+        Ok(())
+    }
+}
+
 #[non_exhaustive]
 #[derive(Debug, thiserror::Error)]
 pub enum LinuxErrorKind {
@@ -283,6 +602,55 @@ pub enum LinuxErrorKind {
         To use a `root`-only Nix install, consider passing `--init none`."
     )]
     Wsl2SystemdNotActive,
+    #[error(
+        "\
+        A Nix daemon is already running (via {}).\n\
+        \n\
+        Starting another would race with it on `{NIX_DAEMON_SOCKET}`. To stop it and continue, pass `--replace-running-daemon`.",
+        .0.iter().map(|v| format!("`{v}`")).collect::<Vec<_>>().join(", ")
+    )]
+    NixDaemonRunning(Vec<String>),
+    #[error(
+        "\
+        A Nix daemon is already answering on `{NIX_DAEMON_SOCKET}`, but not from a known systemd unit.\n\
+        \n\
+        Stop that daemon, then try again."
+    )]
+    UnmanagedNixDaemonRunning,
+    #[error(
+        "\
+        `{}` already has a Nix store.\n\
+        \n\
+        To install over it from the same settings, pass `--force-reinstall`.",
+        .0.display()
+    )]
+    RootHasNix(PathBuf),
+    #[error(
+        "\
+        Nix would be configured to run with {init}, but this machine is running {found}.\n\
+        \n\
+        To configure another init, pass `--init systemd` or `--init openrc`. If {init} will be started later, consider passing `--no-start-daemon`.\n\
+        \n\
+        To use a `root`-only Nix install, consider passing `--init none`."
+    )]
+    UnsupportedInit {
+        init: InitSystem,
+        found: DetectedInit,
+    },
+    #[error("Determinate Nixd can not yet be run with OpenRC, consider passing `--init systemd` or `--init none`")]
+    DeterminateNixdOpenRc,
+    #[error(
+        "\
+        `{nix_store_path}` can't be created, as `{mount_point}` is mounted read-only.\n\
+        \n\
+        On an immutable distribution, use a planner which mounts the store from writable storage, like `ostree` or `steam-deck`, or pass `--nix-store-path` with a writable location.",
+        nix_store_path = .nix_store_path.display(),
+        mount_point = .mount_point.display(),
+    )]
+    ReadOnlyNixStorePath {
+        nix_store_path: PathBuf,
+        mount_point: PathBuf,
+    },
 }
 
 impl HasExpectedErrors for LinuxErrorKind {
@@ -290,6 +658,12 @@ impl HasExpectedErrors for LinuxErrorKind {
         match self {
             LinuxErrorKind::SystemdNotActive => Some(Box::new(self)),
             LinuxErrorKind::Wsl2SystemdNotActive => Some(Box::new(self)),
+            LinuxErrorKind::NixDaemonRunning(_) => Some(Box::new(self)),
+            LinuxErrorKind::UnmanagedNixDaemonRunning => Some(Box::new(self)),
+            LinuxErrorKind::RootHasNix(_) => Some(Box::new(self)),
+            LinuxErrorKind::UnsupportedInit { .. } => Some(Box::new(self)),
+            LinuxErrorKind::DeterminateNixdOpenRc => Some(Box::new(self)),
+            LinuxErrorKind::ReadOnlyNixStorePath { .. } => Some(Box::new(self)),
         }
     }
 }
@@ -299,3 +673,124 @@ impl From<LinuxErrorKind> for PlannerError {
         PlannerError::Custom(Box::new(v))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        init_conflict, nix_daemon_units, running_nix_daemon_conflict, Linux, LinuxErrorKind,
+    };
+    use crate::{
+        os::linux::DetectedInit,
+        planner::Planner,
+        settings::{Component, InitSystem},
+    };
+
+    #[tokio::test]
+    async fn plans_renamed_daemon_units() -> eyre::Result<()> {
+        let mut planner = Linux::default().await?;
+        planner.init.init = InitSystem::Systemd;
+        planner.init.start_daemon = false;
+        planner.init.daemon_unit_name("nix-daemon-2");
+        planner.settings.daemon_restart_on_failure = true;
+        // Plan as if over an existing install, so a Nix on the host doesn't matter
+        planner.settings.reconcile_existing = true;
+
+        let plan = serde_json::to_string(&planner.plan().await?)?;
+        for path in [
+            "/etc/systemd/system/nix-daemon-2.service",
+            "/etc/systemd/system/nix-daemon-2.socket",
+            "/etc/systemd/system/nix-daemon-2.service.d/restart-on-failure.conf",
+        ] {
+            assert!(
+                plan.contains(&format!("\"{path}\"")),
+                "`{path}` is not planned"
+            );
+        }
+        assert!(!plan.contains("\"/etc/systemd/system/nix-daemon.service\""));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn excluded_components_are_not_planned() -> eyre::Result<()> {
+        let mut planner = Linux::default().await?;
+        planner.init.init = InitSystem::Systemd;
+        planner.init.start_daemon = false;
+        // Plan as if over an existing install, so a Nix on the host doesn't matter
+        planner.settings.reconcile_existing = true;
+        let excluded = [
+            "configure_shell_profile",
+            "place_nix_configuration",
+            "create_upstream_init_service",
+            "create_tmpfiles_rule",
+        ];
+
+        let plan = serde_json::to_string(&planner.clone().plan().await?)?;
+        for action_name in excluded {
+            assert!(
+                plan.contains(&format!("\"action_name\":\"{action_name}\"")),
+                "`{action_name}` is not planned with every component"
+            );
+        }
+
+        planner.settings.components = vec![Component::Store];
+        let plan = serde_json::to_string(&planner.plan().await?)?;
+        assert!(plan.contains("\"action_name\":\"provision_nix\""));
+        for action_name in excluded {
+            assert!(
+                !plan.contains(&format!("\"action_name\":\"{action_name}\"")),
+                "`{action_name}` is planned"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn renamed_daemon_units_are_probed() {
+        assert!(!nix_daemon_units("nix-daemon").contains(&"nix-daemon-2.socket".to_string()));
+        let units = nix_daemon_units("nix-daemon-2");
+        assert!(units.contains(&"nix-daemon.socket".to_string()));
+        assert!(units.contains(&"nix-daemon-2.socket".to_string()));
+        assert!(units.contains(&"nix-daemon-2.service".to_string()));
+    }
+
+    #[test]
+    fn running_daemon_requires_opt_in() {
+        let units = vec!["nix-daemon.socket".to_string()];
+        assert!(matches!(
+            running_nix_daemon_conflict(units.clone(), true, false, false),
+            Err(LinuxErrorKind::NixDaemonRunning(_))
+        ));
+        assert!(running_nix_daemon_conflict(units, true, true, false).is_ok());
+    }
+
+    #[test]
+    fn unmanaged_daemon_is_refused() {
+        assert!(matches!(
+            running_nix_daemon_conflict(vec![], true, true, false),
+            Err(LinuxErrorKind::UnmanagedNixDaemonRunning)
+        ));
+        assert!(running_nix_daemon_conflict(vec![], false, false, false).is_ok());
+    }
+
+    #[test]
+    fn reinstall_over_running_daemon_is_allowed() {
+        let units = vec!["nix-daemon.socket".to_string()];
+        assert!(running_nix_daemon_conflict(units, true, false, true).is_ok());
+        assert!(running_nix_daemon_conflict(vec![], true, false, true).is_ok());
+    }
+
+    #[test]
+    fn unsupported_init_is_refused() {
+        assert!(init_conflict(InitSystem::Systemd, true, DetectedInit::Systemd).is_ok());
+        assert!(init_conflict(InitSystem::OpenRc, true, DetectedInit::OpenRc).is_ok());
+        assert!(init_conflict(InitSystem::None, true, DetectedInit::SysVInit).is_ok());
+        assert!(init_conflict(InitSystem::OpenRc, false, DetectedInit::Systemd).is_ok());
+        assert!(matches!(
+            init_conflict(InitSystem::OpenRc, true, DetectedInit::SysVInit),
+            Err(LinuxErrorKind::UnsupportedInit {
+                init: InitSystem::OpenRc,
+                found: DetectedInit::SysVInit,
+            })
+        ));
+    }
+}
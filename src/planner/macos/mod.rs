@@ -12,14 +12,18 @@ use crate::planner::HasExpectedErrors;
 mod profile_queries;
 mod profiles;
 
-use crate::action::common::ConfigureDeterminateNixdInitService;
+use crate::action::common::{
+    configure_determinate_nixd_init_service, configure_upstream_init_service,
+    ConfigureDeterminateNixdInitService,
+};
 use crate::os::darwin::diskutil::DiskUtilList;
 use crate::{
     action::{
-        base::RemoveDirectory,
+        base::{CheckFreeSpace, RemoveDirectory},
         common::{
-            ConfigureNix, ConfigureUpstreamInitService, CreateUsersAndGroups,
-            ProvisionDeterminateNixd, ProvisionNix,
+            ConfigureDaemonRestart, ConfigureNix, ConfigureUpstreamInitService,
+            CreateUsersAndGroups, ProvisionDeterminateNixd, ProvisionNix, RunPostInstall,
+            NIX_DAEMON_PROFILE_SCRIPT,
         },
         macos::{
             ConfigureRemoteBuilding, CreateDeterminateNixVolume, CreateNixHookService,
@@ -31,7 +35,7 @@ use crate::{
     os::darwin::DiskUtilInfoOutput,
     planner::{Planner, PlannerError},
     settings::InstallSettingsError,
-    settings::{CommonSettings, InitSystem},
+    settings::{CommonSettings, Component, InitSystem},
     Action, BuiltinPlanner,
 };
 
@@ -48,16 +52,21 @@ pub struct Macos {
         clap(
             long,
             action(ArgAction::Set),
+            num_args = 0..=1,
             default_value = "false",
+            default_missing_value = "true",
             env = "NIX_INSTALLER_ENCRYPT"
         )
     )]
     pub encrypt: Option<bool>,
     /// Use a case sensitive volume
+    ///
+    /// Only the volume's filesystem changes, it is mounted at `/nix` like any other.
     #[cfg_attr(
         feature = "cli",
         clap(
             long,
+            alias = "volume-case-sensitive",
             action(ArgAction::SetTrue),
             default_value = "false",
             env = "NIX_INSTALLER_CASE_SENSITIVE"
@@ -70,9 +79,27 @@ pub struct Macos {
         clap(long, default_value = "Nix Store", env = "NIX_INSTALLER_VOLUME_LABEL")
     )]
     pub volume_label: String,
+    /// The most the APFS volume may grow to, like `50g`, so a large store can't fill the disk
+    #[cfg_attr(feature = "cli", clap(long, env = "NIX_INSTALLER_VOLUME_QUOTA"))]
+    #[serde(default)]
+    pub volume_quota: Option<String>,
     /// The root disk of the target
     #[cfg_attr(feature = "cli", clap(long, env = "NIX_INSTALLER_ROOT_DISK"))]
     pub root_disk: Option<String>,
+    /// The launchd label of the Nix daemon, whose plist is placed as `/Library/LaunchDaemons/{name}.plist`
+    ///
+    /// The daemon still listens on `/nix/var/nix/daemon-socket/socket`, so a renamed daemon can't
+    /// run alongside another Nix install.
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            default_value = configure_upstream_init_service::DARWIN_LAUNCHD_SERVICE_NAME,
+            env = "NIX_INSTALLER_DAEMON_UNIT_NAME",
+        )
+    )]
+    #[serde(default = "default_daemon_unit_name")]
+    pub daemon_unit_name: String,
 
     /// On AWS, put the Nix Store volume on the EC2 instances' instance store volume.
     ///
@@ -93,6 +120,10 @@ pub struct Macos {
     pub use_ec2_instance_store: bool,
 }
 
+fn default_daemon_unit_name() -> String {
+    configure_upstream_init_service::DARWIN_LAUNCHD_SERVICE_NAME.to_string()
+}
+
 async fn default_root_disk() -> Result<String, PlannerError> {
     let buf = execute_command(
         Command::new("/usr/sbin/diskutil")
@@ -140,10 +171,15 @@ impl Planner for Macos {
             case_sensitive: false,
             encrypt: None,
             volume_label: "Nix Store".into(),
+            volume_quota: None,
+            daemon_unit_name: default_daemon_unit_name(),
         })
     }
 
     async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        crate::planner::check_no_existing_nix(&self.settings).await?;
+        check_daemon_unit_name(&self.daemon_unit_name, &self.settings)?;
+
         if self.use_ec2_instance_store && !self.settings.determinate_nix {
             return Err(PlannerError::Ec2InstanceStoreRequiresDeterminateNix);
         }
@@ -159,61 +195,35 @@ impl Planner for Macos {
             },
         };
 
-        // The encrypt variable isn't used in Determinate Nix since we have our own plan step for it,
-        // however this match accounts for Determinate Nix so the receipt indicates encrypt: true.
-        // This is a goofy thing to do, but it is in an attempt to make a more globally coherent plan / receipt.
-        let encrypt = match (self.settings.determinate_nix, self.encrypt) {
-            (true, _) => true,
-            (false, Some(choice)) => {
-                if let Some(diskutil_info) =
-                    crate::action::macos::get_disk_info_for_label(&self.volume_label)
-                        .await
-                        .ok()
-                        .flatten()
-                {
-                    if diskutil_info.file_vault {
-                        tracing::warn!("Existing volume was encrypted with FileVault, forcing `encrypt` to true");
-                        true
-                    } else {
-                        choice
-                    }
-                } else {
-                    choice
-                }
-            },
-            (false, None) => {
-                let root_disk_is_encrypted = {
-                    let output = Command::new("/usr/bin/fdesetup")
-                        .arg("isactive")
-                        .stdout(std::process::Stdio::null())
-                        .stderr(std::process::Stdio::null())
-                        .process_group(0)
-                        .output()
-                        .await
-                        .map_err(|e| PlannerError::Custom(Box::new(e)))?;
-
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    let stdout_trimmed = stdout.trim();
+        let existing_store_volume_is_encrypted =
+            crate::action::macos::get_disk_info_for_label(&self.volume_label)
+                .await
+                .ok()
+                .flatten()
+                .is_some_and(|diskutil_info| diskutil_info.file_vault);
+        let root_disk_is_encrypted = if self.encrypt.is_none() && !self.settings.determinate_nix {
+            let output = Command::new("/usr/bin/fdesetup")
+                .arg("isactive")
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .process_group(0)
+                .output()
+                .await
+                .map_err(|e| PlannerError::Custom(Box::new(e)))?;
 
-                    stdout_trimmed == "true"
-                };
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let stdout_trimmed = stdout.trim();
 
-                let existing_store_volume_is_encrypted = {
-                    if let Some(diskutil_info) =
-                        crate::action::macos::get_disk_info_for_label(&self.volume_label)
-                            .await
-                            .ok()
-                            .flatten()
-                    {
-                        diskutil_info.file_vault
-                    } else {
-                        false
-                    }
-                };
-
-                root_disk_is_encrypted || existing_store_volume_is_encrypted
-            },
+            stdout_trimmed == "true"
+        } else {
+            false
         };
+        let encrypt = volume_encrypt(
+            self.settings.determinate_nix,
+            self.encrypt,
+            existing_store_volume_is_encrypted,
+            root_disk_is_encrypted,
+        );
 
         let mut plan = vec![];
 
@@ -234,6 +244,7 @@ impl Planner for Macos {
                     self.case_sensitive,
                     self.settings.force,
                     self.use_ec2_instance_store,
+                    self.volume_quota.clone(),
                 )
                 .await
                 .map_err(PlannerError::Action)?
@@ -246,6 +257,19 @@ impl Planner for Macos {
                     self.volume_label.clone(),
                     self.case_sensitive,
                     encrypt,
+                    self.volume_quota.clone(),
+                )
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+            );
+        }
+
+        if self.settings.required_free_space > 0 {
+            plan.push(
+                CheckFreeSpace::plan(
+                    &self.settings.nix_store_path,
+                    self.settings.required_free_space,
                 )
                 .await
                 .map_err(PlannerError::Action)?
@@ -289,7 +313,7 @@ impl Planner for Macos {
                 .boxed(),
         );
 
-        if self.settings.modify_profile {
+        if self.settings.modify_profile && self.settings.installs(Component::Profiles) {
             plan.push(
                 CreateNixHookService::plan()
                     .await
@@ -298,16 +322,56 @@ impl Planner for Macos {
             );
         }
 
-        if self.settings.determinate_nix {
-            plan.push(
-                ConfigureDeterminateNixdInitService::plan(InitSystem::Launchd, true)
+        if self.settings.installs(Component::Daemon) {
+            if self.settings.determinate_nix {
+                plan.push(
+                    ConfigureDeterminateNixdInitService::plan(InitSystem::Launchd, true)
+                        .await
+                        .map_err(PlannerError::Action)?
+                        .boxed(),
+                );
+            } else {
+                plan.push(
+                    ConfigureUpstreamInitService::plan(
+                        InitSystem::Launchd,
+                        true,
+                        &self.daemon_unit_name,
+                    )
                     .await
                     .map_err(PlannerError::Action)?
                     .boxed(),
-            );
-        } else {
+                );
+            }
+            if self.settings.daemon_restart_on_failure {
+                let (plist_path, service_name) = if self.settings.determinate_nix {
+                    (
+                        configure_determinate_nixd_init_service::DARWIN_NIXD_DAEMON_DEST.into(),
+                        configure_determinate_nixd_init_service::DARWIN_NIXD_SERVICE_NAME.into(),
+                    )
+                } else {
+                    (
+                        configure_upstream_init_service::darwin_nix_daemon_dest(
+                            &self.daemon_unit_name,
+                        ),
+                        self.daemon_unit_name.clone(),
+                    )
+                };
+                plan.push(
+                    ConfigureDaemonRestart::plan(
+                        InitSystem::Launchd,
+                        Some(plist_path),
+                        Some(service_name),
+                    )
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+                );
+            }
+        }
+
+        if !self.settings.post_install_commands.is_empty() {
             plan.push(
-                ConfigureUpstreamInitService::plan(InitSystem::Launchd, true)
+                RunPostInstall::plan(NIX_DAEMON_PROFILE_SCRIPT, &self.settings)
                     .await
                     .map_err(PlannerError::Action)?
                     .boxed(),
@@ -328,15 +392,18 @@ impl Planner for Macos {
             settings,
             encrypt,
             volume_label,
+            volume_quota,
             case_sensitive,
             root_disk,
             use_ec2_instance_store,
+            daemon_unit_name,
         } = self;
         let mut map = HashMap::default();
 
         map.extend(settings.settings()?);
         map.insert("volume_encrypt".into(), serde_json::to_value(encrypt)?);
         map.insert("volume_label".into(), serde_json::to_value(volume_label)?);
+        map.insert("volume_quota".into(), serde_json::to_value(volume_quota)?);
         map.insert("root_disk".into(), serde_json::to_value(root_disk)?);
         map.insert(
             "use_ec2_instance_store".into(),
@@ -346,6 +413,10 @@ impl Planner for Macos {
             "case_sensitive".into(),
             serde_json::to_value(case_sensitive)?,
         );
+        map.insert(
+            "daemon_unit_name".into(),
+            serde_json::to_value(daemon_unit_name)?,
+        );
 
         Ok(map)
     }
@@ -384,6 +455,12 @@ impl Planner for Macos {
     }
 
     async fn pre_install_check(&self) -> Result<(), PlannerError> {
+        self.settings.check_offline()?;
+        self.settings.check_components()?;
+        self.settings.check_nix_package_system()?;
+        self.settings.check_default_nix_store_path()?;
+        self.settings.check_no_root()?;
+        check_daemon_unit_name(&self.daemon_unit_name, &self.settings)?;
         check_suis().await?;
         check_not_running_in_rosetta()?;
 
@@ -397,6 +474,58 @@ impl From<Macos> for BuiltinPlanner {
     }
 }
 
+/// Refuse a `--daemon-unit-name` launchd would not accept as a label, or which would rename the
+/// Determinate daemon
+pub(crate) fn check_daemon_unit_name(
+    label: &str,
+    settings: &CommonSettings,
+) -> Result<(), InstallSettingsError> {
+    let valid = !label.is_empty()
+        && !label.starts_with('.')
+        && !label.ends_with(".plist")
+        && label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(InstallSettingsError::InvalidDaemonUnitName(
+            label.to_string(),
+        ));
+    }
+    if settings.determinate_nix
+        && label != configure_upstream_init_service::DARWIN_LAUNCHD_SERVICE_NAME
+    {
+        return Err(InstallSettingsError::DaemonUnitNameUnsupported);
+    }
+    Ok(())
+}
+
+/// Whether the Nix volume should be encrypted
+///
+/// The encrypt variable isn't used in Determinate Nix since we have our own plan step for it,
+/// however this accounts for Determinate Nix so the receipt indicates encrypt: true.
+/// This is a goofy thing to do, but it is in an attempt to make a more globally coherent plan / receipt.
+fn volume_encrypt(
+    determinate_nix: bool,
+    choice: Option<bool>,
+    existing_store_volume_is_encrypted: bool,
+    root_disk_is_encrypted: bool,
+) -> bool {
+    match (determinate_nix, choice) {
+        (true, _) => true,
+        (false, Some(choice)) => {
+            if existing_store_volume_is_encrypted && !choice {
+                tracing::warn!(
+                    "Existing volume was encrypted with FileVault, forcing `encrypt` to true"
+                );
+                true
+            } else {
+                choice
+            }
+        },
+        (false, None) => root_disk_is_encrypted || existing_store_volume_is_encrypted,
+    }
+}
+
 async fn check_nix_darwin_not_installed() -> Result<(), PlannerError> {
     let has_darwin_rebuild = which("darwin-rebuild").is_ok();
     let has_darwin_option = which("darwin-option").is_ok();
@@ -495,3 +624,46 @@ impl HasExpectedErrors for MacosError {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{check_daemon_unit_name, volume_encrypt};
+    use crate::settings::{CommonSettings, InstallSettingsError};
+
+    #[test]
+    fn encryption_follows_the_flag() {
+        assert!(volume_encrypt(false, Some(true), false, false));
+        assert!(!volume_encrypt(false, Some(false), false, true));
+        // An existing encrypted volume can't be used unencrypted
+        assert!(volume_encrypt(false, Some(false), true, false));
+        // Without a choice, follow FileVault on the root disk
+        assert!(volume_encrypt(false, None, false, true));
+        assert!(!volume_encrypt(false, None, false, false));
+        assert!(volume_encrypt(true, Some(false), false, false));
+    }
+
+    #[tokio::test]
+    async fn daemon_label_validated() -> eyre::Result<()> {
+        let mut settings = CommonSettings::default().await?;
+        check_daemon_unit_name("org.nixos.nix-daemon", &settings)?;
+        check_daemon_unit_name("org.example.nix-daemon", &settings)?;
+        for invalid in [
+            "",
+            "org.example.nix-daemon.plist",
+            "org/example",
+            "org example",
+        ] {
+            assert!(matches!(
+                check_daemon_unit_name(invalid, &settings),
+                Err(InstallSettingsError::InvalidDaemonUnitName(_))
+            ));
+        }
+
+        settings.determinate_nix = true;
+        assert!(matches!(
+            check_daemon_unit_name("org.example.nix-daemon", &settings),
+            Err(InstallSettingsError::DaemonUnitNameUnsupported)
+        ));
+        Ok(())
+    }
+}
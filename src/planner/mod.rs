@@ -101,19 +101,26 @@ match plan.install(feedback::devnull::DevNull{}, None).await {
 ```
 
 */
+pub mod container;
 pub mod linux;
 pub mod macos;
 pub mod ostree;
+pub mod single_user;
 pub mod steam_deck;
+pub mod wsl;
 
-use std::{collections::HashMap, path::PathBuf, string::FromUtf8Error};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    string::FromUtf8Error,
+};
 
 use serde::{Deserialize, Serialize};
 
 use crate::{
     action::{ActionError, StatefulAction},
     error::HasExpectedErrors,
-    settings::{CommonSettings, InstallSettingsError},
+    settings::{rooted, CommonSettings, Component, InstallSettingsError},
     Action, InstallPlan, NixInstallerError,
 };
 
@@ -167,6 +174,15 @@ pub enum BuiltinPlanner {
     #[cfg_attr(not(target_os = "linux"), clap(hide = true))]
     /// A planner suitable for immutable systems using ostree, such as Fedora Silverblue
     Ostree(ostree::Ostree),
+    #[cfg_attr(not(target_os = "linux"), clap(hide = true))]
+    /// A planner for Windows Subsystem for Linux (WSL2), with or without systemd
+    Wsl(wsl::Wsl),
+    #[cfg_attr(not(target_os = "linux"), clap(hide = true))]
+    /// A planner for single-user installs without build users or a daemon, such as in containers or CI
+    SingleUser(single_user::SingleUser),
+    #[cfg_attr(not(target_os = "linux"), clap(hide = true))]
+    /// A planner for containers (like `systemd-nspawn`, Docker, or Podman), which only sets up the daemon if systemd manages the container
+    Container(container::Container),
     #[cfg_attr(not(target_os = "macos"), clap(hide = true))]
     /// A planner for MacOS (Darwin) systems
     Macos(macos::Macos),
@@ -182,6 +198,9 @@ impl BuiltinPlanner {
                 Ok(Self::Linux(linux::Linux::default().await?))
             },
             (Architecture::Aarch64(_), OperatingSystem::Linux) => {
+                if crate::os::linux::is_wsl() {
+                    return Ok(Self::Wsl(wsl::Wsl::default().await?));
+                }
                 Ok(Self::Linux(linux::Linux::default().await?))
             },
             (Architecture::X86_64, OperatingSystem::MacOSX { .. })
@@ -212,6 +231,10 @@ impl BuiltinPlanner {
             return Ok(Self::Ostree(ostree::Ostree::default().await?));
         }
 
+        if crate::os::linux::is_wsl() {
+            return Ok(Self::Wsl(wsl::Wsl::default().await?));
+        }
+
         Ok(Self::Linux(linux::Linux::default().await?))
     }
 
@@ -221,16 +244,86 @@ impl BuiltinPlanner {
             BuiltinPlanner::Linux(inner) => inner.settings = settings,
             BuiltinPlanner::SteamDeck(inner) => inner.settings = settings,
             BuiltinPlanner::Ostree(inner) => inner.settings = settings,
+            BuiltinPlanner::Wsl(inner) => inner.settings = settings,
+            BuiltinPlanner::SingleUser(inner) => inner.settings = settings,
+            BuiltinPlanner::Container(inner) => inner.settings = settings,
             BuiltinPlanner::Macos(inner) => inner.settings = settings,
         }
         Ok(built)
     }
 
+    /// The names of the built-in planners, as on the command line and in receipts
+    pub const NAMES: &'static [&'static str] = &[
+        "linux",
+        "steam-deck",
+        "ostree",
+        "wsl",
+        "single-user",
+        "container",
+        "macos",
+    ];
+
+    /// The built-in planner called `name` (one of [`BuiltinPlanner::NAMES`]) with its default
+    /// settings, or `None` for an unknown name
+    ///
+    /// Unlike [`BuiltinPlanner::default`], this does not check the planner suits this host, but
+    /// some planners cannot find their defaults on another one.
+    pub async fn default_named(name: &str) -> Result<Option<Self>, PlannerError> {
+        let built = match name {
+            "linux" => Self::Linux(linux::Linux::default().await?),
+            "steam-deck" => Self::SteamDeck(steam_deck::SteamDeck::default().await?),
+            "ostree" => Self::Ostree(ostree::Ostree::default().await?),
+            "wsl" => Self::Wsl(wsl::Wsl::default().await?),
+            "single-user" => Self::SingleUser(single_user::SingleUser::default().await?),
+            "container" => Self::Container(container::Container::default().await?),
+            "macos" => Self::Macos(macos::Macos::default().await?),
+            _ => return Ok(None),
+        };
+        Ok(Some(built))
+    }
+
+    /// The built-in planner `planner` is, like the planner of a receipt, or `None` for a custom planner
+    pub fn from_planner(planner: &dyn Planner) -> Result<Option<Self>, serde_json::Error> {
+        let mut value = serde_json::to_value(planner)?;
+        let Some(name) = value
+            .as_object_mut()
+            .and_then(|object| object.remove("planner"))
+        else {
+            return Ok(None);
+        };
+        let built = match name.as_str() {
+            Some("linux") => Self::Linux(serde_json::from_value(value)?),
+            Some("steam-deck") => Self::SteamDeck(serde_json::from_value(value)?),
+            Some("ostree") => Self::Ostree(serde_json::from_value(value)?),
+            Some("wsl") => Self::Wsl(serde_json::from_value(value)?),
+            Some("single-user") => Self::SingleUser(serde_json::from_value(value)?),
+            Some("container") => Self::Container(serde_json::from_value(value)?),
+            Some("macos") => Self::Macos(serde_json::from_value(value)?),
+            _ => return Ok(None),
+        };
+        Ok(Some(built))
+    }
+
+    pub fn common_settings(&self) -> &CommonSettings {
+        match self {
+            BuiltinPlanner::Linux(inner) => &inner.settings,
+            BuiltinPlanner::SteamDeck(inner) => &inner.settings,
+            BuiltinPlanner::Ostree(inner) => &inner.settings,
+            BuiltinPlanner::Wsl(inner) => &inner.settings,
+            BuiltinPlanner::SingleUser(inner) => &inner.settings,
+            BuiltinPlanner::Container(inner) => &inner.settings,
+            BuiltinPlanner::Macos(inner) => &inner.settings,
+        }
+    }
+
     pub fn common_settings_mut(&mut self) -> &mut CommonSettings {
         match self {
             BuiltinPlanner::Linux(inner) => &mut inner.settings,
             BuiltinPlanner::SteamDeck(inner) => &mut inner.settings,
             BuiltinPlanner::Ostree(inner) => &mut inner.settings,
+            BuiltinPlanner::Wsl(inner) => &mut inner.settings,
+            BuiltinPlanner::SingleUser(inner) => &mut inner.settings,
+            BuiltinPlanner::Container(inner) => &mut inner.settings,
             BuiltinPlanner::Macos(inner) => &mut inner.settings,
         }
     }
@@ -242,6 +335,9 @@ impl BuiltinPlanner {
             BuiltinPlanner::Linux(inner) => inner.configured_settings().await,
             BuiltinPlanner::SteamDeck(inner) => inner.configured_settings().await,
             BuiltinPlanner::Ostree(inner) => inner.configured_settings().await,
+            BuiltinPlanner::Wsl(inner) => inner.configured_settings().await,
+            BuiltinPlanner::SingleUser(inner) => inner.configured_settings().await,
+            BuiltinPlanner::Container(inner) => inner.configured_settings().await,
             BuiltinPlanner::Macos(inner) => inner.configured_settings().await,
         }
     }
@@ -251,6 +347,9 @@ impl BuiltinPlanner {
             BuiltinPlanner::Linux(planner) => InstallPlan::plan(planner).await,
             BuiltinPlanner::SteamDeck(planner) => InstallPlan::plan(planner).await,
             BuiltinPlanner::Ostree(planner) => InstallPlan::plan(planner).await,
+            BuiltinPlanner::Wsl(planner) => InstallPlan::plan(planner).await,
+            BuiltinPlanner::SingleUser(planner) => InstallPlan::plan(planner).await,
+            BuiltinPlanner::Container(planner) => InstallPlan::plan(planner).await,
             BuiltinPlanner::Macos(planner) => InstallPlan::plan(planner).await,
         }
     }
@@ -259,6 +358,9 @@ impl BuiltinPlanner {
             BuiltinPlanner::Linux(i) => i.boxed(),
             BuiltinPlanner::SteamDeck(i) => i.boxed(),
             BuiltinPlanner::Ostree(i) => i.boxed(),
+            BuiltinPlanner::Wsl(i) => i.boxed(),
+            BuiltinPlanner::SingleUser(i) => i.boxed(),
+            BuiltinPlanner::Container(i) => i.boxed(),
             BuiltinPlanner::Macos(i) => i.boxed(),
         }
     }
@@ -268,15 +370,73 @@ impl BuiltinPlanner {
             BuiltinPlanner::Linux(i) => i.typetag_name(),
             BuiltinPlanner::SteamDeck(i) => i.typetag_name(),
             BuiltinPlanner::Ostree(i) => i.typetag_name(),
+            BuiltinPlanner::Wsl(i) => i.typetag_name(),
+            BuiltinPlanner::SingleUser(i) => i.typetag_name(),
+            BuiltinPlanner::Container(i) => i.typetag_name(),
             BuiltinPlanner::Macos(i) => i.typetag_name(),
         }
     }
 
+    pub async fn platform_check(&self) -> Result<(), PlannerError> {
+        match self {
+            BuiltinPlanner::Linux(i) => i.platform_check().await,
+            BuiltinPlanner::SteamDeck(i) => i.platform_check().await,
+            BuiltinPlanner::Ostree(i) => i.platform_check().await,
+            BuiltinPlanner::Wsl(i) => i.platform_check().await,
+            BuiltinPlanner::SingleUser(i) => i.platform_check().await,
+            BuiltinPlanner::Container(i) => i.platform_check().await,
+            BuiltinPlanner::Macos(i) => i.platform_check().await,
+        }
+    }
+
+    /// Check the settings alone, without looking at the host
+    ///
+    /// Unlike the planner's pre-install check, this holds while Nix is still installed, so a
+    /// reinstall can refuse settings the new install would reject before it uninstalls anything.
+    pub fn check_settings(&self) -> Result<(), PlannerError> {
+        let settings = self.common_settings();
+        settings.check_offline()?;
+        settings.check_components()?;
+        settings.check_build_limits()?;
+        settings.check_substituters()?;
+        settings.check_nix_package_system()?;
+        match self {
+            BuiltinPlanner::Linux(i) => {
+                settings.check_root()?;
+                linux::check_daemon_unit_name(&i.init, settings)?;
+            },
+            BuiltinPlanner::SteamDeck(_) => {
+                settings.check_default_nix_store_path()?;
+                settings.check_no_root()?;
+                settings.check_component_required(Component::Daemon, "steam-deck")?;
+            },
+            BuiltinPlanner::Ostree(_) => {
+                settings.check_default_nix_store_path()?;
+                settings.check_no_root()?;
+                settings.check_component_required(Component::Daemon, "ostree")?;
+            },
+            BuiltinPlanner::Wsl(_) | BuiltinPlanner::SingleUser(_) => settings.check_no_root()?,
+            BuiltinPlanner::Container(_) => {
+                settings.check_default_nix_store_path()?;
+                settings.check_no_root()?;
+            },
+            BuiltinPlanner::Macos(i) => {
+                settings.check_default_nix_store_path()?;
+                settings.check_no_root()?;
+                macos::check_daemon_unit_name(&i.daemon_unit_name, settings)?;
+            },
+        }
+        Ok(())
+    }
+
     pub fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
         match self {
             BuiltinPlanner::Linux(i) => i.settings(),
             BuiltinPlanner::SteamDeck(i) => i.settings(),
             BuiltinPlanner::Ostree(i) => i.settings(),
+            BuiltinPlanner::Wsl(i) => i.settings(),
+            BuiltinPlanner::SingleUser(i) => i.settings(),
+            BuiltinPlanner::Container(i) => i.settings(),
             BuiltinPlanner::Macos(i) => i.settings(),
         }
     }
@@ -307,6 +467,31 @@ impl Default for ShellProfileLocations {
     }
 }
 
+impl ShellProfileLocations {
+    /// The same locations, under `root` instead of `/`
+    pub fn rooted(self, root: &Path) -> Self {
+        let root = Some(root);
+        let Self { fish, bash, zsh } = self;
+        Self {
+            fish: FishShellProfileLocations {
+                confd_prefixes: fish
+                    .confd_prefixes
+                    .iter()
+                    .map(|path| rooted(root, path))
+                    .collect(),
+                vendor_confd_prefixes: fish
+                    .vendor_confd_prefixes
+                    .iter()
+                    .map(|path| rooted(root, path))
+                    .collect(),
+                ..fish
+            },
+            bash: bash.iter().map(|path| rooted(root, path)).collect(),
+            zsh: zsh.iter().map(|path| rooted(root, path)).collect(),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone)]
 pub struct FishShellProfileLocations {
     pub confd_suffix: PathBuf,
@@ -391,8 +576,8 @@ pub enum PlannerError {
     Custom(#[source] Box<dyn std::error::Error + Send + Sync>),
     #[error("NixOS already has Nix installed")]
     NixOs,
-    #[error("`nix` is already a valid command, so it is installed")]
-    NixExists,
+    #[error("Nix is already installed: {0}. Uninstall it first, with `/nix/nix-installer uninstall` if `nix-installer` installed it")]
+    NixExists(crate::os::ExistingNix),
     #[error("WSL1 is not supported, please upgrade to WSL2: https://learn.microsoft.com/en-us/windows/wsl/install#upgrade-version-from-wsl-1-to-wsl-2")]
     Wsl1,
     /// Failed to execute command
@@ -403,6 +588,21 @@ pub enum PlannerError {
     Diagnostic(#[from] crate::diagnostics::DiagnosticError),
 }
 
+/// Refuse to plan over any sign of an existing Nix, before anything is touched
+///
+/// A `--force-reinstall` plans over the install it is reconciling on purpose.
+pub(crate) async fn check_no_existing_nix(settings: &CommonSettings) -> Result<(), PlannerError> {
+    if settings.reconcile_existing {
+        return Ok(());
+    }
+    let existing = crate::os::detect_existing_nix(&settings.nix_store_path).await;
+    if existing.found() {
+        return Err(PlannerError::NixExists(existing));
+    }
+
+    Ok(())
+}
+
 impl HasExpectedErrors for PlannerError {
     fn expected<'a>(&'a self) -> Option<Box<dyn std::error::Error + 'a>> {
         match self {
@@ -423,6 +623,18 @@ impl HasExpectedErrors for PlannerError {
                 if let Some(err) = _e.downcast_ref::<linux::LinuxErrorKind>() {
                     return err.expected();
                 }
+                #[cfg(target_os = "linux")]
+                if let Some(err) = _e.downcast_ref::<wsl::WslErrorKind>() {
+                    return err.expected();
+                }
+                #[cfg(target_os = "linux")]
+                if let Some(err) = _e.downcast_ref::<single_user::SingleUserErrorKind>() {
+                    return err.expected();
+                }
+                #[cfg(target_os = "linux")]
+                if let Some(err) = _e.downcast_ref::<container::ContainerErrorKind>() {
+                    return err.expected();
+                }
                 #[cfg(target_os = "macos")]
                 if let Some(err) = _e.downcast_ref::<macos::MacosError>() {
                     return err.expected();
@@ -430,7 +642,7 @@ impl HasExpectedErrors for PlannerError {
                 None
             },
             this @ PlannerError::NixOs => Some(Box::new(this)),
-            this @ PlannerError::NixExists => Some(Box::new(this)),
+            this @ PlannerError::NixExists(_) => Some(Box::new(this)),
             this @ PlannerError::Wsl1 => Some(Box::new(this)),
             PlannerError::Command(_, _) => None,
             #[cfg(feature = "diagnostics")]
@@ -446,3 +658,49 @@ impl crate::diagnostics::ErrorDiagnostic for PlannerError {
         static_str.to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{BuiltinPlanner, PlannerError};
+    use crate::{settings::InstallSettingsError, InstallPlan};
+
+    #[test]
+    fn from_planner_recovers_receipt_planner() -> eyre::Result<()> {
+        let plan = InstallPlan::from_json(include_str!("../../tests/fixtures/linux/linux.json"))?;
+        let planner =
+            BuiltinPlanner::from_planner(&*plan.planner)?.expect("the linux planner is built in");
+        assert!(matches!(planner, BuiltinPlanner::Linux(_)));
+        assert_eq!(planner.settings()?, plan.planner.settings()?);
+        Ok(())
+    }
+
+    #[test]
+    fn check_settings_refuses_what_the_install_would() -> eyre::Result<()> {
+        let plan = InstallPlan::from_json(include_str!("../../tests/fixtures/linux/linux.json"))?;
+        let mut planner =
+            BuiltinPlanner::from_planner(&*plan.planner)?.expect("the linux planner is built in");
+        planner.check_settings()?;
+
+        planner.common_settings_mut().max_jobs = Some("lots".into());
+        assert!(matches!(
+            planner.check_settings(),
+            Err(PlannerError::InstallSettings(
+                InstallSettingsError::InvalidMaxJobs(_)
+            ))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn default_named_matches_typetag_names() -> eyre::Result<()> {
+        for name in BuiltinPlanner::NAMES {
+            // Some planners can only find their defaults on their own platform
+            if let Ok(planner) = BuiltinPlanner::default_named(name).await {
+                let planner = planner.expect("every name is a built-in planner");
+                assert_eq!(planner.typetag_name(), *name);
+            }
+        }
+        assert!(BuiltinPlanner::default_named("nope").await?.is_none());
+        Ok(())
+    }
+}
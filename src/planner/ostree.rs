@@ -1,9 +1,10 @@
 use crate::{
     action::{
-        base::{CreateDirectory, CreateFile, RemoveDirectory},
+        base::{CheckFreeSpace, CreateDirectory, CreateFile, RemoveDirectory},
         common::{
-            ConfigureNix, ConfigureUpstreamInitService, CreateUsersAndGroups,
-            ProvisionDeterminateNixd, ProvisionNix,
+            ConfigureDaemonRestart, ConfigureNix, ConfigureUpstreamInitService,
+            CreateUsersAndGroups, ProvisionDeterminateNixd, ProvisionNix, RunPostInstall,
+            NIX_DAEMON_PROFILE_SCRIPT,
         },
         linux::{
             provision_selinux::{DETERMINATE_SELINUX_POLICY_PP_CONTENT, SELINUX_POLICY_PP_CONTENT},
@@ -13,10 +14,15 @@ use crate::{
     },
     error::HasExpectedErrors,
     planner::{Planner, PlannerError},
-    settings::{CommonSettings, InitSystem, InstallSettingsError},
+    settings::{
+        CommonSettings, Component, InitSystem, InstallSettingsError, DEFAULT_DAEMON_UNIT_NAME,
+    },
     Action, BuiltinPlanner,
 };
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use super::{
     linux::{
@@ -48,6 +54,8 @@ impl Planner for Ostree {
     }
 
     async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        super::check_no_existing_nix(&self.settings).await?;
+
         let has_selinux = detect_selinux().await?;
         let mut plan = vec![
             // Primarily for uninstall
@@ -161,7 +169,7 @@ impl Planner for Ostree {
             .fish
             .vendor_confd_prefixes
             .iter()
-            .position(|v| *v == PathBuf::from("/usr/share/fish/"))
+            .position(|v| v == Path::new("/usr/share/fish/"))
         {
             shell_profile_locations
                 .fish
@@ -185,6 +193,18 @@ impl Planner for Ostree {
             );
         }
 
+        if self.settings.required_free_space > 0 {
+            plan.push(
+                CheckFreeSpace::plan(
+                    &self.settings.nix_store_path,
+                    self.settings.required_free_space,
+                )
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+            );
+        }
+
         plan.push(
             ProvisionNix::plan(&self.settings.clone())
                 .await
@@ -227,18 +247,35 @@ impl Planner for Ostree {
                 .boxed(),
         );
 
+        // The mount units above are bound to `nix-daemon.service`, so the units keep their names
         plan.push(
-            ConfigureUpstreamInitService::plan(InitSystem::Systemd, true)
+            ConfigureUpstreamInitService::plan(InitSystem::Systemd, true, DEFAULT_DAEMON_UNIT_NAME)
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
         );
+        if self.settings.daemon_restart_on_failure {
+            plan.push(
+                ConfigureDaemonRestart::plan(InitSystem::Systemd, None, None)
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
         plan.push(
             StartSystemdUnit::plan("ensure-symlinked-units-resolve.service".to_string(), true)
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
         );
+        if !self.settings.post_install_commands.is_empty() {
+            plan.push(
+                RunPostInstall::plan(NIX_DAEMON_PROFILE_SCRIPT, &self.settings)
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
         plan.push(
             RemoveDirectory::plan(crate::settings::SCRATCH_DIR)
                 .await
@@ -307,9 +344,17 @@ impl Planner for Ostree {
     }
 
     async fn pre_install_check(&self) -> Result<(), PlannerError> {
+        self.settings.check_offline()?;
+        self.settings.check_components()?;
+        self.settings.check_nix_package_system()?;
+        self.settings.check_default_nix_store_path()?;
+        self.settings.check_no_root()?;
+        // The units mounting `/nix` at boot go on to restart the daemon
+        self.settings
+            .check_component_required(Component::Daemon, "ostree")?;
         check_not_nixos()?;
 
-        check_nix_not_already_installed().await?;
+        check_nix_not_already_installed(&self.settings).await?;
 
         check_not_wsl1()?;
 
@@ -0,0 +1,314 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use nix::unistd::{Uid, User};
+
+use super::linux::{check_nix_not_already_installed, check_not_nixos};
+use crate::{
+    action::{
+        base::{
+            create_or_insert_into_file::Position,
+            create_or_merge_nix_config::EXTRA_EXPERIMENTAL_FEATURES_CONF_NAME, CheckFreeSpace,
+            CreateDirectory, CreateOrInsertIntoFile, CreateOrMergeNixConfig, RemoveDirectory,
+            SetupDefaultProfile,
+        },
+        common::{
+            place_nix_configuration::{CUSTOM_NIX_CONFIG_HEADER, NIX_CONF, NIX_CONF_FOLDER},
+            PlaceNixConfiguration, ProvisionNix, RunPostInstall,
+        },
+        StatefulAction,
+    },
+    error::HasExpectedErrors,
+    planner::{Planner, PlannerError},
+    settings::{CommonSettings, Component, InstallSettingsError, SCRATCH_DIR},
+    Action, BuiltinPlanner,
+};
+
+const PROFILE_NIX_FILE: &str = ".nix-profile/etc/profile.d/nix.sh";
+const PROFILE_NIX_FILE_SHELL: &str = "$HOME/.nix-profile/etc/profile.d/nix.sh";
+
+/**
+A planner for single-user installs, where the installing user owns `/nix` and there are no build users or daemon
+
+Suitable for containers and CI. When not run as `root`, `/nix` must already exist and be owned by the user, and
+`nix.conf` is written to the user's configuration directory instead of `/etc/nix`.
+*/
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct SingleUser {
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub settings: CommonSettings,
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "single-user")]
+impl Planner for SingleUser {
+    async fn default() -> Result<Self, PlannerError> {
+        Ok(Self {
+            settings: CommonSettings::default().await?,
+        })
+    }
+
+    async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        super::check_no_existing_nix(&self.settings).await?;
+
+        if self.settings.determinate_nix {
+            return Err(SingleUserErrorKind::DeterminateNix.into());
+        }
+        if self.settings.relocated_nix_store() {
+            return Err(SingleUserErrorKind::RelocatedNixStore.into());
+        }
+
+        let is_root = Uid::effective().is_root();
+        let user = User::from_uid(Uid::effective())
+            .map_err(SingleUserErrorKind::GettingUser)?
+            .ok_or(SingleUserErrorKind::NoUser)?;
+
+        let nix_store_path = &self.settings.nix_store_path;
+        if !is_root && !nix_store_path.exists() {
+            return Err(SingleUserErrorKind::NixDirectoryMissing {
+                path: nix_store_path.clone(),
+                user: user.name,
+            }
+            .into());
+        }
+
+        let mut plan = vec![];
+
+        plan.push(
+            CreateDirectory::plan(nix_store_path, user.name.clone(), None, 0o0755, true)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        if self.settings.required_free_space > 0 {
+            plan.push(
+                CheckFreeSpace::plan(nix_store_path, self.settings.required_free_space)
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
+
+        // The store belongs to the user, rather than a build users group
+        let mut provision_settings = self.settings.clone();
+        provision_settings.nix_build_group_id = user.gid.as_raw();
+        plan.push(
+            ProvisionNix::plan(&provision_settings)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            SetupDefaultProfile::plan(
+                PathBuf::from(SCRATCH_DIR),
+                self.settings.require_cacert,
+                None,
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
+        );
+
+        if !self.settings.skip_nix_conf && self.settings.installs(Component::Config) {
+            let (nix_conf_folder, nix_conf) = if is_root {
+                (PathBuf::from(NIX_CONF_FOLDER), PathBuf::from(NIX_CONF))
+            } else {
+                let nix_conf_folder = dirs::config_dir()
+                    .ok_or(SingleUserErrorKind::NoConfigDir)?
+                    .join("nix");
+                let nix_conf = nix_conf_folder.join("nix.conf");
+                (nix_conf_folder, nix_conf)
+            };
+            plan.push(
+                CreateDirectory::plan(nix_conf_folder, None, None, 0o0755, false)
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+            plan.push(
+                CreateOrMergeNixConfig::plan(
+                    nix_conf,
+                    PlaceNixConfiguration::single_user_config(&self.settings)
+                        .await
+                        .map_err(PlannerError::Action)?,
+                    CUSTOM_NIX_CONFIG_HEADER.to_string(),
+                    None,
+                    vec![EXTRA_EXPERIMENTAL_FEATURES_CONF_NAME.to_string()],
+                    self.settings.backup_nix_conf,
+                )
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+            );
+        }
+
+        if self.settings.modify_profile && self.settings.installs(Component::Profiles) {
+            plan.push(
+                CreateOrInsertIntoFile::plan(
+                    shell_profile(&user.dir),
+                    None,
+                    None,
+                    0o644,
+                    format!(
+                        "\n\
+                        # Nix\n\
+                        if [ -e \"{PROFILE_NIX_FILE_SHELL}\" ]; then\n\
+                        \x20   . \"{PROFILE_NIX_FILE_SHELL}\"\n\
+                        fi\n\
+                        # End Nix\n"
+                    ),
+                    Position::End,
+                    self.settings.backup_shell_profiles,
+                )
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+            );
+        }
+
+        if !self.settings.post_install_commands.is_empty() {
+            plan.push(
+                RunPostInstall::plan(user.dir.join(PROFILE_NIX_FILE), &self.settings)
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
+
+        plan.push(
+            RemoveDirectory::plan(SCRATCH_DIR)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        Ok(plan)
+    }
+
+    fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
+        let Self { settings } = self;
+        let mut map = HashMap::default();
+
+        map.extend(settings.settings()?);
+
+        Ok(map)
+    }
+
+    async fn configured_settings(
+        &self,
+    ) -> Result<HashMap<String, serde_json::Value>, PlannerError> {
+        let default = Self::default().await?.settings()?;
+        let configured = self.settings()?;
+
+        let mut settings: HashMap<String, serde_json::Value> = HashMap::new();
+        for (key, value) in configured.iter() {
+            if default.get(key) != Some(value) {
+                settings.insert(key.clone(), value.clone());
+            }
+        }
+
+        Ok(settings)
+    }
+
+    async fn platform_check(&self) -> Result<(), PlannerError> {
+        use target_lexicon::OperatingSystem;
+        match target_lexicon::OperatingSystem::host() {
+            OperatingSystem::Linux => Ok(()),
+            host_os => Err(PlannerError::IncompatibleOperatingSystem {
+                planner: self.typetag_name(),
+                host_os,
+            }),
+        }
+    }
+
+    async fn pre_install_check(&self) -> Result<(), PlannerError> {
+        self.settings.check_offline()?;
+        self.settings.check_components()?;
+        self.settings.check_nix_package_system()?;
+        self.settings.check_no_root()?;
+        check_not_nixos()?;
+
+        check_nix_not_already_installed(&self.settings).await?;
+
+        Ok(())
+    }
+}
+
+impl From<SingleUser> for BuiltinPlanner {
+    fn from(val: SingleUser) -> Self {
+        BuiltinPlanner::SingleUser(val)
+    }
+}
+
+/// The login profile a POSIX shell reads first, like upstream's single-user install picks
+fn shell_profile(home: &std::path::Path) -> PathBuf {
+    [".bash_profile", ".bash_login", ".profile"]
+        .iter()
+        .map(|name| home.join(name))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| home.join(".profile"))
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum SingleUserErrorKind {
+    #[error(
+        "Determinate Nix needs a daemon, so it can't be installed by the `single-user` planner"
+    )]
+    DeterminateNix,
+    #[error("The `single-user` planner does not support `--nix-store-path`")]
+    RelocatedNixStore,
+    #[error("Getting the current user")]
+    GettingUser(#[source] nix::errno::Errno),
+    #[error("The current user has no entry in the user database")]
+    NoUser,
+    #[error("Could not find a configuration directory for the current user, consider setting `XDG_CONFIG_HOME`")]
+    NoConfigDir,
+    #[error(
+        "`{path}` does not exist, and creating it needs `root`. Create it for `{user}` first, with `sudo mkdir -m 0755 {path} && sudo chown {user} {path}`",
+        path = .path.display(),
+    )]
+    NixDirectoryMissing { path: PathBuf, user: String },
+}
+
+impl HasExpectedErrors for SingleUserErrorKind {
+    fn expected<'a>(&'a self) -> Option<Box<dyn std::error::Error + 'a>> {
+        match self {
+            SingleUserErrorKind::DeterminateNix => Some(Box::new(self)),
+            SingleUserErrorKind::RelocatedNixStore => Some(Box::new(self)),
+            SingleUserErrorKind::GettingUser(_) => None,
+            SingleUserErrorKind::NoUser => None,
+            SingleUserErrorKind::NoConfigDir => Some(Box::new(self)),
+            SingleUserErrorKind::NixDirectoryMissing { .. } => Some(Box::new(self)),
+        }
+    }
+}
+
+impl From<SingleUserErrorKind> for PlannerError {
+    fn from(v: SingleUserErrorKind) -> PlannerError {
+        PlannerError::Custom(Box::new(v))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::shell_profile;
+
+    #[test]
+    fn picks_first_existing_shell_profile() -> eyre::Result<()> {
+        let home = tempfile::tempdir()?;
+        assert_eq!(shell_profile(home.path()), home.path().join(".profile"));
+
+        std::fs::write(home.path().join(".bash_login"), "")?;
+        assert_eq!(shell_profile(home.path()), home.path().join(".bash_login"));
+
+        std::fs::write(home.path().join(".bash_profile"), "")?;
+        assert_eq!(
+            shell_profile(home.path()),
+            home.path().join(".bash_profile")
+        );
+
+        Ok(())
+    }
+}
@@ -96,16 +96,21 @@ To test on a specific build id of the Steam Deck:
 6. Safely turn off the VM!
 
 */
-use std::{collections::HashMap, path::PathBuf, process::Output};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Output,
+};
 
 use tokio::process::Command;
 
 use crate::{
     action::{
-        base::{CreateDirectory, CreateFile, RemoveDirectory},
+        base::{CheckFreeSpace, CreateDirectory, CreateFile, RemoveDirectory},
         common::{
-            ConfigureNix, ConfigureUpstreamInitService, CreateUsersAndGroups,
-            ProvisionDeterminateNixd, ProvisionNix,
+            ConfigureDaemonRestart, ConfigureNix, ConfigureUpstreamInitService,
+            CreateUsersAndGroups, ProvisionDeterminateNixd, ProvisionNix, RunPostInstall,
+            NIX_DAEMON_PROFILE_SCRIPT,
         },
         linux::{
             EnsureSteamosNixDirectory, RevertCleanSteamosNixOffload, StartSystemdUnit,
@@ -114,7 +119,9 @@ use crate::{
         Action, StatefulAction,
     },
     planner::{Planner, PlannerError},
-    settings::{CommonSettings, InitSystem, InstallSettingsError},
+    settings::{
+        CommonSettings, Component, InitSystem, InstallSettingsError, DEFAULT_DAEMON_UNIT_NAME,
+    },
     BuiltinPlanner,
 };
 
@@ -149,6 +156,8 @@ impl Planner for SteamDeck {
     }
 
     async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        super::check_no_existing_nix(&self.settings).await?;
+
         // Starting in roughly build ID `20230522.1000`, the Steam Deck has a `/home/.steamos/offload/nix` directory and `nix.mount` unit we can use instead of creating a mountpoint.
         let requires_nix_bind_mount = detect_requires_bind_mount().await?;
 
@@ -326,7 +335,7 @@ impl Planner for SteamDeck {
             .fish
             .vendor_confd_prefixes
             .iter()
-            .position(|v| *v == PathBuf::from("/usr/share/fish/"))
+            .position(|v| v == Path::new("/usr/share/fish/"))
         {
             shell_profile_locations
                 .fish
@@ -352,6 +361,15 @@ impl Planner for SteamDeck {
             );
         }
 
+        if self.settings.required_free_space > 0 {
+            actions.push(
+                CheckFreeSpace::plan(&self.persistence, self.settings.required_free_space)
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
+
         actions.append(&mut vec![
             ProvisionNix::plan(&self.settings.clone())
                 .await
@@ -365,8 +383,9 @@ impl Planner for SteamDeck {
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
-            // Init is required for the steam-deck archetype to make the `/nix` mount
-            ConfigureUpstreamInitService::plan(InitSystem::Systemd, true)
+            // Init is required for the steam-deck archetype to make the `/nix` mount, and the mount
+            // units above are bound to `nix-daemon.service`, so the units keep their names
+            ConfigureUpstreamInitService::plan(InitSystem::Systemd, true, DEFAULT_DAEMON_UNIT_NAME)
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
@@ -383,6 +402,22 @@ impl Planner for SteamDeck {
                 .map_err(PlannerError::Action)?
                 .boxed(),
         ]);
+        if self.settings.daemon_restart_on_failure {
+            actions.push(
+                ConfigureDaemonRestart::plan(InitSystem::Systemd, None, None)
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
+        if !self.settings.post_install_commands.is_empty() {
+            actions.push(
+                RunPostInstall::plan(NIX_DAEMON_PROFILE_SCRIPT, &self.settings)
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
         Ok(actions)
     }
 
@@ -439,9 +474,17 @@ impl Planner for SteamDeck {
     }
 
     async fn pre_install_check(&self) -> Result<(), PlannerError> {
+        self.settings.check_offline()?;
+        self.settings.check_components()?;
+        self.settings.check_nix_package_system()?;
+        self.settings.check_default_nix_store_path()?;
+        self.settings.check_no_root()?;
+        // The units mounting `/nix` at boot go on to restart the daemon
+        self.settings
+            .check_component_required(Component::Daemon, "steam-deck")?;
         super::linux::check_not_nixos()?;
 
-        super::linux::check_nix_not_already_installed().await?;
+        super::linux::check_nix_not_already_installed(&self.settings).await?;
 
         super::linux::check_not_wsl1()?;
 
@@ -0,0 +1,366 @@
+use std::collections::HashMap;
+
+use super::{
+    linux::{
+        check_nix_not_already_installed, check_nix_store_path_writable,
+        check_no_running_nix_daemon, check_not_nixos, check_not_wsl1,
+    },
+    ShellProfileLocations,
+};
+use crate::{
+    action::{
+        base::{
+            create_or_insert_into_file::Position, CheckFreeSpace, CreateDirectory,
+            CreateOrInsertIntoFile, RemoveDirectory,
+        },
+        common::{
+            ConfigureDaemonRestart, ConfigureDeterminateNixdInitService, ConfigureNix,
+            ConfigureUpstreamInitService, CreateUsersAndGroups, ProvisionDeterminateNixd,
+            ProvisionNix, RunPostInstall, NIX_DAEMON_PROFILE_SCRIPT,
+        },
+        linux::{CreateTmpfilesRule, DAEMON_SOCKET_TMPFILES_DEST, DAEMON_SOCKET_TMPFILES_RULE},
+        StatefulAction,
+    },
+    error::HasExpectedErrors,
+    os::linux::{detect_init, is_wsl, DetectedInit},
+    planner::{Planner, PlannerError},
+    settings::{
+        CommonSettings, Component, InitSystem, InstallSettingsError, DEFAULT_DAEMON_UNIT_NAME,
+    },
+    Action, BuiltinPlanner,
+};
+
+pub const WSL_CONF: &str = "/etc/wsl.conf";
+const NIX_DAEMON_BOOT_COMMAND: &str = "/nix/var/nix/profiles/default/bin/nix-daemon &";
+
+/**
+A planner for Windows Subsystem for Linux (WSL2) instances
+
+When the instance is booted with systemd, the daemon is run by systemd as on [`Linux`](super::linux::Linux).
+Otherwise, a `[boot]` command is added to `/etc/wsl.conf` so WSL starts `nix-daemon` when the instance starts.
+*/
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct Wsl {
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub settings: CommonSettings,
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "wsl")]
+impl Planner for Wsl {
+    async fn default() -> Result<Self, PlannerError> {
+        Ok(Self {
+            settings: CommonSettings::default().await?,
+        })
+    }
+
+    async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        super::check_no_existing_nix(&self.settings).await?;
+        check_nix_store_path_writable(&self.settings.nix_store_path)?;
+
+        let has_systemd = detect_init() == DetectedInit::Systemd;
+
+        let mut plan = vec![];
+
+        plan.push(
+            CreateDirectory::plan(&self.settings.nix_store_path, None, None, 0o0755, true)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        if self.settings.determinate_nix {
+            if !has_systemd {
+                return Err(WslErrorKind::DeterminateNixdWithoutSystemd.into());
+            }
+            plan.push(
+                ProvisionDeterminateNixd::plan()
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
+
+        if self.settings.required_free_space > 0 {
+            plan.push(
+                CheckFreeSpace::plan(
+                    &self.settings.nix_store_path,
+                    self.settings.required_free_space,
+                )
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+            );
+        }
+
+        plan.push(
+            ProvisionNix::plan(&self.settings.clone())
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            CreateUsersAndGroups::plan(self.settings.clone())
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            ConfigureNix::plan(ShellProfileLocations::default(), &self.settings)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        if self.settings.installs(Component::Daemon) {
+            if has_systemd {
+                plan.push(
+                    CreateDirectory::plan("/etc/tmpfiles.d", None, None, 0o0755, false)
+                        .await
+                        .map_err(PlannerError::Action)?
+                        .boxed(),
+                );
+                plan.push(
+                    CreateTmpfilesRule::plan(
+                        DAEMON_SOCKET_TMPFILES_DEST,
+                        DAEMON_SOCKET_TMPFILES_RULE,
+                    )
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+                );
+                if self.settings.determinate_nix {
+                    plan.push(
+                        ConfigureDeterminateNixdInitService::plan(InitSystem::Systemd, true)
+                            .await
+                            .map_err(PlannerError::Action)?
+                            .boxed(),
+                    );
+                } else {
+                    plan.push(
+                        ConfigureUpstreamInitService::plan(
+                            InitSystem::Systemd,
+                            true,
+                            DEFAULT_DAEMON_UNIT_NAME,
+                        )
+                        .await
+                        .map_err(PlannerError::Action)?
+                        .boxed(),
+                    );
+                }
+                if self.settings.daemon_restart_on_failure {
+                    plan.push(
+                        ConfigureDaemonRestart::plan(InitSystem::Systemd, None, None)
+                            .await
+                            .map_err(PlannerError::Action)?
+                            .boxed(),
+                    );
+                }
+            } else {
+                let existing = match tokio::fs::read_to_string(WSL_CONF).await {
+                    Ok(existing) => existing,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+                    Err(e) => return Err(WslErrorKind::ReadWslConf(e).into()),
+                };
+                if let Some(command) = existing_boot_command(&existing) {
+                    return Err(WslErrorKind::BootCommandExists(command).into());
+                }
+                let (buf, position) = boot_command_insertion(&existing);
+                plan.push(
+                    CreateOrInsertIntoFile::plan(
+                        WSL_CONF, None, None, 0o0644, buf, position, false,
+                    )
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+                );
+            }
+        }
+
+        if !self.settings.post_install_commands.is_empty() {
+            plan.push(
+                RunPostInstall::plan(NIX_DAEMON_PROFILE_SCRIPT, &self.settings)
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
+        plan.push(
+            RemoveDirectory::plan(crate::settings::SCRATCH_DIR)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        Ok(plan)
+    }
+
+    fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
+        let Self { settings } = self;
+        let mut map = HashMap::default();
+
+        map.extend(settings.settings()?);
+
+        Ok(map)
+    }
+
+    async fn configured_settings(
+        &self,
+    ) -> Result<HashMap<String, serde_json::Value>, PlannerError> {
+        let default = Self::default().await?.settings()?;
+        let configured = self.settings()?;
+
+        let mut settings: HashMap<String, serde_json::Value> = HashMap::new();
+        for (key, value) in configured.iter() {
+            if default.get(key) != Some(value) {
+                settings.insert(key.clone(), value.clone());
+            }
+        }
+
+        Ok(settings)
+    }
+
+    async fn platform_check(&self) -> Result<(), PlannerError> {
+        use target_lexicon::OperatingSystem;
+        match target_lexicon::OperatingSystem::host() {
+            OperatingSystem::Linux => Ok(()),
+            host_os => Err(PlannerError::IncompatibleOperatingSystem {
+                planner: self.typetag_name(),
+                host_os,
+            }),
+        }
+    }
+
+    async fn pre_uninstall_check(&self) -> Result<(), PlannerError> {
+        check_not_wsl1()?;
+
+        Ok(())
+    }
+
+    async fn pre_install_check(&self) -> Result<(), PlannerError> {
+        self.settings.check_offline()?;
+        self.settings.check_components()?;
+        self.settings.check_nix_package_system()?;
+        self.settings.check_no_root()?;
+        check_not_nixos()?;
+
+        if !is_wsl() {
+            return Err(WslErrorKind::NotWsl.into());
+        }
+        check_not_wsl1()?;
+
+        check_nix_not_already_installed(&self.settings).await?;
+
+        if detect_init() == DetectedInit::Systemd {
+            check_no_running_nix_daemon(&self.settings, false, DEFAULT_DAEMON_UNIT_NAME).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl From<Wsl> for BuiltinPlanner {
+    fn from(val: Wsl) -> Self {
+        BuiltinPlanner::Wsl(val)
+    }
+}
+
+/// The boot command, and where it goes so that `wsl.conf` keeps a single `[boot]` section
+fn boot_command_insertion(wsl_conf: &str) -> (String, Position) {
+    let command = format!(
+        "# Added by the Nix installer, starts `nix-daemon` when WSL starts\n\
+        command = {NIX_DAEMON_BOOT_COMMAND}\n"
+    );
+    if wsl_conf.lines().any(|line| line.trim() == "[boot]") {
+        (command, Position::AfterLine("[boot]".into()))
+    } else {
+        (format!("\n[boot]\n{command}"), Position::End)
+    }
+}
+
+/// The `command` of the `[boot]` section of a `wsl.conf`, if it has one
+///
+/// WSL only runs a single boot command, so adding ours would replace the user's.
+fn existing_boot_command(wsl_conf: &str) -> Option<String> {
+    let mut in_boot = false;
+    for line in wsl_conf.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_boot = line == "[boot]";
+        } else if in_boot {
+            if let Some((key, value)) = line.split_once('=') {
+                if key.trim() == "command" {
+                    return Some(value.trim().to_string());
+                }
+            }
+        }
+    }
+    None
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum WslErrorKind {
+    #[error("The `wsl` planner can only be used inside Windows Subsystem for Linux, consider using the `linux` planner")]
+    NotWsl,
+    #[error("Reading `{WSL_CONF}`")]
+    ReadWslConf(#[source] std::io::Error),
+    #[error(
+        "\
+        `{WSL_CONF}` already has a boot command (`{0}`), and WSL only runs one.\n\
+        \n\
+        Consider enabling systemd instead by adding `systemd=true` to the `[boot]` section of `{WSL_CONF}`, then restarting WSL2 with `wsl.exe --shutdown`. For more information, see https://devblogs.microsoft.com/commandline/systemd-support-is-now-available-in-wsl/."
+    )]
+    BootCommandExists(String),
+    #[error("Determinate Nixd requires systemd on WSL2. Consider enabling it by adding it to your `{WSL_CONF}` with `echo -e '[boot]\\nsystemd=true'` then restarting WSL2 with `wsl.exe --shutdown`")]
+    DeterminateNixdWithoutSystemd,
+}
+
+impl HasExpectedErrors for WslErrorKind {
+    fn expected<'a>(&'a self) -> Option<Box<dyn std::error::Error + 'a>> {
+        match self {
+            WslErrorKind::NotWsl => Some(Box::new(self)),
+            WslErrorKind::ReadWslConf(_) => None,
+            WslErrorKind::BootCommandExists(_) => Some(Box::new(self)),
+            WslErrorKind::DeterminateNixdWithoutSystemd => Some(Box::new(self)),
+        }
+    }
+}
+
+impl From<WslErrorKind> for PlannerError {
+    fn from(v: WslErrorKind) -> PlannerError {
+        PlannerError::Custom(Box::new(v))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{boot_command_insertion, existing_boot_command};
+    use crate::action::base::create_or_insert_into_file::Position;
+
+    #[test]
+    fn finds_existing_boot_command() {
+        assert_eq!(existing_boot_command(""), None);
+        assert_eq!(
+            existing_boot_command("[boot]\nsystemd=false\n\n[user]\ncommand=ignored\n"),
+            None
+        );
+        assert_eq!(
+            existing_boot_command(
+                "[network]\nhostname = box\n[boot]\ncommand = service docker start\n"
+            ),
+            Some("service docker start".into())
+        );
+    }
+
+    #[test]
+    fn boot_command_joins_existing_boot_section() {
+        let (buf, position) = boot_command_insertion("[boot]\nsystemd=false\n");
+        assert_eq!(position, Position::AfterLine("[boot]".into()));
+        assert!(!buf.contains("[boot]"));
+
+        let (buf, position) = boot_command_insertion("[network]\nhostname = box\n");
+        assert_eq!(position, Position::End);
+        assert!(buf.starts_with("\n[boot]\n"));
+    }
+}
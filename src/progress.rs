@@ -0,0 +1,110 @@
+/*! Structured progress of an install
+
+Embedders which present their own interface can pass a sender to
+[`InstallPlan::install_with_progress`](crate::InstallPlan::install_with_progress) and receive an
+[`InstallProgress`] event as each planned action runs, rather than parsing the logs. Events
+serialize to JSON, so they can also be forwarded as-is.
+
+Actions report finer grained progress, like [`InstallProgress::Download`], through the sender
+of the install they run in. These are best-effort, and dropped if the receiver falls behind.
+*/
+
+use std::future::Future;
+
+use tokio::sync::mpsc::Sender;
+
+tokio::task_local! {
+    static SINK: Sender<InstallProgress>;
+}
+
+/// An event emitted as an [`InstallPlan`](crate::InstallPlan) is carried out
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum InstallProgress {
+    /// The action at `index` (of `total`) is about to run
+    ActionStarted {
+        index: usize,
+        total: usize,
+        synopsis: String,
+    },
+    /// The action at `index` finished
+    ActionCompleted { index: usize, synopsis: String },
+    /// The action at `index` failed, the install stops here
+    ActionFailed {
+        index: usize,
+        synopsis: String,
+        error: String,
+    },
+    /// The Nix package is being downloaded, `total` is `None` if the server did not say its size
+    Download { downloaded: u64, total: Option<u64> },
+}
+
+/// The sender of the install running in this task, if it has one
+pub(crate) fn sink() -> Option<Sender<InstallProgress>> {
+    SINK.try_with(Clone::clone).ok()
+}
+
+/// Run `future` with `sink` receiving its [`report`]s, like spawned parts of an action
+pub(crate) async fn with_sink<F: Future>(
+    sink: Option<Sender<InstallProgress>>,
+    future: F,
+) -> F::Output {
+    match sink {
+        Some(sink) => SINK.scope(sink, future).await,
+        None => future.await,
+    }
+}
+
+/// Send `event` to the install running in this task, without waiting for the receiver
+pub(crate) fn report(event: InstallProgress) {
+    let _ = SINK.try_with(|sink| sink.try_send(event));
+}
+
+#[cfg(test)]
+mod test {
+    use super::{report, sink, with_sink, InstallProgress};
+
+    #[test]
+    fn progress_serializes_as_tagged_json() -> eyre::Result<()> {
+        let event = InstallProgress::ActionStarted {
+            index: 0,
+            total: 2,
+            synopsis: "Provision Nix".into(),
+        };
+        let json = serde_json::to_value(&event)?;
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "event": "action_started",
+                "index": 0,
+                "total": 2,
+                "synopsis": "Provision Nix",
+            })
+        );
+        assert_eq!(serde_json::from_value::<InstallProgress>(json)?, event);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reports_reach_the_scoped_sink() -> eyre::Result<()> {
+        let event = InstallProgress::Download {
+            downloaded: 1024,
+            total: None,
+        };
+        // Outside of an install, reports go nowhere
+        report(event.clone());
+
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+        with_sink(Some(sender), async {
+            report(event.clone());
+            // A full channel drops the report, rather than waiting
+            report(event.clone());
+            with_sink(sink(), async { assert!(sink().is_some()) }).await;
+        })
+        .await;
+
+        assert_eq!(receiver.recv().await, Some(event));
+        assert_eq!(receiver.recv().await, None);
+        Ok(())
+    }
+}
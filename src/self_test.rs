@@ -1,4 +1,8 @@
-use std::{process::Output, time::SystemTime};
+use std::{
+    path::{Path, PathBuf},
+    process::Output,
+    time::{Duration, SystemTime},
+};
 
 use tokio::process::Command;
 use which::which;
@@ -22,6 +26,12 @@ pub enum SelfTestError {
         #[source]
         error: std::io::Error,
     },
+    #[error("The Nix daemon is not accepting connections on `{}`", socket.display())]
+    DaemonUnreachable {
+        socket: PathBuf,
+        #[source]
+        error: std::io::Error,
+    },
     #[error(transparent)]
     SystemTime(#[from] std::time::SystemTimeError),
 }
@@ -33,6 +43,7 @@ impl crate::diagnostics::ErrorDiagnostic for SelfTestError {
         let context = match self {
             Self::ShellFailed { shell, .. } => vec![shell.to_string()],
             Self::Command { shell, .. } => vec![shell.to_string()],
+            Self::DaemonUnreachable { error, .. } => vec![error.kind().to_string()],
             Self::SystemTime(_) => vec![],
         };
         format!(
@@ -147,12 +158,45 @@ impl Shell {
     }
 }
 
+pub const DAEMON_SOCKET: &str = "/nix/var/nix/daemon-socket/socket";
+const DAEMON_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Check the Nix daemon is accepting connections on its socket
+#[tracing::instrument(skip_all)]
+pub async fn daemon_reachable() -> Result<(), SelfTestError> {
+    daemon_reachable_at(Path::new(DAEMON_SOCKET), DAEMON_CONNECT_TIMEOUT).await
+}
+
+async fn daemon_reachable_at(socket: &Path, timeout: Duration) -> Result<(), SelfTestError> {
+    let error = match tokio::time::timeout(timeout, tokio::net::UnixStream::connect(socket)).await {
+        Ok(Ok(_stream)) => return Ok(()),
+        Ok(Err(error)) => error,
+        Err(_elapsed) => std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!("no answer within {}s", timeout.as_secs_f32()),
+        ),
+    };
+    Err(SelfTestError::DaemonUnreachable {
+        socket: socket.to_path_buf(),
+        error,
+    })
+}
+
 #[tracing::instrument(skip_all)]
 pub async fn self_test() -> Result<(), Vec<SelfTestError>> {
     let shells = Shell::discover();
 
     let mut failures = vec![];
 
+    // A root-only install (such as with `--init none`) has no daemon, so no socket
+    if Path::new(DAEMON_SOCKET).exists() {
+        if let Err(err) = daemon_reachable().await {
+            failures.push(err);
+        }
+    } else {
+        tracing::debug!("No Nix daemon socket at `{DAEMON_SOCKET}`, skipping daemon self-test");
+    }
+
     for shell in shells {
         match shell.self_test().await {
             Ok(()) => (),
@@ -166,3 +210,32 @@ pub async fn self_test() -> Result<(), Vec<SelfTestError>> {
         Err(failures)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{daemon_reachable_at, SelfTestError};
+
+    #[tokio::test]
+    async fn daemon_reachable_surfaces_os_error() -> eyre::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let socket = dir.path().join("socket");
+
+        let listener = tokio::net::UnixListener::bind(&socket)?;
+        daemon_reachable_at(&socket, Duration::from_secs(1)).await?;
+
+        // The socket file outlives the listener, as after a daemon crash
+        drop(listener);
+        let err = daemon_reachable_at(&socket, Duration::from_secs(1))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            SelfTestError::DaemonUnreachable { ref error, .. }
+                if error.kind() == std::io::ErrorKind::ConnectionRefused
+        ));
+
+        Ok(())
+    }
+}
@@ -1,6 +1,11 @@
 /*! Configurable knobs and their related errors
 */
-use std::{collections::HashMap, fmt::Display, path::PathBuf, str::FromStr};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 #[cfg(feature = "cli")]
 use clap::{
@@ -9,10 +14,49 @@ use clap::{
 };
 use url::Url;
 
+use crate::action::base::DEFAULT_REQUIRED_FREE_SPACE;
+
 pub const SCRATCH_DIR: &str = "/nix/temp-install-dir";
 
 pub const DEFAULT_NIX_BUILD_USER_GROUP_NAME: &str = "nixbld";
 
+pub const DEFAULT_NIX_STORE_PATH: &str = "/nix";
+
+pub const DEFAULT_NIX_BUILD_USER_HOME: &str = "/var/empty";
+
+pub const DEFAULT_NIX_BUILD_USER_SHELL: &str = "/sbin/nologin";
+
+/// The most `--cores` accepted, well above any machine Nix builds on today
+pub const MAX_CORES: u32 = 1024;
+
+fn default_nix_store_path() -> PathBuf {
+    PathBuf::from(DEFAULT_NIX_STORE_PATH)
+}
+
+pub(crate) fn default_nix_build_user_home() -> PathBuf {
+    PathBuf::from(DEFAULT_NIX_BUILD_USER_HOME)
+}
+
+pub(crate) fn default_nix_build_user_shell() -> PathBuf {
+    PathBuf::from(DEFAULT_NIX_BUILD_USER_SHELL)
+}
+
+fn default_download_attempts() -> u32 {
+    5
+}
+
+fn default_required_free_space() -> u64 {
+    DEFAULT_REQUIRED_FREE_SPACE
+}
+
+fn default_require_cacert() -> bool {
+    true
+}
+
+fn default_auto_optimise_store() -> bool {
+    true
+}
+
 pub const NIX_TARBALL_PATH: &str = env!("NIX_INSTALLER_TARBALL_PATH");
 /// The NIX_INSTALLER_TARBALL_PATH environment variable should point to a target-appropriate
 /// Nix installation tarball, like nix-2.21.2-aarch64-darwin.tar.xz. The contents are embedded
@@ -38,6 +82,8 @@ pub enum InitSystem {
     None,
     Systemd,
     Launchd,
+    #[cfg_attr(feature = "cli", value(name = "openrc"))]
+    OpenRc,
 }
 
 impl std::fmt::Display for InitSystem {
@@ -46,10 +92,46 @@ impl std::fmt::Display for InitSystem {
             InitSystem::None => write!(f, "none"),
             InitSystem::Systemd => write!(f, "systemd"),
             InitSystem::Launchd => write!(f, "launchd"),
+            InitSystem::OpenRc => write!(f, "openrc"),
+        }
+    }
+}
+
+/// A part of an install which can be left out with [`components`](CommonSettings::components)
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Component {
+    /// The Nix store, with Nix and the build users
+    Store,
+    /// `/etc/nix/nix.conf`
+    Config,
+    /// The init service running the Nix daemon
+    Daemon,
+    /// The shell profiles loading Nix
+    Profiles,
+}
+
+impl std::fmt::Display for Component {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Component::Store => write!(f, "store"),
+            Component::Config => write!(f, "config"),
+            Component::Daemon => write!(f, "daemon"),
+            Component::Profiles => write!(f, "profiles"),
         }
     }
 }
 
+fn default_components() -> Vec<Component> {
+    vec![
+        Component::Store,
+        Component::Config,
+        Component::Daemon,
+        Component::Profiles,
+    ]
+}
+
 /** Common settings used by all [`BuiltinPlanner`](crate::planner::BuiltinPlanner)s
 
 Settings which only apply to certain [`Planner`](crate::planner::Planner)s should be located in the planner.
@@ -83,6 +165,34 @@ pub struct CommonSettings {
     )]
     pub modify_profile: bool,
 
+    /// Back up shell profiles before modifying them, and restore them from the backup on uninstall
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action(ArgAction::SetTrue),
+            default_value = "false",
+            global = true,
+            env = "NIX_INSTALLER_BACKUP_SHELL_PROFILES"
+        )
+    )]
+    #[serde(default)]
+    pub backup_shell_profiles: bool,
+
+    /// Copy an existing `nix.conf` to `nix.conf.nix-installer.bak-<timestamp>` before merging into it, and restore it from the backup on uninstall
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action(ArgAction::SetTrue),
+            default_value = "false",
+            global = true,
+            env = "NIX_INSTALLER_BACKUP_NIX_CONF"
+        )
+    )]
+    #[serde(default)]
+    pub backup_nix_conf: bool,
+
     /// The Nix build group name
     #[cfg_attr(
         feature = "cli",
@@ -150,13 +260,168 @@ pub struct CommonSettings {
     )]
     pub nix_build_user_id_base: u32,
 
-    /// The Nix package URL
+    /// The home directory of the Nix build users
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            default_value = DEFAULT_NIX_BUILD_USER_HOME,
+            env = "NIX_INSTALLER_NIX_BUILD_USER_HOME",
+            global = true
+        )
+    )]
+    #[serde(default = "default_nix_build_user_home")]
+    pub nix_build_user_home: PathBuf,
+
+    /// The login shell of the Nix build users, such as `/usr/sbin/nologin` on systems without `/sbin/nologin`
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            default_value = DEFAULT_NIX_BUILD_USER_SHELL,
+            env = "NIX_INSTALLER_NIX_BUILD_USER_SHELL",
+            global = true
+        )
+    )]
+    #[serde(default = "default_nix_build_user_shell")]
+    pub nix_build_user_shell: PathBuf,
+
+    /// The Nix package URL, or a path to a local `.tar.xz` for offline installs
     #[cfg_attr(
         feature = "cli",
         clap(long, env = "NIX_INSTALLER_NIX_PACKAGE_URL", global = true, value_parser = clap::value_parser!(UrlOrPath), default_value = None)
     )]
     pub nix_package_url: Option<UrlOrPath>,
 
+    /// A Nix release to install, like `2.18.1`, fetched from `releases.nixos.org` for this machine's system
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            env = "NIX_INSTALLER_NIX_VERSION",
+            global = true,
+            conflicts_with_all = ["nix_package_url", "nix_package_stdin"]
+        )
+    )]
+    #[serde(default)]
+    pub nix_version: Option<String>,
+
+    /// Read the Nix package (a `.tar.xz`) from stdin instead of fetching it
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action(ArgAction::SetTrue),
+            default_value = "false",
+            global = true,
+            env = "NIX_INSTALLER_NIX_PACKAGE_STDIN",
+            conflicts_with = "nix_package_url",
+        )
+    )]
+    #[serde(default)]
+    pub nix_package_stdin: bool,
+
+    /// The expected SHA-256 of the Nix package, as hex; the unpacked package is removed and the install fails if it does not match
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, env = "NIX_INSTALLER_NIX_PACKAGE_SHA256", global = true)
+    )]
+    #[serde(default)]
+    pub nix_package_sha256: Option<String>,
+
+    /// How long downloading the Nix package may take, in seconds, `0` for no limit
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            default_value = "0",
+            env = "NIX_INSTALLER_DOWNLOAD_TIMEOUT_SECS",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub download_timeout_secs: u64,
+
+    /// How many times downloading the Nix package is attempted before giving up
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            default_value = "5",
+            env = "NIX_INSTALLER_DOWNLOAD_ATTEMPTS",
+            global = true
+        )
+    )]
+    #[serde(default = "default_download_attempts")]
+    pub download_attempts: u32,
+
+    /// The `User-Agent` sent when downloading the Nix package
+    ///
+    /// Defaults to `nix-installer/<version> (<os>-<arch>)`.
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, env = "NIX_INSTALLER_USER_AGENT", global = true)
+    )]
+    #[serde(default)]
+    pub user_agent: Option<String>,
+
+    /// A contact address sent in the `From` header when downloading the Nix package, like `ops@example.com`
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            env = "NIX_INSTALLER_FROM_HEADER",
+            global = true,
+            value_name = "EMAIL"
+        )
+    )]
+    #[serde(default)]
+    pub from_header: Option<String>,
+
+    /// Where the Nix store physically lives, `/nix` is made a symlink to it if this is not `/nix` (Linux only)
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            default_value = DEFAULT_NIX_STORE_PATH,
+            global = true,
+            env = "NIX_INSTALLER_NIX_STORE_PATH"
+        )
+    )]
+    #[serde(default = "default_nix_store_path")]
+    pub nix_store_path: PathBuf,
+
+    /// Install into this directory as if it were `/`, like when building a system image (`linux` planner only)
+    ///
+    /// Build users and the Nix daemon are left for the system booted from it to set up.
+    // NOTE: hidden while experimental. The rooted plan is tested (`tests/root.rs`), but executing
+    // it needs root and a real Nix package, so it has not been exercised end to end.
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_name = "PATH",
+            env = "NIX_INSTALLER_ROOT",
+            global = true,
+            hide = true
+        )
+    )]
+    #[serde(default)]
+    pub root: Option<PathBuf>,
+
+    /// The free space (in bytes) needed where the Nix store will live, checked before Nix is unpacked (`0` skips the check)
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            default_value_t = DEFAULT_REQUIRED_FREE_SPACE,
+            env = "NIX_INSTALLER_REQUIRED_FREE_SPACE",
+            global = true
+        )
+    )]
+    #[serde(default = "default_required_free_space")]
+    pub required_free_space: u64,
+
     #[clap(from_global)]
     pub proxy: Option<Url>,
     #[clap(from_global)]
@@ -166,56 +431,337 @@ pub struct CommonSettings {
     #[cfg_attr(feature = "cli", clap(long, action = ArgAction::Append, num_args = 0.., env = "NIX_INSTALLER_EXTRA_CONF", global = true))]
     pub extra_conf: Vec<UrlOrPathOrString>,
 
-    /// If `nix-installer` should forcibly recreate files it finds existing
+    /// A file of extra configuration for `/etc/nix/nix.conf`, checked when planning (can be passed multiple times)
     #[cfg_attr(
         feature = "cli",
         clap(
             long,
-            action(ArgAction::SetTrue),
-            default_value = "false",
+            action = ArgAction::Append,
+            value_name = "PATH",
+            env = "NIX_INSTALLER_EXTRA_CONF_FILE",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub extra_conf_file: Vec<PathBuf>,
+
+    /// Set `builders-use-substitutes` in `/etc/nix/nix.conf`, letting remote builders fetch from substituters directly
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            env = "NIX_INSTALLER_BUILDERS_USE_SUBSTITUTES",
             global = true,
-            env = "NIX_INSTALLER_FORCE"
+            value_name = "BOOL"
         )
     )]
-    pub force: bool,
+    #[serde(default)]
+    pub builders_use_substitutes: Option<bool>,
 
-    /// If `nix-installer` should skip creating `/etc/nix/nix.conf`
+    /// Set `max-jobs` in `/etc/nix/nix.conf`, the number of builds run at once (a number, or `auto` for one per CPU)
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            env = "NIX_INSTALLER_MAX_JOBS",
+            global = true,
+            value_name = "JOBS"
+        )
+    )]
+    #[serde(default)]
+    pub max_jobs: Option<String>,
+
+    /// Set `cores` in `/etc/nix/nix.conf`, the CPU cores each build may use (`0` for all of them)
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            env = "NIX_INSTALLER_CORES",
+            global = true,
+            value_name = "CORES",
+            value_parser = clap::value_parser!(u32).range(0..=i64::from(MAX_CORES))
+        )
+    )]
+    #[serde(default)]
+    pub cores: Option<u32>,
+
+    /// Users to add to `trusted-users` in `/etc/nix/nix.conf`, alongside `root` (comma separated, or passed multiple times)
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_delimiter = ',',
+            value_name = "USER",
+            env = "NIX_INSTALLER_TRUSTED_USERS",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub trusted_users: Vec<String>,
+
+    /// Binary caches to add to `extra-substituters` in `/etc/nix/nix.conf`, like an internal cache (comma separated, or passed multiple times)
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_delimiter = ',',
+            value_name = "URL",
+            env = "NIX_INSTALLER_EXTRA_SUBSTITUTERS",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub extra_substituters: Vec<String>,
+
+    /// Signing keys (like `cache.example.com-1:<base64>`) to trust alongside the defaults, for `--extra-substituters` (comma separated, or passed multiple times)
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_delimiter = ',',
+            value_name = "KEY",
+            env = "NIX_INSTALLER_TRUSTED_PUBLIC_KEYS",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub trusted_public_keys: Vec<String>,
+
+    /// Add the user running `sudo nix-installer` (from `SUDO_USER`) to `trusted-users`
     #[cfg_attr(
         feature = "cli",
         clap(
             long,
             action(ArgAction::SetTrue),
             default_value = "false",
+            env = "NIX_INSTALLER_TRUST_SUDO_USER",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub trust_sudo_user: bool,
+
+    /// Set `auto-optimise-store = true` in `/etc/nix/nix.conf` (not on macOS), hard linking identical files in the store
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            action(ArgAction::SetFalse),
+            default_value = "true",
             global = true,
-            env = "NIX_INSTALLER_SKIP_NIX_CONF",
-            conflicts_with = "extra_conf",
+            env = "NIX_INSTALLER_AUTO_OPTIMISE_STORE",
+            long = "no-auto-optimise-store"
         )
     )]
-    pub skip_nix_conf: bool,
-}
+    #[serde(default = "default_auto_optimise_store")]
+    pub auto_optimise_store: bool,
 
-pub(crate) fn default_nix_build_user_id_base() -> u32 {
-    use target_lexicon::OperatingSystem;
+    /// The experimental features enabled in `/etc/nix/nix.conf` instead of `nix-command` and `flakes`, comma separated
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_delimiter = ',',
+            value_name = "FEATURES",
+            env = "NIX_INSTALLER_EXPERIMENTAL_FEATURES",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub experimental_features: Option<Vec<String>>,
 
-    match OperatingSystem::host() {
-        OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => 350,
-        _ => 30_000,
-    }
-}
+    /// Experimental features to enable in `/etc/nix/nix.conf` in addition to the defaults, comma separated (can be passed multiple times)
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action = ArgAction::Append,
+            value_delimiter = ',',
+            value_name = "FEATURES",
+            env = "NIX_INSTALLER_EXTRA_EXPERIMENTAL_FEATURES",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub extra_experimental_features: Vec<String>,
 
-pub(crate) fn default_nix_build_group_id() -> u32 {
-    use target_lexicon::OperatingSystem;
+    /// An individual `/etc/nix/nix.conf` setting, as `NAME=VALUE` (can be passed multiple times, a list setting like `extra-substituters` given more than once gets every value)
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action = ArgAction::Append,
+            value_name = "NAME=VALUE",
+            env = "NIX_INSTALLER_NIX_CONF",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub nix_conf: Vec<NixConfSetting>,
 
-    match OperatingSystem::host() {
-        OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => 350,
-        _ => 30_000,
-    }
-}
+    /// Refuse `--nix-conf` settings which are not known Nix settings, instead of warning
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action(ArgAction::SetTrue),
+            default_value = "false",
+            global = true,
+            env = "NIX_INSTALLER_STRICT_NIX_CONF"
+        )
+    )]
+    #[serde(default)]
+    pub strict_nix_conf: bool,
 
-impl CommonSettings {
-    /// The default settings for the given Architecture & Operating System
-    pub async fn default() -> Result<Self, InstallSettingsError> {
-        let nix_build_user_prefix;
+    /// Restart the Nix daemon automatically if it fails
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action(ArgAction::SetTrue),
+            default_value = "false",
+            global = true,
+            env = "NIX_INSTALLER_DAEMON_RESTART_ON_FAILURE"
+        )
+    )]
+    #[serde(default)]
+    pub daemon_restart_on_failure: bool,
+
+    /// Install without any network access, for air-gapped machines
+    ///
+    /// The Nix package and any `--extra-conf` must be local, the global flake registry is disabled
+    /// and no diagnostics are sent.
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action(ArgAction::SetTrue),
+            default_value = "false",
+            global = true,
+            env = "NIX_INSTALLER_OFFLINE"
+        )
+    )]
+    #[serde(default)]
+    pub offline: bool,
+
+    /// If `nix-installer` should forcibly recreate files it finds existing
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action(ArgAction::SetTrue),
+            default_value = "false",
+            global = true,
+            env = "NIX_INSTALLER_FORCE"
+        )
+    )]
+    pub force: bool,
+
+    /// If `nix-installer` should skip creating `/etc/nix/nix.conf`
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action(ArgAction::SetTrue),
+            default_value = "false",
+            global = true,
+            env = "NIX_INSTALLER_SKIP_NIX_CONF",
+            conflicts_with_all = ["extra_conf", "extra_conf_file", "builders_use_substitutes", "max_jobs", "cores", "extra_substituters", "trusted_public_keys", "nix_conf"],
+        )
+    )]
+    pub skip_nix_conf: bool,
+
+    /// Which parts of Nix to install, as a comma separated list
+    ///
+    /// `store` is always required. Leaving out `config`, `daemon`, or `profiles` leaves that part for you to set up.
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_delimiter = ',',
+            default_values_t = default_components(),
+            global = true,
+            env = "NIX_INSTALLER_COMPONENTS"
+        )
+    )]
+    #[serde(default = "default_components")]
+    pub components: Vec<Component>,
+
+    /// Fail the install if the Nix package does not include `nss-cacert`
+    ///
+    /// Without it, TLS connections from Nix may fail until `ssl-cert-file` is configured.
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            action(ArgAction::SetFalse),
+            default_value = "true",
+            global = true,
+            env = "NIX_INSTALLER_REQUIRE_CACERT",
+            long = "no-require-cacert"
+        )
+    )]
+    #[serde(default = "default_require_cacert")]
+    pub require_cacert: bool,
+
+    /// A shell command to run once Nix is installed, with the Nix profile sourced (can be passed multiple times)
+    ///
+    /// A failing command is logged and the install continues, unless `--post-install-abort-on-failure` is set.
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action = ArgAction::Append,
+            value_name = "COMMAND",
+            env = "NIX_INSTALLER_POST_INSTALL_COMMAND",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub post_install_commands: Vec<String>,
+
+    /// Fail (and revert) the install if a `--post-install-command` fails
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action(ArgAction::SetTrue),
+            default_value = "false",
+            global = true,
+            env = "NIX_INSTALLER_POST_INSTALL_ABORT_ON_FAILURE"
+        )
+    )]
+    #[serde(default)]
+    pub post_install_abort_on_failure: bool,
+
+    /// Plan over an existing install from the same settings, so only what has drifted is redone
+    ///
+    /// Set by `install --force-reinstall`, never recorded in the receipt.
+    #[cfg_attr(feature = "cli", clap(skip))]
+    #[serde(skip)]
+    pub(crate) reconcile_existing: bool,
+}
+
+pub(crate) fn default_nix_build_user_id_base() -> u32 {
+    use target_lexicon::OperatingSystem;
+
+    match OperatingSystem::host() {
+        OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => 350,
+        _ => 30_000,
+    }
+}
+
+pub(crate) fn default_nix_build_group_id() -> u32 {
+    use target_lexicon::OperatingSystem;
+
+    match OperatingSystem::host() {
+        OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => 350,
+        _ => 30_000,
+    }
+}
+
+impl CommonSettings {
+    /// The default settings for the given Architecture & Operating System
+    pub async fn default() -> Result<Self, InstallSettingsError> {
+        let nix_build_user_prefix;
 
         use target_lexicon::{Architecture, OperatingSystem};
         match (Architecture::host(), OperatingSystem::host()) {
@@ -243,81 +789,781 @@ impl CommonSettings {
             },
         };
 
-        Ok(Self {
-            determinate_nix: false,
-            modify_profile: true,
-            nix_build_group_name: String::from(crate::settings::DEFAULT_NIX_BUILD_USER_GROUP_NAME),
-            nix_build_group_id: default_nix_build_group_id(),
-            nix_build_user_id_base: default_nix_build_user_id_base(),
-            nix_build_user_count: 32,
-            nix_build_user_prefix: nix_build_user_prefix.to_string(),
-            nix_package_url: None,
-            proxy: Default::default(),
-            extra_conf: Default::default(),
-            force: false,
-            skip_nix_conf: false,
-            ssl_cert_file: Default::default(),
-        })
+        Ok(Self {
+            determinate_nix: false,
+            modify_profile: true,
+            backup_shell_profiles: false,
+            backup_nix_conf: false,
+            nix_build_group_name: String::from(crate::settings::DEFAULT_NIX_BUILD_USER_GROUP_NAME),
+            nix_build_group_id: default_nix_build_group_id(),
+            nix_build_user_id_base: default_nix_build_user_id_base(),
+            nix_build_user_home: PathBuf::from(DEFAULT_NIX_BUILD_USER_HOME),
+            nix_build_user_shell: PathBuf::from(DEFAULT_NIX_BUILD_USER_SHELL),
+            nix_build_user_count: 32,
+            nix_build_user_prefix: nix_build_user_prefix.to_string(),
+            nix_package_url: None,
+            nix_version: None,
+            nix_package_stdin: false,
+            nix_package_sha256: None,
+            download_timeout_secs: 0,
+            download_attempts: default_download_attempts(),
+            user_agent: None,
+            from_header: None,
+            nix_store_path: PathBuf::from(DEFAULT_NIX_STORE_PATH),
+            root: None,
+            required_free_space: DEFAULT_REQUIRED_FREE_SPACE,
+            proxy: Default::default(),
+            extra_conf: Default::default(),
+            extra_conf_file: vec![],
+            builders_use_substitutes: None,
+            max_jobs: None,
+            cores: None,
+            trusted_users: vec![],
+            extra_substituters: vec![],
+            trusted_public_keys: vec![],
+            trust_sudo_user: false,
+            auto_optimise_store: true,
+            experimental_features: None,
+            extra_experimental_features: Vec::new(),
+            nix_conf: Default::default(),
+            strict_nix_conf: false,
+            daemon_restart_on_failure: false,
+            offline: false,
+            force: false,
+            skip_nix_conf: false,
+            components: default_components(),
+            require_cacert: true,
+            post_install_commands: vec![],
+            post_install_abort_on_failure: false,
+            reconcile_existing: false,
+            ssl_cert_file: Default::default(),
+        })
+    }
+
+    /// A listing of the settings, suitable for [`Planner::settings`](crate::planner::Planner::settings)
+    pub fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
+        let Self {
+            determinate_nix,
+            modify_profile,
+            backup_shell_profiles,
+            backup_nix_conf,
+            nix_build_group_name,
+            nix_build_group_id,
+            nix_build_user_prefix,
+            nix_build_user_id_base,
+            nix_build_user_home,
+            nix_build_user_shell,
+            nix_build_user_count,
+            nix_package_url,
+            nix_version,
+            nix_package_stdin,
+            nix_package_sha256,
+            download_timeout_secs,
+            download_attempts,
+            user_agent,
+            from_header,
+            nix_store_path,
+            root,
+            required_free_space,
+            proxy,
+            extra_conf,
+            extra_conf_file,
+            builders_use_substitutes,
+            max_jobs,
+            cores,
+            trusted_users,
+            extra_substituters,
+            trusted_public_keys,
+            trust_sudo_user,
+            auto_optimise_store,
+            experimental_features,
+            extra_experimental_features,
+            nix_conf,
+            strict_nix_conf,
+            daemon_restart_on_failure,
+            offline,
+            force,
+            skip_nix_conf,
+            components,
+            require_cacert,
+            post_install_commands,
+            post_install_abort_on_failure,
+            reconcile_existing: _,
+            ssl_cert_file,
+        } = self;
+        let mut map = HashMap::default();
+
+        map.insert(
+            "determinate_nix".into(),
+            serde_json::to_value(determinate_nix)?,
+        );
+        map.insert(
+            "modify_profile".into(),
+            serde_json::to_value(modify_profile)?,
+        );
+        map.insert(
+            "backup_shell_profiles".into(),
+            serde_json::to_value(backup_shell_profiles)?,
+        );
+        map.insert(
+            "backup_nix_conf".into(),
+            serde_json::to_value(backup_nix_conf)?,
+        );
+        map.insert(
+            "nix_build_group_name".into(),
+            serde_json::to_value(nix_build_group_name)?,
+        );
+        map.insert(
+            "nix_build_group_id".into(),
+            serde_json::to_value(nix_build_group_id)?,
+        );
+        map.insert(
+            "nix_build_user_prefix".into(),
+            serde_json::to_value(nix_build_user_prefix)?,
+        );
+        map.insert(
+            "nix_build_user_id_base".into(),
+            serde_json::to_value(nix_build_user_id_base)?,
+        );
+        map.insert(
+            "nix_build_user_home".into(),
+            serde_json::to_value(nix_build_user_home)?,
+        );
+        map.insert(
+            "nix_build_user_shell".into(),
+            serde_json::to_value(nix_build_user_shell)?,
+        );
+        map.insert(
+            "nix_build_user_count".into(),
+            serde_json::to_value(nix_build_user_count)?,
+        );
+        map.insert(
+            "nix_package_url".into(),
+            serde_json::to_value(nix_package_url)?,
+        );
+        map.insert("nix_version".into(), serde_json::to_value(nix_version)?);
+        map.insert(
+            "nix_package_stdin".into(),
+            serde_json::to_value(nix_package_stdin)?,
+        );
+        map.insert(
+            "nix_package_sha256".into(),
+            serde_json::to_value(nix_package_sha256)?,
+        );
+        map.insert(
+            "download_timeout_secs".into(),
+            serde_json::to_value(download_timeout_secs)?,
+        );
+        map.insert(
+            "download_attempts".into(),
+            serde_json::to_value(download_attempts)?,
+        );
+        map.insert("user_agent".into(), serde_json::to_value(user_agent)?);
+        map.insert("from_header".into(), serde_json::to_value(from_header)?);
+        map.insert(
+            "nix_store_path".into(),
+            serde_json::to_value(nix_store_path)?,
+        );
+        map.insert("root".into(), serde_json::to_value(root)?);
+        map.insert(
+            "required_free_space".into(),
+            serde_json::to_value(required_free_space)?,
+        );
+        map.insert("proxy".into(), serde_json::to_value(proxy)?);
+        map.insert("ssl_cert_file".into(), serde_json::to_value(ssl_cert_file)?);
+        map.insert("extra_conf".into(), serde_json::to_value(extra_conf)?);
+        map.insert(
+            "extra_conf_file".into(),
+            serde_json::to_value(extra_conf_file)?,
+        );
+        map.insert(
+            "builders_use_substitutes".into(),
+            serde_json::to_value(builders_use_substitutes)?,
+        );
+        map.insert("max_jobs".into(), serde_json::to_value(max_jobs)?);
+        map.insert("cores".into(), serde_json::to_value(cores)?);
+        map.insert("trusted_users".into(), serde_json::to_value(trusted_users)?);
+        map.insert(
+            "extra_substituters".into(),
+            serde_json::to_value(extra_substituters)?,
+        );
+        map.insert(
+            "trusted_public_keys".into(),
+            serde_json::to_value(trusted_public_keys)?,
+        );
+        map.insert(
+            "trust_sudo_user".into(),
+            serde_json::to_value(trust_sudo_user)?,
+        );
+        map.insert(
+            "auto_optimise_store".into(),
+            serde_json::to_value(auto_optimise_store)?,
+        );
+        map.insert(
+            "experimental_features".into(),
+            serde_json::to_value(experimental_features)?,
+        );
+        map.insert(
+            "extra_experimental_features".into(),
+            serde_json::to_value(extra_experimental_features)?,
+        );
+        map.insert("nix_conf".into(), serde_json::to_value(nix_conf)?);
+        map.insert(
+            "strict_nix_conf".into(),
+            serde_json::to_value(strict_nix_conf)?,
+        );
+        map.insert(
+            "daemon_restart_on_failure".into(),
+            serde_json::to_value(daemon_restart_on_failure)?,
+        );
+        map.insert("offline".into(), serde_json::to_value(offline)?);
+        map.insert("force".into(), serde_json::to_value(force)?);
+        map.insert("skip_nix_conf".into(), serde_json::to_value(skip_nix_conf)?);
+        map.insert("components".into(), serde_json::to_value(components)?);
+        map.insert(
+            "require_cacert".into(),
+            serde_json::to_value(require_cacert)?,
+        );
+        map.insert(
+            "post_install_commands".into(),
+            serde_json::to_value(post_install_commands)?,
+        );
+        map.insert(
+            "post_install_abort_on_failure".into(),
+            serde_json::to_value(post_install_abort_on_failure)?,
+        );
+
+        Ok(map)
+    }
+
+    /// Ensure nothing configured requires network access when [`offline`](Self::offline) is set
+    pub fn check_offline(&self) -> Result<(), InstallSettingsError> {
+        if !self.offline {
+            return Ok(());
+        }
+
+        if let Some(nix_version) = &self.nix_version {
+            return Err(InstallSettingsError::RequiresNetwork(format!(
+                "--nix-version {nix_version}"
+            )));
+        }
+        if let Some(UrlOrPath::Url(url)) = &self.nix_package_url {
+            if url.scheme() != "file" {
+                return Err(InstallSettingsError::RequiresNetwork(format!(
+                    "--nix-package-url {url}"
+                )));
+            }
+        }
+        for extra_conf in &self.extra_conf {
+            if let UrlOrPathOrString::Url(url) = extra_conf {
+                if url.scheme() != "file" {
+                    return Err(InstallSettingsError::RequiresNetwork(format!(
+                        "--extra-conf {url}"
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether `component` is among the [`components`](Self::components) to install
+    pub fn installs(&self, component: Component) -> bool {
+        self.components.contains(&component)
+    }
+
+    /// Ensure the [`components`](Self::components) make a usable install, warning about odd combinations
+    pub fn check_components(&self) -> Result<(), InstallSettingsError> {
+        if !self.installs(Component::Store) {
+            return Err(InstallSettingsError::StoreComponentRequired);
+        }
+        if self.installs(Component::Daemon) && !self.installs(Component::Config) {
+            tracing::warn!(
+                "Installing the `daemon` component without `config`, the Nix daemon will run without `/etc/nix/nix.conf` and so without build users"
+            );
+        }
+        if self.installs(Component::Profiles) && !self.installs(Component::Daemon) {
+            tracing::warn!(
+                "Installing the `profiles` component without `daemon`, `nix` commands run by users other than root will fail until a Nix daemon is started"
+            );
+        }
+        Ok(())
+    }
+
+    /// Check `--max-jobs` is a number or `auto`, and `--cores` is at most [`MAX_CORES`]
+    pub fn check_build_limits(&self) -> Result<(), InstallSettingsError> {
+        if let Some(max_jobs) = &self.max_jobs {
+            if max_jobs != "auto" && max_jobs.parse::<u32>().is_err() {
+                return Err(InstallSettingsError::InvalidMaxJobs(max_jobs.clone()));
+            }
+        }
+        if let Some(cores) = self.cores {
+            if cores > MAX_CORES {
+                return Err(InstallSettingsError::InvalidCores(cores));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check each `--extra-substituters` is a URL, and each `--trusted-public-keys` looks like
+    /// `name:base64`
+    pub fn check_substituters(&self) -> Result<(), InstallSettingsError> {
+        if let Some(substituter) = self
+            .extra_substituters
+            .iter()
+            .find(|substituter| Url::parse(substituter).is_err())
+        {
+            return Err(InstallSettingsError::InvalidSubstituter(
+                substituter.clone(),
+            ));
+        }
+        if let Some(key) = self
+            .trusted_public_keys
+            .iter()
+            .find(|key| !is_public_key(key))
+        {
+            return Err(InstallSettingsError::InvalidTrustedPublicKey(key.clone()));
+        }
+        Ok(())
+    }
+
+    /// The users to add to `trusted-users`, including the `SUDO_USER` if `--trust-sudo-user` is passed
+    pub fn trusted_users_to_add(&self) -> Vec<String> {
+        let mut users = self.trusted_users.clone();
+        if self.trust_sudo_user {
+            match std::env::var("SUDO_USER") {
+                // `root` is always trusted
+                Ok(sudo_user) if !sudo_user.is_empty() && sudo_user != "root" => {
+                    users.push(sudo_user)
+                },
+                Ok(_) => (),
+                Err(_) => tracing::warn!(
+                    "`--trust-sudo-user` was passed, but `SUDO_USER` is not set, so no user was added to `trusted-users`"
+                ),
+            }
+        }
+        users
+    }
+
+    /// Refuse to leave out `component`, for planners which cannot work without it
+    pub fn check_component_required(
+        &self,
+        component: Component,
+        planner: &'static str,
+    ) -> Result<(), InstallSettingsError> {
+        if !self.installs(component) {
+            return Err(InstallSettingsError::ComponentRequired { component, planner });
+        }
+        Ok(())
+    }
+
+    /// `path` (an absolute path) under the [`root`](Self::root) being installed into, if there is one
+    pub fn rooted(&self, path: impl AsRef<Path>) -> PathBuf {
+        rooted(self.root.as_deref(), path)
+    }
+
+    /// Refuse `--root`, for planners which can only install onto the running system
+    pub fn check_no_root(&self) -> Result<(), InstallSettingsError> {
+        if self.root.is_some() {
+            return Err(InstallSettingsError::RootUnsupported);
+        }
+        Ok(())
+    }
+
+    /// Check the [`root`](Self::root) exists and can be installed into with the other settings
+    pub fn check_root(&self) -> Result<(), InstallSettingsError> {
+        let Some(root) = &self.root else {
+            return Ok(());
+        };
+        if !root.is_absolute() || !root.is_dir() {
+            return Err(InstallSettingsError::RootNotADirectory(root.clone()));
+        }
+        if self.relocated_nix_store() {
+            return Err(InstallSettingsError::RootConflict("--nix-store-path"));
+        }
+        if self.determinate_nix {
+            return Err(InstallSettingsError::RootConflict("--determinate"));
+        }
+        if !self.post_install_commands.is_empty() {
+            return Err(InstallSettingsError::RootConflict("--post-install-command"));
+        }
+        Ok(())
+    }
+
+    /// Whether the Nix store lives somewhere other than `/nix`, see [`nix_store_path`](Self::nix_store_path)
+    pub fn relocated_nix_store(&self) -> bool {
+        self.nix_store_path != std::path::Path::new(DEFAULT_NIX_STORE_PATH)
+    }
+
+    /// Refuse a relocated Nix store, for planners which manage `/nix` themselves
+    pub fn check_default_nix_store_path(&self) -> Result<(), InstallSettingsError> {
+        if self.relocated_nix_store() {
+            return Err(InstallSettingsError::NixStorePathUnsupported);
+        }
+        Ok(())
+    }
+
+    /// The version of Nix which will be installed, if it can be told from the tarball's file name
+    ///
+    /// Tarballs are conventionally named like `nix-2.21.2-aarch64-darwin.tar.xz`.
+    pub fn nix_version_to_install(&self) -> Option<semver::Version> {
+        nix_version_from_tarball_name(&self.nix_package_file_name()?)
+    }
+
+    /// The Nix `system` (like `aarch64-darwin`) the package to install is built for, if it can be
+    /// told from the tarball's file name
+    pub fn nix_package_system(&self) -> Option<&'static str> {
+        nix_system_from_tarball_name(&self.nix_package_file_name()?)
+    }
+
+    /// The Nix package to install, with [`nix_version`](Self::nix_version) resolved to its release
+    /// on `releases.nixos.org`
+    ///
+    /// `None` means the package embedded in `nix-installer`.
+    pub fn nix_package(&self) -> Result<Option<UrlOrPath>, InstallSettingsError> {
+        let Some(nix_version) = &self.nix_version else {
+            return Ok(self.nix_package_url.clone());
+        };
+        if self.nix_package_url.is_some() || self.nix_package_stdin {
+            return Err(InstallSettingsError::NixVersionConflict);
+        }
+        let system = host_nix_system().ok_or(InstallSettingsError::UnsupportedArchitecture(
+            target_lexicon::HOST,
+        ))?;
+        Ok(Some(UrlOrPath::Url(nix_release_url(nix_version, system)?)))
+    }
+
+    /// Refuse a `--nix-package-url` built for another system, like an `x86_64-linux` tarball on
+    /// an `aarch64` machine
+    ///
+    /// Packages whose file name doesn't say which system they are for are let through.
+    pub fn check_nix_package_system(&self) -> Result<(), InstallSettingsError> {
+        let Some(nix_package_url) = &self.nix_package_url else {
+            // The embedded tarball is built for the same system as `nix-installer`
+            return Ok(());
+        };
+        let Some(file_name) = self.nix_package_file_name() else {
+            return Ok(());
+        };
+        match (nix_system_from_tarball_name(&file_name), host_nix_system()) {
+            (Some(package), Some(host)) if package != host => {
+                Err(InstallSettingsError::NixPackageSystemMismatch {
+                    nix_package_url: nix_package_url.to_string(),
+                    package,
+                    host,
+                })
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn nix_package_file_name(&self) -> Option<String> {
+        if self.nix_package_stdin {
+            return None;
+        }
+        let file_name = match &self.nix_package().ok()? {
+            Some(UrlOrPath::Url(url)) => url.path_segments()?.next_back()?.to_string(),
+            Some(UrlOrPath::Path(path)) => path.file_name()?.to_str()?.to_string(),
+            None => std::path::Path::new(NIX_TARBALL_PATH)
+                .file_name()?
+                .to_str()?
+                .to_string(),
+        };
+        Some(file_name)
+    }
+}
+
+impl CommonSettings {
+    /**
+    A [`CommonSettingsBuilder`] starting from the [`default`](Self::default) settings for this machine
+
+    ```rust,no_run
+    use nix_installer::settings::CommonSettings;
+
+    # async fn build_settings() -> color_eyre::Result<()> {
+    let settings = CommonSettings::builder()
+        .await?
+        .nix_build_user_count(8)
+        .extra_conf("max-jobs = 4")
+        .force(true)
+        .build()?;
+    assert_eq!(settings.nix_build_user_count, 8);
+    # Ok(())
+    # }
+    ```
+    */
+    pub async fn builder() -> Result<CommonSettingsBuilder, InstallSettingsError> {
+        Ok(CommonSettingsBuilder {
+            settings: Self::default().await?,
+        })
+    }
+}
+
+/**
+Builds [`CommonSettings`] for library use, checking them in [`build`](Self::build)
+
+Settings without a method here can still be changed on the built [`CommonSettings`].
+
+```rust,no_run
+use nix_installer::settings::CommonSettings;
+
+# async fn build_settings() -> color_eyre::Result<()> {
+// A build user count of zero is refused
+assert!(CommonSettings::builder()
+    .await?
+    .nix_build_user_count(0)
+    .build()
+    .is_err());
+# Ok(())
+# }
+```
+*/
+#[derive(Debug, Clone)]
+pub struct CommonSettingsBuilder {
+    settings: CommonSettings,
+}
+
+impl CommonSettingsBuilder {
+    /// Install Determinate Nix
+    pub fn determinate_nix(mut self, toggle: bool) -> Self {
+        self.settings.determinate_nix = toggle;
+        self
+    }
+
+    /// Modify the user profile to automatically load Nix
+    pub fn modify_profile(mut self, toggle: bool) -> Self {
+        self.settings.modify_profile = toggle;
+        self
+    }
+
+    /// The Nix build group name
+    pub fn nix_build_group_name(mut self, name: impl Into<String>) -> Self {
+        self.settings.nix_build_group_name = name.into();
+        self
+    }
+
+    /// The Nix build group GID
+    pub fn nix_build_group_id(mut self, gid: u32) -> Self {
+        self.settings.nix_build_group_id = gid;
+        self
+    }
+
+    /// The Nix build user prefix (user numbers will be postfixed)
+    pub fn nix_build_user_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.settings.nix_build_user_prefix = prefix.into();
+        self
+    }
+
+    /// The number of build users to create, at least one
+    pub fn nix_build_user_count(mut self, count: u32) -> Self {
+        self.settings.nix_build_user_count = count;
+        self
+    }
+
+    /// The Nix build user base UID (ascending)
+    pub fn nix_build_user_id_base(mut self, uid: u32) -> Self {
+        self.settings.nix_build_user_id_base = uid;
+        self
+    }
+
+    /// The Nix package to install, instead of the bundled one
+    pub fn nix_package_url(mut self, nix_package_url: UrlOrPath) -> Self {
+        self.settings.nix_package_url = Some(nix_package_url);
+        self
+    }
+
+    /// The Nix release to install, like `2.24.9`, instead of the bundled one
+    pub fn nix_version(mut self, version: impl Into<String>) -> Self {
+        self.settings.nix_version = Some(version.into());
+        self
+    }
+
+    /// The proxy to use (if any)
+    pub fn proxy(mut self, proxy: Url) -> Self {
+        self.settings.proxy = Some(proxy);
+        self
+    }
+
+    /// An SSL cert to use (if any)
+    pub fn ssl_cert_file(mut self, ssl_cert_file: impl Into<PathBuf>) -> Self {
+        self.settings.ssl_cert_file = Some(ssl_cert_file.into());
+        self
+    }
+
+    /// Add extra `nix.conf` configuration, like `max-jobs = 4`
+    pub fn extra_conf(mut self, extra_conf: impl Into<String>) -> Self {
+        self.settings
+            .extra_conf
+            .push(UrlOrPathOrString::String(extra_conf.into()));
+        self
+    }
+
+    /// Install without any network access
+    pub fn offline(mut self, toggle: bool) -> Self {
+        self.settings.offline = toggle;
+        self
+    }
+
+    /// Forcibly recreate files `nix-installer` finds existing
+    pub fn force(mut self, toggle: bool) -> Self {
+        self.settings.force = toggle;
+        self
+    }
+
+    /// Skip creating `/etc/nix/nix.conf`
+    pub fn skip_nix_conf(mut self, toggle: bool) -> Self {
+        self.settings.skip_nix_conf = toggle;
+        self
+    }
+
+    /// The parts of Nix to install
+    pub fn components(mut self, components: Vec<Component>) -> Self {
+        self.settings.components = components;
+        self
+    }
+
+    /// Install into this directory as if it were `/`
+    pub fn root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.settings.root = Some(root.into());
+        self
+    }
+
+    /// Users to add to `trusted-users`, alongside `root`
+    pub fn trusted_users(mut self, users: Vec<String>) -> Self {
+        self.settings.trusted_users = users;
+        self
+    }
+
+    /// Set `max-jobs` in `nix.conf`, a number or `auto`
+    pub fn max_jobs(mut self, max_jobs: impl Into<String>) -> Self {
+        self.settings.max_jobs = Some(max_jobs.into());
+        self
     }
 
-    /// A listing of the settings, suitable for [`Planner::settings`](crate::planner::Planner::settings)
-    pub fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
-        let Self {
-            determinate_nix,
-            modify_profile,
-            nix_build_group_name,
-            nix_build_group_id,
-            nix_build_user_prefix,
-            nix_build_user_id_base,
-            nix_build_user_count,
-            nix_package_url,
-            proxy,
-            extra_conf,
-            force,
-            skip_nix_conf,
-            ssl_cert_file,
-        } = self;
-        let mut map = HashMap::default();
+    /// Set `cores` in `nix.conf`, `0` meaning all of them
+    pub fn cores(mut self, cores: u32) -> Self {
+        self.settings.cores = Some(cores);
+        self
+    }
 
-        map.insert(
-            "determinate_nix".into(),
-            serde_json::to_value(determinate_nix)?,
-        );
-        map.insert(
-            "modify_profile".into(),
-            serde_json::to_value(modify_profile)?,
-        );
-        map.insert(
-            "nix_build_group_name".into(),
-            serde_json::to_value(nix_build_group_name)?,
-        );
-        map.insert(
-            "nix_build_group_id".into(),
-            serde_json::to_value(nix_build_group_id)?,
-        );
-        map.insert(
-            "nix_build_user_prefix".into(),
-            serde_json::to_value(nix_build_user_prefix)?,
-        );
-        map.insert(
-            "nix_build_user_id_base".into(),
-            serde_json::to_value(nix_build_user_id_base)?,
-        );
-        map.insert(
-            "nix_build_user_count".into(),
-            serde_json::to_value(nix_build_user_count)?,
-        );
-        map.insert(
-            "nix_package_url".into(),
-            serde_json::to_value(nix_package_url)?,
-        );
-        map.insert("proxy".into(), serde_json::to_value(proxy)?);
-        map.insert("ssl_cert_file".into(), serde_json::to_value(ssl_cert_file)?);
-        map.insert("extra_conf".into(), serde_json::to_value(extra_conf)?);
-        map.insert("force".into(), serde_json::to_value(force)?);
-        map.insert("skip_nix_conf".into(), serde_json::to_value(skip_nix_conf)?);
+    /// Binary caches to add to `extra-substituters`
+    pub fn extra_substituters(mut self, substituters: Vec<String>) -> Self {
+        self.settings.extra_substituters = substituters;
+        self
+    }
 
-        Ok(map)
+    /// Signing keys to trust for the `extra_substituters`
+    pub fn trusted_public_keys(mut self, keys: Vec<String>) -> Self {
+        self.settings.trusted_public_keys = keys;
+        self
+    }
+
+    /// Check the settings make sense together, and return them
+    pub fn build(self) -> Result<CommonSettings, InstallSettingsError> {
+        let Self { settings } = self;
+
+        if settings.nix_build_user_count == 0 {
+            return Err(InstallSettingsError::NoBuildUsers);
+        }
+        if settings
+            .nix_build_user_id_base
+            .checked_add(settings.nix_build_user_count)
+            .is_none()
+        {
+            return Err(InstallSettingsError::BuildUserIdsOverflow {
+                base: settings.nix_build_user_id_base,
+                count: settings.nix_build_user_count,
+            });
+        }
+        // Resolving the package checks `nix_version`
+        settings.nix_package()?;
+        settings.check_offline()?;
+        settings.check_components()?;
+        settings.check_build_limits()?;
+        settings.check_substituters()?;
+
+        Ok(settings)
+    }
+}
+
+/// The Nix systems `nix-installer` can install on
+const NIX_SYSTEMS: [&str; 5] = [
+    "x86_64-linux",
+    "i686-linux",
+    "aarch64-linux",
+    "x86_64-darwin",
+    "aarch64-darwin",
+];
+
+/// The Nix `system` (like `aarch64-linux`) of a host, which Nix tarballs are named after
+pub fn nix_system(
+    architecture: target_lexicon::Architecture,
+    operating_system: target_lexicon::OperatingSystem,
+) -> Option<&'static str> {
+    use target_lexicon::{Architecture, OperatingSystem};
+    match (architecture, operating_system) {
+        (Architecture::X86_64, OperatingSystem::Linux) => Some("x86_64-linux"),
+        (Architecture::X86_32(_), OperatingSystem::Linux) => Some("i686-linux"),
+        (Architecture::Aarch64(_), OperatingSystem::Linux) => Some("aarch64-linux"),
+        (Architecture::X86_64, OperatingSystem::MacOSX { .. })
+        | (Architecture::X86_64, OperatingSystem::Darwin) => Some("x86_64-darwin"),
+        (Architecture::Aarch64(_), OperatingSystem::MacOSX { .. })
+        | (Architecture::Aarch64(_), OperatingSystem::Darwin) => Some("aarch64-darwin"),
+        _ => None,
+    }
+}
+
+/// The Nix `system` of the machine `nix-installer` is running on
+pub fn host_nix_system() -> Option<&'static str> {
+    nix_system(
+        target_lexicon::Architecture::host(),
+        target_lexicon::OperatingSystem::host(),
+    )
+}
+
+/// The canonical URL of a Nix release, like
+/// `https://releases.nixos.org/nix/nix-2.18.1/nix-2.18.1-aarch64-linux.tar.xz`
+fn nix_release_url(version: &str, system: &str) -> Result<Url, InstallSettingsError> {
+    let is_release = matches!(
+        semver::Version::parse(version),
+        Ok(parsed) if parsed.pre.is_empty() && parsed.build.is_empty()
+    );
+    if !is_release {
+        return Err(InstallSettingsError::InvalidNixVersion(version.to_string()));
+    }
+    Ok(Url::parse(&format!(
+        "https://releases.nixos.org/nix/nix-{version}/nix-{version}-{system}.tar.xz"
+    ))?)
+}
+
+fn nix_system_from_tarball_name(file_name: &str) -> Option<&'static str> {
+    let stem = file_name.strip_suffix(".tar.xz").unwrap_or(file_name);
+    NIX_SYSTEMS
+        .into_iter()
+        .find(|system| stem.ends_with(&format!("-{system}")))
+}
+
+fn nix_version_from_tarball_name(file_name: &str) -> Option<semver::Version> {
+    let rest = file_name.strip_prefix("nix-")?;
+    // Drop any suffix, like the `pre20240101_abcdef` of unstable versions
+    let version: String = rest
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    semver::Version::parse(version.trim_end_matches('.')).ok()
+}
+
+/// Pick the init system to configure on Linux, and whether it is running
+///
+/// OpenRC is only chosen when it is found explicitly, otherwise systemd remains the default.
+async fn linux_detect_init() -> (InitSystem, bool) {
+    use crate::os::linux::{detect_init, DetectedInit};
+
+    match detect_init() {
+        DetectedInit::Systemd => (InitSystem::Systemd, linux_detect_systemd_started().await),
+        DetectedInit::OpenRc => (InitSystem::OpenRc, true),
+        _ if std::path::Path::new("/sbin/openrc").exists() => (InitSystem::OpenRc, false),
+        _ => (InitSystem::Systemd, false),
     }
 }
 
@@ -371,6 +1617,41 @@ pub struct InitSettings {
         )
     )]
     pub start_daemon: bool,
+
+    /// Stop an already running `nix-daemon` instead of refusing to install
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            action(ArgAction::SetTrue),
+            default_value = "false",
+            env = "NIX_INSTALLER_REPLACE_RUNNING_DAEMON",
+        )
+    )]
+    #[serde(default)]
+    pub replace_running_daemon: bool,
+
+    /// The name of the systemd units running the Nix daemon, linked as `{name}.service` and `{name}.socket`
+    ///
+    /// The socket unit still listens on `/nix/var/nix/daemon-socket/socket`, so a renamed daemon
+    /// can't run alongside another Nix install.
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            default_value = DEFAULT_DAEMON_UNIT_NAME,
+            env = "NIX_INSTALLER_DAEMON_UNIT_NAME",
+        )
+    )]
+    #[serde(default = "default_daemon_unit_name")]
+    pub daemon_unit_name: String,
+}
+
+/// The name of the Nix daemon's systemd units, unless `--daemon-unit-name` is passed
+pub const DEFAULT_DAEMON_UNIT_NAME: &str = "nix-daemon";
+
+fn default_daemon_unit_name() -> String {
+    DEFAULT_DAEMON_UNIT_NAME.to_string()
 }
 
 impl InitSettings {
@@ -378,15 +1659,9 @@ impl InitSettings {
     pub async fn default() -> Result<Self, InstallSettingsError> {
         use target_lexicon::{Architecture, OperatingSystem};
         let (init, start_daemon) = match (Architecture::host(), OperatingSystem::host()) {
-            (Architecture::X86_64, OperatingSystem::Linux) => {
-                (InitSystem::Systemd, linux_detect_systemd_started().await)
-            },
-            (Architecture::X86_32(_), OperatingSystem::Linux) => {
-                (InitSystem::Systemd, linux_detect_systemd_started().await)
-            },
-            (Architecture::Aarch64(_), OperatingSystem::Linux) => {
-                (InitSystem::Systemd, linux_detect_systemd_started().await)
-            },
+            (Architecture::X86_64, OperatingSystem::Linux)
+            | (Architecture::X86_32(_), OperatingSystem::Linux)
+            | (Architecture::Aarch64(_), OperatingSystem::Linux) => linux_detect_init().await,
             (Architecture::X86_64, OperatingSystem::MacOSX { .. })
             | (Architecture::X86_64, OperatingSystem::Darwin) => (InitSystem::Launchd, true),
             (Architecture::Aarch64(_), OperatingSystem::MacOSX { .. })
@@ -398,16 +1673,34 @@ impl InitSettings {
             },
         };
 
-        Ok(Self { init, start_daemon })
+        Ok(Self {
+            init,
+            start_daemon,
+            replace_running_daemon: false,
+            daemon_unit_name: default_daemon_unit_name(),
+        })
     }
 
     /// A listing of the settings, suitable for [`Planner::settings`](crate::planner::Planner::settings)
     pub fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
-        let Self { init, start_daemon } = self;
+        let Self {
+            init,
+            start_daemon,
+            replace_running_daemon,
+            daemon_unit_name,
+        } = self;
         let mut map = HashMap::default();
 
         map.insert("init".into(), serde_json::to_value(init)?);
         map.insert("start_daemon".into(), serde_json::to_value(start_daemon)?);
+        map.insert(
+            "replace_running_daemon".into(),
+            serde_json::to_value(replace_running_daemon)?,
+        );
+        map.insert(
+            "daemon_unit_name".into(),
+            serde_json::to_value(daemon_unit_name)?,
+        );
         Ok(map)
     }
 
@@ -422,6 +1715,69 @@ impl InitSettings {
         self.start_daemon = toggle;
         self
     }
+
+    /// Stop an already running `nix-daemon` instead of refusing to install
+    pub fn replace_running_daemon(&mut self, toggle: bool) -> &mut Self {
+        self.replace_running_daemon = toggle;
+        self
+    }
+
+    /// The name of the systemd units running the Nix daemon
+    pub fn daemon_unit_name(&mut self, name: impl Into<String>) -> &mut Self {
+        self.daemon_unit_name = name.into();
+        self
+    }
+
+    /// Whether the daemon's units are named something other than `nix-daemon`
+    pub fn custom_daemon_unit_name(&self) -> bool {
+        self.daemon_unit_name != DEFAULT_DAEMON_UNIT_NAME
+    }
+
+    /// Refuse a `--daemon-unit-name` systemd would not accept as a unit name
+    ///
+    /// The name is given without a suffix, `.service` and `.socket` are added to it.
+    pub fn check_daemon_unit_name(&self) -> Result<(), InstallSettingsError> {
+        let name = &self.daemon_unit_name;
+        let valid = !name.is_empty()
+            // Leave room for the longest suffix within systemd's 255 byte limit
+            && name.len() <= 255 - ".service".len()
+            && !name.starts_with('.')
+            && !name.contains('@')
+            && !name.ends_with(".service")
+            && !name.ends_with(".socket")
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, ':' | '-' | '_' | '.' | '\\'));
+        if !valid {
+            return Err(InstallSettingsError::InvalidDaemonUnitName(name.clone()));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `key` is a `name:base64` public key, as in `trusted-public-keys`
+fn is_public_key(key: &str) -> bool {
+    let Some((name, encoded)) = key.split_once(':') else {
+        return false;
+    };
+    let data = encoded.trim_end_matches('=');
+    !name.is_empty()
+        && !name.contains(char::is_whitespace)
+        && !data.is_empty()
+        && encoded.len() % 4 == 0
+        && encoded.len() - data.len() <= 2
+        && data
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/')
+}
+
+/// `path` (an absolute path) under `root`, or `path` itself without one
+pub(crate) fn rooted(root: Option<&Path>, path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    match root {
+        Some(root) => root.join(path.strip_prefix("/").unwrap_or(path)),
+        None => path.to_path_buf(),
+    }
 }
 
 /// An error originating from a [`Planner::settings`](crate::planner::Planner::settings)
@@ -449,6 +1805,59 @@ pub enum InstallSettingsError {
     InitNotSupported,
     #[error(transparent)]
     UrlOrPath(#[from] UrlOrPathError),
+    #[error("`{0}` requires network access, which conflicts with `--offline`")]
+    RequiresNetwork(String),
+    #[error("`--components` must include `store`, which every other component relies on")]
+    StoreComponentRequired,
+    #[error("The `{planner}` planner cannot install without the `{component}` component")]
+    ComponentRequired {
+        component: Component,
+        planner: &'static str,
+    },
+    #[error("`--nix-store-path` is only supported by the `linux` planner")]
+    NixStorePathUnsupported,
+    #[error("`--root` is only supported by the `linux` planner")]
+    RootUnsupported,
+    #[error("`--root` conflicts with `{0}`, which only makes sense when installing onto the running system")]
+    RootConflict(&'static str),
+    #[error("`--root` must be an absolute path to an existing directory, `{}` is not", .0.display())]
+    RootNotADirectory(PathBuf),
+    #[error("`--nix-version` conflicts with `--nix-package-url` and `--nix-package-stdin`, pass only one")]
+    NixVersionConflict,
+    #[error("`{0}` is not a Nix release version, which look like `2.18.1`")]
+    InvalidNixVersion(String),
+    #[error("`--extra-substituters {0}` is not a URL, like `https://cache.example.com`")]
+    InvalidSubstituter(String),
+    #[error("`--trusted-public-keys {0}` is not a signing key, which look like `cache.example.com-1:<base64>`")]
+    InvalidTrustedPublicKey(String),
+    #[error("`--max-jobs {0}` is not a number of jobs or `auto`")]
+    InvalidMaxJobs(String),
+    #[error(
+        "`--cores {0}` is more than the {MAX_CORES} cores supported, pass `0` to use all of them"
+    )]
+    InvalidCores(u32),
+    #[error("`--nix-package-url {nix_package_url}` is a Nix for `{package}`, but this machine is `{host}`")]
+    NixPackageSystemMismatch {
+        nix_package_url: String,
+        package: &'static str,
+        host: &'static str,
+    },
+    #[error("`{0}` is not a valid name for `--daemon-unit-name`, which takes a systemd unit name like `nix-daemon` (without `.service` or `.socket`), or on macOS a launchd label like `org.nixos.nix-daemon`")]
+    InvalidDaemonUnitName(String),
+    #[error(
+        "`--daemon-unit-name` is only supported with the upstream Nix daemon on systemd or launchd"
+    )]
+    DaemonUnitNameUnsupported,
+    #[error("At least one build user is needed, `--nix-build-user-count` can't be 0")]
+    NoBuildUsers,
+    #[error("{count} build users starting after UID {base} would run past the largest UID")]
+    BuildUserIdsOverflow { base: u32, count: u32 },
+}
+
+impl From<InstallSettingsError> for crate::action::ActionErrorKind {
+    fn from(val: InstallSettingsError) -> Self {
+        crate::action::ActionErrorKind::Custom(Box::new(val))
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -596,6 +2005,45 @@ impl clap::builder::TypedValueParser for UrlOrPathOrString {
     }
 }
 
+/// A single `nix.conf` setting, parsed from `NAME=VALUE`
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize, Clone)]
+pub struct NixConfSetting {
+    pub name: String,
+    pub value: String,
+}
+
+impl Display for NixConfSetting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} = {}", self.name, self.value)
+    }
+}
+
+impl FromStr for NixConfSetting {
+    type Err = NixConfSettingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or_else(|| NixConfSettingError::MissingValue(s.to_string()))?;
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(NixConfSettingError::InvalidName(name.to_string()));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            value: value.trim().to_string(),
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum NixConfSettingError {
+    #[error("Expected `NAME=VALUE`, got `{0}`")]
+    MissingValue(String),
+    #[error("`{0}` is not a valid Nix setting name")]
+    InvalidName(String),
+}
+
 #[cfg(feature = "diagnostics")]
 impl crate::diagnostics::ErrorDiagnostic for InstallSettingsError {
     fn diagnostic(&self) -> String {
@@ -606,7 +2054,11 @@ impl crate::diagnostics::ErrorDiagnostic for InstallSettingsError {
 
 #[cfg(test)]
 mod tests {
-    use super::{FromStr, PathBuf, Url, UrlOrPath, UrlOrPathOrString};
+    use super::{
+        host_nix_system, nix_system, nix_system_from_tarball_name, nix_version_from_tarball_name,
+        CommonSettings, Component, FromStr, InitSettings, InstallSettingsError, NixConfSetting,
+        PathBuf, Url, UrlOrPath, UrlOrPathOrString, MAX_CORES, NIX_SYSTEMS,
+    };
 
     #[test]
     fn url_or_path_or_string_parses() -> Result<(), Box<dyn std::error::Error>> {
@@ -647,4 +2099,363 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn nix_conf_setting_parses() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(
+            NixConfSetting::from_str("keep-failed = true")?,
+            NixConfSetting {
+                name: String::from("keep-failed"),
+                value: String::from("true"),
+            },
+        );
+        assert_eq!(
+            NixConfSetting::from_str("builders=ssh://a x86_64-linux; ssh://b")?,
+            NixConfSetting {
+                name: String::from("builders"),
+                value: String::from("ssh://a x86_64-linux; ssh://b"),
+            },
+        );
+        assert!(NixConfSetting::from_str("keep-failed").is_err());
+        assert!(NixConfSetting::from_str(" = true").is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn offline_refuses_network_sources() -> Result<(), Box<dyn std::error::Error>> {
+        let mut settings = CommonSettings::default().await?;
+        settings.offline = true;
+        assert!(settings.check_offline().is_ok());
+
+        settings.nix_package_url = Some(UrlOrPath::Url(Url::from_str(
+            "https://releases.nixos.org/nix.tar.xz",
+        )?));
+        assert!(settings.check_offline().is_err());
+
+        settings.nix_package_url = Some(UrlOrPath::Url(Url::from_str("file:///nix.tar.xz")?));
+        assert!(settings.check_offline().is_ok());
+
+        settings.extra_conf = vec![UrlOrPathOrString::Url(Url::from_str(
+            "https://example.com/nix.conf",
+        )?)];
+        assert!(settings.check_offline().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn nix_version_from_tarball_names() {
+        assert_eq!(
+            nix_version_from_tarball_name("nix-2.21.2-aarch64-darwin.tar.xz"),
+            Some(semver::Version::new(2, 21, 2)),
+        );
+        assert_eq!(
+            nix_version_from_tarball_name("nix-2.24.0pre20240717_5bb0ca7-x86_64-linux.tar.xz"),
+            Some(semver::Version::new(2, 24, 0)),
+        );
+        assert_eq!(nix_version_from_tarball_name("nix.tar.xz"), None);
+        assert_eq!(nix_version_from_tarball_name("nix-installer.tar.xz"), None);
+    }
+
+    #[test]
+    fn nix_system_for_each_host() {
+        use target_lexicon::{
+            Aarch64Architecture, Architecture, OperatingSystem, X86_32Architecture,
+        };
+
+        let cases = [
+            (
+                Architecture::X86_64,
+                OperatingSystem::Linux,
+                Some("x86_64-linux"),
+            ),
+            (
+                Architecture::X86_32(X86_32Architecture::I686),
+                OperatingSystem::Linux,
+                Some("i686-linux"),
+            ),
+            (
+                Architecture::Aarch64(Aarch64Architecture::Aarch64),
+                OperatingSystem::Linux,
+                Some("aarch64-linux"),
+            ),
+            (
+                Architecture::X86_64,
+                OperatingSystem::Darwin,
+                Some("x86_64-darwin"),
+            ),
+            (
+                Architecture::Aarch64(Aarch64Architecture::Aarch64),
+                OperatingSystem::Darwin,
+                Some("aarch64-darwin"),
+            ),
+            (Architecture::X86_64, OperatingSystem::Windows, None),
+        ];
+        for (architecture, operating_system, expected) in cases {
+            assert_eq!(
+                nix_system(architecture, operating_system),
+                expected,
+                "{architecture} {operating_system}"
+            );
+        }
+        assert!(host_nix_system().is_some());
+    }
+
+    #[tokio::test]
+    async fn nix_package_must_match_host_system() -> eyre::Result<()> {
+        let host = host_nix_system().unwrap();
+        let other = NIX_SYSTEMS
+            .into_iter()
+            .find(|system| *system != host)
+            .unwrap();
+        assert_eq!(
+            nix_system_from_tarball_name(&format!("nix-2.21.2-{host}.tar.xz")),
+            Some(host)
+        );
+
+        let mut settings = CommonSettings::default().await?;
+        assert!(settings.check_nix_package_system().is_ok());
+
+        settings.nix_package_url = Some(UrlOrPath::Url(Url::from_str(&format!(
+            "https://releases.nixos.org/nix/nix-2.21.2/nix-2.21.2-{host}.tar.xz"
+        ))?));
+        assert!(settings.check_nix_package_system().is_ok());
+
+        settings.nix_package_url = Some(UrlOrPath::Url(Url::from_str(&format!(
+            "https://releases.nixos.org/nix/nix-2.21.2/nix-2.21.2-{other}.tar.xz"
+        ))?));
+        assert!(matches!(
+            settings.check_nix_package_system(),
+            Err(InstallSettingsError::NixPackageSystemMismatch { package, .. }) if package == other
+        ));
+
+        // Nothing to go on
+        settings.nix_package_url = Some(UrlOrPath::Url(Url::from_str(
+            "https://example.com/nix.tar.xz",
+        )?));
+        assert!(settings.check_nix_package_system().is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn nix_version_resolves_release_url() -> eyre::Result<()> {
+        let host = host_nix_system().unwrap();
+        let mut settings = CommonSettings::default().await?;
+        settings.nix_version = Some("2.18.1".into());
+        assert_eq!(
+            settings.nix_package()?,
+            Some(UrlOrPath::Url(Url::from_str(&format!(
+                "https://releases.nixos.org/nix/nix-2.18.1/nix-2.18.1-{host}.tar.xz"
+            ))?))
+        );
+        assert_eq!(
+            settings.nix_version_to_install(),
+            Some(semver::Version::new(2, 18, 1))
+        );
+
+        for invalid in ["2.18", "latest", "2.18.1-pre", "2.18.1/../../evil"] {
+            settings.nix_version = Some(invalid.into());
+            assert!(
+                matches!(
+                    settings.nix_package(),
+                    Err(InstallSettingsError::InvalidNixVersion(_))
+                ),
+                "{invalid}"
+            );
+        }
+
+        settings.nix_version = Some("2.18.1".into());
+        settings.nix_package_url = Some(UrlOrPath::Url(Url::from_str(
+            "https://example.com/nix.tar.xz",
+        )?));
+        assert!(matches!(
+            settings.nix_package(),
+            Err(InstallSettingsError::NixVersionConflict)
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn builder_validates_settings() -> Result<(), Box<dyn std::error::Error>> {
+        let settings = CommonSettings::builder()
+            .await?
+            .nix_build_user_count(4)
+            .nix_build_group_name("nixbld-custom")
+            .extra_conf("max-jobs = 4")
+            .force(true)
+            .build()?;
+        assert_eq!(settings.nix_build_user_count, 4);
+        assert_eq!(settings.nix_build_group_name, "nixbld-custom");
+        assert!(settings.force);
+        assert!(settings
+            .extra_conf
+            .contains(&UrlOrPathOrString::String("max-jobs = 4".into())));
+
+        assert!(matches!(
+            CommonSettings::builder()
+                .await?
+                .nix_build_user_count(0)
+                .build(),
+            Err(InstallSettingsError::NoBuildUsers)
+        ));
+        assert!(matches!(
+            CommonSettings::builder()
+                .await?
+                .nix_build_user_id_base(u32::MAX - 1)
+                .nix_build_user_count(2)
+                .build(),
+            Err(InstallSettingsError::BuildUserIdsOverflow { .. })
+        ));
+        assert!(matches!(
+            CommonSettings::builder()
+                .await?
+                .nix_version("latest")
+                .build(),
+            Err(InstallSettingsError::InvalidNixVersion(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn components_require_store() -> Result<(), Box<dyn std::error::Error>> {
+        let settings = CommonSettings::builder()
+            .await?
+            .components(vec![Component::Store, Component::Config])
+            .build()?;
+        assert!(settings.installs(Component::Config));
+        assert!(!settings.installs(Component::Daemon));
+        assert!(matches!(
+            settings.check_component_required(Component::Daemon, "steam-deck"),
+            Err(InstallSettingsError::ComponentRequired {
+                component: Component::Daemon,
+                ..
+            })
+        ));
+
+        assert!(matches!(
+            CommonSettings::builder()
+                .await?
+                .components(vec![Component::Config, Component::Daemon])
+                .build(),
+            Err(InstallSettingsError::StoreComponentRequired)
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn daemon_unit_name_validated() -> Result<(), Box<dyn std::error::Error>> {
+        let mut init = InitSettings::default().await?;
+        init.check_daemon_unit_name()?;
+        assert!(!init.custom_daemon_unit_name());
+
+        init.daemon_unit_name("nix-daemon-2");
+        init.check_daemon_unit_name()?;
+        assert!(init.custom_daemon_unit_name());
+
+        for invalid in [
+            "",
+            "nix daemon",
+            "nix-daemon.service",
+            "nix/daemon",
+            "nix@daemon",
+        ] {
+            init.daemon_unit_name(invalid);
+            assert!(matches!(
+                init.check_daemon_unit_name(),
+                Err(InstallSettingsError::InvalidDaemonUnitName(_))
+            ));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn build_limits_validated() -> Result<(), Box<dyn std::error::Error>> {
+        for max_jobs in ["auto", "0", "16"] {
+            CommonSettings::builder()
+                .await?
+                .max_jobs(max_jobs)
+                .build()?;
+        }
+        for max_jobs in ["", "many", "-1", "4.5"] {
+            assert!(
+                matches!(
+                    CommonSettings::builder().await?.max_jobs(max_jobs).build(),
+                    Err(InstallSettingsError::InvalidMaxJobs(_))
+                ),
+                "{max_jobs}"
+            );
+        }
+
+        CommonSettings::builder().await?.cores(MAX_CORES).build()?;
+        assert!(matches!(
+            CommonSettings::builder()
+                .await?
+                .cores(MAX_CORES + 1)
+                .build(),
+            Err(InstallSettingsError::InvalidCores(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn substituters_validated() -> Result<(), Box<dyn std::error::Error>> {
+        CommonSettings::builder()
+            .await?
+            .extra_substituters(vec!["https://cache.example.com".into()])
+            .trusted_public_keys(vec![
+                "cache.nixos.org-1:6NCHdD59X431o0gWypbMrAURkbJ16ZPMQFGspcDShjY=".into(),
+            ])
+            .build()?;
+
+        assert!(matches!(
+            CommonSettings::builder()
+                .await?
+                .extra_substituters(vec!["cache.example.com".into()])
+                .build(),
+            Err(InstallSettingsError::InvalidSubstituter(_))
+        ));
+        for key in [
+            "6NCHdD59X431o0gWypbMrAURkbJ16ZPMQFGspcDShjY=",
+            ":6NCHdD59X431o0gWypbMrAURkbJ16ZPMQFGspcDShjY=",
+            "cache.example.com-1:",
+            "cache.example.com-1:not base64!",
+            "cache.example.com-1:abc",
+        ] {
+            assert!(
+                matches!(
+                    CommonSettings::builder()
+                        .await?
+                        .trusted_public_keys(vec![key.into()])
+                        .build(),
+                    Err(InstallSettingsError::InvalidTrustedPublicKey(_))
+                ),
+                "{key}"
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn root_prefixes_paths() -> Result<(), Box<dyn std::error::Error>> {
+        let mut settings = CommonSettings::default().await?;
+        assert_eq!(settings.rooted("/nix/store"), PathBuf::from("/nix/store"));
+        settings.check_root()?;
+
+        let root = tempfile::tempdir()?;
+        settings.root = Some(root.path().to_path_buf());
+        assert_eq!(settings.rooted("/nix/store"), root.path().join("nix/store"));
+        settings.check_root()?;
+
+        settings.determinate_nix = true;
+        assert!(matches!(
+            settings.check_root(),
+            Err(InstallSettingsError::RootConflict("--determinate"))
+        ));
+
+        settings.root = Some(root.path().join("missing"));
+        assert!(matches!(
+            settings.check_root(),
+            Err(InstallSettingsError::RootNotADirectory(_))
+        ));
+        Ok(())
+    }
 }
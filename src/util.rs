@@ -1,4 +1,7 @@
-use std::path::Path;
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
 use crate::action::ActionErrorKind;
 
@@ -36,6 +39,32 @@ pub(crate) async fn remove_dir_all(path: &Path, on_missing: OnMissing) -> std::i
     }
 }
 
+const BACKUP_EXTENSION: &str = "nix-installer.bak";
+
+/// `<path>.nix-installer.bak-<seconds since the epoch>` at `time`, so an earlier backup is left
+/// alone
+pub(crate) fn backup_path_for(path: &Path, time: SystemTime) -> PathBuf {
+    let timestamp = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default();
+    let mut backup_path = path.as_os_str().to_owned();
+    backup_path.push(format!(".{BACKUP_EXTENSION}-{timestamp}"));
+    PathBuf::from(backup_path)
+}
+
+/// Copy `path` to its [`backup_path_for`] now, refusing to overwrite an existing backup
+pub(crate) async fn backup_file(path: &Path) -> Result<PathBuf, ActionErrorKind> {
+    let dest = backup_path_for(path, SystemTime::now());
+    if dest.exists() {
+        return Err(ActionErrorKind::FileExists(dest));
+    }
+    tokio::fs::copy(path, &dest)
+        .await
+        .map_err(|e| ActionErrorKind::Copy(path.to_owned(), dest.clone(), e))?;
+    Ok(dest)
+}
+
 pub(crate) async fn write_atomic(destination: &Path, body: &str) -> Result<(), ActionErrorKind> {
     let temp = destination.with_extension("tmp");
 
@@ -0,0 +1,120 @@
+//! Replaces the allocator to measure the peak memory of a download, so it is its own test binary,
+//! with only this test running in it
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    io::{BufRead as _, BufReader, Write as _},
+    sync::atomic::{AtomicIsize, Ordering},
+};
+
+use nix_installer::{
+    action::base::{DownloadPolicy, FetchAndUnpackNix},
+    settings::UrlOrPath,
+};
+
+/// Counts everything allocated, and the most that was allocated at once
+struct TrackingAllocator;
+
+#[global_allocator]
+static ALLOCATOR: TrackingAllocator = TrackingAllocator;
+static ALLOCATED: AtomicIsize = AtomicIsize::new(0);
+static PEAK_ALLOCATED: AtomicIsize = AtomicIsize::new(0);
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let size = layout.size() as isize;
+            let allocated = ALLOCATED.fetch_add(size, Ordering::SeqCst);
+            PEAK_ALLOCATED.fetch_max(allocated + size, Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size() as isize, Ordering::SeqCst);
+    }
+}
+
+/// An incompressible `.tar.xz` package of about `size` bytes, and its SHA-256
+fn incompressible_package(size: usize) -> eyre::Result<(Vec<u8>, String)> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let contents = (0..size)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect::<Vec<_>>();
+    let mut builder = tar::Builder::new(xz2::write::XzEncoder::new(Vec::new(), 0));
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, "nix-2.0/store-file", &contents[..])?;
+    let tarball = builder.into_inner()?.finish()?;
+    let sha256 = ring::digest::digest(&ring::digest::SHA256, &tarball)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    Ok((tarball, sha256))
+}
+
+// A package is unpacked as it downloads, and still checked, without ever holding much of it in
+// memory
+#[tokio::test]
+async fn download_is_not_held_in_memory() -> eyre::Result<()> {
+    let (tarball, sha256) = incompressible_package(8 * 1024 * 1024)?;
+    let size = tarball.len();
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = url::Url::parse(&format!("http://{}/nix.tar.xz", listener.local_addr()?))?;
+    let server = std::thread::spawn(move || -> std::io::Result<()> {
+        let (stream, _) = listener.accept()?;
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 && line != "\r\n" {
+            line.clear();
+        }
+        let mut stream = reader.into_inner();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Length: {size}\r\nConnection: close\r\n\r\n"
+        )?;
+        stream.write_all(&tarball)
+    });
+
+    let temp = tempfile::TempDir::new()?;
+    let dest = temp.path().join("unpacked");
+    let mut action = FetchAndUnpackNix::plan(
+        Some(UrlOrPath::Url(url)),
+        false,
+        dest.clone(),
+        None,
+        None,
+        Some(sha256),
+        DownloadPolicy::default(),
+        None,
+        None,
+    )
+    .await?;
+
+    // Everything allocated from here on is counted, including fixed costs like the client's root
+    // certificates, so the bound is loose, but holding the whole package would exceed it
+    let baseline = ALLOCATED.load(Ordering::SeqCst);
+    PEAK_ALLOCATED.store(baseline, Ordering::SeqCst);
+    action.try_execute().await?;
+    let peak = PEAK_ALLOCATED.load(Ordering::SeqCst) - baseline;
+    server.join().expect("the server panicked")?;
+
+    assert!(
+        peak < (size / 2) as isize,
+        "Unpacking a {size} byte package allocated up to {peak} bytes at once"
+    );
+    assert!(dest.join("nix-2.0/store-file").exists());
+
+    Ok(())
+}
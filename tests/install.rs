@@ -0,0 +1,83 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use nix_installer::{
+    action::{
+        base::{CreateDirectory, CreateFile},
+        Action, StatefulAction,
+    },
+    feedback::devnull::DevNull,
+    planner::{Planner, PlannerError},
+    settings::InstallSettingsError,
+    InstallPlan,
+};
+
+/// A planner which only touches a directory of its own, like an embedder installing into a chroot
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct TempRoot {
+    root: PathBuf,
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "temp-root")]
+impl Planner for TempRoot {
+    async fn default() -> Result<Self, PlannerError> {
+        Ok(Self {
+            root: std::env::temp_dir().join("nix-installer-temp-root"),
+        })
+    }
+
+    async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        Ok(vec![
+            CreateDirectory::plan(self.root.join("nix"), None, None, 0o0755, true)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+            CreateFile::plan(
+                self.root.join("nix/hello"),
+                None,
+                None,
+                0o0644,
+                "Hello".to_string(),
+                false,
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
+        ])
+    }
+
+    fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
+        Ok(HashMap::default())
+    }
+
+    async fn configured_settings(
+        &self,
+    ) -> Result<HashMap<String, serde_json::Value>, PlannerError> {
+        Ok(HashMap::default())
+    }
+
+    async fn platform_check(&self) -> Result<(), PlannerError> {
+        Ok(())
+    }
+}
+
+// Library users get no prompts, so a whole install and uninstall runs without a TTY
+#[tokio::test]
+async fn install_and_uninstall_without_prompting() -> eyre::Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path().to_path_buf();
+    let receipt = root.join("nix/receipt.json");
+
+    let mut plan = InstallPlan::plan(TempRoot { root: root.clone() }).await?;
+    plan.set_receipt_path(receipt.clone());
+
+    plan.install(DevNull, None).await?;
+    assert_eq!(std::fs::read_to_string(root.join("nix/hello"))?, "Hello");
+    let written = InstallPlan::from_json(&std::fs::read_to_string(&receipt)?)?;
+    assert_eq!(written.to_json()?, plan.to_json()?);
+
+    plan.uninstall(DevNull, None).await?;
+    assert!(!root.join("nix").exists());
+
+    Ok(())
+}
@@ -8,7 +8,7 @@ const MACOS: &str = include_str!("./fixtures/macos/macos.json");
 // If this breaks and you need to update the fixture, disable these tests, bump `nix_installer` to a new version, and update the plans.
 #[test]
 fn plan_compat_linux() -> eyre::Result<()> {
-    let _: InstallPlan = serde_json::from_str(LINUX)?;
+    let _ = InstallPlan::from_json(LINUX)?;
     Ok(())
 }
 
@@ -16,7 +16,7 @@ fn plan_compat_linux() -> eyre::Result<()> {
 // If this breaks and you need to update the fixture, disable these tests, bump `nix_installer` to a new version, and update the plans.
 #[test]
 fn plan_compat_steam_deck() -> eyre::Result<()> {
-    let _: InstallPlan = serde_json::from_str(STEAM_DECK)?;
+    let _ = InstallPlan::from_json(STEAM_DECK)?;
     Ok(())
 }
 
@@ -24,6 +24,27 @@ fn plan_compat_steam_deck() -> eyre::Result<()> {
 // If this breaks and you need to update the fixture, disable these tests, bump `nix_installer` to a new version, and update the plans.
 #[test]
 fn plan_compat_macos() -> eyre::Result<()> {
-    let _: InstallPlan = serde_json::from_str(MACOS)?;
+    let _ = InstallPlan::from_json(MACOS)?;
+    Ok(())
+}
+
+// Receipts written before action durations were recorded have none
+#[test]
+fn plan_compat_without_durations() -> eyre::Result<()> {
+    for fixture in [LINUX, STEAM_DECK, MACOS] {
+        let plan = InstallPlan::from_json(fixture)?;
+        assert!(!plan.to_json()?.contains("\"duration\""));
+    }
+    Ok(())
+}
+
+// `nix-installer plan --output` writes `to_json`, which `install --plan` must read back unchanged
+#[test]
+fn plan_output_round_trips() -> eyre::Result<()> {
+    for fixture in [LINUX, STEAM_DECK, MACOS] {
+        let written = InstallPlan::from_json(fixture)?.to_json()?;
+        assert!(written.contains("\n  \"version\": "));
+        assert_eq!(InstallPlan::from_json(&written)?.to_json()?, written);
+    }
     Ok(())
 }
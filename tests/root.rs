@@ -0,0 +1,85 @@
+#![cfg(target_os = "linux")]
+
+use nix_installer::{
+    planner::{linux::Linux, Planner},
+    InstallPlan,
+};
+
+/// Every absolute path anywhere in `value`
+fn absolute_paths(value: &serde_json::Value, paths: &mut Vec<String>) {
+    match value {
+        serde_json::Value::String(string) if string.starts_with('/') => paths.push(string.clone()),
+        serde_json::Value::Array(values) => {
+            values.iter().for_each(|value| absolute_paths(value, paths))
+        },
+        serde_json::Value::Object(map) => {
+            map.values().for_each(|value| absolute_paths(value, paths))
+        },
+        _ => (),
+    }
+}
+
+/// Every `action_name` anywhere in `value`
+fn action_names(value: &serde_json::Value, names: &mut Vec<String>) {
+    match value {
+        serde_json::Value::Array(values) => {
+            values.iter().for_each(|value| action_names(value, names))
+        },
+        serde_json::Value::Object(map) => {
+            if let Some(serde_json::Value::String(name)) = map.get("action_name") {
+                names.push(name.clone());
+            }
+            map.values().for_each(|value| action_names(value, names))
+        },
+        _ => (),
+    }
+}
+
+// An install into another root must leave the running system alone
+#[tokio::test]
+async fn plan_into_root_stays_in_root() -> eyre::Result<()> {
+    let root = tempfile::tempdir()?;
+    let mut planner = Linux::default().await?;
+    planner.settings.root = Some(root.path().to_path_buf());
+    planner.pre_install_check().await?;
+
+    let plan = InstallPlan::plan(planner).await?;
+    let plan: serde_json::Value = serde_json::from_str(&plan.to_json()?)?;
+    let actions = &plan["actions"];
+
+    let mut paths = vec![];
+    absolute_paths(actions, &mut paths);
+    assert!(!paths.is_empty());
+    for path in paths {
+        assert!(
+            std::path::Path::new(&path).starts_with(root.path()),
+            "`{path}` is outside of `{}`",
+            root.path().display()
+        );
+    }
+
+    let mut names = vec![];
+    action_names(actions, &mut names);
+    for name in [
+        "setup_default_profile",
+        "place_nix_configuration",
+        "configure_shell_profile",
+    ] {
+        assert!(
+            names.iter().any(|planned| planned == name),
+            "{name}: {names:?}"
+        );
+    }
+    for name in [
+        "create_users_and_group",
+        "configure_init_service",
+        "provision_selinux",
+    ] {
+        assert!(
+            !names.iter().any(|planned| planned == name),
+            "{name}: {names:?}"
+        );
+    }
+
+    Ok(())
+}